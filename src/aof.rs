@@ -0,0 +1,752 @@
+use crate::{
+    client::Client,
+    command::Command,
+    config::Config,
+    rdb,
+    resp::{command, Error as RespError, RespType},
+    store::{ExpireCondition, Store},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// The append-only file, which logs every write so that fewer are lost than with snapshots alone.
+// It is made up of a base snapshot, in the RDB format, followed by files of the writes since, all
+// of which are listed in a manifest.
+// See: https://redis.io/docs/latest/operate/oss_and_stack/management/persistence/#append-only-file
+
+// When writes are flushed to disk, rather than left to the operating system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fsync {
+    Always,
+    EverySec,
+    No,
+}
+
+impl Fsync {
+    const ALL: [Self; 3] = [Self::Always, Self::EverySec, Self::No];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::EverySec => "everysec",
+            Self::No => "no",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|fsync| fsync.name().eq_ignore_ascii_case(name))
+    }
+}
+
+const FSYNC_INTERVAL: Duration = Duration::from_secs(1);
+
+// The files making up the append-only file, along with their sequence numbers, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    base: Option<(String, u64)>,
+    incrs: Vec<(String, u64)>,
+}
+
+impl Manifest {
+    // Lines such as `file appendonly.aof.1.incr.aof seq 1 type i`, as written by Redis, where
+    // history files, of type `h`, are waiting to be deleted
+    fn parse(manifest: &str) -> Result<Self> {
+        let mut parsed = Self::default();
+        for line in manifest.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let field = |name| {
+                fields
+                    .chunks(2)
+                    .find(|pair| pair[0] == name && pair.len() == 2)
+                    .map(|pair| pair[1])
+                    .with_context(|| format!("manifest line is missing '{name}': {line:?}"))
+            };
+            let file = field("file")?.to_string();
+            let seq = field("seq")?
+                .parse()
+                .with_context(|| format!("invalid sequence number: {line:?}"))?;
+            match field("type")? {
+                "b" => parsed.base = Some((file, seq)),
+                "i" => parsed.incrs.push((file, seq)),
+                "h" => {}
+                file_type => bail!("unknown manifest file type {file_type:?}"),
+            }
+        }
+        parsed.incrs.sort_by_key(|(_, seq)| *seq);
+
+        Ok(parsed)
+    }
+
+    fn encode(&self) -> String {
+        let base = self.base.iter().map(|(file, seq)| (file, seq, "b"));
+        let incrs = self.incrs.iter().map(|(file, seq)| (file, seq, "i"));
+        base.chain(incrs)
+            .map(|(file, seq, file_type)| format!("file {file} seq {seq} type {file_type}\n"))
+            .collect()
+    }
+
+    fn path(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{name}.manifest"))
+    }
+
+    fn read(dir: &Path, name: &str) -> Result<Option<Self>> {
+        let path = Self::path(dir, name);
+        match fs::read_to_string(&path) {
+            Ok(manifest) => Self::parse(&manifest)
+                .map(Some)
+                .with_context(|| format!("invalid manifest {}", path.display())),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    // Replaces the manifest all at once, so that it always lists a complete set of files
+    fn write(&self, dir: &Path, name: &str) -> Result<()> {
+        let path = Self::path(dir, name);
+        let temp = dir.join(format!("temp-{name}.manifest"));
+        let mut file = File::create(&temp)?;
+        file.write_all(self.encode().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn files(&self) -> impl Iterator<Item = &str> {
+        self.base
+            .iter()
+            .chain(&self.incrs)
+            .map(|(file, _)| file.as_str())
+    }
+
+    fn next_base(&self, name: &str) -> (String, u64) {
+        let seq = self.base.as_ref().map_or(1, |(_, seq)| seq + 1);
+        (format!("{name}.{seq}.base.rdb"), seq)
+    }
+
+    fn next_incr(&self, name: &str) -> (String, u64) {
+        let seq = self.incrs.last().map_or(1, |(_, seq)| seq + 1);
+        (format!("{name}.{seq}.incr.aof"), seq)
+    }
+}
+
+pub struct Aof {
+    // `None` while the append-only file is off
+    appending: Mutex<Option<Appending>>,
+    // Writes made while the append-only file is off hold this for reading, so that turning it on
+    // waits for them to finish, and none can be missed from both the new base and the log
+    unlogged: RwLock<()>,
+    rewriting: AtomicBool,
+    last_rewrite_failed: AtomicBool,
+    // The replication offset of the last write known to be on disk, for WAITAOF
+    fsynced: AtomicU64,
+}
+
+// The incr file currently being appended to
+pub struct Appending {
+    dir: PathBuf,
+    name: String,
+    manifest: Manifest,
+    file: File,
+    fsync: Fsync,
+    // The database last selected in the file, so that SELECT is only logged when it changes
+    db: Option<usize>,
+    last_fsync: Instant,
+    unsynced: bool,
+    // The replication offset of the last write appended
+    written: u64,
+}
+
+impl Appending {
+    // Appends to the last incr file in the manifest, adding one if there are none
+    fn open(dir: &Path, name: &str, mut manifest: Manifest, fsync: Fsync) -> Result<Self> {
+        if manifest.incrs.is_empty() {
+            let incr = manifest.next_incr(name);
+            manifest.incrs.push(incr);
+        }
+        let (incr, _) = manifest.incrs.last().expect("an incr file was added");
+        let file = open_incr(&dir.join(incr))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            name: name.to_string(),
+            manifest,
+            file,
+            fsync,
+            db: None,
+            last_fsync: Instant::now(),
+            unsynced: false,
+            written: 0,
+        })
+    }
+
+    // Switches to appending to a new incr file
+    fn next_incr(&mut self) -> Result<()> {
+        let (incr, seq) = self.manifest.next_incr(&self.name);
+        self.file = open_incr(&self.dir.join(&incr))?;
+        self.manifest.incrs.push((incr, seq));
+        self.db = None;
+
+        Ok(())
+    }
+
+    fn append(&mut self, db: usize, entry: &[u8], offset: u64) -> io::Result<()> {
+        let mut data = Vec::new();
+        if self.db != Some(db) {
+            data.extend(command(&[b"SELECT", db.to_string().as_bytes()]));
+            self.db = Some(db);
+        }
+        data.extend_from_slice(entry);
+        self.file.write_all(&data)?;
+        self.written = offset;
+
+        // With `no`, writes are left to the operating system, so are as good as on disk once written
+        match self.fsync {
+            Fsync::Always => {
+                self.file.sync_data()?;
+                self.last_fsync = Instant::now();
+            }
+            Fsync::EverySec => self.unsynced = true,
+            Fsync::No => {}
+        }
+        Ok(())
+    }
+}
+
+// Held while a write runs, so that writes are logged in the order they happen
+pub enum WriteGuard<'a> {
+    // Only held to stop the append-only file being turned on while the write runs
+    Unlogged(#[allow(dead_code)] RwLockReadGuard<'a, ()>),
+    Logged(&'a AtomicU64, MutexGuard<'a, Option<Appending>>),
+}
+
+impl WriteGuard<'_> {
+    pub const fn is_logged(&self) -> bool {
+        matches!(self, Self::Logged(..))
+    }
+
+    // `offset` is the replication offset after the write
+    pub fn append(&mut self, db: usize, entry: &[u8], offset: u64) -> io::Result<()> {
+        let Self::Logged(fsynced, appending) = self else {
+            return Ok(());
+        };
+        let appending = appending.as_mut().expect("append-only file should be on");
+        appending.append(db, entry, offset)?;
+        if !appending.unsynced {
+            fsynced.fetch_max(offset, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+impl Aof {
+    pub fn new() -> Self {
+        Self {
+            appending: Mutex::new(None),
+            unlogged: RwLock::new(()),
+            rewriting: AtomicBool::new(false),
+            last_rewrite_failed: AtomicBool::new(false),
+            fsynced: AtomicU64::new(0),
+        }
+    }
+
+    fn appending(&self) -> MutexGuard<'_, Option<Appending>> {
+        self.appending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.appending().is_some()
+    }
+
+    // The replication offset of the last write on disk, while the append-only file is on
+    pub fn fsynced(&self) -> Option<u64> {
+        self.is_on().then(|| self.fsynced.load(Ordering::Relaxed))
+    }
+
+    pub fn is_rewriting(&self) -> bool {
+        self.rewriting.load(Ordering::Relaxed)
+    }
+
+    pub fn last_rewrite_failed(&self) -> bool {
+        self.last_rewrite_failed.load(Ordering::Relaxed)
+    }
+
+    // Taken before running a write, which while the append-only file is on means one at a time
+    pub fn lock(&self) -> WriteGuard<'_> {
+        let unlogged = self.unlogged.read().unwrap_or_else(PoisonError::into_inner);
+        let appending = self.appending();
+        if appending.is_some() {
+            WriteGuard::Logged(&self.fsynced, appending)
+        } else {
+            WriteGuard::Unlogged(unlogged)
+        }
+    }
+
+    // Starts appending to the files loaded on startup, or creates them from the keyspace as it is
+    // when there were none
+    pub fn start(
+        self: &Arc<Self>,
+        store: &Arc<Store>,
+        config: &Config,
+        manifest: Option<Manifest>,
+    ) -> Result<()> {
+        let Some(manifest) = manifest else {
+            return self.turn_on(store, config);
+        };
+
+        let appending = Appending::open(
+            &config.aof_dir(),
+            &config.append_filename,
+            manifest.clone(),
+            config.append_fsync,
+        )?;
+        if appending.manifest != manifest {
+            appending.manifest.write(&appending.dir, &appending.name)?;
+        }
+        *self.appending() = Some(appending);
+
+        Ok(())
+    }
+
+    // Follows changes to `appendonly` and `appendfsync` made with CONFIG SET
+    pub fn configure(self: &Arc<Self>, store: &Arc<Store>, config: &Config) -> Result<()> {
+        if config.append_only {
+            self.turn_on(store, config)?;
+        } else if let Some(appending) = self.appending().take() {
+            appending.file.sync_data()?;
+        }
+
+        if let Some(appending) = self.appending().as_mut() {
+            appending.fsync = config.append_fsync;
+        }
+        Ok(())
+    }
+
+    // Starts logging writes to a new incr file, along with a rewrite to create its base. Any files
+    // left from before are replaced once the rewrite finishes.
+    fn turn_on(self: &Arc<Self>, store: &Arc<Store>, config: &Config) -> Result<()> {
+        let _unlogged = self
+            .unlogged
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut appending = self.appending();
+        if appending.is_some() {
+            return Ok(());
+        }
+        // The rewrite from when it was last on would otherwise update the new files
+        if self.is_rewriting() {
+            bail!("a rewrite of the append-only file is still in progress");
+        }
+
+        let dir = config.aof_dir();
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let mut manifest = Manifest::read(&dir, &config.append_filename)?.unwrap_or_default();
+        let incr = manifest.next_incr(&config.append_filename);
+        manifest.incrs.push(incr);
+        *appending = Some(Appending::open(
+            &dir,
+            &config.append_filename,
+            manifest,
+            config.append_fsync,
+        )?);
+        self.rewrite_locked(store, &mut appending, false)
+    }
+
+    // As per BGREWRITEAOF, returning false when a rewrite is already in progress
+    pub fn rewrite(self: &Arc<Self>, store: &Arc<Store>) -> Result<bool> {
+        let mut appending = self.appending();
+        let Some(current) = appending.as_mut() else {
+            bail!("the append-only file is off");
+        };
+        if self.is_rewriting() {
+            return Ok(false);
+        }
+
+        current.next_incr()?;
+        self.rewrite_locked(store, &mut appending, true)?;
+        Ok(true)
+    }
+
+    // Takes a snapshot for the new base, while writes are held up so that it matches the start of
+    // the current incr file, and writes it in the background. When `keep`, the files before it
+    // are kept in the manifest until then, otherwise the manifest is only written once the base
+    // has been.
+    fn rewrite_locked(
+        self: &Arc<Self>,
+        store: &Store,
+        appending: &mut MutexGuard<'_, Option<Appending>>,
+        keep: bool,
+    ) -> Result<()> {
+        let current = appending.as_ref().expect("append-only file is on");
+        if keep {
+            current.manifest.write(&current.dir, &current.name)?;
+        }
+        self.rewriting.store(true, Ordering::Relaxed);
+
+        let snapshot = store.snapshot();
+        let (dir, name) = (current.dir.clone(), current.name.clone());
+        let (base, base_seq) = current.manifest.next_base(&name);
+        let first_incr = current.manifest.incrs.last().map_or(0, |(_, seq)| *seq);
+
+        let aof = Arc::clone(self);
+        thread::spawn(move || {
+            let result = rdb::save(&snapshot, &dir.join(&base), true);
+            drop(snapshot);
+            let result = result.and_then(|()| {
+                let mut appending = aof.appending();
+                let Some(appending) = appending.as_mut() else {
+                    // Turned off while rewriting
+                    let _ = fs::remove_file(dir.join(&base));
+                    return Ok(());
+                };
+
+                let previous = appending.manifest.clone();
+                appending.manifest.base = Some((base.clone(), base_seq));
+                appending
+                    .manifest
+                    .incrs
+                    .retain(|(_, seq)| *seq >= first_incr);
+                appending.manifest.write(&dir, &name)?;
+
+                for file in previous.files() {
+                    if !appending.manifest.files().any(|kept| kept == file) {
+                        let _ = fs::remove_file(dir.join(file));
+                    }
+                }
+                Ok(())
+            });
+
+            match &result {
+                Ok(()) => println!("append-only file rewrite succeeded"),
+                Err(error) => eprintln!("append-only file rewrite failed: {error:#}"),
+            }
+            aof.last_rewrite_failed
+                .store(result.is_err(), Ordering::Relaxed);
+            aof.rewriting.store(false, Ordering::Relaxed);
+        });
+
+        Ok(())
+    }
+
+    // Called periodically to flush writes to disk, once a second with `appendfsync everysec`.
+    // The file is flushed without holding up writes.
+    pub fn cron(&self) {
+        let (file, written) = {
+            let mut appending = self.appending();
+            match appending.as_mut() {
+                Some(appending)
+                    if appending.fsync == Fsync::EverySec
+                        && appending.unsynced
+                        && appending.last_fsync.elapsed() >= FSYNC_INTERVAL =>
+                {
+                    appending.unsynced = false;
+                    appending.last_fsync = Instant::now();
+                    (appending.file.try_clone(), appending.written)
+                }
+                _ => return,
+            }
+        };
+
+        match file.and_then(|file| file.sync_data()) {
+            Ok(()) => {
+                self.fsynced.fetch_max(written, Ordering::Relaxed);
+            }
+            Err(error) => eprintln!("failed to fsync the append-only file: {error}"),
+        }
+    }
+}
+
+fn open_incr(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))
+}
+
+// What is logged for a write, which is the request as it was sent, other than for relative
+// deadlines, which are logged as absolute ones so that they don't move when replayed
+pub enum Entry<'a> {
+    Request(&'a [u8]),
+    SetWithDeadline(&'a str, &'a [u8]),
+    Expire(&'a str, i64, Option<ExpireCondition>),
+    Restore(&'a str, &'a [u8]),
+    Del(Vec<&'a str>),
+}
+
+impl<'a> Entry<'a> {
+    // Decided before the command runs, as running it consumes it
+    pub fn new(command: &Command<'a>, request: &'a [u8]) -> Self {
+        match command {
+            Command::Set(key, value, Some(_)) => Self::SetWithDeadline(key, value.as_bytes()),
+            Command::Expire(key, deadline, condition) => Self::Expire(key, *deadline, *condition),
+            Command::Restore(key, restore) | Command::RestoreAsking(key, restore) => {
+                Self::Restore(key, restore.payload)
+            }
+            _ => Self::Request(request),
+        }
+    }
+
+    // The deadlines set by SET and RESTORE are read back once they have run
+    pub fn encode(&self, store: &Store, db: usize) -> Vec<u8> {
+        match self {
+            Self::Request(request) => request.to_vec(),
+            Self::SetWithDeadline(key, value) => {
+                let mut entry = command(&[b"SET", key.as_bytes(), value]);
+                if let Some(Some(deadline)) = store.read(db, [*key]).expires(key) {
+                    let deadline = deadline.to_string();
+                    entry.extend(command(&[
+                        b"PEXPIREAT",
+                        key.as_bytes(),
+                        deadline.as_bytes(),
+                    ]));
+                }
+                entry
+            }
+            Self::Expire(key, deadline, condition) => {
+                let deadline = deadline.to_string();
+                let mut arguments = vec![&b"PEXPIREAT"[..], key.as_bytes(), deadline.as_bytes()];
+                arguments.extend(condition.map(|condition| match condition {
+                    ExpireCondition::NoDeadline => &b"NX"[..],
+                    ExpireCondition::HasDeadline => b"XX",
+                    ExpireCondition::Later => b"GT",
+                    ExpireCondition::Earlier => b"LT",
+                }));
+                command(&arguments)
+            }
+            // A key restored with a deadline which had already passed was removed instead
+            Self::Restore(key, payload) => match store.read(db, [*key]).expires(key) {
+                Some(deadline) => {
+                    let deadline = deadline.unwrap_or(0).to_string();
+                    command(&[
+                        b"RESTORE",
+                        key.as_bytes(),
+                        deadline.as_bytes(),
+                        payload,
+                        b"REPLACE",
+                        b"ABSTTL",
+                    ])
+                }
+                None => command(&[b"DEL", key.as_bytes()]),
+            },
+            Self::Del(keys) => {
+                let mut arguments = vec![&b"DEL"[..]];
+                arguments.extend(keys.iter().map(|key| key.as_bytes()));
+                command(&arguments)
+            }
+        }
+    }
+}
+
+// Replays the append-only file through `client`, returning its manifest, or `None` when there
+// isn't one. Like Redis with `aof-load-truncated yes`, a command cut short at the end of the last
+// file, such as by a crash while it was written, is removed from the file rather than stopping the
+// server from starting.
+pub fn load(client: &Client, store: &Store, config: &Config) -> Result<Option<Manifest>> {
+    let dir = config.aof_dir();
+    let Some(manifest) = Manifest::read(&dir, &config.append_filename)? else {
+        return Ok(None);
+    };
+
+    if let Some((base, _)) = &manifest.base {
+        let path = dir.join(base);
+        let data = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        // Redis writes the base as commands when `aof-use-rdb-preamble` is off
+        if data.starts_with(b"REDIS") {
+            rdb::load(store, &path)?;
+        } else {
+            let valid = replay(client, &data).with_context(|| format!("in {}", path.display()))?;
+            if valid < data.len() {
+                bail!("{} is truncated", path.display());
+            }
+        }
+    }
+
+    for (index, (incr, _)) in manifest.incrs.iter().enumerate() {
+        let path = dir.join(incr);
+        let data = fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        let valid = replay(client, &data).with_context(|| format!("in {}", path.display()))?;
+        if valid == data.len() {
+            continue;
+        }
+        if index + 1 < manifest.incrs.len() {
+            bail!("{} is truncated", path.display());
+        }
+
+        eprintln!(
+            "{} ends with an incomplete command, truncating it from {} to {valid} bytes",
+            path.display(),
+            data.len()
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(valid as u64))
+            .with_context(|| format!("failed to truncate {}", path.display()))?;
+    }
+
+    // What was loaded is already saved
+    store.saved(store.dirty());
+    println!("loaded append-only file from {}", dir.display());
+    Ok(Some(manifest))
+}
+
+// Runs each command in the data, returning how many bytes were of complete commands
+fn replay(client: &Client, data: &[u8]) -> Result<usize> {
+    let mut offset = 0;
+    while offset < data.len() {
+        let remaining = &data[offset..];
+        let (request, length) = match RespType::parse_prefix(remaining) {
+            Ok((request, rest)) => (request, remaining.len() - rest.len()),
+            Err(RespError::EmptyValue | RespError::UnterminatedSequence) => break,
+            Err(error) => bail!("invalid command at byte {offset}: {error}"),
+        };
+
+        let command = Command::try_from(request)
+            .map_err(|error| anyhow!("invalid command at byte {offset}: {error}"))?;
+        client.apply(command, &remaining[..length]);
+        offset += length;
+    }
+
+    Ok(offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest() {
+        let manifest = "file appendonly.aof.2.base.rdb seq 2 type b\n\
+                        file appendonly.aof.1.incr.aof seq 1 type h\n\
+                        file appendonly.aof.4.incr.aof seq 4 type i\n\
+                        file appendonly.aof.3.incr.aof seq 3 type i\n";
+        let manifest = Manifest::parse(manifest).unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                base: Some(("appendonly.aof.2.base.rdb".to_string(), 2)),
+                incrs: vec![
+                    ("appendonly.aof.3.incr.aof".to_string(), 3),
+                    ("appendonly.aof.4.incr.aof".to_string(), 4),
+                ],
+            }
+        );
+        assert_eq!(Manifest::parse(&manifest.encode()).unwrap(), manifest);
+        assert_eq!(
+            manifest.next_base("appendonly.aof"),
+            ("appendonly.aof.3.base.rdb".to_string(), 3)
+        );
+        assert_eq!(
+            manifest.next_incr("appendonly.aof"),
+            ("appendonly.aof.5.incr.aof".to_string(), 5)
+        );
+
+        assert!(Manifest::parse("file a seq 1 type x").is_err());
+        assert!(Manifest::parse("file a type i").is_err());
+    }
+
+    #[test]
+    fn relative_deadlines_are_absolute() {
+        let store = Store::new();
+        store.set(
+            0,
+            "a".to_string(),
+            "1".into(),
+            Some(Duration::from_secs(10)),
+        );
+        let deadline = store.read(0, ["a"]).expires("a").unwrap().unwrap();
+
+        let entry = Entry::SetWithDeadline("a", b"1").encode(&store, 0);
+        let expected = [
+            command(&[b"SET", b"a", b"1"]),
+            command(&[b"PEXPIREAT", b"a", deadline.to_string().as_bytes()]),
+        ]
+        .concat();
+        assert_eq!(entry, expected);
+
+        let entry = Entry::Expire("a", 5, Some(ExpireCondition::Later)).encode(&store, 0);
+        assert_eq!(entry, command(&[b"PEXPIREAT", b"a", b"5", b"GT"]));
+
+        let entry = Entry::Restore("a", b"payload").encode(&store, 0);
+        let deadline = deadline.to_string();
+        let arguments: [&[u8]; 6] = [
+            b"RESTORE",
+            b"a",
+            deadline.as_bytes(),
+            b"payload",
+            b"REPLACE",
+            b"ABSTTL",
+        ];
+        assert_eq!(entry, command(&arguments));
+        let entry = Entry::Restore("b", b"payload").encode(&store, 0);
+        assert_eq!(entry, command(&[b"DEL", b"b"]));
+    }
+
+    #[test]
+    fn load_truncated() {
+        let dir = std::env::temp_dir().join(format!("aof-{}", std::process::id()));
+        let config = Config {
+            dir,
+            append_only: true,
+            ..Config::default()
+        };
+        let aof_dir = config.aof_dir();
+        fs::create_dir_all(&aof_dir).unwrap();
+
+        let manifest = Manifest {
+            base: None,
+            incrs: vec![("appendonly.aof.1.incr.aof".to_string(), 1)],
+        };
+        manifest.write(&aof_dir, "appendonly.aof").unwrap();
+        let complete = [
+            command(&[b"SET", b"a", b"1"]),
+            command(&[b"SELECT", b"1"]),
+            command(&[b"SET", b"b", b"2"]),
+        ]
+        .concat();
+        let path = aof_dir.join("appendonly.aof.1.incr.aof");
+        fs::write(
+            &path,
+            [&complete[..], b"*3\r\n$3\r\nSET\r\n$1\r\nc"].concat(),
+        )
+        .unwrap();
+
+        let store = Arc::new(Store::new());
+        let client = Client::new(
+            Arc::clone(&store),
+            Arc::new(RwLock::new(config.clone())),
+            Arc::new(rdb::Saves::new()),
+            Arc::new(Aof::new()),
+            Arc::new(crate::replication::Replication::new(
+                config.repl_backlog_size,
+            )),
+            Arc::new(crate::pubsub::PubSub::new()),
+        );
+        assert_eq!(load(&client, &store, &config).unwrap(), Some(manifest));
+        assert_eq!(store.get(0, "a").unwrap(), Some("1".into()));
+        assert_eq!(store.get(1, "b").unwrap(), Some("2".into()));
+        assert_eq!(store.get(0, "c").unwrap(), None);
+        assert_eq!(fs::read(&path).unwrap(), complete);
+        assert_eq!(store.dirty(), 0);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -0,0 +1,522 @@
+// Bit-level operations on string values, where bit 0 is the most significant bit of the first
+// byte, as per Redis
+// See: https://redis.io/docs/latest/develop/data-types/bitmaps/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Byte,
+    Bit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: i64,
+    pub end: Option<i64>,
+    pub unit: Unit,
+}
+
+impl Range {
+    // Resolves (possibly negative) indexes into an inclusive range of bits within a value of
+    // `length` bytes, or `None` if the range is empty
+    fn resolve(&self, length: usize) -> Option<(u64, u64)> {
+        let total = match self.unit {
+            Unit::Byte => length as i64,
+            Unit::Bit => length as i64 * 8,
+        };
+
+        let (mut start, mut end) = (self.start, self.end.unwrap_or(total - 1));
+        if start < 0 && end < 0 && start > end {
+            return None;
+        }
+        if start < 0 {
+            start += total;
+        }
+        if end < 0 {
+            end += total;
+        }
+        let start = start.max(0);
+        let end = end.max(0).min(total - 1);
+        if start > end {
+            return None;
+        }
+
+        #[allow(clippy::cast_sign_loss)]
+        match self.unit {
+            Unit::Byte => Some((start as u64 * 8, end as u64 * 8 + 7)),
+            Unit::Bit => Some((start as u64, end as u64)),
+        }
+    }
+}
+
+// See: https://redis.io/docs/latest/commands/bitop/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    And,
+    Or,
+    Xor,
+    Not,
+    // Bits set in the first key but in none of the others
+    Diff,
+    // Bits set in exactly one of the keys
+    One,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Encoding {
+    pub signed: bool,
+    pub bits: u8,
+}
+
+impl TryFrom<&str> for Encoding {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let signed = match value.as_bytes().first() {
+            Some(b'i' | b'I') => true,
+            Some(b'u' | b'U') => false,
+            _ => return Err(()),
+        };
+        let bits = value[1..].parse::<u8>().map_err(|_| ())?;
+
+        // `u64` cannot be represented in the `i64` integer replies, so is not supported
+        match (signed, bits) {
+            (true, 1..=64) | (false, 1..=63) => Ok(Self { signed, bits }),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+// See: https://redis.io/docs/latest/commands/bitfield/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subcommand {
+    Get(Encoding, u64),
+    Set(Encoding, u64, i64),
+    IncrBy(Encoding, u64, i64),
+    Overflow(Overflow),
+}
+
+impl Subcommand {
+    pub const fn is_write(&self) -> bool {
+        matches!(self, Self::Set(..) | Self::IncrBy(..))
+    }
+}
+
+pub fn get_bit(value: &[u8], offset: u64) -> u8 {
+    let Ok(byte) = usize::try_from(offset >> 3) else {
+        return 0;
+    };
+
+    value
+        .get(byte)
+        .map_or(0, |byte| (byte >> (7 - (offset & 7))) & 1)
+}
+
+pub fn set_bit(value: &mut Vec<u8>, offset: u64, bit: bool) -> u8 {
+    let byte = (offset >> 3) as usize;
+    if value.len() <= byte {
+        value.resize(byte + 1, 0);
+    }
+
+    let mask = 1 << (7 - (offset & 7));
+    let previous = u8::from(value[byte] & mask != 0);
+    if bit {
+        value[byte] |= mask;
+    } else {
+        value[byte] &= !mask;
+    }
+
+    previous
+}
+
+pub fn count(value: &[u8], range: Option<&Range>) -> u64 {
+    let Some((start, end)) = range.map_or_else(
+        || (!value.is_empty()).then(|| (0, value.len() as u64 * 8 - 1)),
+        |range| range.resolve(value.len()),
+    ) else {
+        return 0;
+    };
+
+    let (first, last) = ((start >> 3) as usize, (end >> 3) as usize);
+    let mut count: u64 = value[first..=last]
+        .iter()
+        .map(|byte| u64::from(byte.count_ones()))
+        .sum();
+
+    // Remove bits at the edges that fall outside of the range
+    let leading = u8::MAX.checked_shl(8 - (start & 7) as u32).unwrap_or(0);
+    let trailing = (1u8 << (7 - (end & 7))) - 1;
+    count -= u64::from((value[first] & leading).count_ones());
+    count -= u64::from((value[last] & trailing).count_ones());
+
+    count
+}
+
+pub fn position(value: &[u8], bit: bool, range: Option<&Range>) -> i64 {
+    let Some((start, end)) = range.map_or_else(
+        || (!value.is_empty()).then(|| (0, value.len() as u64 * 8 - 1)),
+        |range| range.resolve(value.len()),
+    ) else {
+        return -1;
+    };
+
+    let skip = if bit { 0 } else { u8::MAX };
+    let mut offset = start;
+    while offset <= end {
+        let byte = value[(offset >> 3) as usize];
+        if offset & 7 == 0 && offset + 7 <= end && byte == skip {
+            offset += 8;
+            continue;
+        }
+        if get_bit(value, offset) == u8::from(bit) {
+            #[allow(clippy::cast_possible_wrap)]
+            return offset as i64;
+        }
+        offset += 1;
+    }
+
+    // When looking for a clear bit without an explicit end, the value is treated as if it were
+    // padded with zeros to the right
+    match range {
+        Some(Range { end: Some(_), .. }) => -1,
+        _ if bit => -1,
+        #[allow(clippy::cast_possible_wrap)]
+        _ => offset as i64,
+    }
+}
+
+pub fn operate(operation: Operation, sources: &[&[u8]]) -> Vec<u8> {
+    let length = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+    let byte = |source: &[u8], index: usize| source.get(index).copied().unwrap_or(0);
+
+    (0..length)
+        .map(|index| {
+            let mut bytes = sources.iter().map(|source| byte(source, index));
+            let first = bytes.next().unwrap_or(0);
+            match operation {
+                Operation::And => bytes.fold(first, |result, byte| result & byte),
+                Operation::Or => bytes.fold(first, |result, byte| result | byte),
+                Operation::Xor => bytes.fold(first, |result, byte| result ^ byte),
+                Operation::Not => !first,
+                Operation::Diff => first & !bytes.fold(0, |result, byte| result | byte),
+                Operation::One => {
+                    let (once, many) = bytes.fold((first, 0), |(once, many), byte| {
+                        (once ^ byte, many | (once & byte))
+                    });
+                    once & !many
+                }
+            }
+        })
+        .collect()
+}
+
+// Runs the subcommands against `value`, which is grown to fit any writes, returning the result of
+// each one, where `None` represents a write that failed due to `Overflow::Fail`
+pub fn field(value: &mut Vec<u8>, subcommands: &[Subcommand]) -> Vec<Option<i64>> {
+    // Like Redis, the value is grown to fit every write up front, even those that later fail
+    let highest = subcommands
+        .iter()
+        .filter_map(|subcommand| match subcommand {
+            Subcommand::Set(encoding, offset, _) | Subcommand::IncrBy(encoding, offset, _) => {
+                Some(offset + u64::from(encoding.bits) - 1)
+            }
+            _ => None,
+        })
+        .max();
+    if let Some(highest) = highest {
+        let length = (highest >> 3) as usize + 1;
+        if value.len() < length {
+            value.resize(length, 0);
+        }
+    }
+
+    let mut overflow = Overflow::Wrap;
+    let mut results = Vec::with_capacity(subcommands.len());
+
+    for subcommand in subcommands {
+        let (encoding, offset, update) = match *subcommand {
+            Subcommand::Overflow(behaviour) => {
+                overflow = behaviour;
+                continue;
+            }
+            Subcommand::Get(encoding, offset) => {
+                results.push(Some(get_field(value, encoding, offset)));
+                continue;
+            }
+            Subcommand::Set(encoding, offset, update) => (encoding, offset, Err(update)),
+            Subcommand::IncrBy(encoding, offset, increment) => (encoding, offset, Ok(increment)),
+        };
+
+        let previous = get_field(value, encoding, offset);
+        let (new, overflowed) = match update {
+            Ok(increment) => checked_add(encoding, previous, increment, overflow),
+            Err(new) => checked_add(encoding, new, 0, overflow),
+        };
+
+        if overflowed && overflow == Overflow::Fail {
+            results.push(None);
+            continue;
+        }
+
+        set_field(value, encoding, offset, new);
+        results.push(Some(if update.is_ok() { new } else { previous }));
+    }
+
+    results
+}
+
+pub fn get_field(value: &[u8], encoding: Encoding, offset: u64) -> i64 {
+    let bits = u64::from(encoding.bits);
+    let mut result: u64 = 0;
+    for bit in offset..offset + bits {
+        result = (result << 1) | u64::from(get_bit(value, bit));
+    }
+
+    // Sign extend
+    if encoding.signed && bits < 64 && result & (1 << (bits - 1)) != 0 {
+        result |= u64::MAX << bits;
+    }
+
+    #[allow(clippy::cast_possible_wrap)]
+    let result = result as i64;
+    result
+}
+
+fn set_field(value: &mut Vec<u8>, encoding: Encoding, offset: u64, new: i64) {
+    let bits = u64::from(encoding.bits);
+    #[allow(clippy::cast_sign_loss)]
+    let new = new as u64;
+    for index in 0..bits {
+        let bit = (new >> (bits - 1 - index)) & 1 == 1;
+        set_bit(value, offset + index, bit);
+    }
+}
+
+// Adds `increment` to `value`, returning the result and whether it overflowed `encoding`, in which
+// case the result has been wrapped or saturated as per the `overflow` behaviour
+fn checked_add(encoding: Encoding, value: i64, increment: i64, overflow: Overflow) -> (i64, bool) {
+    let bits = u32::from(encoding.bits);
+
+    if encoding.signed {
+        let max = if bits == 64 {
+            i64::MAX
+        } else {
+            (1 << (bits - 1)) - 1
+        };
+        let min = -max - 1;
+
+        let result = i128::from(value) + i128::from(increment);
+        if result >= i128::from(min) && result <= i128::from(max) {
+            #[allow(clippy::cast_possible_truncation)]
+            return (result as i64, false);
+        }
+
+        let limit = match overflow {
+            Overflow::Sat if result > i128::from(max) => max,
+            Overflow::Sat => min,
+            _ => {
+                #[allow(clippy::cast_sign_loss)]
+                let mut wrapped = value.wrapping_add(increment) as u64;
+                if bits < 64 {
+                    let mask = u64::MAX << bits;
+                    if wrapped & (1 << (bits - 1)) == 0 {
+                        wrapped &= !mask;
+                    } else {
+                        wrapped |= mask;
+                    }
+                }
+                #[allow(clippy::cast_possible_wrap)]
+                let wrapped = wrapped as i64;
+                wrapped
+            }
+        };
+
+        (limit, true)
+    } else {
+        let max = (1u64 << bits) - 1;
+
+        // Unsigned fields are at most 63 bits, so values can be represented as signed
+        #[allow(clippy::cast_sign_loss)]
+        let result = i128::from(value as u64) + i128::from(increment);
+        if result >= 0 && result <= i128::from(max) {
+            #[allow(clippy::cast_possible_truncation)]
+            return (result as i64, false);
+        }
+
+        #[allow(clippy::cast_possible_wrap)]
+        let limit = match overflow {
+            Overflow::Sat if result > 0 => max as i64,
+            Overflow::Sat => 0,
+            _ => (value.wrapping_add(increment) as u64 & max) as i64,
+        };
+
+        (limit, true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FOOBAR: &[u8] = b"foobar";
+
+    fn range(start: i64, end: Option<i64>, unit: Unit) -> Option<Range> {
+        Some(Range { start, end, unit })
+    }
+
+    #[test]
+    fn set_and_get_bits() {
+        let mut value = Vec::new();
+        assert_eq!(set_bit(&mut value, 7, true), 0);
+        assert_eq!(value, vec![0x01]);
+        assert_eq!(set_bit(&mut value, 7, false), 1);
+        assert_eq!(set_bit(&mut value, 100, true), 0);
+        assert_eq!(value.len(), 13);
+        assert_eq!(get_bit(&value, 100), 1);
+        assert_eq!(get_bit(&value, 1_000), 0);
+    }
+
+    #[test]
+    fn count_matches_redis_examples() {
+        assert_eq!(count(FOOBAR, None), 26);
+        assert_eq!(count(FOOBAR, range(0, Some(0), Unit::Byte).as_ref()), 4);
+        assert_eq!(count(FOOBAR, range(1, Some(1), Unit::Byte).as_ref()), 6);
+        assert_eq!(count(FOOBAR, range(1, Some(1), Unit::Byte).as_ref()), 6);
+        assert_eq!(count(FOOBAR, range(5, Some(30), Unit::Bit).as_ref()), 17);
+        assert_eq!(count(FOOBAR, range(-2, Some(-1), Unit::Byte).as_ref()), 7);
+        assert_eq!(count(FOOBAR, range(-1, Some(-2), Unit::Byte).as_ref()), 0);
+        assert_eq!(count(b"", None), 0);
+    }
+
+    #[test]
+    fn position_matches_redis_examples() {
+        let value = [0xff, 0xf0, 0x00];
+        assert_eq!(position(&value, false, None), 12);
+
+        let value = [0x00, 0xff, 0xf0];
+        assert_eq!(
+            position(&value, true, range(0, None, Unit::Byte).as_ref()),
+            8
+        );
+        assert_eq!(
+            position(&value, true, range(2, None, Unit::Byte).as_ref()),
+            16
+        );
+        assert_eq!(
+            position(&value, true, range(2, Some(-1), Unit::Byte).as_ref()),
+            16
+        );
+        assert_eq!(
+            position(&value, true, range(7, Some(15), Unit::Bit).as_ref()),
+            8
+        );
+
+        let value = [0x00; 3];
+        assert_eq!(position(&value, true, None), -1);
+
+        // Zero padding is only assumed when no end is given
+        let value = [0xff];
+        assert_eq!(position(&value, false, None), 8);
+        assert_eq!(
+            position(&value, false, range(0, Some(-1), Unit::Byte).as_ref()),
+            -1
+        );
+    }
+
+    #[test]
+    fn operations() {
+        let a: &[u8] = &[0b1100_1100, 0xff];
+        let b: &[u8] = &[0b1010_1010];
+        let c: &[u8] = &[0b1000_0001];
+
+        assert_eq!(operate(Operation::And, &[a, b]), vec![0b1000_1000, 0x00]);
+        assert_eq!(operate(Operation::Or, &[a, b]), vec![0b1110_1110, 0xff]);
+        assert_eq!(operate(Operation::Xor, &[a, b]), vec![0b0110_0110, 0xff]);
+        assert_eq!(operate(Operation::Not, &[a]), vec![0b0011_0011, 0x00]);
+        assert_eq!(
+            operate(Operation::Diff, &[a, b, c]),
+            vec![0b0100_0100, 0xff]
+        );
+        assert_eq!(operate(Operation::One, &[a, b, c]), vec![0b0110_0111, 0xff]);
+    }
+
+    #[test]
+    fn parse_encoding() {
+        assert_eq!(
+            Encoding::try_from("i64"),
+            Ok(Encoding {
+                signed: true,
+                bits: 64
+            })
+        );
+        assert_eq!(
+            Encoding::try_from("u8"),
+            Ok(Encoding {
+                signed: false,
+                bits: 8
+            })
+        );
+        assert!(Encoding::try_from("u64").is_err());
+        assert!(Encoding::try_from("i0").is_err());
+        assert!(Encoding::try_from("x8").is_err());
+    }
+
+    #[test]
+    fn field_overflow_behaviours() {
+        let u2 = Encoding::try_from("u2").unwrap();
+        let i8 = Encoding::try_from("i8").unwrap();
+        let mut value = Vec::new();
+
+        let results = field(
+            &mut value,
+            &[
+                Subcommand::IncrBy(u2, 100, 1),
+                Subcommand::Overflow(Overflow::Sat),
+                Subcommand::IncrBy(u2, 102, 1),
+            ],
+        );
+        assert_eq!(results, vec![Some(1), Some(1)]);
+
+        let results = field(
+            &mut value,
+            &[
+                Subcommand::IncrBy(u2, 100, 3),
+                Subcommand::Overflow(Overflow::Sat),
+                Subcommand::IncrBy(u2, 102, 3),
+                Subcommand::Overflow(Overflow::Fail),
+                Subcommand::IncrBy(u2, 102, 1),
+            ],
+        );
+        assert_eq!(results, vec![Some(0), Some(3), None]);
+
+        let results = field(
+            &mut value,
+            &[
+                Subcommand::Set(i8, 0, 200),
+                Subcommand::Get(i8, 0),
+                Subcommand::Overflow(Overflow::Sat),
+                Subcommand::IncrBy(i8, 0, -500),
+            ],
+        );
+        assert_eq!(results, vec![Some(0), Some(-56), Some(-128)]);
+    }
+
+    #[test]
+    fn field_matches_redis_example() {
+        let i5 = Encoding::try_from("i5").unwrap();
+        let u4 = Encoding::try_from("u4").unwrap();
+        let mut value = Vec::new();
+
+        let results = field(
+            &mut value,
+            &[Subcommand::IncrBy(i5, 100, 1), Subcommand::Get(u4, 0)],
+        );
+        assert_eq!(results, vec![Some(1), Some(0)]);
+    }
+}
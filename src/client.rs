@@ -1,93 +1,689 @@
+mod bitmap;
+mod cluster;
+mod expire;
+mod generic;
+mod geo;
+mod hyperloglog;
+mod pubsub;
+mod replication;
+mod server;
+
 use crate::{
+    aof::{self, Aof},
+    cluster::{Cluster, Migrate},
     command::Command,
-    resp::{BulkString, NullBulkString, RespType, SimpleError, SimpleString},
-    store::Store,
+    config::Config,
+    pubsub::{Kind, PubSub, Subscription},
+    rdb::Saves,
+    replication::{FeedGuard, Replication},
+    resp::{
+        Array, BulkString, Error as RespError, Integer, NullBulkString, RespType, Response,
+        SimpleError, SimpleString,
+    },
+    store::{OutOfMemory, Store, WrongType},
 };
 use anyhow::Result;
+use bytes::Bytes;
 use std::{
-    io::{Read, Write},
+    cell::{Cell, RefCell, RefMut},
+    io::Read,
+    mem,
     net::{Shutdown, TcpStream},
     str,
-    sync::Arc,
+    sync::{Arc, PoisonError, RwLock},
 };
 
+// The encoded response for a command, or the error to reply with instead
+type Reply = std::result::Result<Vec<u8>, SimpleError<'static>>;
+
+// The reply to the HELP subcommand of commands such as OBJECT
+fn help(lines: &[&str]) -> Reply {
+    let lines = lines
+        .iter()
+        .map(|line| SimpleString::new(line).encode())
+        .collect::<Vec<_>>();
+    Ok(Array::from(lines).encode())
+}
+
+impl From<WrongType> for SimpleError<'_> {
+    fn from(_: WrongType) -> Self {
+        SimpleError::from("WRONGTYPE Operation against a key holding the wrong kind of value")
+    }
+}
+
+impl From<OutOfMemory> for SimpleError<'_> {
+    fn from(_: OutOfMemory) -> Self {
+        SimpleError::from("OOM command not allowed when used memory > 'maxmemory'")
+    }
+}
+
+// Writes to keys being migrated are refused until the other instance has them
+const MIGRATING: SimpleError = SimpleError::new("TRYAGAIN Keys are being migrated");
+
+// The name of the command in a request, in lower case, for errors
+fn command_name(request: &[u8]) -> String {
+    let Ok(RespType::Array(mut arguments)) = RespType::try_from(request) else {
+        return String::new();
+    };
+    match arguments.pop_front() {
+        Some(RespType::BulkString(name)) => name.as_string().unwrap_or_default().to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+// The database numbered `index`, as given to commands such as SELECT
+fn db_index(store: &Store, index: i64) -> std::result::Result<usize, SimpleError<'static>> {
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index < store.databases())
+        .ok_or(SimpleError::from("ERR DB index is out of range"))
+}
+
 pub struct Client {
-    stream: TcpStream,
     request_buffer: Vec<u8>,
     store: Arc<Store>,
+    config: Arc<RwLock<Config>>,
+    saves: Arc<Saves>,
+    aof: Arc<Aof>,
+    replication: Arc<Replication>,
+    pubsub: Arc<PubSub>,
+    // The database selected with SELECT, as a `Cell` since commands borrow from the request buffer
+    db: Cell<usize>,
+    // The port a replica listens on, as given with REPLCONF before PSYNC
+    listening_port: Cell<Option<u16>>,
+    // The replication offset after the last write of this client, which WAIT waits for replicas
+    // to acknowledge
+    last_write: Cell<u64>,
+    // Whether this is the link of a replica to its master, whose requests are passed on as they
+    // are to replicas of this replica
+    from_master: bool,
+    // The node of the cluster this server is, in cluster mode
+    cluster: Option<Arc<Cluster>>,
+    // Whether the next command may be for a slot this node is importing, as set by ASKING
+    asking: Cell<bool>,
+    // Whether replies are in RESP3, as chosen with HELLO, which only changes pub/sub messages
+    resp3: Cell<bool>,
+    // Set once the client first subscribes to anything, from when all replies are queued along
+    // with its messages
+    subscription: RefCell<Option<Subscription>>,
 }
 
 impl Client {
-    pub const fn new(stream: TcpStream, store: Arc<Store>) -> Self {
+    pub const fn new(
+        store: Arc<Store>,
+        config: Arc<RwLock<Config>>,
+        saves: Arc<Saves>,
+        aof: Arc<Aof>,
+        replication: Arc<Replication>,
+        pubsub: Arc<PubSub>,
+    ) -> Self {
         Self {
-            stream,
             request_buffer: Vec::new(),
             store,
+            config,
+            saves,
+            aof,
+            replication,
+            pubsub,
+            db: Cell::new(0),
+            listening_port: Cell::new(None),
+            last_write: Cell::new(0),
+            from_master: false,
+            cluster: None,
+            asking: Cell::new(false),
+            resp3: Cell::new(false),
+            subscription: RefCell::new(None),
         }
     }
 
-    pub fn handle(&mut self) -> Result<()> {
-        'request: loop {
+    pub const fn for_master(mut self) -> Self {
+        self.from_master = true;
+        self
+    }
+
+    pub fn with_cluster(mut self, cluster: Option<Arc<Cluster>>) -> Self {
+        self.cluster = cluster;
+        self
+    }
+
+    // A client to apply what a master sends, for REPLICAOF
+    fn master_link(&self) -> Self {
+        Self::new(
+            Arc::clone(&self.store),
+            Arc::clone(&self.config),
+            Arc::clone(&self.saves),
+            Arc::clone(&self.aof),
+            Arc::clone(&self.replication),
+            Arc::clone(&self.pubsub),
+        )
+        .for_master()
+        .with_cluster(self.cluster.clone())
+    }
+
+    fn cluster(&self) -> std::result::Result<&Cluster, SimpleError<'static>> {
+        self.cluster.as_deref().ok_or(SimpleError::from(
+            "ERR This instance has cluster support disabled",
+        ))
+    }
+
+    fn subscription(&self) -> RefMut<'_, Subscription> {
+        RefMut::map(self.subscription.borrow_mut(), |subscription| {
+            subscription.get_or_insert_with(|| Subscription::new(&self.pubsub, self.resp3.get()))
+        })
+    }
+
+    // Whether only pub/sub commands are allowed, as once subscribed to anything over RESP2
+    fn is_subscribed(&self) -> bool {
+        !self.resp3.get()
+            && self
+                .subscription
+                .borrow()
+                .as_ref()
+                .is_some_and(|subscription| subscription.count() > 0)
+    }
+
+    // Whether writes are refused, other than from the master
+    fn is_read_only(&self) -> bool {
+        self.replication.is_replica()
+            && self
+                .config
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .replica_read_only
+    }
+
+    pub fn handle(&mut self, mut stream: TcpStream) -> Result<()> {
+        let mut read_buffer = [0; 16 * 1024];
+        loop {
+            let read = stream.read(&mut read_buffer)?;
+            println!("read {read} bytes from stream");
+            if read == 0 {
+                println!("no requests left from client, shutting down connection");
+                break;
+            }
+            self.request_buffer.extend_from_slice(&read_buffer[..read]);
+
+            // Requests may be pipelined, or span several reads, so every complete one is run in
+            // turn, and any partial one is kept until the rest of it is read
+            let buffer = mem::take(&mut self.request_buffer);
+            let mut requests = buffer.as_slice();
             loop {
-                let mut read_buffer = [0; 1024];
-                let read = self.stream.read(&mut read_buffer)?;
-                println!("read {read} bytes from stream");
-
-                if read == 0 {
-                    if read_buffer == [0; 1024] {
-                        println!("no requests left from client, shutting down connection");
-                        break 'request;
+                let (request, rest) = match RespType::parse_prefix(requests) {
+                    Ok(parsed) => parsed,
+                    Err(RespError::EmptyValue | RespError::UnterminatedSequence) => break,
+                    Err(error) => return Err(error.into()),
+                };
+                let raw = &requests[..requests.len() - rest.len()];
+                requests = rest;
+
+                let response = match request.try_into() {
+                    // The connection is handed over to the replica's feed from now on
+                    // Like Redis, replicas of a replica wait for it to sync, so that they can
+                    // continue the history of its master
+                    Ok(Command::Psync(..)) if !self.replication.is_synced() => SimpleError::from(
+                        "NOMASTERLINK Can't SYNC while not connected with my master",
+                    )
+                    .encode()
+                    .into(),
+                    Ok(Command::Psync(id, offset)) => {
+                        let port = self.listening_port.get();
+                        let store = &self.store;
+                        return self
+                            .replication
+                            .add_replica(store, stream, port, (id, offset));
                     }
+                    Ok(command) => self.execute(command, raw),
+                    Err(error) => Into::<SimpleError>::into(error).encode().into(),
+                };
 
-                    println!("nothing to read from stream, exiting request loop");
-                    break;
+                match self.subscription.get_mut() {
+                    Some(subscription) => {
+                        subscription.reply(response.to_vec());
+                        subscription.start(&stream)?;
+                    }
+                    None => response.write_to(&mut stream)?,
                 }
+            }
+            let consumed = buffer.len() - requests.len();
+            self.request_buffer = buffer;
+            self.request_buffer.drain(..consumed);
+        }
+        stream.shutdown(Shutdown::Both)?;
 
-                self.request_buffer.extend_from_slice(&read_buffer[..read]);
+        Ok(())
+    }
+}
+
+impl Client {
+    // Runs a command without a connection, such as when replaying the append-only file, or from
+    // a master, ignoring the reply
+    pub fn apply(&self, command: Command, request: &[u8]) {
+        self.execute(command, request);
+    }
 
-                if read < read_buffer.len() {
-                    println!("read less than buffer size in, exiting request loop");
-                    break;
+    // `request` is the command as it was sent, for logging to the append-only file and sending to
+    // replicas
+    fn execute(&self, command: Command, request: &[u8]) -> Response {
+        if self.is_subscribed() && !command.is_allowed_subscribed() {
+            let message = format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / \
+                 RESET are allowed in this context",
+                command_name(request)
+            );
+            return SimpleError::from(message.as_str()).encode().into();
+        }
+
+        // In cluster mode, commands are only run for keys in the slots of this node, and clients
+        // are redirected to the node serving any others
+        if let (Some(cluster), false) = (&self.cluster, self.from_master) {
+            let keys = command.keys();
+            let db = self.db.get();
+            let asking = self.asking.replace(false);
+            let exists = |key: &str| self.store.read(db, [key]).contains(key);
+            let routed = match command {
+                Command::RestoreAsking(..) => cluster.route(&keys, true, exists),
+                // Like Redis, MIGRATE is run here for any keys of a slot being migrated or imported
+                Command::Migrate(..) => cluster.route(&keys, true, |_| true),
+                // Shard channels are served by the node which owns their slot, even while it is
+                // being migrated, as they are not moved along with keys
+                Command::SPublish(..) | Command::SSubscribe(..) | Command::SUnsubscribe(..) => {
+                    cluster.route(&keys, false, |_| true)
                 }
+                _ => cluster.route(&keys, asking, exists),
+            };
+            if let Err(refused) = routed {
+                let message = refused.to_string();
+                return SimpleError::from(message.as_str()).encode().into();
             }
+        }
 
-            if let Ok(request) = str::from_utf8(self.request_buffer.as_slice()) {
-                dbg!(request);
+        // Like Redis, keys are evicted before any command when over the memory limit, but only
+        // commands which may use more memory are refused when that is not possible. Replicas
+        // leave it to their master, which deletes the keys it evicts on them, as with
+        // `replica-ignore-maxmemory yes`.
+        if !self.from_master
+            && !self.replication.is_replica()
+            && self.store.is_over_max_memory()
+            && self.evict().is_err()
+            && command.is_denyoom()
+        {
+            return SimpleError::from(OutOfMemory).encode().into();
+        }
+
+        let is_write = command.is_write();
+        if is_write && !self.from_master && self.is_read_only() {
+            return SimpleError::from("READONLY You can't write against a read only replica.")
+                .encode()
+                .into();
+        }
+
+        let db = self.db.get();
+        // MIGRATE waits on the other instance, so it takes the append-only file and replicas only
+        // once done, to remove and propagate the keys it moved
+        if !is_write && !self.from_master || matches!(command, Command::Migrate(..)) {
+            return self
+                .run(command, db)
+                .unwrap_or_else(|error| error.encode().into());
+        }
+
+        let mut log = self.aof.lock();
+        let mut feed = self.replication.lock();
+        if is_write && !self.from_master && self.store.is_migrating(db, &command.keys()) {
+            return MIGRATING.encode().into();
+        }
+        let entry = (is_write && (log.is_logged() || feed.is_fed()))
+            .then(|| aof::Entry::new(&command, request));
+        let response = self.run(command, db);
+        let proxied = self.from_master.then(|| feed.proxy(request));
+        let response = match response {
+            Ok(response) => response,
+            Err(error) => return error.encode().into(),
+        };
+        if let Some(entry) = entry {
+            let entry = entry.encode(&self.store, db);
+            if let Err(error) = self.propagate(&mut log, &mut feed, db, &entry, proxied) {
+                return error.into();
             }
+        }
 
-            let request: RespType = self.request_buffer.as_slice().try_into()?;
-            let response = match request.try_into() {
-                Ok(command) => match command {
-                    Command::Ping => SimpleString::new("PONG").encode(),
-                    Command::Echo(message) => message.encode(),
-                    Command::Set(key, value, ttl) => {
-                        if let Some(key) = key.as_string() {
-                            // TODO: Are copies for key/value needed?
-                            self.store.set(key.to_string(), value.to_vec(), ttl);
-                            SimpleString::new("OK").encode()
-                        } else {
-                            SimpleError::from("ERR key is not UTF8 string").encode()
-                        }
-                    }
-                    Command::Get(key) => self
-                        .store
-                        .get(key.as_string().unwrap_or_default())
-                        .map_or_else(NullBulkString::encode, |value| {
-                            let value: BulkString = value.as_slice().into();
-                            value.encode()
-                        }),
-                },
-                Err(error) => Into::<SimpleError>::into(error).encode(),
-            };
+        response
+    }
 
-            if let Ok(response) = str::from_utf8(response.as_slice()) {
-                dbg!(response);
+    // Evicts keys, deleting each of them in the append-only file and on replicas as it goes, so
+    // that no other write to them comes in between
+    fn evict(&self) -> std::result::Result<(), OutOfMemory> {
+        let mut log = self.aof.lock();
+        let mut feed = self.replication.lock();
+        let propagated = log.is_logged() || feed.is_fed();
+        self.store.evict(|db, key| {
+            if propagated {
+                let entry = aof::Entry::Del(vec![key]).encode(&self.store, db);
+                // Failures are only reported, as the keys are gone regardless
+                let _ = self.propagate(&mut log, &mut feed, db, &entry, None);
             }
-            self.stream.write_all(response.as_slice())?;
-            self.request_buffer.drain(..);
+        })
+    }
+
+    // Sends a write to replicas, unless proxied from the master at the given offset, and appends
+    // it to the append-only file, returning the error to reply with if that fails
+    fn propagate(
+        &self,
+        log: &mut aof::WriteGuard,
+        feed: &mut FeedGuard,
+        db: usize,
+        entry: &[u8],
+        proxied: Option<u64>,
+    ) -> std::result::Result<(), Vec<u8>> {
+        let offset = proxied.unwrap_or_else(|| feed.propagate(db, entry));
+        self.last_write.set(offset);
+        if let Err(error) = log.append(db, entry, offset) {
+            eprintln!("failed to write to the append-only file: {error}");
+            let message = format!("MISCONF Errors writing to the AOF file: {error}");
+            return Err(SimpleError::from(message.as_str()).encode());
         }
-        self.stream.shutdown(Shutdown::Both)?;
 
         Ok(())
     }
+
+    // The keys are held against writes until the other instance replies, marked under the same
+    // locks writes check them under, and only those MIGRATE did move are removed, and propagated as
+    // deleted
+    fn migrate(&self, migrate: &Migrate, db: usize) -> Reply {
+        let log = self.aof.lock();
+        let feed = self.replication.lock();
+        let held = self.store.hold_for_migration(db, &migrate.keys);
+        drop((feed, log));
+        if held.is_none() {
+            return Err(MIGRATING);
+        }
+
+        let (reply, moved) = generic::migrate(&self.store, db, migrate, self.cluster.is_some());
+        if moved.is_empty() {
+            return reply;
+        }
+
+        let mut log = self.aof.lock();
+        let mut feed = self.replication.lock();
+        let removed = generic::remove_migrated(&self.store, db, &moved);
+        if !removed.is_empty() && (log.is_logged() || feed.is_fed()) {
+            let entry = aof::Entry::Del(removed).encode(&self.store, db);
+            if let Err(error) = self.propagate(&mut log, &mut feed, db, &entry, None) {
+                return Ok(error);
+            }
+        }
+
+        reply
+    }
+
+    fn run(
+        &self,
+        command: Command,
+        db: usize,
+    ) -> std::result::Result<Response, SimpleError<'static>> {
+        let reply = match command {
+            // Replies are an array when subscribed, as they could otherwise be taken for messages
+            Command::Ping if self.is_subscribed() => Ok(Array::from(vec![
+                BulkString::from(&b"pong"[..]).encode(),
+                BulkString::from(&b""[..]).encode(),
+            ])
+            .encode()),
+            Command::Ping => Ok(SimpleString::new("PONG").encode()),
+            Command::Hello(protocol) => {
+                let protocol = protocol.unwrap_or(if self.resp3.get() { 3 } else { 2 });
+                self.resp3.set(protocol == 3);
+                if let Some(subscription) = self.subscription.borrow().as_ref() {
+                    subscription.set_resp3(protocol == 3);
+                }
+                let mode = if self.cluster.is_some() {
+                    "cluster"
+                } else {
+                    "standalone"
+                };
+                let role = if self.replication.is_replica() {
+                    "replica"
+                } else {
+                    "master"
+                };
+                server::hello(protocol, mode, role)
+            }
+            Command::Echo(message) => Ok(message.encode()),
+            Command::Info(sections) => server::info(
+                &self.store,
+                &self.saves,
+                &self.aof,
+                &self.replication,
+                &sections,
+            ),
+            Command::ConfigGet(patterns) => server::config_get(&self.config, &patterns),
+            Command::ConfigSet(settings) => server::config_set(
+                &self.config,
+                &self.store,
+                &self.aof,
+                &self.replication,
+                &settings,
+            ),
+            Command::MemoryUsage(key, samples) => {
+                server::memory_usage(&self.store, db, key, samples)
+            }
+            Command::MemoryStats => server::memory_stats(&self.store),
+            Command::MemoryDoctor => server::memory_doctor(&self.store),
+            Command::MemoryHelp => server::memory_help(),
+            Command::Select(index) if index != 0 && self.cluster.is_some() => Err(
+                SimpleError::from("ERR SELECT is not allowed in cluster mode"),
+            ),
+            Command::Select(index) => db_index(&self.store, index).map(|index| {
+                self.db.set(index);
+                SimpleString::new("OK").encode()
+            }),
+            Command::SwapDb(..) if self.cluster.is_some() => Err(SimpleError::from(
+                "ERR SWAPDB is not allowed in cluster mode",
+            )),
+            Command::SwapDb(first, second) => server::swapdb(&self.store, first, second),
+            Command::FlushDb(lazy) => server::flushdb(&self.store, db, lazy),
+            Command::FlushAll(lazy) => server::flushall(&self.store, lazy),
+            Command::DbSize => server::dbsize(&self.store, db),
+            Command::Save => server::save(&self.store, &self.saves, &self.config),
+            Command::BgSave(schedule) => {
+                server::bgsave(&self.store, &self.saves, &self.config, schedule)
+            }
+            Command::BgRewriteAof => server::bgrewriteaof(&self.store, &self.aof),
+            Command::ReplConf(port) => {
+                if port.is_some() {
+                    self.listening_port.set(port);
+                }
+                Ok(SimpleString::new("OK").encode())
+            }
+            // Acknowledgements are only expected on the connections of replicas and masters, which
+            // handle them themselves, and are never replied to
+            Command::ReplConfAck(..) | Command::ReplConfGetAck => Ok(Vec::new()),
+            Command::Psync(..) => Err(SimpleError::from(
+                "ERR PSYNC is only allowed from a replica's connection",
+            )),
+            Command::Wait(replicas, timeout) => {
+                let offset = self.last_write.get();
+                replication::wait(&self.replication, offset, replicas, timeout)
+            }
+            Command::WaitAof(local, replicas, timeout) => {
+                let (replication, offset) = (&self.replication, self.last_write.get());
+                replication::waitaof(replication, &self.aof, offset, (local, replicas), timeout)
+            }
+            Command::ReplicaOf(..) if self.cluster.is_some() => Err(SimpleError::from(
+                "ERR REPLICAOF not allowed in cluster mode.",
+            )),
+            Command::ReplicaOf(master) => replication::replicaof(
+                &self.replication,
+                &self.config,
+                (&self.store, &self.aof),
+                master,
+                || self.master_link(),
+            ),
+            Command::Role => Ok(self.replication.role()),
+            Command::Publish(channel, message) => {
+                pubsub::publish(&self.pubsub, channel, message.as_bytes())
+            }
+            // Confirmations are queued along with messages, rather than replied
+            Command::Subscribe(channels) => {
+                self.subscription().subscribe(Kind::Channel, &channels);
+                Ok(Vec::new())
+            }
+            Command::Unsubscribe(channels) => {
+                self.subscription().unsubscribe(Kind::Channel, &channels);
+                Ok(Vec::new())
+            }
+            Command::PSubscribe(patterns) => {
+                self.subscription().subscribe(Kind::Pattern, &patterns);
+                Ok(Vec::new())
+            }
+            Command::PUnsubscribe(patterns) => {
+                self.subscription().unsubscribe(Kind::Pattern, &patterns);
+                Ok(Vec::new())
+            }
+            Command::SPublish(channel, message) => {
+                pubsub::spublish(&self.pubsub, channel, message.as_bytes())
+            }
+            Command::SSubscribe(channels) => {
+                self.subscription().subscribe(Kind::Shard, &channels);
+                Ok(Vec::new())
+            }
+            Command::SUnsubscribe(channels) => {
+                self.subscription().unsubscribe(Kind::Shard, &channels);
+                Ok(Vec::new())
+            }
+            Command::PubSubChannels(pattern) => {
+                pubsub::channels(&self.pubsub, Kind::Channel, pattern)
+            }
+            Command::PubSubNumSub(channels) => {
+                pubsub::numsub(&self.pubsub, Kind::Channel, &channels)
+            }
+            Command::PubSubShardChannels(pattern) => {
+                pubsub::channels(&self.pubsub, Kind::Shard, pattern)
+            }
+            Command::PubSubShardNumSub(channels) => {
+                pubsub::numsub(&self.pubsub, Kind::Shard, &channels)
+            }
+            Command::PubSubNumPat => pubsub::numpat(&self.pubsub),
+            Command::PubSubHelp => pubsub::help(),
+            // Only answered when running as a sentinel
+            Command::SentinelMasters
+            | Command::SentinelMaster(..)
+            | Command::SentinelReplicas(..)
+            | Command::SentinelSentinels(..)
+            | Command::SentinelGetMasterAddrByName(..)
+            | Command::SentinelIsMasterDownByAddr(..)
+            | Command::SentinelFailover(..)
+            | Command::SentinelMyId => Err(SimpleError::from("ERR unknown command")),
+            Command::ClusterKeySlot(key) => cluster::keyslot(key),
+            Command::ClusterCountKeysInSlot(slot) => {
+                self.cluster()?;
+                cluster::countkeysinslot(&self.store, slot)
+            }
+            Command::ClusterGetKeysInSlot(slot, count) => {
+                self.cluster()?;
+                cluster::getkeysinslot(&self.store, slot, count)
+            }
+            Command::ClusterAddSlots(slots) => cluster::addslots(self.cluster()?, &slots),
+            Command::ClusterMeet(host, port, bus_port) => {
+                cluster::meet(self.cluster()?, host, port, bus_port)
+            }
+            Command::ClusterSlots => cluster::slots(self.cluster()?),
+            Command::ClusterShards => cluster::shards(self.cluster()?),
+            Command::ClusterNodes => cluster::nodes(self.cluster()?),
+            Command::ClusterMyId => cluster::myid(self.cluster()?),
+            Command::ClusterInfo => cluster::info(self.cluster()?),
+            Command::ClusterSetSlot(slot, to) => {
+                cluster::setslot(self.cluster()?, &self.store, slot, &to)
+            }
+            Command::Asking => {
+                self.cluster()?;
+                self.asking.set(true);
+                Ok(SimpleString::new("OK").encode())
+            }
+            Command::LastSave => Ok(Integer::from(
+                i64::try_from(self.saves.last_save()).unwrap_or(i64::MAX),
+            )
+            .encode()),
+            Command::Set(key, value, ttl) => {
+                // TODO: Are copies for key/value needed?
+                let value = Bytes::copy_from_slice(value.as_bytes());
+                self.store.set(db, key.to_string(), value, ttl);
+                Ok(SimpleString::new("OK").encode())
+            }
+            // The value is shared with the store, rather than copied into the response
+            Command::Get(key) => {
+                return match self.store.get(db, key) {
+                    Ok(Some(value)) => Ok(Response::bulk_string(value)),
+                    Ok(None) => Ok(NullBulkString::encode().into()),
+                    Err(error) => Err(error.into()),
+                };
+            }
+            Command::Del(keys) => generic::del(&self.store, db, &keys),
+            Command::Unlink(keys) => generic::unlink(&self.store, db, &keys),
+            Command::Exists(keys) => generic::exists(&self.store, db, &keys),
+            Command::Type(key) => generic::type_of(&self.store, db, key),
+            Command::Rename(key, new_key) => generic::rename(&self.store, db, key, new_key),
+            Command::RenameNx(key, new_key) => generic::renamenx(&self.store, db, key, new_key),
+            Command::Move(..) if self.cluster.is_some() => {
+                Err(SimpleError::from("ERR MOVE is not allowed in cluster mode"))
+            }
+            Command::Move(key, to) => generic::move_key(&self.store, db, key, to),
+            Command::RandomKey => generic::randomkey(&self.store, db),
+            Command::Copy(source, destination, replace) => {
+                generic::copy(&self.store, db, source, destination, replace)
+            }
+            Command::Dump(key) => generic::dump(&self.store, db, key),
+            Command::Restore(key, restore) | Command::RestoreAsking(key, restore) => {
+                generic::restore(&self.store, db, key, &restore)
+            }
+            Command::Migrate(migrate) => self.migrate(&migrate, db),
+            Command::Touch(keys) => generic::touch(&self.store, db, &keys),
+            Command::Keys(pattern) => generic::keys(&self.store, db, &pattern),
+            Command::ObjectEncoding(key) => generic::object_encoding(&self.store, db, key),
+            Command::ObjectFreq(key) => generic::object_freq(&self.store, db, key),
+            Command::ObjectIdleTime(key) => generic::object_idletime(&self.store, db, key),
+            Command::ObjectRefCount(key) => generic::object_refcount(&self.store, db, key),
+            Command::ObjectHelp => generic::object_help(),
+            Command::Scan(cursor, pattern, count, type_name) => {
+                generic::scan(&self.store, db, cursor, pattern.as_ref(), count, type_name)
+            }
+            Command::Expire(key, deadline, condition) => {
+                expire::expire(&self.store, db, key, deadline, condition)
+            }
+            Command::Ttl(key) => expire::ttl(&self.store, db, key, false),
+            Command::PTtl(key) => expire::ttl(&self.store, db, key, true),
+            Command::ExpireTime(key) => expire::expiretime(&self.store, db, key, false),
+            Command::PExpireTime(key) => expire::expiretime(&self.store, db, key, true),
+            Command::Persist(key) => expire::persist(&self.store, db, key),
+            Command::SetBit(key, offset, bit) => bitmap::setbit(&self.store, db, key, offset, bit),
+            Command::GetBit(key, offset) => bitmap::getbit(&self.store, db, key, offset),
+            Command::BitCount(key, range) => bitmap::bitcount(&self.store, db, key, range.as_ref()),
+            Command::BitPos(key, bit, range) => {
+                bitmap::bitpos(&self.store, db, key, bit, range.as_ref())
+            }
+            Command::BitOp(operation, destination, keys) => {
+                bitmap::bitop(&self.store, db, operation, destination, &keys)
+            }
+            Command::BitField(key, subcommands) | Command::BitFieldRo(key, subcommands) => {
+                bitmap::bitfield(&self.store, db, key, &subcommands)
+            }
+            Command::PfAdd(key, elements) => hyperloglog::pfadd(&self.store, db, key, &elements),
+            Command::PfCount(keys) => hyperloglog::pfcount(&self.store, db, &keys),
+            Command::PfMerge(destination, keys) => {
+                hyperloglog::pfmerge(&self.store, db, destination, &keys)
+            }
+            Command::GeoAdd(key, condition, changed, items) => {
+                geo::geoadd(&self.store, db, key, condition, changed, &items)
+            }
+            Command::GeoPos(key, members) => geo::geopos(&self.store, db, key, &members),
+            Command::GeoDist(key, from, to, unit) => {
+                geo::geodist(&self.store, db, key, from, to, unit)
+            }
+            Command::GeoHash(key, members) => geo::geohash(&self.store, db, key, &members),
+            Command::GeoSearch(key, search) => geo::geosearch(&self.store, db, key, &search),
+            Command::GeoSearchStore(destination, key, search) => {
+                geo::geosearchstore(&self.store, db, destination, key, &search)
+            }
+        };
+
+        reply.map(Response::from)
+    }
 }
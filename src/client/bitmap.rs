@@ -0,0 +1,95 @@
+use super::Reply;
+use crate::{
+    bitmap::{self, Operation, Range, Subcommand},
+    resp::{Array, Integer, NullBulkString},
+    store::Store,
+};
+use bytes::Bytes;
+use std::iter;
+
+pub fn setbit(store: &Store, db: usize, key: &str, offset: u64, bit: bool) -> Reply {
+    let mut keyspace = store.write(db, [key]);
+    let mut value = keyspace.get_or_insert_string_with(key, Vec::new)?;
+
+    Ok(Integer::from(i64::from(bitmap::set_bit(&mut value, offset, bit))).encode())
+}
+
+pub fn getbit(store: &Store, db: usize, key: &str, offset: u64) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let bit = keyspace
+        .get::<Bytes>(key)?
+        .map_or(0, |value| bitmap::get_bit(value, offset));
+
+    Ok(Integer::from(i64::from(bit)).encode())
+}
+
+pub fn bitcount(store: &Store, db: usize, key: &str, range: Option<&Range>) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let count = keyspace
+        .get::<Bytes>(key)?
+        .map_or(0, |value| bitmap::count(value, range));
+
+    Ok(Integer::from(i64::try_from(count).unwrap_or(i64::MAX)).encode())
+}
+
+pub fn bitpos(store: &Store, db: usize, key: &str, bit: bool, range: Option<&Range>) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let position = match keyspace.get::<Bytes>(key)? {
+        Some(value) => bitmap::position(value, bit, range),
+        None if bit => -1,
+        None => 0,
+    };
+
+    Ok(Integer::from(position).encode())
+}
+
+pub fn bitop(
+    store: &Store,
+    db: usize,
+    operation: Operation,
+    destination: &str,
+    keys: &[&str],
+) -> Reply {
+    let mut keyspace = store.write(db, iter::once(destination).chain(keys.iter().copied()));
+    let mut sources = Vec::with_capacity(keys.len());
+    for key in keys {
+        sources.push(keyspace.get::<Bytes>(key)?.map_or(&[][..], Bytes::as_ref));
+    }
+    let result = bitmap::operate(operation, &sources);
+    let length = result.len();
+
+    // An empty result, such as when none of the keys exist, removes the destination
+    if result.is_empty() {
+        keyspace.remove(destination);
+    } else {
+        keyspace.insert(destination, Bytes::from(result));
+    }
+
+    Ok(Integer::from(i64::try_from(length).unwrap_or(i64::MAX)).encode())
+}
+
+pub fn bitfield(store: &Store, db: usize, key: &str, subcommands: &[Subcommand]) -> Reply {
+    let results = if subcommands.iter().any(Subcommand::is_write) {
+        let mut keyspace = store.write(db, [key]);
+        let mut value = keyspace.get_or_insert_string_with(key, Vec::new)?;
+        bitmap::field(&mut value, subcommands)
+    } else {
+        // Reads alone never create the key
+        let keyspace = store.read(db, [key]);
+        let mut value = keyspace
+            .get::<Bytes>(key)?
+            .map(|value| value.to_vec())
+            .unwrap_or_default();
+        bitmap::field(&mut value, subcommands)
+    };
+
+    let results = results
+        .iter()
+        .map(|result| {
+            result.map_or_else(NullBulkString::encode, |value| {
+                Integer::from(value).encode()
+            })
+        })
+        .collect::<Vec<_>>();
+    Ok(Array::from(results).encode())
+}
@@ -0,0 +1,80 @@
+use super::Reply;
+use crate::{
+    cluster::{self, Cluster, SetSlot},
+    resp::{Array, BulkString, Integer, SimpleError, SimpleString},
+    store::Store,
+};
+use std::collections::HashSet;
+
+// Only the first database is used in cluster mode
+const DB: usize = 0;
+
+fn integer(value: usize) -> Vec<u8> {
+    Integer::from(i64::try_from(value).unwrap_or(i64::MAX)).encode()
+}
+
+pub fn keyslot(key: &str) -> Reply {
+    Ok(Integer::from(i64::from(cluster::key_slot(key))).encode())
+}
+
+pub fn countkeysinslot(store: &Store, slot: u16) -> Reply {
+    let count = store.read_all(DB).keys_in_slot(slot).count();
+    Ok(integer(count))
+}
+
+pub fn getkeysinslot(store: &Store, slot: u16, count: usize) -> Reply {
+    let keyspace = store.read_all(DB);
+    let keys = keyspace
+        .keys_in_slot(slot)
+        .take(count)
+        .map(|key| BulkString::from(key.as_bytes()).encode())
+        .collect::<Vec<_>>();
+    Ok(Array::from(keys).encode())
+}
+
+pub fn addslots(cluster: &Cluster, slots: &[u16]) -> Reply {
+    let mut seen = HashSet::new();
+    if let Some(slot) = slots.iter().find(|slot| !seen.insert(**slot)) {
+        let message = format!("ERR Slot {slot} specified multiple times");
+        return Ok(SimpleError::from(message.as_str()).encode());
+    }
+    if let Err(slot) = cluster.add_slots(slots) {
+        let message = format!("ERR Slot {slot} is already busy");
+        return Ok(SimpleError::from(message.as_str()).encode());
+    }
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+pub fn setslot(cluster: &Cluster, store: &Store, slot: u16, to: &SetSlot) -> Reply {
+    let has_keys = store.read_all(DB).keys_in_slot(slot).next().is_some();
+    match cluster.set_slot(slot, to, has_keys) {
+        Ok(()) => Ok(SimpleString::new("OK").encode()),
+        Err(message) => Ok(SimpleError::from(message.as_str()).encode()),
+    }
+}
+
+pub fn meet(cluster: &Cluster, host: &str, port: u16, bus_port: Option<u16>) -> Reply {
+    cluster.meet(host, port, bus_port);
+    Ok(SimpleString::new("OK").encode())
+}
+
+pub fn slots(cluster: &Cluster) -> Reply {
+    Ok(cluster.slots())
+}
+
+pub fn shards(cluster: &Cluster) -> Reply {
+    Ok(cluster.shards())
+}
+
+pub fn nodes(cluster: &Cluster) -> Reply {
+    Ok(BulkString::from(cluster.nodes().as_bytes()).encode())
+}
+
+pub fn myid(cluster: &Cluster) -> Reply {
+    Ok(BulkString::from(cluster.myid().as_bytes()).encode())
+}
+
+pub fn info(cluster: &Cluster) -> Reply {
+    Ok(BulkString::from(cluster.info().as_bytes()).encode())
+}
@@ -0,0 +1,78 @@
+use super::Reply;
+use crate::{
+    resp::Integer,
+    store::{self, ExpireCondition, Store},
+};
+
+// Replies for keys which do not exist, or exist without a deadline
+const MISSING: i64 = -2;
+const PERSISTENT: i64 = -1;
+
+pub fn expire(
+    store: &Store,
+    db: usize,
+    key: &str,
+    deadline: i64,
+    condition: Option<ExpireCondition>,
+) -> Reply {
+    let mut keyspace = store.write(db, [key]);
+    let Some(current) = keyspace.expires(key) else {
+        return Ok(Integer::from(0).encode());
+    };
+    if !condition.is_none_or(|condition| condition.allows(current, deadline)) {
+        return Ok(Integer::from(0).encode());
+    }
+
+    // A deadline which has already passed deletes the key straight away
+    match u64::try_from(deadline) {
+        Ok(deadline) if deadline > store::now() => keyspace.set_expires(key, Some(deadline)),
+        _ => keyspace.remove(key).is_some(),
+    };
+
+    Ok(Integer::from(1).encode())
+}
+
+// The time left before the key expires, rounded to the nearest second unless in milliseconds
+pub fn ttl(store: &Store, db: usize, key: &str, milliseconds: bool) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let ttl = match keyspace.expires(key) {
+        None => MISSING,
+        Some(None) => PERSISTENT,
+        Some(Some(deadline)) => {
+            let ttl = i64::try_from(deadline.saturating_sub(store::now())).unwrap_or(i64::MAX);
+            if milliseconds {
+                ttl
+            } else {
+                (ttl + 500) / 1000
+            }
+        }
+    };
+
+    Ok(Integer::from(ttl).encode())
+}
+
+// The deadline as a Unix timestamp, in seconds unless in milliseconds
+pub fn expiretime(store: &Store, db: usize, key: &str, milliseconds: bool) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let time = match keyspace.expires(key) {
+        None => MISSING,
+        Some(None) => PERSISTENT,
+        Some(Some(deadline)) => {
+            let deadline = i64::try_from(deadline).unwrap_or(i64::MAX);
+            if milliseconds {
+                deadline
+            } else {
+                deadline / 1000
+            }
+        }
+    };
+
+    Ok(Integer::from(time).encode())
+}
+
+pub fn persist(store: &Store, db: usize, key: &str) -> Reply {
+    let mut keyspace = store.write(db, [key]);
+    let persisted = keyspace.expires(key).flatten().is_some() && keyspace.set_expires(key, None);
+
+    Ok(Integer::from(i64::from(persisted)).encode())
+}
@@ -0,0 +1,472 @@
+use super::Reply;
+use crate::{
+    cluster::Migrate,
+    connection::Connection,
+    evict, glob,
+    rdb::{self, PayloadError, Restore},
+    resp::{Array, BulkString, Integer, NullBulkString, RespType, SimpleError, SimpleString},
+    store::{self, Store, Value},
+};
+use std::net::{TcpStream, ToSocketAddrs};
+
+fn count(keys: &[&str], mut f: impl FnMut(&str) -> bool) -> Vec<u8> {
+    let count = keys.iter().filter(|key| f(key)).count();
+    Integer::from(i64::try_from(count).unwrap_or(i64::MAX)).encode()
+}
+
+pub fn del(store: &Store, db: usize, keys: &[&str]) -> Reply {
+    let mut keyspace = store.write(db, keys.iter().copied());
+    Ok(count(keys, |key| keyspace.remove(key).is_some()))
+}
+
+// Keys are removed straight away, but reclaiming large values happens in the background
+pub fn unlink(store: &Store, db: usize, keys: &[&str]) -> Reply {
+    let mut keyspace = store.write(db, keys.iter().copied());
+    let values = keys
+        .iter()
+        .filter_map(|key| keyspace.remove(key))
+        .collect::<Vec<_>>();
+    drop(keyspace);
+
+    let count = values.len();
+    for value in values {
+        store.free(value);
+    }
+
+    Ok(Integer::from(i64::try_from(count).unwrap_or(i64::MAX)).encode())
+}
+
+// Keys given more than once are counted each time
+pub fn exists(store: &Store, db: usize, keys: &[&str]) -> Reply {
+    let keyspace = store.read(db, keys.iter().copied());
+    Ok(count(keys, |key| keyspace.contains(key)))
+}
+
+pub fn type_of(store: &Store, db: usize, key: &str) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let name = keyspace.peek(key).map_or("none", |value| value.type_name());
+
+    Ok(SimpleString::new(name).encode())
+}
+
+pub fn rename(store: &Store, db: usize, key: &str, new_key: &str) -> Reply {
+    let mut keyspace = store.write(db, [key, new_key]);
+    if !keyspace.rename(key, new_key) {
+        return Err(SimpleError::from("ERR no such key"));
+    }
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+pub fn renamenx(store: &Store, db: usize, key: &str, new_key: &str) -> Reply {
+    let mut keyspace = store.write(db, [key, new_key]);
+    if !keyspace.contains(key) {
+        return Err(SimpleError::from("ERR no such key"));
+    }
+    if keyspace.contains(new_key) {
+        return Ok(Integer::from(0).encode());
+    }
+
+    keyspace.rename(key, new_key);
+    Ok(Integer::from(1).encode())
+}
+
+pub fn move_key(store: &Store, db: usize, key: &str, to: i64) -> Reply {
+    let to = super::db_index(store, to)?;
+    if db == to {
+        return Err(SimpleError::from(
+            "ERR source and destination objects are the same",
+        ));
+    }
+
+    let mut keyspace = store.write(db, [key]);
+    Ok(Integer::from(i64::from(keyspace.move_to(key, to))).encode())
+}
+
+pub fn randomkey(store: &Store, db: usize) -> Reply {
+    Ok(store
+        .random_key(db)
+        .map_or_else(NullBulkString::encode, |key| {
+            BulkString::from(key.as_bytes()).encode()
+        }))
+}
+
+pub fn copy(store: &Store, db: usize, source: &str, destination: &str, replace: bool) -> Reply {
+    if source == destination {
+        return Err(SimpleError::from(
+            "ERR source and destination objects are the same",
+        ));
+    }
+
+    let mut keyspace = store.write(db, [source, destination]);
+    if !keyspace.contains(source) || (!replace && keyspace.contains(destination)) {
+        return Ok(Integer::from(0).encode());
+    }
+
+    keyspace.copy(source, destination);
+    Ok(Integer::from(1).encode())
+}
+
+pub fn dump(store: &Store, db: usize, key: &str) -> Reply {
+    let keyspace = store.read(db, [key]);
+    Ok(keyspace
+        .value(key)
+        .map_or_else(NullBulkString::encode, |value| {
+            BulkString::from(rdb::dump(value).as_slice()).encode()
+        }))
+}
+
+// A deadline which has already passed removes the key instead, as in Redis
+pub fn restore(store: &Store, db: usize, key: &str, restore: &Restore) -> Reply {
+    let mut keyspace = store.write(db, [key]);
+    if !restore.replace && keyspace.contains(key) {
+        return Err(SimpleError::from("BUSYKEY Target key name already exists."));
+    }
+    let value = rdb::restore(restore.payload).map_err(|error| match error {
+        PayloadError::Checksum => {
+            SimpleError::from("ERR DUMP payload version or checksum are wrong")
+        }
+        PayloadError::Format => SimpleError::from("ERR Bad data format"),
+    })?;
+
+    let deadline = match restore.ttl {
+        0 => None,
+        ttl if restore.absolute_ttl => Some(ttl),
+        ttl => Some(store::now().saturating_add(ttl)),
+    };
+    if deadline.is_some_and(|deadline| deadline <= store::now()) {
+        keyspace.remove(key);
+        return Ok(SimpleString::new("OK").encode());
+    }
+
+    keyspace.insert(key, value);
+    if deadline.is_some() {
+        keyspace.set_expires(key, deadline);
+    }
+    let access = evict::restored_access(
+        store.max_memory().policy,
+        restore.idle_time,
+        restore.frequency,
+    );
+    if let Some(access) = access {
+        keyspace.set_access(key, access);
+    }
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+// Moves keys to another instance by restoring their DUMP payload there, returning the keys which
+// were restored, unless only copied. In cluster mode, keys are restored with RESTORE-ASKING, for
+// the node importing their slot to accept them before it serves the slot. No lock is held while
+// waiting on the other instance, so the caller holds the keys against writes with
+// `Store::hold_for_migration`, and removes them here once sent with `remove_migrated`.
+pub fn migrate<'a>(
+    store: &Store,
+    db: usize,
+    migrate: &Migrate<'a>,
+    cluster: bool,
+) -> (Reply, Vec<&'a str>) {
+    let keyspace = store.read(db, migrate.keys.iter().copied());
+    let now = store::now();
+    let dumps = migrate
+        .keys
+        .iter()
+        .filter_map(|key| {
+            let value = keyspace.peek(key)?;
+            // A deadline due this very millisecond is sent as 1, as 0 would mean none
+            let ttl = keyspace
+                .expires(key)
+                .flatten()
+                .map_or(0, |deadline| deadline.saturating_sub(now).max(1));
+            Some((*key, rdb::dump(value), ttl))
+        })
+        .collect::<Vec<_>>();
+    drop(keyspace);
+    if dumps.is_empty() {
+        return (Ok(SimpleString::new("NOKEY").encode()), Vec::new());
+    }
+
+    let restore: &[u8] = if cluster {
+        b"RESTORE-ASKING"
+    } else {
+        b"RESTORE"
+    };
+    let (restored, error) = send_keys(migrate, &dumps, restore);
+    let reply = match error {
+        Some(message) => Ok(SimpleError::from(message.as_str()).encode()),
+        None => Ok(SimpleString::new("OK").encode()),
+    };
+    if migrate.copy {
+        return (reply, Vec::new());
+    }
+    let moved = dumps[..restored].iter().map(|(key, ..)| *key).collect();
+    (reply, moved)
+}
+
+// Removes the keys moved by MIGRATE, returning those removed
+pub fn remove_migrated<'a>(store: &Store, db: usize, moved: &[&'a str]) -> Vec<&'a str> {
+    let mut keyspace = store.write(db, moved.iter().copied());
+    let mut removed = Vec::new();
+    let mut values = Vec::new();
+    for key in moved {
+        if let Some(value) = keyspace.remove(key) {
+            values.push(value);
+            removed.push(*key);
+        }
+    }
+    drop(keyspace);
+    for value in values {
+        store.free(value);
+    }
+
+    removed
+}
+
+// Restores the keys on the other instance, one at a time, returning how many were restored, along
+// with the error which stopped the rest, if any
+fn send_keys(
+    migrate: &Migrate,
+    dumps: &[(&str, Vec<u8>, u64)],
+    restore: &[u8],
+) -> (usize, Option<String>) {
+    let connect = || {
+        let address = (migrate.host, migrate.port)
+            .to_socket_addrs()
+            .ok()?
+            .next()?;
+        let stream = TcpStream::connect_timeout(&address, migrate.timeout).ok()?;
+        stream.set_read_timeout(Some(migrate.timeout)).ok()?;
+        stream.set_write_timeout(Some(migrate.timeout)).ok()?;
+        Some(Connection::new(stream))
+    };
+    let Some(mut connection) = connect() else {
+        return (
+            0,
+            Some("IOERR error or timeout connecting to the client".to_string()),
+        );
+    };
+    let mut request = |arguments: &[&[u8]]| -> Result<(), String> {
+        let reading = "IOERR error or timeout reading to target instance".to_string();
+        connection
+            .send(arguments)
+            .map_err(|_| "IOERR error or timeout writing to target instance".to_string())?;
+        let reply = connection
+            .read_request()
+            .ok()
+            .flatten()
+            .ok_or(reading.clone())?;
+        match RespType::try_from(reply.as_slice()) {
+            Ok(RespType::SimpleString(_)) => Ok(()),
+            Ok(RespType::SimpleError(error)) => Err(format!(
+                "ERR Target instance replied with error: {}",
+                error.as_str()
+            )),
+            _ => Err(reading),
+        }
+    };
+
+    let db = migrate.db.to_string();
+    let setup = match migrate.auth {
+        Some((Some(username), password)) => {
+            request(&[b"AUTH", username.as_bytes(), password.as_bytes()])
+        }
+        Some((None, password)) => request(&[b"AUTH", password.as_bytes()]),
+        None => Ok(()),
+    }
+    .and_then(|()| request(&[b"SELECT", db.as_bytes()]));
+    if let Err(error) = setup {
+        return (0, Some(error));
+    }
+
+    for (restored, (key, payload, ttl)) in dumps.iter().enumerate() {
+        let ttl = ttl.to_string();
+        let mut arguments = vec![restore, key.as_bytes(), ttl.as_bytes(), payload];
+        if migrate.replace {
+            arguments.push(b"REPLACE");
+        }
+        if let Err(error) = request(&arguments) {
+            return (restored, Some(error));
+        }
+    }
+
+    (dumps.len(), None)
+}
+
+pub fn touch(store: &Store, db: usize, keys: &[&str]) -> Reply {
+    let keyspace = store.read(db, keys.iter().copied());
+    Ok(count(keys, |key| keyspace.value(key).is_some()))
+}
+
+pub fn keys(store: &Store, db: usize, pattern: &BulkString) -> Reply {
+    let keyspace = store.read_all(db);
+    let pattern = pattern.as_bytes();
+    let everything = pattern == b"*";
+
+    let keys = keyspace
+        .keys()
+        .filter(|(key, _)| everything || glob::matches(pattern, key.as_bytes()))
+        .map(|(key, _)| BulkString::from(key.as_bytes()).encode())
+        .collect::<Vec<_>>();
+    Ok(Array::from(keys).encode())
+}
+
+// Visits buckets until `count` keys are found, or ten times as many buckets as asked for have been
+// visited, so that a sparse keyspace, or a selective pattern, does not block for too long
+pub fn scan(
+    store: &Store,
+    db: usize,
+    mut cursor: u64,
+    pattern: Option<&BulkString>,
+    count: usize,
+    type_name: Option<&str>,
+) -> Reply {
+    let pattern = pattern
+        .map(BulkString::as_bytes)
+        .filter(|pattern| *pattern != b"*");
+    let matches = |key: &str, value: &Value| {
+        pattern.is_none_or(|pattern| glob::matches(pattern, key.as_bytes()))
+            && type_name.is_none_or(|name| name.eq_ignore_ascii_case(value.type_name()))
+    };
+
+    let mut keys = Vec::new();
+    let mut iterations = count.saturating_mul(10);
+    loop {
+        cursor = store.scan(db, cursor, |key, value| {
+            if matches(key, value) {
+                keys.push(BulkString::from(key.as_bytes()).encode());
+            }
+        });
+
+        iterations -= 1;
+        if cursor == 0 || iterations == 0 || keys.len() >= count {
+            break;
+        }
+    }
+
+    let cursor = cursor.to_string();
+    Ok(Array::from(vec![
+        BulkString::from(cursor.as_bytes()).encode(),
+        Array::from(keys).encode(),
+    ])
+    .encode())
+}
+
+// Like Redis, OBJECT does not count as accessing the key
+pub fn object_encoding(store: &Store, db: usize, key: &str) -> Reply {
+    let keyspace = store.read(db, [key]);
+    Ok(keyspace
+        .peek(key)
+        .map_or_else(NullBulkString::encode, |value| {
+            BulkString::from(value.encoding().as_bytes()).encode()
+        }))
+}
+
+pub fn object_freq(store: &Store, db: usize, key: &str) -> Reply {
+    if !store.max_memory().policy.is_lfu() {
+        return Err(SimpleError::from(
+            "ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+        ));
+    }
+
+    let keyspace = store.read(db, [key]);
+    Ok(keyspace
+        .access(key)
+        .map_or_else(NullBulkString::encode, |access| {
+            let frequency = evict::lfu_decay(access);
+            Integer::from(i64::try_from(frequency).unwrap_or(i64::MAX)).encode()
+        }))
+}
+
+// In seconds
+pub fn object_idletime(store: &Store, db: usize, key: &str) -> Reply {
+    if store.max_memory().policy.is_lfu() {
+        return Err(SimpleError::from(
+            "ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+        ));
+    }
+
+    let keyspace = store.read(db, [key]);
+    Ok(keyspace
+        .access(key)
+        .map_or_else(NullBulkString::encode, |access| {
+            let idle = store::now().saturating_sub(access) / 1000;
+            Integer::from(i64::try_from(idle).unwrap_or(i64::MAX)).encode()
+        }))
+}
+
+// Values are never shared between keys, as they can be in Redis
+pub fn object_refcount(store: &Store, db: usize, key: &str) -> Reply {
+    let keyspace = store.read(db, [key]);
+    Ok(if keyspace.contains(key) {
+        Integer::from(1).encode()
+    } else {
+        NullBulkString::encode()
+    })
+}
+
+pub fn object_help() -> Reply {
+    super::help(&[
+        "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        "ENCODING <key>",
+        "    Return the kind of internal representation used in order to store the value",
+        "    associated with a <key>.",
+        "FREQ <key>",
+        "    Return the access frequency index of the <key>. The returned integer is",
+        "    proportional to the logarithm of the recent access frequency of the key.",
+        "IDLETIME <key>",
+        "    Return the idle time of the <key>, that is the approximated number of",
+        "    seconds elapsed since the last access to the key.",
+        "REFCOUNT <key>",
+        "    Return the number of references of the value associated with the specified",
+        "    <key>.",
+        "HELP",
+        "    Print this help.",
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{io::Write, net::TcpListener, thread, time::Duration};
+
+    #[test]
+    fn migrate_stops_at_rejected_key() {
+        // A target which rejects the second key it is sent
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let mut connection = Connection::new(listener.accept().unwrap().0);
+            let replies = [&b"+OK\r\n"[..], b"+OK\r\n", b"-ERR Busy\r\n"];
+            for reply in replies {
+                connection.read_request().unwrap();
+                connection.stream.write_all(reply).unwrap();
+            }
+        });
+
+        let store = Store::new();
+        for key in ["a", "b", "c"] {
+            store.set(0, key.to_string(), key.into(), None);
+        }
+        let migrate = Migrate {
+            host: "127.0.0.1",
+            port,
+            keys: vec!["a", "b", "c"],
+            db: 0,
+            timeout: Duration::from_secs(5),
+            copy: false,
+            replace: false,
+            auth: None,
+        };
+        let (reply, moved) = super::migrate(&store, 0, &migrate, false);
+        assert_eq!(
+            reply,
+            Ok(b"-ERR Target instance replied with error: ERR Busy\r\n".to_vec())
+        );
+        assert_eq!(moved, ["a"]);
+
+        assert_eq!(remove_migrated(&store, 0, &moved), ["a"]);
+        let keyspace = store.read(0, ["a", "b", "c"]);
+        assert!(!keyspace.contains("a"));
+        assert!(keyspace.contains("b") && keyspace.contains("c"));
+    }
+}
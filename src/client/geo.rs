@@ -0,0 +1,265 @@
+use super::Reply;
+use crate::{
+    geo::{self, Order, Origin, Point, Search, Shape, Unit},
+    resp::{Array, BulkString, Integer, NullArray, NullBulkString, SimpleError},
+    sorted_set::{Condition, SortedSet},
+    store::Store,
+};
+use std::cmp::Ordering;
+
+// Scores are 52-bit geohashes, so convert exactly to and from `f64`
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+const fn to_hash(score: f64) -> u64 {
+    score as u64
+}
+
+#[allow(clippy::cast_precision_loss)]
+const fn to_score(hash: u64) -> f64 {
+    hash as f64
+}
+
+fn invalid_coordinates(longitude: f64, latitude: f64) -> Vec<u8> {
+    let message = format!("ERR invalid longitude,latitude pair {longitude:.6},{latitude:.6}");
+    SimpleError::from(message.as_str()).encode()
+}
+
+fn encode_coordinates(longitude: f64, latitude: f64) -> Vec<u8> {
+    let longitude = geo::format_coordinate(longitude);
+    let latitude = geo::format_coordinate(latitude);
+    Array::from(vec![
+        BulkString::from(longitude.as_bytes()).encode(),
+        BulkString::from(latitude.as_bytes()).encode(),
+    ])
+    .encode()
+}
+
+fn encode_distance(distance: f64, unit: Unit) -> Vec<u8> {
+    let distance = format!("{:.4}", distance / unit.to_meters());
+    BulkString::from(distance.as_bytes()).encode()
+}
+
+pub fn geoadd(
+    store: &Store,
+    db: usize,
+    key: &str,
+    condition: Option<Condition>,
+    changed: bool,
+    items: &[(f64, f64, BulkString)],
+) -> Reply {
+    // Like Redis, all of the coordinates are validated before any are added
+    if let Some((longitude, latitude, _)) = items
+        .iter()
+        .find(|(longitude, latitude, _)| !geo::is_valid(*longitude, *latitude))
+    {
+        return Ok(invalid_coordinates(*longitude, *latitude));
+    }
+
+    let mut keyspace = store.write(db, [key]);
+    if condition == Some(Condition::IfExists) && keyspace.get::<SortedSet>(key)?.is_none() {
+        return Ok(Integer::from(0).encode());
+    }
+    let mut set = keyspace.get_or_insert_with(key, SortedSet::new)?;
+
+    let mut count = 0;
+    for (longitude, latitude, member) in items {
+        let score = to_score(geo::encode(*longitude, *latitude));
+        let previous = set.score(member.as_bytes());
+        match (condition, previous) {
+            (Some(Condition::IfExists), None) | (Some(Condition::IfMissing), Some(_)) => continue,
+            (_, None) => count += 1,
+            (_, Some(previous)) if changed && previous != score => count += 1,
+            (_, Some(_)) => {}
+        }
+        set.insert(member.to_vec(), score);
+    }
+
+    Ok(Integer::from(count).encode())
+}
+
+pub fn geopos(store: &Store, db: usize, key: &str, members: &[BulkString]) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let set = keyspace.get::<SortedSet>(key)?;
+
+    let positions = members
+        .iter()
+        .map(|member| {
+            set.and_then(|set| set.score(member.as_bytes()))
+                .map_or_else(NullArray::encode, |score| {
+                    let (longitude, latitude) = geo::decode(to_hash(score));
+                    encode_coordinates(longitude, latitude)
+                })
+        })
+        .collect::<Vec<_>>();
+    Ok(Array::from(positions).encode())
+}
+
+pub fn geodist(
+    store: &Store,
+    db: usize,
+    key: &str,
+    from: BulkString,
+    to: BulkString,
+    unit: Unit,
+) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let Some(set) = keyspace.get::<SortedSet>(key)? else {
+        return Ok(NullBulkString::encode());
+    };
+    let (Some(from), Some(to)) = (set.score(from.as_bytes()), set.score(to.as_bytes())) else {
+        return Ok(NullBulkString::encode());
+    };
+
+    let from = geo::decode(to_hash(from));
+    let to = geo::decode(to_hash(to));
+    Ok(encode_distance(
+        geo::distance(from.0, from.1, to.0, to.1),
+        unit,
+    ))
+}
+
+pub fn geohash(store: &Store, db: usize, key: &str, members: &[BulkString]) -> Reply {
+    let keyspace = store.read(db, [key]);
+    let set = keyspace.get::<SortedSet>(key)?;
+
+    let hashes = members
+        .iter()
+        .map(|member| {
+            set.and_then(|set| set.score(member.as_bytes()))
+                .map_or_else(NullBulkString::encode, |score| {
+                    let hash = geo::geohash(to_hash(score));
+                    BulkString::from(hash.as_bytes()).encode()
+                })
+        })
+        .collect::<Vec<_>>();
+    Ok(Array::from(hashes).encode())
+}
+
+// The matching points, ordered and limited as requested, or an error reply
+fn search<'a>(set: &'a SortedSet, search: &Search) -> Result<Vec<Point<'a>>, Vec<u8>> {
+    let (longitude, latitude) = match search.origin {
+        Origin::Coordinates(longitude, latitude) => (longitude, latitude),
+        Origin::Member(member) => {
+            let Some(score) = set.score(member) else {
+                return Err(
+                    SimpleError::from("ERR could not decode requested zset member").encode(),
+                );
+            };
+            geo::decode(to_hash(score))
+        }
+    };
+
+    let meters = search.unit.to_meters();
+    let shape = match search.shape {
+        Shape::Radius(radius) => Shape::Radius(radius * meters),
+        Shape::Box(width, height) => Shape::Box(width * meters, height * meters),
+    };
+    let limit = search.count.filter(|_| search.any);
+    let mut points = geo::search(set, longitude, latitude, shape, limit);
+
+    let by_distance = |a: &Point, b: &Point| -> Ordering { a.distance.total_cmp(&b.distance) };
+    match search.order {
+        Some(Order::Ascending) => points.sort_by(by_distance),
+        Some(Order::Descending) => points.sort_by(|a, b| by_distance(b, a)),
+        None => {}
+    }
+    if let Some(count) = search.count {
+        points.truncate(count);
+    }
+
+    Ok(points)
+}
+
+// Coordinates given directly are validated before the key is looked up, as in Redis
+fn validate(search: &Search) -> Result<(), Vec<u8>> {
+    match search.origin {
+        Origin::Coordinates(longitude, latitude) if !geo::is_valid(longitude, latitude) => {
+            Err(invalid_coordinates(longitude, latitude))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn geosearch(store: &Store, db: usize, key: &str, options: &Search) -> Reply {
+    if let Err(error) = validate(options) {
+        return Ok(error);
+    }
+
+    let keyspace = store.read(db, [key]);
+    let Some(set) = keyspace.get::<SortedSet>(key)? else {
+        return Ok(Array::from(vec![]).encode());
+    };
+    let points = match search(set, options) {
+        Ok(points) => points,
+        Err(error) => return Ok(error),
+    };
+
+    let with_anything = options.with_distance || options.with_hash || options.with_coordinates;
+    let points = points
+        .iter()
+        .map(|point| {
+            let member = BulkString::from(point.member).encode();
+            if !with_anything {
+                return member;
+            }
+
+            let mut item = vec![member];
+            if options.with_distance {
+                item.push(encode_distance(point.distance, options.unit));
+            }
+            if options.with_hash {
+                #[allow(clippy::cast_possible_wrap)]
+                item.push(Integer::from(to_hash(point.score) as i64).encode());
+            }
+            if options.with_coordinates {
+                item.push(encode_coordinates(point.longitude, point.latitude));
+            }
+            Array::from(item).encode()
+        })
+        .collect::<Vec<_>>();
+    Ok(Array::from(points).encode())
+}
+
+pub fn geosearchstore(
+    store: &Store,
+    db: usize,
+    destination: &str,
+    key: &str,
+    options: &Search,
+) -> Reply {
+    if let Err(error) = validate(options) {
+        return Ok(error);
+    }
+
+    let mut keyspace = store.write(db, [destination, key]);
+    let points = match keyspace.get::<SortedSet>(key)? {
+        None => Vec::new(),
+        Some(set) => match search(set, options) {
+            Ok(points) => points
+                .iter()
+                .map(|point| {
+                    let score = if options.store_distance {
+                        point.distance / options.unit.to_meters()
+                    } else {
+                        point.score
+                    };
+                    (point.member.to_vec(), score)
+                })
+                .collect(),
+            Err(error) => return Ok(error),
+        },
+    };
+
+    // Like other commands storing a result, the destination is removed when there is none
+    let count = points.len();
+    if points.is_empty() {
+        keyspace.remove(destination);
+    } else {
+        let mut set = SortedSet::new();
+        for (member, score) in points {
+            set.insert(member, score);
+        }
+        keyspace.insert(destination, set);
+    }
+
+    Ok(Integer::from(i64::try_from(count).unwrap_or(i64::MAX)).encode())
+}
@@ -0,0 +1,116 @@
+use super::Reply;
+use crate::{
+    hyperloglog::{self, Corrupted},
+    resp::{BulkString, Integer, SimpleError, SimpleString},
+    store::Store,
+};
+use bytes::Bytes;
+use std::iter;
+
+const WRONG_TYPE: SimpleError =
+    SimpleError::new("WRONGTYPE Key is not a valid HyperLogLog string value.");
+
+impl From<Corrupted> for SimpleError<'_> {
+    fn from(_: Corrupted) -> Self {
+        SimpleError::from("INVALIDOBJ Corrupted HLL object detected")
+    }
+}
+
+pub fn pfadd(store: &Store, db: usize, key: &str, elements: &[BulkString]) -> Reply {
+    let mut keyspace = store.write(db, [key]);
+    let mut created = false;
+    let mut value = keyspace.get_or_insert_string_with(key, || {
+        created = true;
+        hyperloglog::new()
+    })?;
+    if !hyperloglog::is_valid(&value) {
+        return Err(WRONG_TYPE);
+    }
+
+    let mut updated = created;
+    for element in elements {
+        updated |= hyperloglog::add(&mut value, element.as_bytes())?;
+    }
+
+    Ok(Integer::from(i64::from(updated)).encode())
+}
+
+pub fn pfcount(store: &Store, db: usize, keys: &[&str]) -> Reply {
+    let mut keyspace = store.write(db, keys.iter().copied());
+
+    // Only a single key can make use of, and update, the cached cardinality
+    let count = if let [key] = keys {
+        match keyspace.get::<Bytes>(key)? {
+            None => 0,
+            Some(value) if !hyperloglog::is_valid(value) => return Err(WRONG_TYPE),
+            Some(value) => match hyperloglog::cached(value) {
+                Some(count) => count,
+                None => {
+                    let mut value = value.to_vec();
+                    let count = hyperloglog::count(&mut value)?;
+                    keyspace.cache_string(key, Bytes::from(value));
+                    count
+                }
+            },
+        }
+    } else {
+        let mut registers = [0; hyperloglog::REGISTERS];
+        for key in keys {
+            match keyspace.get::<Bytes>(key)? {
+                None => continue,
+                Some(value) if !hyperloglog::is_valid(value) => return Err(WRONG_TYPE),
+                Some(value) => hyperloglog::merge(&mut registers, value)?,
+            }
+        }
+
+        hyperloglog::count_registers(&registers)
+    };
+
+    Ok(Integer::from(i64::try_from(count).unwrap_or(i64::MAX)).encode())
+}
+
+pub fn pfmerge(store: &Store, db: usize, destination: &str, keys: &[&str]) -> Reply {
+    let mut keyspace = store.write(db, iter::once(destination).chain(keys.iter().copied()));
+
+    // Like Redis, the destination is merged too, and the result is only dense when one of the
+    // inputs was
+    let mut registers = [0; hyperloglog::REGISTERS];
+    let mut dense = false;
+    for key in iter::once(&destination).chain(keys) {
+        match keyspace.get::<Bytes>(key)? {
+            None => continue,
+            Some(value) if !hyperloglog::is_valid(value) => return Err(WRONG_TYPE),
+            Some(value) => {
+                dense |= hyperloglog::is_dense(value);
+                hyperloglog::merge(&mut registers, value)?;
+            }
+        }
+    }
+
+    let mut value = keyspace.get_or_insert_string_with(destination, hyperloglog::new)?;
+    hyperloglog::store(&mut value, &registers, dense)?;
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pfcount_caches_without_changing() {
+        let store = Store::new();
+        let elements = [BulkString::from(&b"a"[..]), BulkString::from(&b"b"[..])];
+        pfadd(&store, 0, "hll", &elements).unwrap();
+
+        // Caching the cardinality on the first count isn't counted as a change
+        let dirty = store.dirty();
+        for _ in 0..2 {
+            assert_eq!(pfcount(&store, 0, &["hll"]), Ok(b":2\r\n".to_vec()));
+        }
+        assert_eq!(store.dirty(), dirty);
+        let keyspace = store.read(0, ["hll"]);
+        let value = keyspace.get::<Bytes>("hll").unwrap().unwrap();
+        assert_eq!(hyperloglog::cached(value), Some(2));
+    }
+}
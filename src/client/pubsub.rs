@@ -0,0 +1,63 @@
+use super::Reply;
+use crate::{
+    pubsub::{Kind, PubSub},
+    resp::{Array, BulkString, Integer},
+};
+
+fn integer(value: usize) -> Vec<u8> {
+    Integer::from(i64::try_from(value).unwrap_or(i64::MAX)).encode()
+}
+
+pub fn publish(pubsub: &PubSub, channel: &str, message: &[u8]) -> Reply {
+    Ok(integer(pubsub.publish(channel, message)))
+}
+
+pub fn spublish(pubsub: &PubSub, channel: &str, message: &[u8]) -> Reply {
+    Ok(integer(pubsub.publish_shard(channel, message)))
+}
+
+pub fn channels(pubsub: &PubSub, kind: Kind, pattern: Option<&str>) -> Reply {
+    let channels = pubsub
+        .channels(kind, pattern)
+        .iter()
+        .map(|channel| BulkString::from(channel.as_bytes()).encode())
+        .collect::<Vec<_>>();
+    Ok(Array::from(channels).encode())
+}
+
+// Each channel followed by its number of subscribers
+pub fn numsub(pubsub: &PubSub, kind: Kind, channels: &[&str]) -> Reply {
+    let counts = channels
+        .iter()
+        .flat_map(|channel| {
+            [
+                BulkString::from(channel.as_bytes()).encode(),
+                integer(pubsub.subscribers(kind, channel)),
+            ]
+        })
+        .collect::<Vec<_>>();
+    Ok(Array::from(counts).encode())
+}
+
+pub fn numpat(pubsub: &PubSub) -> Reply {
+    Ok(integer(pubsub.patterns()))
+}
+
+pub fn help() -> Reply {
+    super::help(&[
+        "PUBSUB <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        "CHANNELS [<pattern>]",
+        "    Return the currently active channels matching a <pattern> (default: '*').",
+        "NUMPAT",
+        "    Return number of subscriptions to patterns.",
+        "NUMSUB [<channel> ...]",
+        "    Return the number of subscribers for the specified channels, excluding",
+        "    pattern subscriptions(default: no channels).",
+        "SHARDCHANNELS [<pattern>]",
+        "    Return the currently active shard level channels matching a <pattern> (default: '*').",
+        "SHARDNUMSUB [<shardchannel> ...]",
+        "    Return the number of subscribers for the specified shard level channel(s)",
+        "HELP",
+        "    Print this help.",
+    ])
+}
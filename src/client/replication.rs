@@ -0,0 +1,110 @@
+use super::{Client, Reply};
+use crate::{
+    aof::Aof,
+    config::Config,
+    replication::Replication,
+    resp::{Array, Integer, SimpleError, SimpleString},
+    store::Store,
+};
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+fn integer(value: usize) -> Vec<u8> {
+    Integer::from(i64::try_from(value).unwrap_or(i64::MAX)).encode()
+}
+
+// A timeout of 0 means to wait forever
+fn deadline(timeout: u64) -> Option<Instant> {
+    (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout))
+}
+
+// `offset` is that of the last write of the client
+pub fn wait(replication: &Replication, offset: u64, replicas: i64, timeout: u64) -> Reply {
+    if replication.is_replica() {
+        return Err(SimpleError::from(
+            "ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 \
+             if a replica is configured to be writable (which is not the default) writes to \
+             replicas are just local and are not propagated.",
+        ));
+    }
+
+    let replicas = usize::try_from(replicas).unwrap_or(0);
+    let mut acknowledged = replication.acknowledged(offset, false);
+    if acknowledged < replicas {
+        replication.request_acks();
+        replication.wait(deadline(timeout), || {
+            acknowledged = replication.acknowledged(offset, false);
+            acknowledged >= replicas
+        });
+    }
+
+    Ok(integer(acknowledged))
+}
+
+// Like WAIT, but for writes being on disk, here and on replicas, replying with both counts
+pub fn waitaof(
+    replication: &Replication,
+    aof: &Aof,
+    offset: u64,
+    (local, replicas): (i64, i64),
+    timeout: u64,
+) -> Reply {
+    if replication.is_replica() {
+        return Err(SimpleError::from(
+            "ERR WAITAOF cannot be used with replica instances. Please also note that writes to \
+             replicas are just local and are not propagated.",
+        ));
+    }
+    if local > 0 && aof.fsynced().is_none() {
+        return Err(SimpleError::from(
+            "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled.",
+        ));
+    }
+
+    let (local, replicas) = (
+        usize::try_from(local).unwrap_or(0),
+        usize::try_from(replicas).unwrap_or(0),
+    );
+    let mut counts = (0, 0);
+    let mut count = || {
+        let fsynced = aof.fsynced().is_some_and(|fsynced| fsynced >= offset);
+        counts = (usize::from(fsynced), replication.acknowledged(offset, true));
+        counts.0 >= local && counts.1 >= replicas
+    };
+    if !count() {
+        replication.request_acks();
+        replication.wait(deadline(timeout), count);
+    }
+
+    Ok(Array::from(vec![integer(counts.0), integer(counts.1)]).encode())
+}
+
+// Replicates the master given, or with none, promotes this replica to a master. `link` is the
+// client to apply what the master sends.
+pub fn replicaof(
+    replication: &Arc<Replication>,
+    config: &RwLock<Config>,
+    (store, aof): (&Arc<Store>, &Arc<Aof>),
+    master: Option<(&str, u16)>,
+    link: impl FnOnce() -> Client,
+) -> Reply {
+    let mut config = config.write().unwrap_or_else(PoisonError::into_inner);
+    let Some((host, port)) = master else {
+        if replication.promote() {
+            println!("MASTER MODE enabled (user request)");
+        }
+        config.replica_of = None;
+        return Ok(SimpleString::new("OK").encode());
+    };
+    if replication.is_replica_of(host, port) {
+        return Ok(SimpleString::new("OK Already connected to specified master").encode());
+    }
+
+    let (store, aof) = (Arc::clone(store), Arc::clone(aof));
+    replication.replicate(host.to_string(), port, link(), store, aof, config.port);
+    config.replica_of = Some((host.to_string(), port));
+    println!("REPLICAOF {host}:{port} enabled (user request)");
+    Ok(SimpleString::new("OK").encode())
+}
@@ -0,0 +1,429 @@
+use super::Reply;
+use crate::{
+    aof::Aof,
+    config::{Config, SetError},
+    rdb::Saves,
+    replication::Replication,
+    resp::{Array, BulkString, Integer, Map, NullBulkString, SimpleError, SimpleString},
+    store::Store,
+};
+use std::{
+    fmt::Write,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+// Sections reported when none are asked for
+const DEFAULT_SECTIONS: [&str; 5] = ["memory", "persistence", "stats", "replication", "keyspace"];
+
+// Sizes such as `1.50M`, as per `bytesToHuman` in Redis
+#[allow(clippy::cast_precision_loss)]
+fn to_human(bytes: usize) -> String {
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    for unit in ["K", "M", "G"] {
+        if size < 1024.0 {
+            return format!("{size:.2}{unit}");
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.2}T")
+}
+
+pub fn info(
+    store: &Store,
+    saves: &Saves,
+    aof: &Aof,
+    replication: &Replication,
+    sections: &[&str],
+) -> Reply {
+    let info = store.info();
+    let everything = sections.is_empty()
+        || sections.iter().any(|section| {
+            ["default", "all", "everything"]
+                .iter()
+                .any(|name| section.eq_ignore_ascii_case(name))
+        });
+
+    let mut reply = String::new();
+    for section in DEFAULT_SECTIONS {
+        if !everything
+            && !sections
+                .iter()
+                .any(|name| name.eq_ignore_ascii_case(section))
+        {
+            continue;
+        }
+        if !reply.is_empty() {
+            reply.push_str("\r\n");
+        }
+
+        match section {
+            "memory" => {
+                let _ = write!(
+                    reply,
+                    "# Memory\r\n\
+                     used_memory:{}\r\n\
+                     used_memory_human:{}\r\n\
+                     used_memory_peak:{}\r\n\
+                     used_memory_peak_human:{}\r\n\
+                     maxmemory:{}\r\n\
+                     maxmemory_human:{}\r\n\
+                     maxmemory_policy:{}\r\n",
+                    info.used_memory,
+                    to_human(info.used_memory),
+                    info.peak_memory,
+                    to_human(info.peak_memory),
+                    info.max_memory.limit,
+                    to_human(info.max_memory.limit),
+                    info.max_memory.policy.name(),
+                );
+            }
+            "persistence" => {
+                let _ = write!(
+                    reply,
+                    "# Persistence\r\n\
+                     loading:0\r\n\
+                     rdb_changes_since_last_save:{}\r\n\
+                     rdb_bgsave_in_progress:{}\r\n\
+                     rdb_last_save_time:{}\r\n\
+                     rdb_last_bgsave_status:{}\r\n\
+                     aof_enabled:{}\r\n\
+                     aof_rewrite_in_progress:{}\r\n\
+                     aof_last_bgrewrite_status:{}\r\n",
+                    store.dirty(),
+                    u8::from(saves.in_progress()),
+                    saves.last_save(),
+                    if saves.last_failed() { "err" } else { "ok" },
+                    u8::from(aof.is_on()),
+                    u8::from(aof.is_rewriting()),
+                    if aof.last_rewrite_failed() {
+                        "err"
+                    } else {
+                        "ok"
+                    },
+                );
+            }
+            "stats" => {
+                let stats = info.stats;
+                let _ = write!(
+                    reply,
+                    "# Stats\r\n\
+                     expired_keys:{}\r\n\
+                     expired_stale_perc:{:.2}\r\n\
+                     expired_time_cap_reached_count:{}\r\n\
+                     evicted_keys:{}\r\n",
+                    stats.expired_keys,
+                    stats.expired_stale_perc * 100.0,
+                    stats.expired_time_cap_reached_count,
+                    stats.evicted_keys,
+                );
+            }
+            "replication" => reply.push_str(&replication.info()),
+            _ => {
+                reply.push_str("# Keyspace\r\n");
+                for (index, db) in info.keyspace.iter().enumerate() {
+                    if db.keys > 0 {
+                        let _ = write!(
+                            reply,
+                            "db{index}:keys={},expires={},avg_ttl={}\r\n",
+                            db.keys, db.expires, db.average_ttl
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(BulkString::from(reply.as_bytes()).encode())
+}
+
+// Settings matching more than one pattern are only included once
+pub fn config_get(config: &RwLock<Config>, patterns: &[&str]) -> Reply {
+    let config = config.read().unwrap_or_else(PoisonError::into_inner);
+
+    let mut settings = Vec::new();
+    for pattern in patterns {
+        for setting in config.get(pattern) {
+            if !settings.contains(&setting) {
+                settings.push(setting);
+            }
+        }
+    }
+
+    let reply = settings
+        .iter()
+        .flat_map(|(name, value)| {
+            [
+                BulkString::from(name.as_bytes()).encode(),
+                BulkString::from(value.as_bytes()).encode(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    Ok(Array::from(reply).encode())
+}
+
+// Settings are changed all at once, or not at all if any are invalid
+pub fn config_set(
+    config: &RwLock<Config>,
+    store: &Arc<Store>,
+    aof: &Arc<Aof>,
+    replication: &Replication,
+    settings: &[(&str, &str)],
+) -> Reply {
+    let mut config = config.write().unwrap_or_else(PoisonError::into_inner);
+
+    let mut updated = config.clone();
+    for (index, (name, value)) in settings.iter().enumerate() {
+        let reason = if settings[..index]
+            .iter()
+            .any(|(previous, _)| previous.eq_ignore_ascii_case(name))
+        {
+            "duplicate parameter"
+        } else {
+            match updated.update(name, value) {
+                Ok(()) => continue,
+                Err(SetError::Invalid(reason)) => reason,
+                Err(SetError::Immutable) => "can't set immutable config",
+                Err(SetError::Unknown) => {
+                    let message = format!(
+                        "ERR Unknown option or number of arguments for CONFIG SET - '{name}'"
+                    );
+                    return Ok(SimpleError::from(message.as_str()).encode());
+                }
+            }
+        };
+
+        let message =
+            format!("ERR CONFIG SET failed (possibly related to argument '{name}') - {reason}");
+        return Ok(SimpleError::from(message.as_str()).encode());
+    }
+
+    *config = updated;
+    store.set_max_memory(config.max_memory);
+    replication.set_backlog_size(config.repl_backlog_size);
+    if let Err(error) = aof.configure(store, &config) {
+        eprintln!("{error:#}");
+        let message = format!("ERR CONFIG SET failed - {error}");
+        return Ok(SimpleError::from(message.as_str()).encode());
+    }
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+fn integer(value: usize) -> Vec<u8> {
+    Integer::from(i64::try_from(value).unwrap_or(i64::MAX)).encode()
+}
+
+pub fn swapdb(store: &Store, first: i64, second: i64) -> Reply {
+    let first = super::db_index(store, first)?;
+    let second = super::db_index(store, second)?;
+    store.swap_dbs(first, second);
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+pub fn flushdb(store: &Store, db: usize, lazy: bool) -> Reply {
+    store.flush(Some(db), lazy);
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+pub fn flushall(store: &Store, lazy: bool) -> Reply {
+    store.flush(None, lazy);
+
+    Ok(SimpleString::new("OK").encode())
+}
+
+pub fn dbsize(store: &Store, db: usize) -> Reply {
+    Ok(integer(store.read_all(db).len()))
+}
+
+pub fn save(store: &Store, saves: &Saves, config: &RwLock<Config>) -> Reply {
+    let path = config
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .rdb_path();
+    match saves.save(store, &path) {
+        Ok(true) => Ok(SimpleString::new("OK").encode()),
+        Ok(false) => Err(SimpleError::from("ERR Background save already in progress")),
+        Err(error) => {
+            eprintln!("{error:#}");
+            Err(SimpleError::from("ERR"))
+        }
+    }
+}
+
+// With `schedule`, a save already in progress is followed by another, rather than being an error
+pub fn bgsave(
+    store: &Arc<Store>,
+    saves: &Arc<Saves>,
+    config: &RwLock<Config>,
+    schedule: bool,
+) -> Reply {
+    let path = config
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .rdb_path();
+    if saves.background_save(store, path) {
+        Ok(SimpleString::new("Background saving started").encode())
+    } else if schedule {
+        saves.schedule();
+        Ok(SimpleString::new("Background saving scheduled").encode())
+    } else {
+        Err(SimpleError::from("ERR Background save already in progress"))
+    }
+}
+
+// BGREWRITEAOF
+pub fn bgrewriteaof(store: &Arc<Store>, aof: &Arc<Aof>) -> Reply {
+    match aof.rewrite(store) {
+        Ok(true) => Ok(SimpleString::new("Background append only file rewriting started").encode()),
+        Ok(false) => Err(SimpleError::from(
+            "ERR Background append only file rewriting already in progress",
+        )),
+        Err(error) => {
+            let message = format!("ERR {error}");
+            Ok(SimpleError::from(message.as_str()).encode())
+        }
+    }
+}
+
+pub fn memory_usage(store: &Store, db: usize, key: &str, samples: usize) -> Reply {
+    let keyspace = store.read(db, [key]);
+    Ok(keyspace
+        .memory_usage(key, samples)
+        .map_or_else(NullBulkString::encode, integer))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percentage(part: usize, total: usize) -> Vec<u8> {
+    let percentage = if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    };
+    BulkString::from(percentage.to_string().as_bytes()).encode()
+}
+
+// A subset of the fields reported by Redis, with pairs of names and values in a flat array
+pub fn memory_stats(store: &Store) -> Reply {
+    let info = store.info();
+    let keys = info.keys();
+
+    let mut stats = Vec::new();
+    let mut push = |name: &str, value| {
+        stats.push(BulkString::from(name.as_bytes()).encode());
+        stats.push(value);
+    };
+    push("peak.allocated", integer(info.peak_memory));
+    push("total.allocated", integer(info.used_memory));
+    push("startup.allocated", integer(0));
+
+    let mut overhead = 0;
+    for (index, db) in info.keyspace.iter().enumerate() {
+        if db.keys == 0 {
+            continue;
+        }
+
+        let (hashtable_main, hashtable_expires) = db.overhead();
+        overhead += hashtable_main + hashtable_expires;
+        let db = vec![
+            BulkString::from("overhead.hashtable.main".as_bytes()).encode(),
+            integer(hashtable_main),
+            BulkString::from("overhead.hashtable.expires".as_bytes()).encode(),
+            integer(hashtable_expires),
+        ];
+        push(&format!("db.{index}"), Array::from(db).encode());
+    }
+
+    let dataset = info.used_memory.saturating_sub(overhead);
+    push("overhead.total", integer(overhead));
+    push("keys.count", integer(keys));
+    push(
+        "keys.bytes-per-key",
+        integer(info.used_memory.checked_div(keys).unwrap_or(0)),
+    );
+    push("dataset.bytes", integer(dataset));
+    push("dataset.percentage", percentage(dataset, info.used_memory));
+    push(
+        "peak.percentage",
+        percentage(info.used_memory, info.peak_memory),
+    );
+
+    Ok(Array::from(stats).encode())
+}
+
+// Below this, there is too little data for the report to be useful, as in Redis
+const MEMORY_DOCTOR_MIN_USED: usize = 5 * 1024 * 1024;
+
+// Only the memory peak is checked for, as the allocator's fragmentation isn't known
+// See: https://github.com/redis/redis/blob/unstable/src/object.c (`getMemoryDoctorReport`)
+pub fn memory_doctor(store: &Store) -> Reply {
+    let used = store.used_memory();
+    let peak = store.peak_memory();
+
+    let report = if used < MEMORY_DOCTOR_MIN_USED {
+        "Hi Sam, this instance is empty or is using very little memory, my issues detector can't \
+         be used in these conditions. Please, leave for your mission on Earth and fill it with \
+         some data. The new Sam and I will be back to our programming as soon as I finished \
+         rebooting."
+    } else if peak.saturating_mul(2) > used.saturating_mul(3) {
+        "Sam, I detected a few issues in this Redis instance memory implants:\n\n \
+         * Peak memory: In the past this instance used more than 150% the memory that is \
+         currently using. The allocator is normally not able to release memory after a peak, so \
+         you can expect to see a big fragmentation ratio, however this is actually harmless and \
+         is only due to the memory peak, and if the Redis instance Resident Set Size (RSS) is \
+         currently bigger than expected, the memory will be used as soon as you fill the Redis \
+         instance with more data. If the memory peak was only occasional and you want to try to \
+         reclaim memory, the only option is to shutdown and restart the instance.\n\n\
+         I'm here to keep you safe, Sam. I want to help you.\n"
+    } else {
+        "Hi Sam, I can't find any memory issue in your instance. I can only account for what \
+         occurs on this base."
+    };
+
+    Ok(BulkString::from(report.as_bytes()).encode())
+}
+
+// The server's details, as a map in RESP3, or as an array of alternating fields and values
+pub fn hello(protocol: u8, mode: &str, role: &str) -> Reply {
+    let fields = [
+        ("server", BulkString::from(&b"redis"[..]).encode()),
+        (
+            "version",
+            BulkString::from(env!("CARGO_PKG_VERSION").as_bytes()).encode(),
+        ),
+        ("proto", Integer::from(i64::from(protocol)).encode()),
+        ("mode", BulkString::from(mode.as_bytes()).encode()),
+        ("role", BulkString::from(role.as_bytes()).encode()),
+        ("modules", Array::from(Vec::new()).encode()),
+    ]
+    .map(|(field, value)| (BulkString::from(field.as_bytes()).encode(), value));
+
+    if protocol == 3 {
+        return Ok(Map::from(fields.to_vec()).encode());
+    }
+    let fields = fields
+        .into_iter()
+        .flat_map(|(field, value)| [field, value])
+        .collect::<Vec<_>>();
+    Ok(Array::from(fields).encode())
+}
+
+pub fn memory_help() -> Reply {
+    super::help(&[
+        "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+        "DOCTOR",
+        "    Return memory problems reports.",
+        "STATS",
+        "    Return information about the memory usage of the server.",
+        "USAGE <key> [SAMPLES <count>]",
+        "    Return memory in bytes used by <key> and its value. Nested values are",
+        "    sampled up to <count> times (default: 5, 0 means sample all).",
+        "HELP",
+        "    Print this help.",
+    ])
+}
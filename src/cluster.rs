@@ -0,0 +1,1119 @@
+use crate::{
+    connection::Connection,
+    pubsub::PubSub,
+    replication::new_id,
+    resp::{Array, BulkString, Integer, RespType},
+    store,
+};
+use anyhow::{anyhow, bail, Result};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Nodes of a cluster share the keyspace between them, each serving the keys in the hash slots
+// assigned to it and redirecting clients to the node serving any other key. Nodes gossip over a
+// second port, the cluster bus, to find each other and learn which slots each serves.
+// See: https://redis.io/docs/latest/operate/oss_and_stack/reference/cluster-spec/
+
+pub const SLOTS: usize = 16384;
+// The cluster bus is on the port plus this, unless `cluster-port` is given
+const BUS_PORT_OFFSET: u16 = 10000;
+
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+// For connecting to, reading from and writing to the bus of other nodes
+const IO_TIMEOUT: Duration = Duration::from_millis(500);
+
+// The slot of a key, from the CRC16 of the key, or only of the part of the key between the first `{`
+// and the next `}`, if not empty, so that related keys can be kept in the same slot
+pub fn key_slot(key: &str) -> u16 {
+    let key = key.as_bytes();
+    let tag = key.iter().position(|&byte| byte == b'{').and_then(|start| {
+        let rest = &key[start + 1..];
+        let end = rest.iter().position(|&byte| byte == b'}')?;
+        (end > 0).then(|| &rest[..end])
+    });
+
+    #[allow(clippy::cast_possible_truncation)]
+    let slot = crc16(tag.unwrap_or(key)) % SLOTS as u16;
+    slot
+}
+
+// CRC16-CCITT, as in XMODEM
+// See: https://github.com/redis/redis/blob/unstable/src/crc16.c
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x1021
+            }
+        })
+    })
+}
+
+// Why a command for some keys is not run by this node, as the error to reply with
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refused {
+    CrossSlot,
+    Down,
+    // The slot and the address of the node serving it
+    Moved(u16, String),
+    // The slot is being migrated to that node, which any missing keys may already be on
+    Ask(u16, String),
+    // Some of the keys have been migrated, and some have not
+    TryAgain,
+}
+
+impl fmt::Display for Refused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CrossSlot => write!(f, "CROSSSLOT Keys in request don't hash to the same slot"),
+            Self::Down => write!(f, "CLUSTERDOWN The cluster is down"),
+            Self::Moved(slot, address) => write!(f, "MOVED {slot} {address}"),
+            Self::Ask(slot, address) => write!(f, "ASK {slot} {address}"),
+            Self::TryAgain => write!(f, "TRYAGAIN Multiple keys request during rehashing of slot"),
+        }
+    }
+}
+
+// The state CLUSTER SETSLOT moves a slot to, as a step of migrating it from one node to another
+pub enum SetSlot<'a> {
+    // On the node the slot is migrated from, with the ID of the node it is migrated to
+    Migrating(&'a str),
+    // On the node the slot is migrated to, with the ID of the node it is migrated from
+    Importing(&'a str),
+    // Once every key has been migrated, to assign the slot to the node with the ID
+    Node(&'a str),
+    Stable,
+}
+
+// The keys MIGRATE moves to another instance, and how
+pub struct Migrate<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub keys: Vec<&'a str>,
+    // The database on the other instance
+    pub db: i64,
+    pub timeout: Duration,
+    // Whether to keep the keys here too, and to replace any which already exist there
+    pub copy: bool,
+    pub replace: bool,
+    // The username, if any, and password to authenticate with
+    pub auth: Option<(Option<&'a str>, &'a str)>,
+}
+
+// A node of the cluster, as last heard from
+struct Node {
+    id: String,
+    host: String,
+    port: u16,
+    bus_port: u16,
+    // Met with CLUSTER MEET but yet to reply, so that its ID is made up until it does
+    handshake: bool,
+    // The epoch in which the node last claimed slots, which wins over claims of the same slots
+    // made in earlier epochs
+    config_epoch: u64,
+    // In milliseconds since the Unix epoch, when the oldest unanswered PING was sent, if any, and
+    // when the last PONG was received
+    ping_sent: u64,
+    pong_received: u64,
+    // Whether this node's link to it is up, and when it was last sent a PING
+    connected: bool,
+    last_ping: Option<Instant>,
+}
+
+impl Node {
+    const fn new(id: String, host: String, port: u16, bus_port: u16) -> Self {
+        Self {
+            id,
+            host,
+            port,
+            bus_port,
+            handshake: false,
+            config_epoch: 0,
+            ping_sent: 0,
+            pong_received: 0,
+            connected: false,
+            last_ping: None,
+        }
+    }
+
+    fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+// What nodes tell each other in each PING, PONG and MEET, about themselves, the slots they serve,
+// and the other nodes they know of, as per `clusterMsg`
+#[derive(Debug, PartialEq, Eq)]
+struct Message {
+    kind: String,
+    id: String,
+    host: String,
+    port: u16,
+    bus_port: u16,
+    current_epoch: u64,
+    config_epoch: u64,
+    slots: Vec<(u16, u16)>,
+    // The ID, host, port and bus port of each other node
+    gossip: Vec<(String, String, u16, u16)>,
+}
+
+impl Message {
+    // As a command, such as `PING <id> <host> <port> <bus port> <current epoch> <config epoch>
+    // <slots> [<gossip> ...]`, where slots are ranges such as `0-5460,5462`, and each gossip entry is
+    // `<id>,<host>,<port>,<bus port>`
+    fn encode(&self) -> Vec<String> {
+        let slots = self
+            .slots
+            .iter()
+            .map(|(start, end)| format_range(*start, *end))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut arguments = vec![
+            self.kind.clone(),
+            self.id.clone(),
+            self.host.clone(),
+            self.port.to_string(),
+            self.bus_port.to_string(),
+            self.current_epoch.to_string(),
+            self.config_epoch.to_string(),
+            slots,
+        ];
+        arguments.extend(
+            self.gossip
+                .iter()
+                .map(|(id, host, port, bus_port)| format!("{id},{host},{port},{bus_port}")),
+        );
+        arguments
+    }
+
+    fn parse(request: &[u8]) -> Result<Self> {
+        let RespType::Array(arguments) = RespType::try_from(request)? else {
+            bail!("message is not an array");
+        };
+        let arguments = arguments
+            .into_iter()
+            .map(|argument| match argument {
+                RespType::BulkString(argument) => argument.as_string(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("message is not made up of strings"))?;
+        let [kind, id, host, port, bus_port, current_epoch, config_epoch, slots, gossip @ ..] =
+            &arguments[..]
+        else {
+            bail!("message is missing fields");
+        };
+
+        let slots = slots
+            .split(',')
+            .filter(|range| !range.is_empty())
+            .map(|range| {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                Ok((start.parse()?, end.parse()?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let gossip = gossip
+            .iter()
+            .map(|node| {
+                let &[id, host, port, bus_port] = &node.split(',').collect::<Vec<_>>()[..] else {
+                    bail!("invalid gossip {node:?}");
+                };
+                Ok((
+                    id.to_string(),
+                    host.to_string(),
+                    port.parse()?,
+                    bus_port.parse()?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            kind: kind.to_string(),
+            id: id.to_string(),
+            host: host.to_string(),
+            port: port.parse()?,
+            bus_port: bus_port.parse()?,
+            current_epoch: current_epoch.parse()?,
+            config_epoch: config_epoch.parse()?,
+            slots,
+            gossip,
+        })
+    }
+}
+
+fn format_range(start: u16, end: u16) -> String {
+    if start == end {
+        start.to_string()
+    } else {
+        format!("{start}-{end}")
+    }
+}
+
+struct State {
+    // Increases whenever a node needs a config epoch greater than any other
+    current_epoch: u64,
+    // Including this node, first
+    nodes: Vec<Node>,
+    // The ID of the node serving each slot
+    owners: Vec<Option<String>>,
+    assigned: usize,
+    // Slots of this node being migrated to another, by the ID of that node, for which keys which
+    // are no longer here are redirected to it with ASK
+    migrating: HashMap<u16, String>,
+    // Slots of other nodes being migrated to this one, by the ID of that node, for which commands
+    // are only run here after ASKING
+    importing: HashMap<u16, String>,
+    // Slots this node has stopped serving, the shard channels of which are yet to be dropped
+    lost: Vec<u16>,
+}
+
+impl State {
+    fn node(&self, id: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    fn node_mut(&mut self, id: &str) -> Option<&mut Node> {
+        self.nodes.iter_mut().find(|node| node.id == id)
+    }
+
+    fn assign(&mut self, slot: u16, id: &str) {
+        let owner = &mut self.owners[usize::from(slot)];
+        match owner {
+            None => self.assigned += 1,
+            Some(owner) if *owner == self.nodes[0].id && owner != id => self.lost.push(slot),
+            Some(_) => {}
+        }
+        *owner = Some(id.to_string());
+    }
+
+    // The ranges of slots served by each node, in order of the first slot of each range
+    fn ranges(&self) -> Vec<(u16, u16, &str)> {
+        let mut ranges: Vec<(u16, u16, &str)> = Vec::new();
+        for (slot, owner) in self.owners.iter().enumerate() {
+            let Some(owner) = owner else {
+                continue;
+            };
+            #[allow(clippy::cast_possible_truncation)]
+            let slot = slot as u16;
+            match ranges.last_mut() {
+                Some((_, end, id)) if *end + 1 == slot && *id == owner => *end = slot,
+                _ => ranges.push((slot, slot, owner)),
+            }
+        }
+        ranges
+    }
+
+    fn slots_of(&self, id: &str) -> Vec<(u16, u16)> {
+        self.ranges()
+            .into_iter()
+            .filter(|(_, _, owner)| *owner == id)
+            .map(|(start, end, _)| (start, end))
+            .collect()
+    }
+
+    fn message(&self, kind: &str) -> Message {
+        let myself = &self.nodes[0];
+        Message {
+            kind: kind.to_string(),
+            id: myself.id.clone(),
+            host: myself.host.clone(),
+            port: myself.port,
+            bus_port: myself.bus_port,
+            current_epoch: self.current_epoch,
+            config_epoch: myself.config_epoch,
+            slots: self.slots_of(&myself.id),
+            gossip: self.nodes[1..]
+                .iter()
+                .filter(|node| !node.handshake)
+                .map(|node| (node.id.clone(), node.host.clone(), node.port, node.bus_port))
+                .collect(),
+        }
+    }
+
+    // Takes in a message from another node, either one sent to this node, or the reply to one this
+    // node sent to `to`
+    fn receive(&mut self, message: &Message, to: Option<&str>) {
+        let myself = self.nodes[0].id.clone();
+        if message.id == myself {
+            return;
+        }
+
+        // A node met with CLUSTER MEET is known by its ID from its first reply
+        if let Some(to) = to.filter(|to| *to != message.id) {
+            let Some(index) = self.nodes.iter().position(|node| node.id == to) else {
+                return;
+            };
+            if !self.nodes[index].handshake {
+                return;
+            }
+            if self.node(&message.id).is_some() {
+                self.nodes.remove(index);
+            } else {
+                let node = &mut self.nodes[index];
+                println!(
+                    "handshake with {} completed, as {}",
+                    node.address(),
+                    message.id
+                );
+                node.id.clone_from(&message.id);
+                node.handshake = false;
+            }
+        }
+        if self.node(&message.id).is_none() {
+            // Only nodes being met, or which this node already knows of, are listened to
+            if message.kind != "MEET" {
+                return;
+            }
+            let node = Node::new(
+                message.id.clone(),
+                message.host.clone(),
+                message.port,
+                message.bus_port,
+            );
+            println!("met node {} at {}", message.id, node.address());
+            self.nodes.push(node);
+        }
+
+        self.current_epoch = self.current_epoch.max(message.current_epoch);
+        let node = self.node_mut(&message.id).expect("node is known");
+        node.host.clone_from(&message.host);
+        (node.port, node.bus_port) = (message.port, message.bus_port);
+        node.config_epoch = message.config_epoch;
+
+        // Claims of slots win over those of nodes with an earlier config epoch
+        for &(start, end) in &message.slots {
+            for slot in start..=end.min(SLOTS as u16 - 1) {
+                let claimed = match &self.owners[usize::from(slot)] {
+                    Some(owner) if *owner == message.id => false,
+                    Some(owner) => {
+                        let epoch = self.node(owner).map_or(0, |node| node.config_epoch);
+                        message.config_epoch > epoch
+                    }
+                    None => true,
+                };
+                if claimed {
+                    self.assign(slot, &message.id);
+                }
+            }
+        }
+
+        // Nodes with the same config epoch would never win each other's slots, so the one with the
+        // lower ID moves on to a new epoch, as per `clusterHandleConfigEpochCollision`
+        let me = &self.nodes[0];
+        if message.config_epoch == me.config_epoch && message.id > me.id {
+            self.current_epoch += 1;
+            self.nodes[0].config_epoch = self.current_epoch;
+        }
+
+        for (id, host, port, bus_port) in &message.gossip {
+            if *id != myself && self.node(id).is_none() {
+                println!("learned of node {id} at {host}:{port} from {}", message.id);
+                let node = Node::new(id.clone(), host.clone(), *port, *bus_port);
+                self.nodes.push(node);
+            }
+        }
+    }
+
+    // Moves this node to a new config epoch, unless its own is already the greatest, so that slots
+    // it takes over win over earlier claims, without an agreement from the other nodes, as per
+    // `clusterBumpConfigEpochWithoutConsensus`
+    fn bump_epoch(&mut self) {
+        let greatest = self.nodes[1..]
+            .iter()
+            .map(|node| node.config_epoch)
+            .fold(self.current_epoch, u64::max);
+        let myself = &mut self.nodes[0];
+        if myself.config_epoch == 0 || myself.config_epoch != greatest {
+            self.current_epoch = greatest + 1;
+            myself.config_epoch = self.current_epoch;
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        self.assigned == SLOTS
+    }
+
+    // Flagged as `fail?` once it has been waiting on a reply to PING for too long
+    fn is_failing(node: &Node, timeout: Duration) -> bool {
+        let timeout = u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX);
+        node.ping_sent > 0 && store::now().saturating_sub(node.ping_sent) > timeout
+    }
+}
+
+pub struct Cluster {
+    listener: TcpListener,
+    node_timeout: Duration,
+    state: Mutex<State>,
+    // For shard channels to only have subscribers on the node serving their slot
+    pubsub: Arc<PubSub>,
+}
+
+impl Cluster {
+    // Listens on the cluster bus, as a node which knows of no others and serves no slots yet
+    #[allow(clippy::missing_errors_doc)]
+    pub fn bind(
+        host: &str,
+        port: u16,
+        bus_port: u16,
+        node_timeout: Duration,
+        pubsub: Arc<PubSub>,
+    ) -> Result<Self> {
+        let bus_port = match bus_port {
+            0 => port
+                .checked_add(BUS_PORT_OFFSET)
+                .ok_or_else(|| anyhow!("port {port} is too high for a cluster bus port"))?,
+            bus_port => bus_port,
+        };
+        let listener = TcpListener::bind((host, bus_port))?;
+        let myself = Node::new(new_id(), host.to_string(), port, bus_port);
+
+        Ok(Self {
+            listener,
+            node_timeout,
+            state: Mutex::new(State {
+                current_epoch: 0,
+                nodes: vec![myself],
+                owners: vec![None; SLOTS],
+                assigned: 0,
+                migrating: HashMap::new(),
+                importing: HashMap::new(),
+                lost: Vec::new(),
+            }),
+            pubsub,
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Unsubscribes clients from the shard channels of slots now served by other nodes, as Redis
+    // does whenever a slot is taken over
+    fn drop_lost(&self, state: &mut State) {
+        for slot in state.lost.drain(..) {
+            self.pubsub.unsubscribe_slot(slot);
+        }
+    }
+
+    // Accepts connections from other nodes on the bus, and pings them all from another thread
+    pub fn start(self: &Arc<Self>) {
+        let cluster = Arc::clone(self);
+        thread::spawn(move || loop {
+            let Ok((stream, _)) = cluster.listener.accept() else {
+                continue;
+            };
+            let cluster = Arc::clone(&cluster);
+            thread::spawn(move || {
+                if let Err(error) = cluster.serve(stream) {
+                    eprintln!("cluster bus error: {error:#}");
+                }
+            });
+        });
+
+        let cluster = Arc::clone(self);
+        thread::spawn(move || cluster.cron());
+    }
+
+    // Replies to each message from another node with a PONG
+    fn serve(&self, stream: TcpStream) -> Result<()> {
+        let mut connection = Connection::new(stream);
+        while let Some(request) = connection.read_request()? {
+            let message = Message::parse(&request)?;
+            let mut state = self.state();
+            state.receive(&message, None);
+            self.drop_lost(&mut state);
+            let reply = state.message("PONG").encode();
+            drop(state);
+
+            let reply = reply.iter().map(String::as_bytes).collect::<Vec<_>>();
+            connection.send(&reply)?;
+        }
+
+        Ok(())
+    }
+
+    // Pings every other node each `PING_INTERVAL`, or sends MEET to those being met, never holding
+    // the state while waiting on other nodes
+    fn cron(&self) {
+        let mut links = HashMap::<(String, u16), Connection>::new();
+        loop {
+            thread::sleep(CRON_INTERVAL);
+            let now = Instant::now();
+            let mut state = self.state();
+            let timeout = self.node_timeout;
+            // Nodes which never reply to MEET are forgotten
+            state
+                .nodes
+                .retain(|node| !(node.handshake && State::is_failing(node, timeout)));
+            let mut due = Vec::new();
+            for node in &mut state.nodes[1..] {
+                if node
+                    .last_ping
+                    .is_some_and(|last| now.saturating_duration_since(last) < PING_INTERVAL)
+                {
+                    continue;
+                }
+                node.last_ping = Some(now);
+                if node.ping_sent == 0 {
+                    node.ping_sent = store::now();
+                }
+                let kind = if node.handshake { "MEET" } else { "PING" };
+                due.push((node.id.clone(), (node.host.clone(), node.bus_port), kind));
+            }
+            let messages = due
+                .into_iter()
+                .map(|(id, address, kind)| (id, address, state.message(kind).encode()))
+                .collect::<Vec<_>>();
+            drop(state);
+
+            for (id, address, message) in messages {
+                let reply =
+                    send(&mut links, &address, &message).and_then(|reply| Message::parse(&reply));
+                let mut state = self.state();
+                match reply {
+                    Ok(reply) => {
+                        let receiver = reply.id.clone();
+                        state.receive(&reply, Some(&id));
+                        self.drop_lost(&mut state);
+                        if let Some(node) = state.node_mut(&receiver) {
+                            node.ping_sent = 0;
+                            node.pong_received = store::now();
+                            node.connected = true;
+                        }
+                    }
+                    Err(_) => {
+                        if let Some(node) = state.node_mut(&id) {
+                            node.connected = false;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Whether this node serves the keys, all of which must be in the same slot. Keys of a slot
+    // being migrated are only served while they are still here, as given by `exists`, and those
+    // of a slot being imported only once the client is `asking`, as per `getNodeByQuery`.
+    #[allow(clippy::missing_errors_doc)]
+    pub fn route(
+        &self,
+        keys: &[&str],
+        asking: bool,
+        exists: impl Fn(&str) -> bool,
+    ) -> Result<(), Refused> {
+        let Some(slot) = keys.first().map(|key| key_slot(key)) else {
+            return Ok(());
+        };
+        if keys.iter().any(|key| key_slot(key) != slot) {
+            return Err(Refused::CrossSlot);
+        }
+
+        let state = self.state();
+        if !state.is_ok() {
+            return Err(Refused::Down);
+        }
+        let Some(owner) = &state.owners[usize::from(slot)] else {
+            return Err(Refused::Down);
+        };
+        let missing = || keys.iter().filter(|key| !exists(key)).count();
+        if *owner != state.nodes[0].id {
+            if asking && state.importing.contains_key(&slot) {
+                return match missing() {
+                    missing if missing > 0 && keys.len() > 1 => Err(Refused::TryAgain),
+                    _ => Ok(()),
+                };
+            }
+            let address = state.node(owner).map_or_else(String::new, Node::address);
+            return Err(Refused::Moved(slot, address));
+        }
+        if let Some(target) = state.migrating.get(&slot) {
+            match missing() {
+                0 => {}
+                missing if missing == keys.len() => {
+                    let address = state.node(target).map_or_else(String::new, Node::address);
+                    return Err(Refused::Ask(slot, address));
+                }
+                _ => return Err(Refused::TryAgain),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn myid(&self) -> String {
+        self.state().nodes[0].id.clone()
+    }
+
+    // Assigns the slots to this node, unless any are already served by a node
+    #[allow(clippy::missing_errors_doc)]
+    pub fn add_slots(&self, slots: &[u16]) -> Result<(), u16> {
+        let mut state = self.state();
+        if let Some(slot) = slots
+            .iter()
+            .find(|slot| state.owners[usize::from(**slot)].is_some())
+        {
+            return Err(*slot);
+        }
+
+        let myself = state.nodes[0].id.clone();
+        for slot in slots {
+            state.assign(*slot, &myself);
+        }
+        Ok(())
+    }
+
+    // Moves a slot along its migration, as per CLUSTER SETSLOT, where `has_keys` is whether any keys
+    // of the slot are still here
+    #[allow(clippy::missing_errors_doc)]
+    pub fn set_slot(&self, slot: u16, to: &SetSlot, has_keys: bool) -> Result<(), String> {
+        let mut state = self.state();
+        let myself = state.nodes[0].id.clone();
+        let owner = state.owners[usize::from(slot)].clone();
+        let known = match *to {
+            SetSlot::Migrating(id) | SetSlot::Importing(id) | SetSlot::Node(id) => {
+                state.node(id).is_some_and(|node| !node.handshake)
+            }
+            SetSlot::Stable => true,
+        };
+        match *to {
+            SetSlot::Migrating(id) => {
+                if owner.as_deref() != Some(myself.as_str()) {
+                    return Err(format!("ERR I'm not the owner of hash slot {slot}"));
+                }
+                if !known {
+                    return Err(format!("ERR I don't know about node {id}"));
+                }
+                if id == myself {
+                    return Err("ERR Can't MIGRATE to myself".to_string());
+                }
+                state.migrating.insert(slot, id.to_string());
+            }
+            SetSlot::Importing(id) => {
+                if owner.as_deref() == Some(myself.as_str()) {
+                    return Err(format!("ERR I'm already the owner of hash slot {slot}"));
+                }
+                if !known {
+                    return Err(format!("ERR I don't know about node {id}"));
+                }
+                if id == myself {
+                    return Err("ERR Can't IMPORT from myself".to_string());
+                }
+                state.importing.insert(slot, id.to_string());
+            }
+            SetSlot::Node(id) => {
+                if !known {
+                    return Err(format!("ERR Unknown node {id}"));
+                }
+                if owner.as_deref() == Some(myself.as_str()) && id != myself && has_keys {
+                    return Err(format!(
+                        "ERR Can't assign hashslot {slot} to a different node while I still hold \
+                         keys for this hash slot."
+                    ));
+                }
+                if id != myself {
+                    state.migrating.remove(&slot);
+                }
+                // The node which imported the slot claims it in a new epoch, for the other nodes
+                // to take its claim over that of the node it was migrated from
+                if id == myself && state.importing.remove(&slot).is_some() {
+                    state.bump_epoch();
+                }
+                state.assign(slot, id);
+                self.drop_lost(&mut state);
+            }
+            SetSlot::Stable => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Starts a handshake with the node at the address, which is then told about this node and the
+    // others it knows, and the other way around
+    pub fn meet(&self, host: &str, port: u16, bus_port: Option<u16>) {
+        let mut state = self.state();
+        let bus_port = bus_port.unwrap_or_else(|| port.saturating_add(BUS_PORT_OFFSET));
+        let known = state
+            .nodes
+            .iter()
+            .any(|node| node.host == host && node.port == port && node.bus_port == bus_port);
+        if !known {
+            let mut node = Node::new(new_id(), host.to_string(), port, bus_port);
+            node.handshake = true;
+            node.ping_sent = store::now();
+            state.nodes.push(node);
+        }
+    }
+
+    // As per CLUSTER SLOTS, each range of slots with the node serving it
+    pub fn slots(&self) -> Vec<u8> {
+        let state = self.state();
+        let ranges = state
+            .ranges()
+            .into_iter()
+            .filter_map(|(start, end, owner)| {
+                let node = state.node(owner)?;
+                let node = Array::from(vec![
+                    BulkString::from(node.host.as_bytes()).encode(),
+                    Integer::from(i64::from(node.port)).encode(),
+                    BulkString::from(node.id.as_bytes()).encode(),
+                ]);
+                Some(
+                    Array::from(vec![
+                        Integer::from(i64::from(start)).encode(),
+                        Integer::from(i64::from(end)).encode(),
+                        node.encode(),
+                    ])
+                    .encode(),
+                )
+            })
+            .collect::<Vec<_>>();
+        Array::from(ranges).encode()
+    }
+
+    // As per CLUSTER SHARDS, each node with the slots it serves, as there are no replicas
+    pub fn shards(&self) -> Vec<u8> {
+        let state = self.state();
+        let shards = state
+            .nodes
+            .iter()
+            .filter(|node| !node.handshake)
+            .map(|node| {
+                let slots = state
+                    .slots_of(&node.id)
+                    .into_iter()
+                    .flat_map(|(start, end)| [start, end])
+                    .map(|slot| Integer::from(i64::from(slot)).encode())
+                    .collect::<Vec<_>>();
+                let health = if State::is_failing(node, self.node_timeout) {
+                    "fail"
+                } else {
+                    "online"
+                };
+                let fields: [(&str, Vec<u8>); 7] = [
+                    ("id", BulkString::from(node.id.as_bytes()).encode()),
+                    ("port", Integer::from(i64::from(node.port)).encode()),
+                    ("ip", BulkString::from(node.host.as_bytes()).encode()),
+                    ("endpoint", BulkString::from(node.host.as_bytes()).encode()),
+                    ("role", BulkString::from(&b"master"[..]).encode()),
+                    ("replication-offset", Integer::from(0).encode()),
+                    ("health", BulkString::from(health.as_bytes()).encode()),
+                ];
+                let fields = fields
+                    .into_iter()
+                    .flat_map(|(name, value)| [BulkString::from(name.as_bytes()).encode(), value])
+                    .collect::<Vec<_>>();
+                Array::from(vec![
+                    BulkString::from(&b"slots"[..]).encode(),
+                    Array::from(slots).encode(),
+                    BulkString::from(&b"nodes"[..]).encode(),
+                    Array::from(vec![Array::from(fields).encode()]).encode(),
+                ])
+                .encode()
+            })
+            .collect::<Vec<_>>();
+        Array::from(shards).encode()
+    }
+
+    // As per CLUSTER NODES, a line for each node, such as
+    // `<id> <ip:port@cport> <flags> <master> <ping-sent> <pong-recv> <config-epoch> <link-state>
+    // <slot> <slot> ... <slot>`
+    pub fn nodes(&self) -> String {
+        let state = self.state();
+        let mut nodes = String::new();
+        for (index, node) in state.nodes.iter().enumerate() {
+            let mut flags = vec![];
+            if index == 0 {
+                flags.push("myself");
+            }
+            flags.push("master");
+            if node.handshake {
+                flags.push("handshake");
+            }
+            if index > 0 && State::is_failing(node, self.node_timeout) {
+                flags.push("fail?");
+            }
+            let connected = index == 0 || node.connected;
+            let _ = write!(
+                nodes,
+                "{} {}:{}@{} {} - {} {} {} {}",
+                node.id,
+                node.host,
+                node.port,
+                node.bus_port,
+                flags.join(","),
+                node.ping_sent,
+                node.pong_received,
+                node.config_epoch,
+                if connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                },
+            );
+            for (start, end) in state.slots_of(&node.id) {
+                let _ = write!(nodes, " {}", format_range(start, end));
+            }
+            // Slots being migrated are listed for this node only
+            if index == 0 {
+                let mut migrating = state.migrating.iter().collect::<Vec<_>>();
+                migrating.sort_unstable();
+                for (slot, id) in migrating {
+                    let _ = write!(nodes, " [{slot}->-{id}]");
+                }
+                let mut importing = state.importing.iter().collect::<Vec<_>>();
+                importing.sort_unstable();
+                for (slot, id) in importing {
+                    let _ = write!(nodes, " [{slot}-<-{id}]");
+                }
+            }
+            nodes.push('\n');
+        }
+        nodes
+    }
+
+    // As per CLUSTER INFO
+    pub fn info(&self) -> String {
+        let state = self.state();
+        let failing = state
+            .nodes
+            .iter()
+            .skip(1)
+            .filter(|node| State::is_failing(node, self.node_timeout))
+            .map(|node| node.id.as_str())
+            .collect::<Vec<_>>();
+        let pfail = state
+            .owners
+            .iter()
+            .flatten()
+            .filter(|owner| failing.contains(&owner.as_str()))
+            .count();
+        let size = state
+            .nodes
+            .iter()
+            .filter(|node| !state.slots_of(&node.id).is_empty())
+            .count();
+        format!(
+            "cluster_enabled:1\r\n\
+             cluster_state:{}\r\n\
+             cluster_slots_assigned:{}\r\n\
+             cluster_slots_ok:{}\r\n\
+             cluster_slots_pfail:{pfail}\r\n\
+             cluster_slots_fail:0\r\n\
+             cluster_known_nodes:{}\r\n\
+             cluster_size:{size}\r\n\
+             cluster_current_epoch:{}\r\n\
+             cluster_my_epoch:{}\r\n",
+            if state.is_ok() { "ok" } else { "fail" },
+            state.assigned,
+            state.assigned - pfail,
+            state.nodes.len(),
+            state.current_epoch,
+            state.nodes[0].config_epoch,
+        )
+    }
+}
+
+// Sends a message to the bus of another node, returning its reply. Connections are kept open
+// between messages, and dropped on any error to be connected again next time.
+fn send(
+    links: &mut HashMap<(String, u16), Connection>,
+    address: &(String, u16),
+    message: &[String],
+) -> Result<Vec<u8>> {
+    let mut connection = match links.remove(address) {
+        Some(connection) => connection,
+        None => {
+            let (host, port) = address;
+            let Some(socket) = (host.as_str(), *port).to_socket_addrs()?.next() else {
+                bail!("no address for {host}:{port}");
+            };
+            let stream = TcpStream::connect_timeout(&socket, IO_TIMEOUT)?;
+            stream.set_read_timeout(Some(IO_TIMEOUT))?;
+            stream.set_write_timeout(Some(IO_TIMEOUT))?;
+            Connection::new(stream)
+        }
+    };
+
+    let message = message.iter().map(String::as_bytes).collect::<Vec<_>>();
+    connection.send(&message)?;
+    let Some(reply) = connection.read_request()? else {
+        bail!("connection closed");
+    };
+    links.insert(address.clone(), connection);
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pubsub::{Kind, Subscription};
+
+    fn state(id: &str) -> State {
+        State {
+            current_epoch: 0,
+            nodes: vec![Node::new(id.to_string(), "127.0.0.1".into(), 7000, 17000)],
+            owners: vec![None; SLOTS],
+            assigned: 0,
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            lost: Vec::new(),
+        }
+    }
+
+    // A node, as `a`, serving every slot but that of `{b}`, which is served by `b`
+    fn cluster() -> Cluster {
+        let mut state = state("a");
+        let mut b = Node::new("b".into(), "127.0.0.1".into(), 7001, 17001);
+        b.config_epoch = 1;
+        state.nodes.push(b);
+        for slot in 0..SLOTS as u16 {
+            let owner = if slot == key_slot("{b}") { "b" } else { "a" };
+            state.assign(slot, owner);
+        }
+        Cluster {
+            listener: TcpListener::bind("127.0.0.1:0").unwrap(),
+            node_timeout: Duration::from_secs(15),
+            state: Mutex::new(state),
+            pubsub: Arc::new(PubSub::new()),
+        }
+    }
+
+    #[test]
+    fn slots() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(key_slot("foo"), 12182);
+        assert_eq!(key_slot("bar"), 5061);
+        assert_eq!(key_slot("{user1000}.following"), key_slot("user1000"));
+        assert_eq!(key_slot("{user1000}.followers"), key_slot("user1000"));
+        // Empty tags are not tags
+        assert_eq!(key_slot("foo{}{bar}"), key_slot("foo{}{bar}"));
+        assert_ne!(key_slot("foo{}{bar}"), key_slot("bar"));
+        assert_eq!(key_slot("foo{{bar}}zap"), key_slot("{bar"));
+    }
+
+    #[test]
+    fn message_round_trip() {
+        let mut state = state("a");
+        state.assign(0, "a");
+        state.assign(1, "a");
+        state.assign(5, "a");
+        state
+            .nodes
+            .push(Node::new("b".into(), "127.0.0.1".into(), 7001, 17001));
+        let message = state.message("PING");
+        assert_eq!(message.slots, [(0, 1), (5, 5)]);
+
+        let encoded = message.encode();
+        let arguments = encoded.iter().map(String::as_bytes).collect::<Vec<_>>();
+        let request = crate::resp::command(&arguments);
+        assert_eq!(Message::parse(&request).unwrap(), message);
+    }
+
+    #[test]
+    fn gossip() {
+        let mut a = state("a");
+        let mut b = state("b");
+        b.assign(100, "b");
+
+        // Nodes only listen to unknown nodes which are meeting them
+        a.receive(&b.message("PING"), None);
+        assert_eq!(a.nodes.len(), 1);
+        a.receive(&b.message("MEET"), None);
+        assert_eq!(a.nodes.len(), 2);
+        assert_eq!(a.owners[100].as_deref(), Some("b"));
+        // Which moves the node with the lower ID to a new epoch
+        assert_eq!(a.nodes[0].config_epoch, 1);
+
+        // Slots go to the node claiming them in the later config epoch
+        a.assign(200, "a");
+        b.assign(200, "b");
+        b.receive(&a.message("MEET"), None);
+        assert_eq!(b.owners[200].as_deref(), Some("a"));
+        a.receive(&b.message("PING"), None);
+        assert_eq!(a.owners[200].as_deref(), Some("a"));
+
+        // Other nodes are learned of from gossip
+        let c = state("c");
+        b.receive(&c.message("MEET"), None);
+        a.receive(&b.message("PING"), None);
+        assert!(a.node("c").is_some());
+    }
+
+    #[test]
+    fn route() {
+        let cluster = cluster();
+        let exists = |key: &str| key.ends_with("here");
+        assert_eq!(cluster.route(&[], false, exists), Ok(()));
+        assert_eq!(cluster.route(&["{a}"], false, exists), Ok(()));
+        assert_eq!(
+            cluster.route(&["{a}", "{c}"], false, exists),
+            Err(Refused::CrossSlot)
+        );
+        let slot = key_slot("{b}");
+        let moved = Refused::Moved(slot, "127.0.0.1:7001".into());
+        assert_eq!(cluster.route(&["{b}"], false, exists), Err(moved));
+
+        cluster.state().owners[0] = None;
+        cluster.state().assigned -= 1;
+        assert_eq!(cluster.route(&["{a}"], false, exists), Err(Refused::Down));
+    }
+
+    #[test]
+    fn migration() {
+        let cluster = cluster();
+        let (a, b) = (key_slot("{a}"), key_slot("{b}"));
+        let exists = |key: &str| key.ends_with("here");
+        let ask = |slot| Err(Refused::Ask(slot, "127.0.0.1:7001".into()));
+
+        // Keys still here are served, while missing keys are asked for on the node importing them
+        assert!(cluster
+            .set_slot(b, &SetSlot::Migrating("b"), false)
+            .is_err());
+        assert!(cluster
+            .set_slot(a, &SetSlot::Migrating("c"), false)
+            .is_err());
+        assert_eq!(cluster.set_slot(a, &SetSlot::Migrating("b"), true), Ok(()));
+        assert_eq!(cluster.route(&["{a}here"], false, exists), Ok(()));
+        assert_eq!(cluster.route(&["{a}gone"], false, exists), ask(a));
+        let keys = ["{a}here", "{a}gone"];
+        assert_eq!(cluster.route(&keys, false, exists), Err(Refused::TryAgain));
+        assert!(cluster.nodes().contains(&format!("[{a}->-b]")));
+
+        // Only once every key is gone can the slot be given away, along with its shard channels
+        let subscription = Subscription::new(&cluster.pubsub, false);
+        subscription.subscribe(Kind::Shard, &["{a}news"]);
+        assert!(cluster.set_slot(a, &SetSlot::Node("b"), true).is_err());
+        assert_eq!(cluster.set_slot(a, &SetSlot::Node("b"), false), Ok(()));
+        assert_eq!(subscription.count(), 0);
+        assert_eq!(cluster.pubsub.publish_shard("{a}news", b"hi"), 0);
+        let moved = Refused::Moved(a, "127.0.0.1:7001".into());
+        assert_eq!(cluster.route(&["{a}here"], false, exists), Err(moved));
+
+        // The importing node serves the slot only after ASKING, and claims it in a new epoch
+        assert!(cluster
+            .set_slot(a + 1, &SetSlot::Importing("b"), false)
+            .is_err());
+        assert_eq!(cluster.set_slot(b, &SetSlot::Importing("b"), false), Ok(()));
+        assert!(cluster.route(&["{b}gone"], false, exists).is_err());
+        assert_eq!(cluster.route(&["{b}gone"], true, exists), Ok(()));
+        let keys = ["{b}here", "{b}gone"];
+        assert_eq!(cluster.route(&keys, true, exists), Err(Refused::TryAgain));
+        assert_eq!(cluster.set_slot(b, &SetSlot::Node("a"), false), Ok(()));
+        assert_eq!(cluster.route(&["{b}gone"], false, exists), Ok(()));
+        let state = cluster.state();
+        assert!(state.importing.is_empty() && state.migrating.is_empty());
+        assert_eq!(state.nodes[0].config_epoch, 2);
+    }
+}
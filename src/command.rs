@@ -1,11 +1,260 @@
-use crate::resp::{BulkString, RespType};
-use std::time::Duration;
+mod bitmap;
+mod cluster;
+mod expire;
+mod generic;
+mod geo;
+mod hyperloglog;
+mod pubsub;
+mod replication;
+mod sentinel;
+mod server;
+
+use crate::{
+    bitmap::{Operation, Range, Subcommand},
+    cluster::{Migrate, SetSlot},
+    geo::{Search, Unit},
+    rdb::Restore,
+    resp::{BulkString, RespType},
+    sorted_set::Condition,
+    store::ExpireCondition,
+};
+use std::{collections::VecDeque, time::Duration};
+
+type Arguments<'a> = VecDeque<RespType<'a>>;
 
 pub enum Command<'a> {
     Ping,
     Echo(BulkString<'a>),
-    Set(BulkString<'a>, BulkString<'a>, Option<Duration>),
-    Get(BulkString<'a>),
+    Set(&'a str, BulkString<'a>, Option<Duration>),
+    Get(&'a str),
+    SetBit(&'a str, u64, bool),
+    GetBit(&'a str, u64),
+    BitCount(&'a str, Option<Range>),
+    BitPos(&'a str, bool, Option<Range>),
+    BitOp(Operation, &'a str, Vec<&'a str>),
+    BitField(&'a str, Vec<Subcommand>),
+    BitFieldRo(&'a str, Vec<Subcommand>),
+    PfAdd(&'a str, Vec<BulkString<'a>>),
+    PfCount(Vec<&'a str>),
+    PfMerge(&'a str, Vec<&'a str>),
+    GeoAdd(
+        &'a str,
+        Option<Condition>,
+        bool,
+        Vec<(f64, f64, BulkString<'a>)>,
+    ),
+    GeoPos(&'a str, Vec<BulkString<'a>>),
+    GeoDist(&'a str, BulkString<'a>, BulkString<'a>, Unit),
+    GeoHash(&'a str, Vec<BulkString<'a>>),
+    GeoSearch(&'a str, Search<'a>),
+    GeoSearchStore(&'a str, &'a str, Search<'a>),
+    Del(Vec<&'a str>),
+    Unlink(Vec<&'a str>),
+    Exists(Vec<&'a str>),
+    Type(&'a str),
+    Rename(&'a str, &'a str),
+    RenameNx(&'a str, &'a str),
+    Move(&'a str, i64),
+    RandomKey,
+    Copy(&'a str, &'a str, bool),
+    Dump(&'a str),
+    Restore(&'a str, Restore<'a>),
+    RestoreAsking(&'a str, Restore<'a>),
+    Migrate(Migrate<'a>),
+    Touch(Vec<&'a str>),
+    Keys(BulkString<'a>),
+    Scan(u64, Option<BulkString<'a>>, usize, Option<&'a str>),
+    ObjectEncoding(&'a str),
+    ObjectFreq(&'a str),
+    ObjectIdleTime(&'a str),
+    ObjectRefCount(&'a str),
+    ObjectHelp,
+    Expire(&'a str, i64, Option<ExpireCondition>),
+    Ttl(&'a str),
+    PTtl(&'a str),
+    ExpireTime(&'a str),
+    PExpireTime(&'a str),
+    Persist(&'a str),
+    Info(Vec<&'a str>),
+    ConfigGet(Vec<&'a str>),
+    ConfigSet(Vec<(&'a str, &'a str)>),
+    MemoryUsage(&'a str, usize),
+    MemoryStats,
+    MemoryDoctor,
+    MemoryHelp,
+    Select(i64),
+    SwapDb(i64, i64),
+    FlushDb(bool),
+    FlushAll(bool),
+    DbSize,
+    Save,
+    BgSave(bool),
+    LastSave,
+    BgRewriteAof,
+    ReplConf(Option<u16>),
+    ReplConfAck(u64, Option<u64>),
+    ReplConfGetAck,
+    Psync(&'a str, i64),
+    Wait(i64, u64),
+    WaitAof(i64, i64, u64),
+    ReplicaOf(Option<(&'a str, u16)>),
+    Role,
+    Publish(&'a str, BulkString<'a>),
+    Subscribe(Vec<&'a str>),
+    Unsubscribe(Vec<&'a str>),
+    PSubscribe(Vec<&'a str>),
+    PUnsubscribe(Vec<&'a str>),
+    SPublish(&'a str, BulkString<'a>),
+    SSubscribe(Vec<&'a str>),
+    SUnsubscribe(Vec<&'a str>),
+    PubSubChannels(Option<&'a str>),
+    PubSubNumSub(Vec<&'a str>),
+    PubSubNumPat,
+    PubSubShardChannels(Option<&'a str>),
+    PubSubShardNumSub(Vec<&'a str>),
+    PubSubHelp,
+    Hello(Option<u8>),
+    SentinelMasters,
+    SentinelMaster(&'a str),
+    SentinelReplicas(&'a str),
+    SentinelSentinels(&'a str),
+    SentinelGetMasterAddrByName(&'a str),
+    SentinelIsMasterDownByAddr(&'a str, u16, u64, &'a str),
+    SentinelFailover(&'a str),
+    SentinelMyId,
+    ClusterKeySlot(&'a str),
+    ClusterCountKeysInSlot(u16),
+    ClusterGetKeysInSlot(u16, usize),
+    ClusterAddSlots(Vec<u16>),
+    ClusterMeet(&'a str, u16, Option<u16>),
+    ClusterSlots,
+    ClusterShards,
+    ClusterNodes,
+    ClusterMyId,
+    ClusterInfo,
+    ClusterSetSlot(u16, SetSlot<'a>),
+    Asking,
+}
+
+impl<'a> Command<'a> {
+    // Commands which may use more memory, so are refused when over the memory limit, as per the
+    // `denyoom` flag in Redis. Others, such as DEL, are still allowed so that memory can be freed.
+    pub const fn is_denyoom(&self) -> bool {
+        matches!(
+            self,
+            Self::Set(..)
+                | Self::SetBit(..)
+                | Self::BitOp(..)
+                | Self::BitField(..)
+                | Self::PfAdd(..)
+                | Self::PfMerge(..)
+                | Self::GeoAdd(..)
+                | Self::GeoSearchStore(..)
+                | Self::Copy(..)
+                | Self::Restore(..)
+                | Self::RestoreAsking(..)
+        )
+    }
+
+    // Commands which may change keys, and so are logged to the append-only file
+    pub const fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Self::Set(..)
+                | Self::SetBit(..)
+                | Self::BitOp(..)
+                | Self::BitField(..)
+                | Self::PfAdd(..)
+                | Self::PfMerge(..)
+                | Self::GeoAdd(..)
+                | Self::GeoSearchStore(..)
+                | Self::Del(..)
+                | Self::Unlink(..)
+                | Self::Rename(..)
+                | Self::RenameNx(..)
+                | Self::Move(..)
+                | Self::Copy(..)
+                | Self::Restore(..)
+                | Self::RestoreAsking(..)
+                | Self::Migrate(Migrate { copy: false, .. })
+                | Self::Expire(..)
+                | Self::Persist(..)
+                | Self::SwapDb(..)
+                | Self::FlushDb(..)
+                | Self::FlushAll(..)
+        )
+    }
+
+    // Commands allowed in subscribed mode over RESP2, where all else sent is a subscriber's
+    pub const fn is_allowed_subscribed(&self) -> bool {
+        matches!(
+            self,
+            Self::Subscribe(..)
+                | Self::Unsubscribe(..)
+                | Self::PSubscribe(..)
+                | Self::PUnsubscribe(..)
+                | Self::SSubscribe(..)
+                | Self::SUnsubscribe(..)
+                | Self::Ping
+        )
+    }
+
+    // The keys the command accesses, which must all be in the same hash slot, served by this node,
+    // in a cluster
+    pub fn keys(&self) -> Vec<&'a str> {
+        match self {
+            Self::Set(key, ..)
+            | Self::Get(key)
+            | Self::SetBit(key, ..)
+            | Self::GetBit(key, ..)
+            | Self::BitCount(key, ..)
+            | Self::BitPos(key, ..)
+            | Self::BitField(key, ..)
+            | Self::BitFieldRo(key, ..)
+            | Self::PfAdd(key, ..)
+            | Self::GeoAdd(key, ..)
+            | Self::GeoPos(key, ..)
+            | Self::GeoDist(key, ..)
+            | Self::GeoHash(key, ..)
+            | Self::GeoSearch(key, ..)
+            | Self::Type(key)
+            | Self::Move(key, ..)
+            | Self::Dump(key)
+            | Self::Restore(key, ..)
+            | Self::RestoreAsking(key, ..)
+            | Self::ObjectEncoding(key)
+            | Self::ObjectFreq(key)
+            | Self::ObjectIdleTime(key)
+            | Self::ObjectRefCount(key)
+            | Self::Expire(key, ..)
+            | Self::Ttl(key)
+            | Self::PTtl(key)
+            | Self::ExpireTime(key)
+            | Self::PExpireTime(key)
+            | Self::Persist(key)
+            | Self::MemoryUsage(key, ..)
+            // Shard channels belong to slots like keys
+            | Self::SPublish(key, ..) => vec![*key],
+            Self::Rename(key, other)
+            | Self::RenameNx(key, other)
+            | Self::Copy(key, other, ..)
+            | Self::GeoSearchStore(key, other, ..) => vec![*key, *other],
+            Self::BitOp(_, destination, keys) | Self::PfMerge(destination, keys) => {
+                std::iter::once(*destination)
+                    .chain(keys.iter().copied())
+                    .collect()
+            }
+            Self::PfCount(keys)
+            | Self::Del(keys)
+            | Self::Unlink(keys)
+            | Self::Exists(keys)
+            | Self::Touch(keys)
+            | Self::SSubscribe(keys)
+            | Self::SUnsubscribe(keys) => keys.clone(),
+            Self::Migrate(migrate) => migrate.keys.clone(),
+            _ => Vec::new(),
+        }
+    }
 }
 
 impl<'a> TryFrom<RespType<'a>> for Command<'a> {
@@ -26,6 +275,39 @@ impl<'a> TryFrom<RespType<'a>> for Command<'a> {
 
         match command {
             x if x.eq_ignore_ascii_case("ping") => Ok(Self::Ping),
+            x if x.eq_ignore_ascii_case("info") => server::info(&mut array),
+            x if x.eq_ignore_ascii_case("config") => server::config(&mut array),
+            x if x.eq_ignore_ascii_case("memory") => server::memory(&mut array),
+            x if x.eq_ignore_ascii_case("select") => server::select(&mut array),
+            x if x.eq_ignore_ascii_case("swapdb") => server::swapdb(&mut array),
+            x if x.eq_ignore_ascii_case("flushdb") => server::flushdb(&mut array),
+            x if x.eq_ignore_ascii_case("flushall") => server::flushall(&mut array),
+            x if x.eq_ignore_ascii_case("dbsize") => server::dbsize(&mut array),
+            x if x.eq_ignore_ascii_case("save") => server::save(&mut array),
+            x if x.eq_ignore_ascii_case("bgsave") => server::bgsave(&mut array),
+            x if x.eq_ignore_ascii_case("lastsave") => server::lastsave(&mut array),
+            x if x.eq_ignore_ascii_case("bgrewriteaof") => server::bgrewriteaof(&mut array),
+            x if x.eq_ignore_ascii_case("replconf") => replication::replconf(&mut array),
+            x if x.eq_ignore_ascii_case("psync") => replication::psync(&mut array),
+            x if x.eq_ignore_ascii_case("wait") => replication::wait(&mut array),
+            x if x.eq_ignore_ascii_case("waitaof") => replication::waitaof(&mut array),
+            x if x.eq_ignore_ascii_case("replicaof") || x.eq_ignore_ascii_case("slaveof") => {
+                replication::replicaof(&mut array)
+            }
+            x if x.eq_ignore_ascii_case("role") => Ok(Self::Role),
+            x if x.eq_ignore_ascii_case("sentinel") => sentinel::sentinel(&mut array),
+            x if x.eq_ignore_ascii_case("publish") => pubsub::publish(&mut array),
+            x if x.eq_ignore_ascii_case("subscribe") => pubsub::subscribe(&mut array),
+            x if x.eq_ignore_ascii_case("unsubscribe") => pubsub::unsubscribe(&mut array),
+            x if x.eq_ignore_ascii_case("psubscribe") => pubsub::psubscribe(&mut array),
+            x if x.eq_ignore_ascii_case("punsubscribe") => pubsub::punsubscribe(&mut array),
+            x if x.eq_ignore_ascii_case("pubsub") => pubsub::pubsub(&mut array),
+            x if x.eq_ignore_ascii_case("spublish") => pubsub::spublish(&mut array),
+            x if x.eq_ignore_ascii_case("ssubscribe") => pubsub::ssubscribe(&mut array),
+            x if x.eq_ignore_ascii_case("sunsubscribe") => pubsub::sunsubscribe(&mut array),
+            x if x.eq_ignore_ascii_case("hello") => server::hello(&mut array),
+            x if x.eq_ignore_ascii_case("cluster") => cluster::cluster(&mut array),
+            x if x.eq_ignore_ascii_case("asking") => cluster::asking(&mut array),
 
             // ECHO
             // See: https://redis.io/docs/latest/commands/echo/
@@ -40,9 +322,7 @@ impl<'a> TryFrom<RespType<'a>> for Command<'a> {
             // SET
             // See: https://redis.io/docs/latest/commands/set/
             x if x.eq_ignore_ascii_case("set") => {
-                let Some(RespType::BulkString(key)) = array.pop_front() else {
-                    return Err("ERR missing or incorrectly formatted key");
-                };
+                let key = next_key(&mut array)?;
 
                 let Some(RespType::BulkString(value)) = array.pop_front() else {
                     return Err("ERR missing or incorrectly formatted value");
@@ -77,15 +357,95 @@ impl<'a> TryFrom<RespType<'a>> for Command<'a> {
 
             // GET
             // See: https://redis.io/docs/latest/commands/get/
-            x if x.eq_ignore_ascii_case("get") => {
-                let Some(RespType::BulkString(key)) = array.pop_front() else {
-                    return Err("ERR missing or incorrectly formatted key");
-                };
+            x if x.eq_ignore_ascii_case("get") => Ok(Command::Get(next_key(&mut array)?)),
 
-                Ok(Command::Get(key))
-            }
+            x if x.eq_ignore_ascii_case("del") => generic::del(&mut array),
+            x if x.eq_ignore_ascii_case("unlink") => generic::unlink(&mut array),
+            x if x.eq_ignore_ascii_case("exists") => generic::exists(&mut array),
+            x if x.eq_ignore_ascii_case("type") => generic::type_of(&mut array),
+            x if x.eq_ignore_ascii_case("rename") => generic::rename(&mut array),
+            x if x.eq_ignore_ascii_case("renamenx") => generic::renamenx(&mut array),
+            x if x.eq_ignore_ascii_case("move") => generic::move_key(&mut array),
+            x if x.eq_ignore_ascii_case("randomkey") => generic::randomkey(&mut array),
+            x if x.eq_ignore_ascii_case("copy") => generic::copy(&mut array),
+            x if x.eq_ignore_ascii_case("dump") => generic::dump(&mut array),
+            x if x.eq_ignore_ascii_case("restore") => generic::restore(&mut array),
+            x if x.eq_ignore_ascii_case("restore-asking") => generic::restore_asking(&mut array),
+            x if x.eq_ignore_ascii_case("migrate") => generic::migrate(&mut array),
+            x if x.eq_ignore_ascii_case("touch") => generic::touch(&mut array),
+            x if x.eq_ignore_ascii_case("keys") => generic::keys(&mut array),
+            x if x.eq_ignore_ascii_case("scan") => generic::scan(&mut array),
+            x if x.eq_ignore_ascii_case("object") => generic::object(&mut array),
+
+            x if x.eq_ignore_ascii_case("expire") => expire::expire(&mut array),
+            x if x.eq_ignore_ascii_case("pexpire") => expire::pexpire(&mut array),
+            x if x.eq_ignore_ascii_case("expireat") => expire::expireat(&mut array),
+            x if x.eq_ignore_ascii_case("pexpireat") => expire::pexpireat(&mut array),
+            x if x.eq_ignore_ascii_case("ttl") => expire::ttl(&mut array),
+            x if x.eq_ignore_ascii_case("pttl") => expire::pttl(&mut array),
+            x if x.eq_ignore_ascii_case("expiretime") => expire::expiretime(&mut array),
+            x if x.eq_ignore_ascii_case("pexpiretime") => expire::pexpiretime(&mut array),
+            x if x.eq_ignore_ascii_case("persist") => expire::persist(&mut array),
+
+            x if x.eq_ignore_ascii_case("setbit") => bitmap::setbit(&mut array),
+            x if x.eq_ignore_ascii_case("getbit") => bitmap::getbit(&mut array),
+            x if x.eq_ignore_ascii_case("bitcount") => bitmap::bitcount(&mut array),
+            x if x.eq_ignore_ascii_case("bitpos") => bitmap::bitpos(&mut array),
+            x if x.eq_ignore_ascii_case("bitop") => bitmap::bitop(&mut array),
+            x if x.eq_ignore_ascii_case("bitfield") => bitmap::bitfield(&mut array, false),
+            x if x.eq_ignore_ascii_case("bitfield_ro") => bitmap::bitfield(&mut array, true),
+
+            x if x.eq_ignore_ascii_case("pfadd") => hyperloglog::pfadd(&mut array),
+            x if x.eq_ignore_ascii_case("pfcount") => hyperloglog::pfcount(&mut array),
+            x if x.eq_ignore_ascii_case("pfmerge") => hyperloglog::pfmerge(&mut array),
+
+            x if x.eq_ignore_ascii_case("geoadd") => geo::geoadd(&mut array),
+            x if x.eq_ignore_ascii_case("geopos") => geo::geopos(&mut array),
+            x if x.eq_ignore_ascii_case("geodist") => geo::geodist(&mut array),
+            x if x.eq_ignore_ascii_case("geohash") => geo::geohash(&mut array),
+            x if x.eq_ignore_ascii_case("geosearch") => geo::geosearch(&mut array),
+            x if x.eq_ignore_ascii_case("geosearchstore") => geo::geosearchstore(&mut array),
 
             _ => Err("ERR unknown command"),
         }
     }
 }
+
+fn next_argument<'a>(arguments: &mut Arguments<'a>) -> Option<BulkString<'a>> {
+    match arguments.pop_front() {
+        Some(RespType::BulkString(argument)) => Some(argument),
+        _ => None,
+    }
+}
+
+fn next_string<'a>(arguments: &mut Arguments<'a>) -> Option<&'a str> {
+    next_argument(arguments).and_then(|argument| argument.as_string())
+}
+
+fn next_key<'a>(arguments: &mut Arguments<'a>) -> Result<&'a str, &'static str> {
+    next_argument(arguments)
+        .ok_or("ERR missing or incorrectly formatted key")?
+        .as_string()
+        .ok_or("ERR key is not UTF8 string")
+}
+
+fn next_keys<'a>(arguments: &mut Arguments<'a>) -> Result<Vec<&'a str>, &'static str> {
+    let mut keys = Vec::with_capacity(arguments.len());
+    while !arguments.is_empty() {
+        keys.push(next_key(arguments)?);
+    }
+
+    Ok(keys)
+}
+
+fn next_i64(arguments: &mut Arguments) -> Result<i64, &'static str> {
+    next_argument(arguments)
+        .and_then(|argument| argument.as_i64())
+        .ok_or("ERR value is not an integer or out of range")
+}
+
+fn next_f64(arguments: &mut Arguments) -> Result<f64, &'static str> {
+    next_argument(arguments)
+        .and_then(|argument| argument.as_f64())
+        .ok_or("ERR value is not a valid float")
+}
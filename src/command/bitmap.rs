@@ -0,0 +1,210 @@
+use super::{next_i64, next_key, next_keys, next_string, Arguments, Command};
+use crate::bitmap::{Encoding, Operation, Overflow, Range, Subcommand, Unit};
+
+// Offsets are limited by the maximum size of a string, 512MB
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8;
+
+fn next_offset(arguments: &mut Arguments) -> Result<u64, &'static str> {
+    next_string(arguments)
+        .and_then(|offset| offset.parse::<u64>().ok())
+        .filter(|offset| *offset < MAX_BIT_OFFSET)
+        .ok_or("ERR bit offset is not an integer or out of range")
+}
+
+fn next_bit(arguments: &mut Arguments, error: &'static str) -> Result<bool, &'static str> {
+    match next_string(arguments) {
+        Some("0") => Ok(false),
+        Some("1") => Ok(true),
+        _ => Err(error),
+    }
+}
+
+fn next_range(
+    arguments: &mut Arguments,
+    end_required: bool,
+) -> Result<Option<Range>, &'static str> {
+    if arguments.is_empty() {
+        return Ok(None);
+    }
+
+    let start = next_i64(arguments)?;
+    let end = match arguments.is_empty() {
+        true if end_required => return Err("ERR syntax error"),
+        true => None,
+        false => Some(next_i64(arguments)?),
+    };
+    let unit = match next_string(arguments) {
+        None => Unit::Byte,
+        Some(unit) if unit.eq_ignore_ascii_case("byte") => Unit::Byte,
+        Some(unit) if unit.eq_ignore_ascii_case("bit") => Unit::Bit,
+        Some(_) => return Err("ERR syntax error"),
+    };
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    Ok(Some(Range { start, end, unit }))
+}
+
+// SETBIT
+// See: https://redis.io/docs/latest/commands/setbit/
+pub fn setbit<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 3 {
+        return Err("ERR wrong number of arguments for 'setbit' command");
+    }
+
+    let key = next_key(arguments)?;
+    let offset = next_offset(arguments)?;
+    let bit = next_bit(arguments, "ERR bit is not an integer or out of range")?;
+
+    Ok(Command::SetBit(key, offset, bit))
+}
+
+// GETBIT
+// See: https://redis.io/docs/latest/commands/getbit/
+pub fn getbit<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'getbit' command");
+    }
+
+    let key = next_key(arguments)?;
+    let offset = next_offset(arguments)?;
+
+    Ok(Command::GetBit(key, offset))
+}
+
+// BITCOUNT
+// See: https://redis.io/docs/latest/commands/bitcount/
+pub fn bitcount<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'bitcount' command");
+    }
+
+    let key = next_key(arguments)?;
+    let range = next_range(arguments, true)?;
+
+    Ok(Command::BitCount(key, range))
+}
+
+// BITPOS
+// See: https://redis.io/docs/latest/commands/bitpos/
+pub fn bitpos<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 2 {
+        return Err("ERR wrong number of arguments for 'bitpos' command");
+    }
+
+    let key = next_key(arguments)?;
+    let bit = next_bit(arguments, "ERR The bit argument must be 1 or 0.")?;
+    let range = next_range(arguments, false)?;
+
+    Ok(Command::BitPos(key, bit, range))
+}
+
+// BITOP
+// See: https://redis.io/docs/latest/commands/bitop/
+pub fn bitop<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 3 {
+        return Err("ERR wrong number of arguments for 'bitop' command");
+    }
+
+    let operation = match next_string(arguments) {
+        Some(x) if x.eq_ignore_ascii_case("and") => Operation::And,
+        Some(x) if x.eq_ignore_ascii_case("or") => Operation::Or,
+        Some(x) if x.eq_ignore_ascii_case("xor") => Operation::Xor,
+        Some(x) if x.eq_ignore_ascii_case("not") => Operation::Not,
+        Some(x) if x.eq_ignore_ascii_case("diff") => Operation::Diff,
+        Some(x) if x.eq_ignore_ascii_case("one") => Operation::One,
+        _ => return Err("ERR syntax error"),
+    };
+    let destination = next_key(arguments)?;
+    let keys = next_keys(arguments)?;
+
+    match operation {
+        Operation::Not if keys.len() != 1 => {
+            Err("ERR BITOP NOT must be called with a single source key.")
+        }
+        Operation::Diff if keys.len() < 2 => {
+            Err("ERR BITOP DIFF must be called with at least two source keys.")
+        }
+        _ => Ok(Command::BitOp(operation, destination, keys)),
+    }
+}
+
+// BITFIELD and BITFIELD_RO
+// See: https://redis.io/docs/latest/commands/bitfield/
+pub fn bitfield<'a>(
+    arguments: &mut Arguments<'a>,
+    read_only: bool,
+) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err(if read_only {
+            "ERR wrong number of arguments for 'bitfield_ro' command"
+        } else {
+            "ERR wrong number of arguments for 'bitfield' command"
+        });
+    }
+
+    let key = next_key(arguments)?;
+    let mut subcommands = Vec::new();
+    while let Some(subcommand) = next_string(arguments) {
+        let is_get = subcommand.eq_ignore_ascii_case("get");
+        if read_only && !is_get {
+            return Err("ERR BITFIELD_RO only supports the GET subcommand");
+        }
+
+        if subcommand.eq_ignore_ascii_case("overflow") {
+            let overflow = match next_string(arguments) {
+                Some(x) if x.eq_ignore_ascii_case("wrap") => Overflow::Wrap,
+                Some(x) if x.eq_ignore_ascii_case("sat") => Overflow::Sat,
+                Some(x) if x.eq_ignore_ascii_case("fail") => Overflow::Fail,
+                _ => return Err("ERR Invalid OVERFLOW type specified"),
+            };
+            subcommands.push(Subcommand::Overflow(overflow));
+            continue;
+        }
+
+        let is_set = subcommand.eq_ignore_ascii_case("set");
+        let is_incrby = subcommand.eq_ignore_ascii_case("incrby");
+        if !(is_get || is_set || is_incrby) {
+            return Err("ERR syntax error");
+        }
+
+        let encoding = next_string(arguments)
+            .and_then(|encoding| Encoding::try_from(encoding).ok())
+            .ok_or(
+                "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not \
+                 supported but i64 is.",
+            )?;
+        let offset = next_field_offset(arguments, encoding)?;
+
+        subcommands.push(if is_get {
+            Subcommand::Get(encoding, offset)
+        } else if is_set {
+            Subcommand::Set(encoding, offset, next_i64(arguments)?)
+        } else {
+            Subcommand::IncrBy(encoding, offset, next_i64(arguments)?)
+        });
+    }
+
+    if read_only {
+        Ok(Command::BitFieldRo(key, subcommands))
+    } else {
+        Ok(Command::BitField(key, subcommands))
+    }
+}
+
+// Offsets prefixed with `#` are multiplied by the width of the field
+fn next_field_offset(arguments: &mut Arguments, encoding: Encoding) -> Result<u64, &'static str> {
+    let error = "ERR bit offset is not an integer or out of range";
+    let offset = next_string(arguments).ok_or(error)?;
+    let (offset, multiplier) = offset
+        .strip_prefix('#')
+        .map_or((offset, 1), |offset| (offset, u64::from(encoding.bits)));
+
+    offset
+        .parse::<u64>()
+        .ok()
+        .and_then(|offset| offset.checked_mul(multiplier))
+        .filter(|offset| offset + u64::from(encoding.bits) <= MAX_BIT_OFFSET)
+        .ok_or(error)
+}
@@ -0,0 +1,108 @@
+use super::{next_i64, next_key, next_string, Arguments, Command};
+use crate::cluster::{SetSlot, SLOTS};
+
+// CLUSTER
+// See: https://redis.io/docs/latest/commands/cluster/
+pub fn cluster<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let subcommand =
+        next_string(arguments).ok_or("ERR wrong number of arguments for 'cluster' command")?;
+
+    match subcommand {
+        x if x.eq_ignore_ascii_case("keyslot") => {
+            if arguments.len() != 1 {
+                return Err("ERR wrong number of arguments for 'cluster|keyslot' command");
+            }
+            Ok(Command::ClusterKeySlot(next_key(arguments)?))
+        }
+        x if x.eq_ignore_ascii_case("countkeysinslot") => {
+            if arguments.len() != 1 {
+                return Err("ERR wrong number of arguments for 'cluster|countkeysinslot' command");
+            }
+            let slot = next_slot(arguments).map_err(|_| "ERR Invalid slot")?;
+            Ok(Command::ClusterCountKeysInSlot(slot))
+        }
+        x if x.eq_ignore_ascii_case("getkeysinslot") => {
+            if arguments.len() != 2 {
+                return Err("ERR wrong number of arguments for 'cluster|getkeysinslot' command");
+            }
+            let slot = next_slot(arguments).map_err(|_| "ERR Invalid slot or number of keys")?;
+            let count = usize::try_from(next_i64(arguments)?)
+                .map_err(|_| "ERR Invalid slot or number of keys")?;
+            Ok(Command::ClusterGetKeysInSlot(slot, count))
+        }
+        x if x.eq_ignore_ascii_case("addslots") => {
+            if arguments.is_empty() {
+                return Err("ERR wrong number of arguments for 'cluster|addslots' command");
+            }
+            let mut slots = Vec::with_capacity(arguments.len());
+            while !arguments.is_empty() {
+                slots.push(next_slot(arguments)?);
+            }
+            Ok(Command::ClusterAddSlots(slots))
+        }
+        // CLUSTER MEET ip port [cluster-bus-port]
+        x if x.eq_ignore_ascii_case("meet") => {
+            if !(2..=3).contains(&arguments.len()) {
+                return Err("ERR wrong number of arguments for 'cluster|meet' command");
+            }
+            let host = next_string(arguments).ok_or("ERR syntax error")?;
+            let port = next_port(arguments)?;
+            let bus_port = if arguments.is_empty() {
+                None
+            } else {
+                Some(next_port(arguments)?)
+            };
+            Ok(Command::ClusterMeet(host, port, bus_port))
+        }
+        // CLUSTER SETSLOT slot <IMPORTING node-id | MIGRATING node-id | NODE node-id | STABLE>
+        x if x.eq_ignore_ascii_case("setslot") => {
+            if !(2..=3).contains(&arguments.len()) {
+                return Err("ERR wrong number of arguments for 'cluster|setslot' command");
+            }
+            let slot = next_slot(arguments)?;
+            let state = next_string(arguments).ok_or("ERR syntax error")?;
+            let id = next_string(arguments);
+            let to = match (state, id) {
+                (x, Some(id)) if x.eq_ignore_ascii_case("migrating") => SetSlot::Migrating(id),
+                (x, Some(id)) if x.eq_ignore_ascii_case("importing") => SetSlot::Importing(id),
+                (x, Some(id)) if x.eq_ignore_ascii_case("node") => SetSlot::Node(id),
+                (x, None) if x.eq_ignore_ascii_case("stable") => SetSlot::Stable,
+                _ => {
+                    return Err(
+                        "ERR Invalid CLUSTER SETSLOT action or number of arguments. Try \
+                                CLUSTER HELP",
+                    )
+                }
+            };
+            Ok(Command::ClusterSetSlot(slot, to))
+        }
+        x if x.eq_ignore_ascii_case("slots") && arguments.is_empty() => Ok(Command::ClusterSlots),
+        x if x.eq_ignore_ascii_case("shards") && arguments.is_empty() => Ok(Command::ClusterShards),
+        x if x.eq_ignore_ascii_case("nodes") && arguments.is_empty() => Ok(Command::ClusterNodes),
+        x if x.eq_ignore_ascii_case("myid") && arguments.is_empty() => Ok(Command::ClusterMyId),
+        x if x.eq_ignore_ascii_case("info") && arguments.is_empty() => Ok(Command::ClusterInfo),
+        _ => Err("ERR unknown subcommand. Try CLUSTER HELP."),
+    }
+}
+
+// ASKING
+// See: https://redis.io/docs/latest/commands/asking/
+pub fn asking<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'asking' command");
+    }
+
+    Ok(Command::Asking)
+}
+
+fn next_slot<'a>(arguments: &mut Arguments<'a>) -> Result<u16, &'a str> {
+    next_i64(arguments)
+        .ok()
+        .and_then(|slot| u16::try_from(slot).ok())
+        .filter(|slot| usize::from(*slot) < SLOTS)
+        .ok_or("ERR Invalid or out of range slot")
+}
+
+fn next_port<'a>(arguments: &mut Arguments<'a>) -> Result<u16, &'a str> {
+    u16::try_from(next_i64(arguments)?).map_err(|_| "ERR Invalid node address specified")
+}
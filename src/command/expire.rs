@@ -0,0 +1,153 @@
+use super::{next_argument, next_key, next_string, Arguments, Command};
+use crate::store::{self, ExpireCondition};
+
+// Parses the arguments shared by EXPIRE and friends into a deadline in milliseconds since the Unix
+// epoch, which can be in the past
+fn next_expire<'a>(
+    arguments: &mut Arguments<'a>,
+    seconds: bool,
+    absolute: bool,
+    invalid: &'static str,
+) -> Result<Command<'a>, &'a str> {
+    let key = next_key(arguments)?;
+    let time = next_argument(arguments).ok_or("ERR value is not an integer or out of range")?;
+
+    let (mut if_none, mut if_some, mut later, mut earlier) = (false, false, false, false);
+    while let Some(option) = next_string(arguments) {
+        match option {
+            x if x.eq_ignore_ascii_case("nx") => if_none = true,
+            x if x.eq_ignore_ascii_case("xx") => if_some = true,
+            x if x.eq_ignore_ascii_case("gt") => later = true,
+            x if x.eq_ignore_ascii_case("lt") => earlier = true,
+            _ => return Err("ERR Unsupported option"),
+        }
+    }
+    if !arguments.is_empty() {
+        return Err("ERR Unsupported option");
+    }
+    if if_none && (if_some || later || earlier) {
+        return Err("ERR NX and XX, GT or LT options at the same time are not compatible");
+    }
+    if later && earlier {
+        return Err("ERR GT and LT options at the same time are not compatible");
+    }
+    // XX combined with GT or LT is implied by the latter
+    let condition = match (if_none, if_some, later, earlier) {
+        (true, ..) => Some(ExpireCondition::NoDeadline),
+        (_, _, true, _) => Some(ExpireCondition::Later),
+        (_, _, _, true) => Some(ExpireCondition::Earlier),
+        (_, true, ..) => Some(ExpireCondition::HasDeadline),
+        _ => None,
+    };
+
+    let time = time
+        .as_i64()
+        .ok_or("ERR value is not an integer or out of range")?;
+    let time = if seconds {
+        time.checked_mul(1000).ok_or(invalid)?
+    } else {
+        time
+    };
+    let deadline = if absolute {
+        time
+    } else {
+        let now = i64::try_from(store::now()).unwrap_or(i64::MAX);
+        time.checked_add(now).ok_or(invalid)?
+    };
+
+    Ok(Command::Expire(key, deadline, condition))
+}
+
+// EXPIRE
+// See: https://redis.io/docs/latest/commands/expire/
+pub fn expire<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 2 {
+        return Err("ERR wrong number of arguments for 'expire' command");
+    }
+
+    let invalid = "ERR invalid expire time in 'expire' command";
+    next_expire(arguments, true, false, invalid)
+}
+
+// PEXPIRE
+// See: https://redis.io/docs/latest/commands/pexpire/
+pub fn pexpire<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 2 {
+        return Err("ERR wrong number of arguments for 'pexpire' command");
+    }
+
+    let invalid = "ERR invalid expire time in 'pexpire' command";
+    next_expire(arguments, false, false, invalid)
+}
+
+// EXPIREAT
+// See: https://redis.io/docs/latest/commands/expireat/
+pub fn expireat<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 2 {
+        return Err("ERR wrong number of arguments for 'expireat' command");
+    }
+
+    let invalid = "ERR invalid expire time in 'expireat' command";
+    next_expire(arguments, true, true, invalid)
+}
+
+// PEXPIREAT
+// See: https://redis.io/docs/latest/commands/pexpireat/
+pub fn pexpireat<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 2 {
+        return Err("ERR wrong number of arguments for 'pexpireat' command");
+    }
+
+    let invalid = "ERR invalid expire time in 'pexpireat' command";
+    next_expire(arguments, false, true, invalid)
+}
+
+// TTL
+// See: https://redis.io/docs/latest/commands/ttl/
+pub fn ttl<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'ttl' command");
+    }
+
+    Ok(Command::Ttl(next_key(arguments)?))
+}
+
+// PTTL
+// See: https://redis.io/docs/latest/commands/pttl/
+pub fn pttl<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'pttl' command");
+    }
+
+    Ok(Command::PTtl(next_key(arguments)?))
+}
+
+// EXPIRETIME
+// See: https://redis.io/docs/latest/commands/expiretime/
+pub fn expiretime<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'expiretime' command");
+    }
+
+    Ok(Command::ExpireTime(next_key(arguments)?))
+}
+
+// PEXPIRETIME
+// See: https://redis.io/docs/latest/commands/pexpiretime/
+pub fn pexpiretime<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'pexpiretime' command");
+    }
+
+    Ok(Command::PExpireTime(next_key(arguments)?))
+}
+
+// PERSIST
+// See: https://redis.io/docs/latest/commands/persist/
+pub fn persist<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'persist' command");
+    }
+
+    Ok(Command::Persist(next_key(arguments)?))
+}
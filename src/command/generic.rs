@@ -0,0 +1,333 @@
+use super::{next_argument, next_i64, next_key, next_keys, next_string, Arguments, Command};
+use crate::{cluster::Migrate, rdb::Restore};
+use std::time::Duration;
+
+// The default COUNT of SCAN, as the number of keys asked for
+const SCAN_COUNT: usize = 10;
+
+// DEL
+// See: https://redis.io/docs/latest/commands/del/
+pub fn del<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'del' command");
+    }
+
+    Ok(Command::Del(next_keys(arguments)?))
+}
+
+// UNLINK
+// See: https://redis.io/docs/latest/commands/unlink/
+pub fn unlink<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'unlink' command");
+    }
+
+    Ok(Command::Unlink(next_keys(arguments)?))
+}
+
+// EXISTS
+// See: https://redis.io/docs/latest/commands/exists/
+pub fn exists<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'exists' command");
+    }
+
+    Ok(Command::Exists(next_keys(arguments)?))
+}
+
+// TYPE
+// See: https://redis.io/docs/latest/commands/type/
+pub fn type_of<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'type' command");
+    }
+
+    Ok(Command::Type(next_key(arguments)?))
+}
+
+// RENAME
+// See: https://redis.io/docs/latest/commands/rename/
+pub fn rename<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'rename' command");
+    }
+
+    let key = next_key(arguments)?;
+    Ok(Command::Rename(key, next_key(arguments)?))
+}
+
+// RENAMENX
+// See: https://redis.io/docs/latest/commands/renamenx/
+pub fn renamenx<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'renamenx' command");
+    }
+
+    let key = next_key(arguments)?;
+    Ok(Command::RenameNx(key, next_key(arguments)?))
+}
+
+// MOVE
+// See: https://redis.io/docs/latest/commands/move/
+pub fn move_key<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'move' command");
+    }
+
+    let key = next_key(arguments)?;
+    Ok(Command::Move(key, next_i64(arguments)?))
+}
+
+// RANDOMKEY
+// See: https://redis.io/docs/latest/commands/randomkey/
+pub fn randomkey<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'randomkey' command");
+    }
+
+    Ok(Command::RandomKey)
+}
+
+// COPY
+// See: https://redis.io/docs/latest/commands/copy/
+pub fn copy<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 2 {
+        return Err("ERR wrong number of arguments for 'copy' command");
+    }
+
+    let source = next_key(arguments)?;
+    let destination = next_key(arguments)?;
+    let mut replace = false;
+    while let Some(option) = next_string(arguments) {
+        match option {
+            x if x.eq_ignore_ascii_case("replace") => replace = true,
+            _ => return Err("ERR syntax error"),
+        }
+    }
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    Ok(Command::Copy(source, destination, replace))
+}
+
+// DUMP
+// See: https://redis.io/docs/latest/commands/dump/
+pub fn dump<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'dump' command");
+    }
+
+    Ok(Command::Dump(next_key(arguments)?))
+}
+
+// RESTORE
+// See: https://redis.io/docs/latest/commands/restore/
+pub fn restore<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 3 {
+        return Err("ERR wrong number of arguments for 'restore' command");
+    }
+
+    let (key, restore) = restore_arguments(arguments)?;
+    Ok(Command::Restore(key, restore))
+}
+
+// RESTORE-ASKING, as sent by MIGRATE to a node importing the slot of the key
+pub fn restore_asking<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 3 {
+        return Err("ERR wrong number of arguments for 'restore-asking' command");
+    }
+
+    let (key, restore) = restore_arguments(arguments)?;
+    Ok(Command::RestoreAsking(key, restore))
+}
+
+fn restore_arguments<'a>(arguments: &mut Arguments<'a>) -> Result<(&'a str, Restore<'a>), &'a str> {
+    let key = next_key(arguments)?;
+    let ttl =
+        u64::try_from(next_i64(arguments)?).map_err(|_| "ERR Invalid TTL value, must be >= 0")?;
+    let payload = next_argument(arguments)
+        .ok_or("ERR syntax error")?
+        .as_bytes();
+    let mut restore = Restore {
+        ttl,
+        payload,
+        replace: false,
+        absolute_ttl: false,
+        idle_time: None,
+        frequency: None,
+    };
+    // Like Redis, IDLETIME and FREQ can't both be given
+    while let Some(option) = next_string(arguments) {
+        match option {
+            x if x.eq_ignore_ascii_case("replace") => restore.replace = true,
+            x if x.eq_ignore_ascii_case("absttl") => restore.absolute_ttl = true,
+            x if x.eq_ignore_ascii_case("idletime")
+                && !arguments.is_empty()
+                && restore.frequency.is_none() =>
+            {
+                let idle_time = u64::try_from(next_i64(arguments)?)
+                    .map_err(|_| "ERR Invalid IDLETIME value, must be >= 0")?;
+                restore.idle_time = Some(idle_time);
+            }
+            x if x.eq_ignore_ascii_case("freq")
+                && !arguments.is_empty()
+                && restore.idle_time.is_none() =>
+            {
+                let frequency = u8::try_from(next_i64(arguments)?)
+                    .map_err(|_| "ERR Invalid FREQ value, must be >= 0 and <= 255")?;
+                restore.frequency = Some(frequency);
+            }
+            _ => return Err("ERR syntax error"),
+        }
+    }
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    Ok((key, restore))
+}
+
+// MIGRATE host port <key | ""> destination-db timeout [COPY] [REPLACE] [AUTH password]
+//   [AUTH2 username password] [KEYS key [key ...]]
+// See: https://redis.io/docs/latest/commands/migrate/
+pub fn migrate<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 5 {
+        return Err("ERR wrong number of arguments for 'migrate' command");
+    }
+
+    let host = next_string(arguments).ok_or("ERR syntax error")?;
+    let port = u16::try_from(next_i64(arguments)?).map_err(|_| "ERR Invalid TCP port")?;
+    let key = next_key(arguments)?;
+    let db = next_i64(arguments)?;
+    let timeout = next_i64(arguments)?;
+    let mut migrate = Migrate {
+        host,
+        port,
+        keys: vec![key],
+        db,
+        // Like Redis, the timeout defaults to a second
+        timeout: u64::try_from(timeout)
+            .ok()
+            .filter(|timeout| *timeout > 0)
+            .map_or(Duration::from_secs(1), Duration::from_millis),
+        copy: false,
+        replace: false,
+        auth: None,
+    };
+    while let Some(option) = next_string(arguments) {
+        match option {
+            x if x.eq_ignore_ascii_case("copy") => migrate.copy = true,
+            x if x.eq_ignore_ascii_case("replace") => migrate.replace = true,
+            x if x.eq_ignore_ascii_case("auth") && !arguments.is_empty() => {
+                let password = next_string(arguments).ok_or("ERR syntax error")?;
+                migrate.auth = Some((None, password));
+            }
+            x if x.eq_ignore_ascii_case("auth2") && arguments.len() >= 2 => {
+                let username = next_string(arguments).ok_or("ERR syntax error")?;
+                let password = next_string(arguments).ok_or("ERR syntax error")?;
+                migrate.auth = Some((Some(username), password));
+            }
+            x if x.eq_ignore_ascii_case("keys") => {
+                if !key.is_empty() {
+                    return Err(
+                        "ERR When using MIGRATE KEYS option, the key argument must be set \
+                                to the empty string",
+                    );
+                }
+                if arguments.is_empty() {
+                    return Err("ERR syntax error");
+                }
+                migrate.keys = next_keys(arguments)?;
+            }
+            _ => return Err("ERR syntax error"),
+        }
+    }
+
+    Ok(Command::Migrate(migrate))
+}
+
+// TOUCH
+// See: https://redis.io/docs/latest/commands/touch/
+pub fn touch<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'touch' command");
+    }
+
+    Ok(Command::Touch(next_keys(arguments)?))
+}
+
+// KEYS
+// See: https://redis.io/docs/latest/commands/keys/
+pub fn keys<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'keys' command");
+    }
+
+    let pattern = next_argument(arguments).ok_or("ERR pattern is not a BulkString")?;
+    Ok(Command::Keys(pattern))
+}
+
+// SCAN
+// See: https://redis.io/docs/latest/commands/scan/
+pub fn scan<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'scan' command");
+    }
+
+    let cursor = next_argument(arguments)
+        .and_then(|cursor| cursor.as_u64())
+        .ok_or("ERR invalid cursor")?;
+    let mut pattern = None;
+    let mut count = SCAN_COUNT;
+    let mut type_name = None;
+    while let Some(option) = next_string(arguments) {
+        if arguments.is_empty() {
+            return Err("ERR syntax error");
+        }
+
+        match option {
+            x if x.eq_ignore_ascii_case("match") => pattern = next_argument(arguments),
+            x if x.eq_ignore_ascii_case("count") => {
+                count = usize::try_from(next_i64(arguments)?)
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or("ERR syntax error")?;
+            }
+            x if x.eq_ignore_ascii_case("type") => type_name = next_string(arguments),
+            _ => return Err("ERR syntax error"),
+        }
+    }
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    Ok(Command::Scan(cursor, pattern, count, type_name))
+}
+
+// OBJECT
+// See: https://redis.io/docs/latest/commands/object/
+pub fn object<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let subcommand =
+        next_string(arguments).ok_or("ERR wrong number of arguments for 'object' command")?;
+
+    if subcommand.eq_ignore_ascii_case("help") {
+        if !arguments.is_empty() {
+            return Err("ERR wrong number of arguments for 'object|help' command");
+        }
+        return Ok(Command::ObjectHelp);
+    }
+
+    let command: fn(&'a str) -> Command<'a> = match subcommand {
+        x if x.eq_ignore_ascii_case("encoding") => Command::ObjectEncoding,
+        x if x.eq_ignore_ascii_case("freq") => Command::ObjectFreq,
+        x if x.eq_ignore_ascii_case("idletime") => Command::ObjectIdleTime,
+        x if x.eq_ignore_ascii_case("refcount") => Command::ObjectRefCount,
+        _ => return Err("ERR unknown subcommand. Try OBJECT HELP."),
+    };
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'object' subcommand");
+    }
+
+    Ok(command(next_key(arguments)?))
+}
@@ -0,0 +1,264 @@
+use super::{next_argument, next_f64, next_i64, next_key, next_string, Arguments, Command};
+use crate::{
+    geo::{Order, Origin, Search, Shape, Unit},
+    resp::{BulkString, RespType},
+    sorted_set::Condition,
+};
+
+fn next_unit(arguments: &mut Arguments) -> Result<Unit, &'static str> {
+    next_string(arguments)
+        .ok_or("ERR unsupported unit provided. please use M, KM, FT, MI")?
+        .try_into()
+}
+
+fn next_members<'a>(arguments: &mut Arguments<'a>) -> Result<Vec<BulkString<'a>>, &'static str> {
+    let mut members = Vec::with_capacity(arguments.len());
+    while !arguments.is_empty() {
+        members.push(next_argument(arguments).ok_or("ERR member is not a BulkString")?);
+    }
+
+    Ok(members)
+}
+
+// The options shared by GEOSEARCH and GEOSEARCHSTORE, where only the latter accepts STOREDIST
+fn next_search<'a>(arguments: &mut Arguments<'a>, store: bool) -> Result<Search<'a>, &'a str> {
+    let (origin_error, shape_error) = if store {
+        (
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearchstore",
+            "ERR exactly one of BYRADIUS and BYBOX can be specified for geosearchstore",
+        )
+    } else {
+        (
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for geosearch",
+            "ERR exactly one of BYRADIUS and BYBOX can be specified for geosearch",
+        )
+    };
+
+    let mut origin = None;
+    let mut shape = None;
+    let mut unit = Unit::Meters;
+    let mut order = None;
+    let mut count = None;
+    let mut any = false;
+    let (mut with_coordinates, mut with_distance, mut with_hash) = (false, false, false);
+    let mut store_distance = false;
+
+    while let Some(option) = next_string(arguments) {
+        match option {
+            x if x.eq_ignore_ascii_case("frommember") => {
+                let member = next_argument(arguments).ok_or("ERR syntax error")?;
+                if origin.replace(Origin::Member(member.as_bytes())).is_some() {
+                    return Err(origin_error);
+                }
+            }
+            x if x.eq_ignore_ascii_case("fromlonlat") => {
+                if arguments.len() < 2 {
+                    return Err("ERR syntax error");
+                }
+                let longitude = next_f64(arguments)?;
+                let latitude = next_f64(arguments)?;
+                if origin
+                    .replace(Origin::Coordinates(longitude, latitude))
+                    .is_some()
+                {
+                    return Err(origin_error);
+                }
+            }
+            x if x.eq_ignore_ascii_case("byradius") => {
+                if arguments.len() < 2 {
+                    return Err("ERR syntax error");
+                }
+                let radius = next_f64(arguments).map_err(|_| "ERR need numeric radius")?;
+                if radius < 0.0 {
+                    return Err("ERR radius cannot be negative");
+                }
+                unit = next_unit(arguments)?;
+                if shape.replace(Shape::Radius(radius)).is_some() {
+                    return Err(shape_error);
+                }
+            }
+            x if x.eq_ignore_ascii_case("bybox") => {
+                if arguments.len() < 3 {
+                    return Err("ERR syntax error");
+                }
+                let width = next_f64(arguments).map_err(|_| "ERR need numeric width")?;
+                let height = next_f64(arguments).map_err(|_| "ERR need numeric height")?;
+                if width < 0.0 || height < 0.0 {
+                    return Err("ERR height or width cannot be negative");
+                }
+                unit = next_unit(arguments)?;
+                if shape.replace(Shape::Box(width, height)).is_some() {
+                    return Err(shape_error);
+                }
+            }
+            x if x.eq_ignore_ascii_case("asc") => order = Some(Order::Ascending),
+            x if x.eq_ignore_ascii_case("desc") => order = Some(Order::Descending),
+            x if x.eq_ignore_ascii_case("count") => {
+                if arguments.is_empty() {
+                    return Err("ERR syntax error");
+                }
+                let value = next_i64(arguments)?;
+                if value <= 0 {
+                    return Err("ERR COUNT must be > 0");
+                }
+                count = Some(usize::try_from(value).unwrap_or(usize::MAX));
+
+                if let Some(RespType::BulkString(next)) = arguments.front() {
+                    if next
+                        .as_string()
+                        .is_some_and(|next| next.eq_ignore_ascii_case("any"))
+                    {
+                        arguments.pop_front();
+                        any = true;
+                    }
+                }
+            }
+            x if x.eq_ignore_ascii_case("withcoord") => with_coordinates = true,
+            x if x.eq_ignore_ascii_case("withdist") => with_distance = true,
+            x if x.eq_ignore_ascii_case("withhash") => with_hash = true,
+            x if store && x.eq_ignore_ascii_case("storedist") => store_distance = true,
+            _ => return Err("ERR syntax error"),
+        }
+    }
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    let origin = origin.ok_or(origin_error)?;
+    let shape = shape.ok_or(shape_error)?;
+    if any && count.is_none() {
+        return Err("ERR the ANY argument requires COUNT argument");
+    }
+    if store && (with_coordinates || with_distance || with_hash) {
+        return Err(
+            "ERR GEOSEARCHSTORE is not compatible with WITHDIST, WITHHASH and WITHCOORD options",
+        );
+    }
+
+    // Returning the closest N members requires sorting, unless any N will do
+    if count.is_some() && order.is_none() && !any {
+        order = Some(Order::Ascending);
+    }
+
+    Ok(Search {
+        origin,
+        shape,
+        unit,
+        order,
+        count,
+        any,
+        with_coordinates,
+        with_distance,
+        with_hash,
+        store_distance,
+    })
+}
+
+// GEOADD
+// See: https://redis.io/docs/latest/commands/geoadd/
+pub fn geoadd<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 4 {
+        return Err("ERR wrong number of arguments for 'geoadd' command");
+    }
+
+    let key = next_key(arguments)?;
+    let (mut if_exists, mut if_missing, mut changed) = (false, false, false);
+    while let Some(RespType::BulkString(option)) = arguments.front() {
+        match option.as_string() {
+            Some(x) if x.eq_ignore_ascii_case("xx") => if_exists = true,
+            Some(x) if x.eq_ignore_ascii_case("nx") => if_missing = true,
+            Some(x) if x.eq_ignore_ascii_case("ch") => changed = true,
+            _ => break,
+        }
+        arguments.pop_front();
+    }
+    if arguments.is_empty() || arguments.len() % 3 != 0 {
+        return Err("ERR syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... ");
+    }
+    let condition = match (if_exists, if_missing) {
+        (true, true) => return Err("ERR XX and NX options at the same time are not compatible"),
+        (true, false) => Some(Condition::IfExists),
+        (false, true) => Some(Condition::IfMissing),
+        (false, false) => None,
+    };
+
+    let mut items = Vec::with_capacity(arguments.len() / 3);
+    while !arguments.is_empty() {
+        let longitude = next_f64(arguments)?;
+        let latitude = next_f64(arguments)?;
+        let member = next_argument(arguments).ok_or("ERR member is not a BulkString")?;
+        items.push((longitude, latitude, member));
+    }
+
+    Ok(Command::GeoAdd(key, condition, changed, items))
+}
+
+// GEOPOS
+// See: https://redis.io/docs/latest/commands/geopos/
+pub fn geopos<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'geopos' command");
+    }
+
+    let key = next_key(arguments)?;
+    Ok(Command::GeoPos(key, next_members(arguments)?))
+}
+
+// GEODIST
+// See: https://redis.io/docs/latest/commands/geodist/
+pub fn geodist<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    match arguments.len() {
+        0..=2 => return Err("ERR wrong number of arguments for 'geodist' command"),
+        3 | 4 => {}
+        _ => return Err("ERR syntax error"),
+    }
+
+    let key = next_key(arguments)?;
+    let from = next_argument(arguments).ok_or("ERR member is not a BulkString")?;
+    let to = next_argument(arguments).ok_or("ERR member is not a BulkString")?;
+    let unit = if arguments.is_empty() {
+        Unit::Meters
+    } else {
+        next_unit(arguments)?
+    };
+
+    Ok(Command::GeoDist(key, from, to, unit))
+}
+
+// GEOHASH
+// See: https://redis.io/docs/latest/commands/geohash/
+pub fn geohash<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'geohash' command");
+    }
+
+    let key = next_key(arguments)?;
+    Ok(Command::GeoHash(key, next_members(arguments)?))
+}
+
+// GEOSEARCH
+// See: https://redis.io/docs/latest/commands/geosearch/
+pub fn geosearch<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 6 {
+        return Err("ERR wrong number of arguments for 'geosearch' command");
+    }
+
+    let key = next_key(arguments)?;
+    Ok(Command::GeoSearch(key, next_search(arguments, false)?))
+}
+
+// GEOSEARCHSTORE
+// See: https://redis.io/docs/latest/commands/geosearchstore/
+pub fn geosearchstore<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() < 7 {
+        return Err("ERR wrong number of arguments for 'geosearchstore' command");
+    }
+
+    let destination = next_key(arguments)?;
+    let key = next_key(arguments)?;
+    Ok(Command::GeoSearchStore(
+        destination,
+        key,
+        next_search(arguments, true)?,
+    ))
+}
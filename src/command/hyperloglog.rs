@@ -0,0 +1,38 @@
+use super::{next_argument, next_key, next_keys, Arguments, Command};
+
+// PFADD
+// See: https://redis.io/docs/latest/commands/pfadd/
+pub fn pfadd<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'pfadd' command");
+    }
+
+    let key = next_key(arguments)?;
+    let mut elements = Vec::with_capacity(arguments.len());
+    while !arguments.is_empty() {
+        elements.push(next_argument(arguments).ok_or("ERR element is not a BulkString")?);
+    }
+
+    Ok(Command::PfAdd(key, elements))
+}
+
+// PFCOUNT
+// See: https://redis.io/docs/latest/commands/pfcount/
+pub fn pfcount<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'pfcount' command");
+    }
+
+    Ok(Command::PfCount(next_keys(arguments)?))
+}
+
+// PFMERGE
+// See: https://redis.io/docs/latest/commands/pfmerge/
+pub fn pfmerge<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'pfmerge' command");
+    }
+
+    let destination = next_key(arguments)?;
+    Ok(Command::PfMerge(destination, next_keys(arguments)?))
+}
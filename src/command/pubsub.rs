@@ -0,0 +1,110 @@
+use super::{next_argument, next_keys, next_string, Arguments, Command};
+
+// PUBLISH channel message
+// See: https://redis.io/docs/latest/commands/publish/
+pub fn publish<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'publish' command");
+    }
+
+    let channel = next_string(arguments).ok_or("ERR channel is not UTF8 string")?;
+    let message = next_argument(arguments).ok_or("ERR syntax error")?;
+    Ok(Command::Publish(channel, message))
+}
+
+// SUBSCRIBE channel [channel ...]
+// See: https://redis.io/docs/latest/commands/subscribe/
+pub fn subscribe<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'subscribe' command");
+    }
+
+    Ok(Command::Subscribe(next_channels(arguments)?))
+}
+
+// UNSUBSCRIBE [channel [channel ...]]
+// See: https://redis.io/docs/latest/commands/unsubscribe/
+pub fn unsubscribe<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    Ok(Command::Unsubscribe(next_channels(arguments)?))
+}
+
+// PSUBSCRIBE pattern [pattern ...]
+// See: https://redis.io/docs/latest/commands/psubscribe/
+pub fn psubscribe<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'psubscribe' command");
+    }
+
+    Ok(Command::PSubscribe(next_channels(arguments)?))
+}
+
+// PUNSUBSCRIBE [pattern [pattern ...]]
+// See: https://redis.io/docs/latest/commands/punsubscribe/
+pub fn punsubscribe<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    Ok(Command::PUnsubscribe(next_channels(arguments)?))
+}
+
+// SPUBLISH shardchannel message
+// See: https://redis.io/docs/latest/commands/spublish/
+pub fn spublish<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'spublish' command");
+    }
+
+    let channel = next_string(arguments).ok_or("ERR channel is not UTF8 string")?;
+    let message = next_argument(arguments).ok_or("ERR syntax error")?;
+    Ok(Command::SPublish(channel, message))
+}
+
+// SSUBSCRIBE shardchannel [shardchannel ...]
+// See: https://redis.io/docs/latest/commands/ssubscribe/
+pub fn ssubscribe<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'ssubscribe' command");
+    }
+
+    Ok(Command::SSubscribe(next_channels(arguments)?))
+}
+
+// SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+// See: https://redis.io/docs/latest/commands/sunsubscribe/
+pub fn sunsubscribe<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    Ok(Command::SUnsubscribe(next_channels(arguments)?))
+}
+
+// PUBSUB CHANNELS, NUMSUB, NUMPAT, SHARDCHANNELS and SHARDNUMSUB
+// See: https://redis.io/docs/latest/commands/pubsub/
+pub fn pubsub<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let subcommand =
+        next_string(arguments).ok_or("ERR wrong number of arguments for 'pubsub' command")?;
+
+    match subcommand {
+        x if x.eq_ignore_ascii_case("channels") => {
+            if arguments.len() > 1 {
+                return Err("ERR wrong number of arguments for 'pubsub|channels' command");
+            }
+            let pattern = next_channels(arguments)?.pop();
+            Ok(Command::PubSubChannels(pattern))
+        }
+        x if x.eq_ignore_ascii_case("numsub") => {
+            Ok(Command::PubSubNumSub(next_channels(arguments)?))
+        }
+        x if x.eq_ignore_ascii_case("numpat") && arguments.is_empty() => Ok(Command::PubSubNumPat),
+        x if x.eq_ignore_ascii_case("shardchannels") => {
+            if arguments.len() > 1 {
+                return Err("ERR wrong number of arguments for 'pubsub|shardchannels' command");
+            }
+            let pattern = next_channels(arguments)?.pop();
+            Ok(Command::PubSubShardChannels(pattern))
+        }
+        x if x.eq_ignore_ascii_case("shardnumsub") => {
+            Ok(Command::PubSubShardNumSub(next_channels(arguments)?))
+        }
+        x if x.eq_ignore_ascii_case("help") && arguments.is_empty() => Ok(Command::PubSubHelp),
+        _ => Err("ERR unknown subcommand. Try PUBSUB HELP."),
+    }
+}
+
+fn next_channels<'a>(arguments: &mut Arguments<'a>) -> Result<Vec<&'a str>, &'a str> {
+    next_keys(arguments).map_err(|_| "ERR channel is not UTF8 string")
+}
@@ -0,0 +1,106 @@
+use super::{next_argument, next_i64, next_string, Arguments, Command};
+
+// REPLCONF, as sent by replicas to their master, other than GETACK, which goes the other way
+// See: https://github.com/redis/redis/blob/unstable/src/replication.c (`replconfCommand`)
+pub fn replconf<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() || arguments.len() % 2 != 0 {
+        return Err("ERR syntax error");
+    }
+
+    let mut listening_port = None;
+    while let Some(option) = next_string(arguments) {
+        match option {
+            x if x.eq_ignore_ascii_case("listening-port") => {
+                let port = next_i64(arguments)?;
+                listening_port =
+                    Some(u16::try_from(port).map_err(|_| "ERR value is out of range")?);
+            }
+            // With FACK, the offset up to which the replica's append-only file is on disk
+            x if x.eq_ignore_ascii_case("ack") => {
+                let offset = u64::try_from(next_i64(arguments)?).unwrap_or(0);
+                let fsynced = match next_string(arguments) {
+                    Some(x) if x.eq_ignore_ascii_case("fack") => {
+                        Some(u64::try_from(next_i64(arguments)?).unwrap_or(0))
+                    }
+                    Some(_) => return Err("ERR syntax error"),
+                    None => None,
+                };
+                return Ok(Command::ReplConfAck(offset, fsynced));
+            }
+            x if x.eq_ignore_ascii_case("getack") => return Ok(Command::ReplConfGetAck),
+            // Capabilities are only those of Redis 7, so are ignored
+            x if x.eq_ignore_ascii_case("ip-address") || x.eq_ignore_ascii_case("capa") => {
+                next_argument(arguments);
+            }
+            _ => return Err("ERR Unrecognized REPLCONF option"),
+        }
+    }
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    Ok(Command::ReplConf(listening_port))
+}
+
+// PSYNC
+// See: https://redis.io/docs/latest/commands/psync/
+pub fn psync<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'psync' command");
+    }
+
+    // The history the replica has, and the offset of the first write it's missing from it, or `?`
+    // and -1 to ask for a full snapshot
+    let id = next_string(arguments).ok_or("ERR syntax error")?;
+    let offset = next_i64(arguments)?;
+    Ok(Command::Psync(id, offset))
+}
+
+// WAIT numreplicas timeout
+// See: https://redis.io/docs/latest/commands/wait/
+pub fn wait<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'wait' command");
+    }
+
+    let replicas = next_i64(arguments)?;
+    let timeout = next_timeout(arguments)?;
+    Ok(Command::Wait(replicas, timeout))
+}
+
+// WAITAOF numlocal numreplicas timeout
+// See: https://redis.io/docs/latest/commands/waitaof/
+pub fn waitaof<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 3 {
+        return Err("ERR wrong number of arguments for 'waitaof' command");
+    }
+
+    let local = next_i64(arguments)?;
+    let replicas = next_i64(arguments)?;
+    if local < 0 || replicas < 0 {
+        return Err("ERR value is out of range, must be positive");
+    }
+    let timeout = next_timeout(arguments)?;
+    Ok(Command::WaitAof(local, replicas, timeout))
+}
+
+// In milliseconds, where 0 means to wait forever
+fn next_timeout<'a>(arguments: &mut Arguments<'a>) -> Result<u64, &'a str> {
+    u64::try_from(next_i64(arguments)?).map_err(|_| "ERR timeout is negative")
+}
+
+// REPLICAOF host port, or REPLICAOF NO ONE, as well as by its old name, SLAVEOF
+// See: https://redis.io/docs/latest/commands/replicaof/
+pub fn replicaof<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'replicaof' command");
+    }
+
+    let host = next_string(arguments).ok_or("ERR syntax error")?;
+    let port = next_string(arguments).ok_or("ERR syntax error")?;
+    if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+        return Ok(Command::ReplicaOf(None));
+    }
+    let port = port.parse().map_err(|_| "ERR Invalid master port")?;
+    Ok(Command::ReplicaOf(Some((host, port))))
+}
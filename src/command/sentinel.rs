@@ -0,0 +1,65 @@
+use super::{next_i64, next_string, Arguments, Command};
+
+// SENTINEL, which is only answered when running as a sentinel
+// See: https://redis.io/docs/latest/operate/oss_and_stack/management/sentinel/#sentinel-commands
+pub fn sentinel<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let subcommand =
+        next_string(arguments).ok_or("ERR wrong number of arguments for 'sentinel' command")?;
+
+    match subcommand {
+        x if x.eq_ignore_ascii_case("masters") && arguments.is_empty() => {
+            Ok(Command::SentinelMasters)
+        }
+        x if x.eq_ignore_ascii_case("myid") && arguments.is_empty() => Ok(Command::SentinelMyId),
+        x if x.eq_ignore_ascii_case("master") => Ok(Command::SentinelMaster(name(
+            arguments,
+            "ERR wrong number of arguments for 'sentinel|master' command",
+        )?)),
+        x if x.eq_ignore_ascii_case("replicas") || x.eq_ignore_ascii_case("slaves") => {
+            Ok(Command::SentinelReplicas(name(
+                arguments,
+                "ERR wrong number of arguments for 'sentinel|replicas' command",
+            )?))
+        }
+        x if x.eq_ignore_ascii_case("sentinels") => Ok(Command::SentinelSentinels(name(
+            arguments,
+            "ERR wrong number of arguments for 'sentinel|sentinels' command",
+        )?)),
+        x if x.eq_ignore_ascii_case("get-master-addr-by-name") => {
+            Ok(Command::SentinelGetMasterAddrByName(name(
+                arguments,
+                "ERR wrong number of arguments for 'sentinel|get-master-addr-by-name' command",
+            )?))
+        }
+        x if x.eq_ignore_ascii_case("failover") => Ok(Command::SentinelFailover(name(
+            arguments,
+            "ERR wrong number of arguments for 'sentinel|failover' command",
+        )?)),
+        // Asked by other sentinels, with the ID of the one asking for a vote to lead a failover of
+        // the master, or `*` to only ask whether it is down
+        x if x.eq_ignore_ascii_case("is-master-down-by-addr") => {
+            if arguments.len() != 4 {
+                return Err(
+                    "ERR wrong number of arguments for 'sentinel|is-master-down-by-addr' command",
+                );
+            }
+            let host = next_string(arguments).ok_or("ERR syntax error")?;
+            let port =
+                u16::try_from(next_i64(arguments)?).map_err(|_| "ERR value is out of range")?;
+            let epoch =
+                u64::try_from(next_i64(arguments)?).map_err(|_| "ERR value is out of range")?;
+            let id = next_string(arguments).ok_or("ERR syntax error")?;
+            Ok(Command::SentinelIsMasterDownByAddr(host, port, epoch, id))
+        }
+        _ => Err("ERR unknown subcommand. Try SENTINEL HELP."),
+    }
+}
+
+// The name of the master, as the only argument of most subcommands
+fn name<'a>(arguments: &mut Arguments<'a>, wrong_number: &'a str) -> Result<&'a str, &'a str> {
+    if arguments.len() != 1 {
+        return Err(wrong_number);
+    }
+
+    next_string(arguments).ok_or("ERR syntax error")
+}
@@ -0,0 +1,224 @@
+use super::{next_i64, next_key, next_keys, next_string, Arguments, Command};
+
+// Like Redis, MEMORY USAGE estimates the size of a value from this many of its elements by default
+const MEMORY_USAGE_SAMPLES: usize = 5;
+
+// INFO
+// See: https://redis.io/docs/latest/commands/info/
+pub fn info<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let sections = next_keys(arguments).map_err(|_| "ERR section is not UTF8 string")?;
+
+    Ok(Command::Info(sections))
+}
+
+// CONFIG GET and CONFIG SET
+// See: https://redis.io/docs/latest/commands/config-get/
+// See: https://redis.io/docs/latest/commands/config-set/
+pub fn config<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let subcommand =
+        next_string(arguments).ok_or("ERR wrong number of arguments for 'config' command")?;
+
+    match subcommand {
+        x if x.eq_ignore_ascii_case("get") => {
+            if arguments.is_empty() {
+                return Err("ERR wrong number of arguments for 'config|get' command");
+            }
+            let patterns = next_keys(arguments).map_err(|_| "ERR pattern is not UTF8 string")?;
+
+            Ok(Command::ConfigGet(patterns))
+        }
+        x if x.eq_ignore_ascii_case("set") => {
+            if arguments.is_empty() || arguments.len() % 2 != 0 {
+                return Err("ERR wrong number of arguments for 'config|set' command");
+            }
+            let mut settings = Vec::with_capacity(arguments.len() / 2);
+            while !arguments.is_empty() {
+                let name = next_string(arguments).ok_or("ERR setting is not UTF8 string")?;
+                let value = next_string(arguments).ok_or("ERR value is not UTF8 string")?;
+                settings.push((name, value));
+            }
+
+            Ok(Command::ConfigSet(settings))
+        }
+        _ => Err("ERR unknown subcommand. Try CONFIG HELP."),
+    }
+}
+
+// MEMORY
+// See: https://redis.io/docs/latest/commands/memory-usage/
+// See: https://redis.io/docs/latest/commands/memory-stats/
+// See: https://redis.io/docs/latest/commands/memory-doctor/
+pub fn memory<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let subcommand =
+        next_string(arguments).ok_or("ERR wrong number of arguments for 'memory' command")?;
+
+    match subcommand {
+        x if x.eq_ignore_ascii_case("usage") => {
+            if arguments.is_empty() {
+                return Err("ERR wrong number of arguments for 'memory|usage' command");
+            }
+            let key = next_key(arguments)?;
+
+            let mut samples = MEMORY_USAGE_SAMPLES;
+            while let Some(option) = next_string(arguments) {
+                if !option.eq_ignore_ascii_case("samples") || arguments.is_empty() {
+                    return Err("ERR syntax error");
+                }
+                // 0 samples every element
+                samples = usize::try_from(next_i64(arguments)?).map_err(|_| "ERR syntax error")?;
+            }
+            if !arguments.is_empty() {
+                return Err("ERR syntax error");
+            }
+
+            Ok(Command::MemoryUsage(key, samples))
+        }
+        x if x.eq_ignore_ascii_case("stats") && arguments.is_empty() => Ok(Command::MemoryStats),
+        x if x.eq_ignore_ascii_case("doctor") && arguments.is_empty() => Ok(Command::MemoryDoctor),
+        x if x.eq_ignore_ascii_case("help") && arguments.is_empty() => Ok(Command::MemoryHelp),
+        x if ["stats", "doctor", "help"]
+            .iter()
+            .any(|name| x.eq_ignore_ascii_case(name)) =>
+        {
+            Err("ERR wrong number of arguments for 'memory' subcommand")
+        }
+        _ => Err("ERR unknown subcommand. Try MEMORY HELP."),
+    }
+}
+
+// SELECT
+// See: https://redis.io/docs/latest/commands/select/
+pub fn select<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 1 {
+        return Err("ERR wrong number of arguments for 'select' command");
+    }
+
+    Ok(Command::Select(next_i64(arguments)?))
+}
+
+// SWAPDB
+// See: https://redis.io/docs/latest/commands/swapdb/
+pub fn swapdb<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.len() != 2 {
+        return Err("ERR wrong number of arguments for 'swapdb' command");
+    }
+
+    let first = next_i64(arguments).map_err(|_| "ERR invalid first DB index")?;
+    let second = next_i64(arguments).map_err(|_| "ERR invalid second DB index")?;
+    Ok(Command::SwapDb(first, second))
+}
+
+// Whether keys are dropped in the background, with SYNC being the default
+fn flush_mode(arguments: &mut Arguments) -> Result<bool, &'static str> {
+    let lazy = match next_string(arguments) {
+        None => false,
+        Some(mode) if mode.eq_ignore_ascii_case("async") => true,
+        Some(mode) if mode.eq_ignore_ascii_case("sync") => false,
+        Some(_) => return Err("ERR syntax error"),
+    };
+    if !arguments.is_empty() {
+        return Err("ERR syntax error");
+    }
+
+    Ok(lazy)
+}
+
+// FLUSHDB
+// See: https://redis.io/docs/latest/commands/flushdb/
+pub fn flushdb<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    Ok(Command::FlushDb(flush_mode(arguments)?))
+}
+
+// FLUSHALL
+// See: https://redis.io/docs/latest/commands/flushall/
+pub fn flushall<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    Ok(Command::FlushAll(flush_mode(arguments)?))
+}
+
+// DBSIZE
+// See: https://redis.io/docs/latest/commands/dbsize/
+pub fn dbsize<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'dbsize' command");
+    }
+
+    Ok(Command::DbSize)
+}
+
+// HELLO [protover [AUTH username password] [SETNAME clientname]]
+// See: https://redis.io/docs/latest/commands/hello/
+pub fn hello<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if arguments.is_empty() {
+        return Ok(Command::Hello(None));
+    }
+
+    let protocol = next_i64(arguments)
+        .map_err(|_| "ERR Protocol version is not an integer or out of range")?;
+    let protocol = u8::try_from(protocol)
+        .ok()
+        .filter(|protocol| (2..=3).contains(protocol))
+        .ok_or("NOPROTO unsupported protocol version")?;
+    while let Some(option) = next_string(arguments) {
+        match option {
+            // Like Redis without a password configured
+            x if x.eq_ignore_ascii_case("auth") && arguments.len() >= 2 => {
+                return Err(
+                    "ERR AUTH <password> called without any password configured for the \
+                            default user. Are you sure your configuration is correct?",
+                );
+            }
+            // Client names are not kept
+            x if x.eq_ignore_ascii_case("setname") && !arguments.is_empty() => {
+                next_string(arguments).ok_or("ERR syntax error")?;
+            }
+            _ => return Err("ERR Syntax error in HELLO option"),
+        }
+    }
+
+    Ok(Command::Hello(Some(protocol)))
+}
+
+// SAVE
+// See: https://redis.io/docs/latest/commands/save/
+pub fn save<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'save' command");
+    }
+
+    Ok(Command::Save)
+}
+
+// BGSAVE, where SCHEDULE saves once any save in progress finishes, rather than failing
+// See: https://redis.io/docs/latest/commands/bgsave/
+pub fn bgsave<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    let schedule = match next_string(arguments) {
+        None => false,
+        Some(option) if option.eq_ignore_ascii_case("schedule") => true,
+        Some(_) => return Err("ERR syntax error"),
+    };
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'bgsave' command");
+    }
+
+    Ok(Command::BgSave(schedule))
+}
+
+// LASTSAVE
+// See: https://redis.io/docs/latest/commands/lastsave/
+pub fn lastsave<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'lastsave' command");
+    }
+
+    Ok(Command::LastSave)
+}
+
+// BGREWRITEAOF
+// See: https://redis.io/docs/latest/commands/bgrewriteaof/
+pub fn bgrewriteaof<'a>(arguments: &mut Arguments<'a>) -> Result<Command<'a>, &'a str> {
+    if !arguments.is_empty() {
+        return Err("ERR wrong number of arguments for 'bgrewriteaof' command");
+    }
+
+    Ok(Command::BgRewriteAof)
+}
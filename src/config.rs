@@ -0,0 +1,545 @@
+use crate::{
+    aof::Fsync,
+    evict::{MaxMemory, Policy},
+    glob,
+    sentinel::{self, Monitor},
+    store::DEFAULT_DATABASES,
+};
+use anyhow::{anyhow, bail, Result};
+use std::path::{Path, PathBuf};
+
+// Settings given on the command line as `--name value`, which can also be read and changed with
+// CONFIG GET and CONFIG SET
+// See: https://redis.io/docs/latest/operate/oss_and_stack/management/config/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub port: u16,
+    // The masters to monitor, when running as a sentinel rather than a server
+    pub sentinel: Option<Vec<Monitor>>,
+    // Whether this is a node of a cluster, sharing hash slots with other nodes, which it talks to
+    // on its cluster bus port, by default the port plus 10000
+    pub cluster_enabled: bool,
+    pub cluster_port: u16,
+    // How long a node goes without replying before it is flagged as possibly failing
+    pub cluster_node_timeout: u64,
+    // The master to replicate, if this is a replica
+    pub replica_of: Option<(String, u16)>,
+    // Whether clients other than the master are refused writes while this is a replica
+    pub replica_read_only: bool,
+    // How many bytes of the most recent writes are kept for replicas which reconnect
+    pub repl_backlog_size: usize,
+    pub databases: usize,
+    pub max_memory: MaxMemory,
+    // Where RDB snapshots are read from on startup
+    pub dir: PathBuf,
+    pub db_filename: String,
+    // Seconds and numbers of changes, after which a snapshot is saved in the background
+    pub save_points: Vec<(u64, u64)>,
+    // Whether writes are logged to the append-only file, which is then read on startup instead,
+    // and where within `dir` it is kept
+    pub append_only: bool,
+    pub append_fsync: Fsync,
+    pub append_dirname: String,
+    pub append_filename: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 6379,
+            sentinel: None,
+            cluster_enabled: false,
+            cluster_port: 0,
+            cluster_node_timeout: 15000,
+            replica_of: None,
+            replica_read_only: true,
+            repl_backlog_size: 1024 * 1024,
+            databases: DEFAULT_DATABASES,
+            max_memory: MaxMemory::default(),
+            dir: PathBuf::from("."),
+            db_filename: "dump.rdb".to_string(),
+            save_points: vec![(3600, 1), (300, 100), (60, 10000)],
+            append_only: false,
+            append_fsync: Fsync::EverySec,
+            append_dirname: "appendonlydir".to_string(),
+            append_filename: "appendonly.aof".to_string(),
+        }
+    }
+}
+
+const NAMES: [&str; 18] = [
+    "appenddirname",
+    "appendfilename",
+    "appendfsync",
+    "appendonly",
+    "cluster-enabled",
+    "cluster-node-timeout",
+    "cluster-port",
+    "databases",
+    "dbfilename",
+    "dir",
+    "maxmemory",
+    "maxmemory-policy",
+    "maxmemory-samples",
+    "port",
+    "repl-backlog-size",
+    "replica-read-only",
+    "replicaof",
+    "save",
+];
+
+// Settings which can only be given on the command line
+const IMMUTABLE: [&str; 9] = [
+    "appenddirname",
+    "appendfilename",
+    "cluster-enabled",
+    "cluster-node-timeout",
+    "cluster-port",
+    "databases",
+    "port",
+    "replicaof",
+    "sentinel",
+];
+
+impl Config {
+    #[allow(clippy::missing_errors_doc)]
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = Self::default();
+        let mut port_given = false;
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| anyhow!("expected an option starting with '--', got {arg:?}"))?;
+            // Like Redis, values can be given as several arguments, such as `--replicaof host port`
+            let mut values = Vec::new();
+            while let Some(value) = args.next_if(|value| !value.starts_with("--")) {
+                values.push(value);
+            }
+            // Other than for `--sentinel` alone, which only switches to running as a sentinel
+            if values.is_empty() && name != "sentinel" {
+                bail!("missing value for '{name}'");
+            }
+            port_given |= name == "port";
+            let value = values.join(" ");
+            match config.set(name, &value) {
+                Ok(()) => {}
+                Err(SetError::Unknown | SetError::Immutable) => bail!("unknown option '{name}'"),
+                Err(SetError::Invalid(reason)) => {
+                    bail!("invalid value {value:?} for '{name}': {reason}");
+                }
+            }
+        }
+        if config.sentinel.is_some() && !port_given {
+            config.port = sentinel::DEFAULT_PORT;
+        }
+
+        Ok(config)
+    }
+
+    // The names and values of the settings with names matching the pattern
+    pub fn get(&self, pattern: &str) -> Vec<(&'static str, String)> {
+        let pattern = pattern.to_ascii_lowercase();
+        NAMES
+            .into_iter()
+            .filter(|name| glob::matches(pattern.as_bytes(), name.as_bytes()))
+            .map(|name| (name, self.value(name)))
+            .collect()
+    }
+
+    fn value(&self, name: &str) -> String {
+        match name {
+            "appenddirname" => self.append_dirname.clone(),
+            "appendfilename" => self.append_filename.clone(),
+            "appendfsync" => self.append_fsync.name().to_string(),
+            "appendonly" => yes_or_no(self.append_only),
+            "cluster-enabled" => yes_or_no(self.cluster_enabled),
+            "cluster-node-timeout" => self.cluster_node_timeout.to_string(),
+            "cluster-port" => self.cluster_port.to_string(),
+            "databases" => self.databases.to_string(),
+            "dbfilename" => self.db_filename.clone(),
+            "dir" => self.dir.display().to_string(),
+            "maxmemory" => self.max_memory.limit.to_string(),
+            "maxmemory-policy" => self.max_memory.policy.name().to_string(),
+            "maxmemory-samples" => self.max_memory.samples.to_string(),
+            "port" => self.port.to_string(),
+            "repl-backlog-size" => self.repl_backlog_size.to_string(),
+            "replica-read-only" => yes_or_no(self.replica_read_only),
+            "replicaof" => self
+                .replica_of
+                .as_ref()
+                .map_or_else(String::new, |(host, port)| format!("{host} {port}")),
+            "save" => self
+                .save_points
+                .iter()
+                .map(|(seconds, changes)| format!("{seconds} {changes}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => unreachable!("unknown setting {name:?}"),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), SetError> {
+        match name.to_ascii_lowercase().as_str() {
+            "appenddirname" | "appendfilename" => {
+                if value.contains('/') {
+                    return Err(SetError::Invalid(
+                        "appendfilename and appenddirname can't be paths, just names",
+                    ));
+                }
+                if name.eq_ignore_ascii_case("appenddirname") {
+                    value.clone_into(&mut self.append_dirname);
+                } else {
+                    value.clone_into(&mut self.append_filename);
+                }
+            }
+            "appendfsync" => {
+                self.append_fsync = Fsync::from_name(value).ok_or(SetError::Invalid(
+                    "argument(s) must be one of the following: always, everysec, no",
+                ))?;
+            }
+            "appendonly" => self.append_only = parse_yes_or_no(value)?,
+            "cluster-enabled" => self.cluster_enabled = parse_yes_or_no(value)?,
+            "cluster-node-timeout" => {
+                self.cluster_node_timeout = value
+                    .parse()
+                    .ok()
+                    .filter(|timeout| *timeout > 0)
+                    .ok_or(SetError::Invalid("argument must be greater than 0"))?;
+            }
+            "cluster-port" => {
+                self.cluster_port = value.parse().map_err(|_| {
+                    SetError::Invalid("argument must be between 0 and 65535 inclusive")
+                })?;
+            }
+            "databases" => {
+                self.databases = value
+                    .parse()
+                    .ok()
+                    .filter(|databases| (1..=i32::MAX as usize).contains(databases))
+                    .ok_or(SetError::Invalid(
+                        "argument must be between 1 and 2147483647 inclusive",
+                    ))?;
+            }
+            "dbfilename" => {
+                if value.contains('/') {
+                    return Err(SetError::Invalid(
+                        "dbfilename can't be a path, just a filename",
+                    ));
+                }
+                value.clone_into(&mut self.db_filename);
+            }
+            "dir" => {
+                if !Path::new(value).is_dir() {
+                    return Err(SetError::Invalid("No such file or directory"));
+                }
+                self.dir = PathBuf::from(value);
+            }
+            "maxmemory" => {
+                self.max_memory.limit = parse_memory(value)
+                    .ok_or(SetError::Invalid("argument must be a memory value"))?;
+            }
+            "maxmemory-policy" => {
+                self.max_memory.policy =
+                    Policy::from_name(value).ok_or(SetError::Invalid(INVALID_POLICY))?;
+            }
+            "maxmemory-samples" => {
+                self.max_memory.samples = value
+                    .parse()
+                    .ok()
+                    .filter(|samples| (1..=64).contains(samples))
+                    .ok_or(SetError::Invalid(
+                        "argument must be between 1 and 64 inclusive",
+                    ))?;
+            }
+            "port" => {
+                self.port = value.parse().map_err(|_| {
+                    SetError::Invalid("argument must be between 0 and 65535 inclusive")
+                })?;
+            }
+            "repl-backlog-size" => {
+                self.repl_backlog_size = parse_memory(value)
+                    .filter(|size| *size > 0)
+                    .ok_or(SetError::Invalid("argument must be a memory value"))?;
+            }
+            "replica-read-only" => self.replica_read_only = parse_yes_or_no(value)?,
+            "replicaof" => {
+                self.replica_of = parse_replica_of(value)?;
+            }
+            // Directives such as `monitor mymaster 127.0.0.1 6379 2`, as in the configuration file
+            // of a sentinel
+            "sentinel" => {
+                let monitors = self.sentinel.get_or_insert_with(Vec::new);
+                if !value.is_empty() {
+                    sentinel::configure(monitors, value).map_err(SetError::Invalid)?;
+                }
+            }
+            "save" => {
+                self.save_points =
+                    parse_save_points(value).ok_or(SetError::Invalid("Invalid save parameters"))?;
+            }
+            _ => return Err(SetError::Unknown),
+        }
+
+        Ok(())
+    }
+
+    // As per CONFIG SET, which cannot change some settings
+    pub fn update(&mut self, name: &str, value: &str) -> Result<(), SetError> {
+        if IMMUTABLE
+            .iter()
+            .any(|immutable| immutable.eq_ignore_ascii_case(name))
+        {
+            return Err(SetError::Immutable);
+        }
+
+        self.set(name, value)
+    }
+
+    pub fn rdb_path(&self) -> PathBuf {
+        self.dir.join(&self.db_filename)
+    }
+
+    // The directory holding the files making up the append-only file, and its manifest
+    pub fn aof_dir(&self) -> PathBuf {
+        self.dir.join(&self.append_dirname)
+    }
+}
+
+const INVALID_POLICY: &str = "argument(s) must be one of the following: noeviction, allkeys-lru, \
+    allkeys-lfu, allkeys-random, volatile-lru, volatile-lfu, volatile-random, volatile-ttl";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SetError {
+    Unknown,
+    Immutable,
+    // Why the value is invalid
+    Invalid(&'static str),
+}
+
+// Either `no one`, or the host and port of the master
+fn parse_replica_of(value: &str) -> Result<Option<(String, u16)>, SetError> {
+    let arguments = value.split_whitespace().collect::<Vec<_>>();
+    match arguments[..] {
+        [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => Ok(None),
+        [host, port] => {
+            let port = port
+                .parse()
+                .map_err(|_| SetError::Invalid("Invalid master port"))?;
+            Ok(Some((host.to_string(), port)))
+        }
+        _ => Err(SetError::Invalid("wrong number of arguments")),
+    }
+}
+
+// Sizes such as `100mb`, where `k`, `m` and `g` are powers of 1000, and `kb`, `mb` and `gb` powers
+// of 1024, as per `memtoull` in Redis
+fn parse_memory(value: &str) -> Option<usize> {
+    let value = value.to_ascii_lowercase();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier = match &value[digits.len()..] {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+fn yes_or_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn parse_yes_or_no(value: &str) -> Result<bool, SetError> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(SetError::Invalid("argument must be 'yes' or 'no'")),
+    }
+}
+
+// Pairs of seconds and changes, such as `3600 1 300 100`, or none for an empty string
+fn parse_save_points(value: &str) -> Option<Vec<(u64, u64)>> {
+    let values = value
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if values.len() % 2 != 0 {
+        return None;
+    }
+
+    Some(values.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(parse_memory("0"), Some(0));
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1k"), Some(1000));
+        assert_eq!(parse_memory("1KB"), Some(1024));
+        assert_eq!(parse_memory("2mb"), Some(2 * 1024 * 1024));
+        assert_eq!(parse_memory("1g"), Some(1_000_000_000));
+        assert_eq!(parse_memory("1tb"), None);
+        assert_eq!(parse_memory("-1"), None);
+        assert_eq!(parse_memory("mb"), None);
+    }
+
+    #[test]
+    fn from_args() {
+        let args = ["--maxmemory", "1mb", "--maxmemory-policy", "allkeys-lfu"];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        assert_eq!(config.max_memory.limit, 1024 * 1024);
+        assert_eq!(config.max_memory.policy, Policy::AllKeysLfu);
+        assert_eq!(config.max_memory.samples, 5);
+
+        assert!(Config::from_args(["--maxmemory".to_string()]).is_err());
+        assert!(Config::from_args(["maxmemory", "1"].map(String::from)).is_err());
+        assert!(Config::from_args(["--nope", "1"].map(String::from)).is_err());
+        assert!(Config::from_args(["--maxmemory-samples", "0"].map(String::from)).is_err());
+
+        let config = Config::from_args(["--databases", "4"].map(String::from)).unwrap();
+        assert_eq!(config.databases, 4);
+        assert!(Config::from_args(["--databases", "0"].map(String::from)).is_err());
+
+        let args = ["--dir", "/tmp", "--dbfilename", "seed.rdb"];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        assert_eq!(config.rdb_path(), Path::new("/tmp/seed.rdb"));
+        assert!(Config::from_args(["--dir", "/nonexistent"].map(String::from)).is_err());
+        assert!(Config::from_args(["--dbfilename", "a/b.rdb"].map(String::from)).is_err());
+
+        let config = Config::from_args(["--save", "60 1 10 5"].map(String::from)).unwrap();
+        assert_eq!(config.save_points, vec![(60, 1), (10, 5)]);
+        let config = Config::from_args(["--save", ""].map(String::from)).unwrap();
+        assert!(config.save_points.is_empty());
+        assert!(Config::from_args(["--save", "60"].map(String::from)).is_err());
+        assert!(Config::from_args(["--save", "60 x"].map(String::from)).is_err());
+
+        let args = ["--appendonly", "yes", "--appendfsync", "always"];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        assert!(config.append_only);
+        assert_eq!(config.append_fsync, Fsync::Always);
+        assert_eq!(config.aof_dir(), Path::new("./appendonlydir"));
+        assert!(Config::from_args(["--appendonly", "maybe"].map(String::from)).is_err());
+        assert!(Config::from_args(["--appenddirname", "a/b"].map(String::from)).is_err());
+
+        let args = [
+            "--port",
+            "6380",
+            "--replicaof",
+            "localhost",
+            "6379",
+            "--databases",
+            "2",
+        ];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        assert_eq!(config.port, 6380);
+        assert_eq!(config.replica_of, Some(("localhost".to_string(), 6379)));
+        assert_eq!(config.databases, 2);
+        let config =
+            Config::from_args(["--replicaof", "localhost 6379"].map(String::from)).unwrap();
+        assert_eq!(config.value("replicaof"), "localhost 6379");
+        let config = Config::from_args(["--replicaof", "NO ONE"].map(String::from)).unwrap();
+        assert_eq!(config.replica_of, None);
+
+        let args = ["--cluster-enabled", "yes", "--cluster-node-timeout", "5000"];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        assert!(config.cluster_enabled);
+        assert_eq!(config.cluster_node_timeout, 5000);
+        assert_eq!(config.cluster_port, 0);
+        assert!(Config::from_args(["--cluster-node-timeout", "0"].map(String::from)).is_err());
+        assert!(Config::from_args(["--replicaof", "localhost"].map(String::from)).is_err());
+        assert!(Config::from_args(["--port", "65536"].map(String::from)).is_err());
+
+        let config = Config::from_args(["--sentinel".to_string()]).unwrap();
+        assert_eq!(config.sentinel, Some(Vec::new()));
+        assert_eq!(config.port, 26379);
+        let args = [
+            "--port",
+            "5000",
+            "--sentinel",
+            "monitor",
+            "mymaster",
+            "127.0.0.1",
+            "6379",
+            "2",
+            "--sentinel",
+            "down-after-milliseconds mymaster 1000",
+        ];
+        let config = Config::from_args(args.map(String::from)).unwrap();
+        assert_eq!(config.port, 5000);
+        let monitors = config.sentinel.unwrap();
+        assert_eq!(monitors[0].quorum, 2);
+        assert_eq!(monitors[0].down_after.as_millis(), 1000);
+        let args = ["--sentinel", "down-after-milliseconds", "nope", "1000"];
+        assert!(Config::from_args(args.map(String::from)).is_err());
+    }
+
+    #[test]
+    fn get_and_set() {
+        let mut config = Config::default();
+        assert_eq!(config.set("MAXMEMORY-POLICY", "volatile-ttl"), Ok(()));
+        assert_eq!(
+            config.set("maxmemory-policy", "lru"),
+            Err(SetError::Invalid(INVALID_POLICY))
+        );
+        assert_eq!(config.set("nope", "1"), Err(SetError::Unknown));
+        assert_eq!(config.update("DATABASES", "1"), Err(SetError::Immutable));
+        assert_eq!(
+            config.update("appendfilename", "a.aof"),
+            Err(SetError::Immutable)
+        );
+        assert_eq!(config.update("appendfsync", "no"), Ok(()));
+        assert_eq!(config.update("maxmemory", "1"), Ok(()));
+        assert_eq!(config.set("maxmemory", "0"), Ok(()));
+        assert_eq!(config.update("repl-backlog-size", "16kb"), Ok(()));
+        assert_eq!(config.repl_backlog_size, 16 * 1024);
+        assert!(config.update("repl-backlog-size", "0").is_err());
+        assert_eq!(config.update("replica-read-only", "NO"), Ok(()));
+        assert_eq!(config.value("replica-read-only"), "no");
+        assert!(config.update("replica-read-only", "maybe").is_err());
+
+        assert_eq!(
+            config.get("maxmemory*"),
+            vec![
+                ("maxmemory", "0".to_string()),
+                ("maxmemory-policy", "volatile-ttl".to_string()),
+                ("maxmemory-samples", "5".to_string()),
+            ]
+        );
+        assert_eq!(
+            config.get("MAXMEMORY"),
+            vec![("maxmemory", "0".to_string())]
+        );
+        assert!(config.get("nope").is_empty());
+        assert_eq!(
+            config.get("append*"),
+            vec![
+                ("appenddirname", "appendonlydir".to_string()),
+                ("appendfilename", "appendonly.aof".to_string()),
+                ("appendfsync", "no".to_string()),
+                ("appendonly", "no".to_string()),
+            ]
+        );
+        assert_eq!(
+            config.get("save"),
+            vec![("save", "3600 1 300 100 60 10000".to_string())]
+        );
+        assert_eq!(
+            config.get("d*"),
+            vec![
+                ("databases", "16".to_string()),
+                ("dbfilename", "dump.rdb".to_string()),
+                ("dir", ".".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use crate::resp::{command, Error as RespError, RespType};
+use anyhow::{bail, Context, Result};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+// Whether reading failed because nothing arrived within the read timeout
+pub fn is_timeout(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|error| matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut))
+}
+
+// A connection to another instance, such as between a master and replica, buffering what is read
+// from it
+pub struct Connection {
+    pub stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    pub const fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+        }
+    }
+
+    // Returns false once the other end has closed the connection
+    fn fill(&mut self) -> Result<bool> {
+        let mut read_buffer = [0; 16 * 1024];
+        let read = self.stream.read(&mut read_buffer)?;
+        self.buffer.extend_from_slice(&read_buffer[..read]);
+        Ok(read > 0)
+    }
+
+    pub fn send(&mut self, arguments: &[&[u8]]) -> Result<()> {
+        self.stream.write_all(&command(arguments))?;
+        Ok(())
+    }
+
+    // Sends a command during the handshake, which fails unless the reply is as expected
+    pub fn expect(&mut self, arguments: &[&[u8]], expected: &str) -> Result<()> {
+        self.send(arguments)?;
+        let reply = self.read_line()?;
+        if reply != expected {
+            let name = String::from_utf8_lossy(arguments[0]);
+            bail!("unexpected reply to {name}: {reply:?}");
+        }
+        Ok(())
+    }
+
+    pub fn read_line(&mut self) -> Result<String> {
+        loop {
+            if let Some(end) = self.buffer.windows(2).position(|window| window == b"\r\n") {
+                let line = self.buffer.drain(..end + 2).take(end).collect::<Vec<_>>();
+                return String::from_utf8(line).context("reply is not UTF-8");
+            }
+            if !self.fill()? {
+                bail!("connection closed");
+            }
+        }
+    }
+
+    pub fn read_exact(&mut self, length: usize) -> Result<Vec<u8>> {
+        while self.buffer.len() < length {
+            if !self.fill()? {
+                bail!("connection closed");
+            }
+        }
+        Ok(self.buffer.drain(..length).collect())
+    }
+
+    // The next complete request, or `None` once the connection is closed
+    pub fn read_request(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            match RespType::parse_prefix(&self.buffer) {
+                Ok((_, rest)) => {
+                    let length = self.buffer.len() - rest.len();
+                    return Ok(Some(self.buffer.drain(..length).collect()));
+                }
+                Err(RespError::EmptyValue | RespError::UnterminatedSequence) => {}
+                Err(error) => bail!("invalid request: {error}"),
+            }
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::hash::{BuildHasher, RandomState};
+
+// Like Redis, tables start with a few buckets, grow once there are as many entries as buckets and
+// shrink once less than 10% of the buckets would be used
+const INITIAL_SIZE: usize = 4;
+const MIN_FILL_PERCENT: usize = 10;
+
+// A chained hash table with a power of two number of buckets. Unlike `HashMap`, the buckets are
+// exposed through `scan`, which allows iterating over the table across calls with a cursor.
+// See: https://github.com/redis/redis/blob/unstable/src/dict.c
+pub struct Dict<V> {
+    buckets: Vec<Vec<(String, V)>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<V> Dict<V> {
+    pub fn new() -> Self {
+        Self {
+            buckets: Self::empty_buckets(INITIAL_SIZE),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, key: &str) -> Option<&V> {
+        // Saves hashing the key, such as when checking for a deadline when no keys expire
+        if self.len == 0 {
+            return None;
+        }
+
+        self.buckets[self.bucket(key)]
+            .iter()
+            .find_map(|(k, value)| (k == key).then_some(value))
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut V> {
+        let bucket = self.bucket(key);
+        self.buckets[bucket]
+            .iter_mut()
+            .find_map(|(k, value)| (k == key).then_some(value))
+    }
+
+    pub fn get_or_insert_with(&mut self, key: &str, default: impl FnOnce() -> V) -> &mut V {
+        let bucket = self.bucket(key);
+        match self.buckets[bucket].iter().position(|(k, _)| k == key) {
+            Some(position) => &mut self.buckets[bucket][position].1,
+            None => self.insert_new(key.to_string(), default()),
+        }
+    }
+
+    // Returns the previous value, if there was one
+    pub fn insert(&mut self, key: String, value: V) -> Option<V> {
+        match self.get_mut(&key) {
+            Some(previous) => Some(std::mem::replace(previous, value)),
+            None => {
+                self.insert_new(key, value);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let bucket = self.bucket(key);
+        let position = self.buckets[bucket].iter().position(|(k, _)| k == key)?;
+        let (_, value) = self.buckets[bucket].swap_remove(position);
+        self.len -= 1;
+
+        if self.buckets.len() > INITIAL_SIZE
+            && self.len * 100 / self.buckets.len() < MIN_FILL_PERCENT
+        {
+            self.resize(self.len.next_power_of_two().max(INITIAL_SIZE));
+        }
+
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.as_str(), value))
+    }
+
+    // Visits the entries of the bucket at `cursor`, returning the cursor to continue from, with 0
+    // once all buckets have been visited. The cursor's bits are incremented from the most
+    // significant end, so that entries present for a whole scan are visited at least once, even if
+    // the table is resized between calls. Entries may be visited more than once after shrinking.
+    // See: https://github.com/redis/redis/blob/unstable/src/dict.c (`dictScan`)
+    pub fn scan(&self, cursor: u64, mut visit: impl FnMut(&str, &V)) -> u64 {
+        let mask = self.buckets.len() as u64 - 1;
+        #[allow(clippy::cast_possible_truncation)]
+        for (key, value) in &self.buckets[(cursor & mask) as usize] {
+            visit(key, value);
+        }
+
+        // Increment the unmasked bits of the reversed cursor
+        (cursor | !mask)
+            .reverse_bits()
+            .wrapping_add(1)
+            .reverse_bits()
+    }
+
+    // Up to `count` entries from consecutive buckets, starting from a bucket chosen by `start`.
+    // These are cheap to find, but not uniformly distributed, which is fine for picking keys to
+    // evict. See: https://github.com/redis/redis/blob/unstable/src/dict.c (`dictGetSomeKeys`)
+    pub fn sample(&self, start: u64, count: usize) -> impl Iterator<Item = (&str, &V)> {
+        #[allow(clippy::cast_possible_truncation)]
+        let start = start as usize;
+        let mask = self.buckets.len() - 1;
+        let buckets = if self.len == 0 { 0 } else { self.buckets.len() };
+
+        (0..buckets)
+            .flat_map(move |i| &self.buckets[start.wrapping_add(i) & mask])
+            .map(|(key, value)| (key.as_str(), value))
+            .take(count)
+    }
+
+    fn bucket(&self, key: &str) -> usize {
+        #[allow(clippy::cast_possible_truncation)]
+        let hash = self.hasher.hash_one(key) as usize;
+        hash & (self.buckets.len() - 1)
+    }
+
+    fn insert_new(&mut self, key: String, value: V) -> &mut V {
+        if self.len >= self.buckets.len() {
+            self.resize((self.len + 1).next_power_of_two());
+        }
+
+        let bucket = self.bucket(&key);
+        let entries = &mut self.buckets[bucket];
+        entries.push((key, value));
+        self.len += 1;
+
+        let position = entries.len() - 1;
+        &mut entries[position].1
+    }
+
+    fn resize(&mut self, size: usize) {
+        let buckets = std::mem::replace(&mut self.buckets, Self::empty_buckets(size));
+        for (key, value) in buckets.into_iter().flatten() {
+            let bucket = self.bucket(&key);
+            self.buckets[bucket].push((key, value));
+        }
+    }
+
+    fn empty_buckets(size: usize) -> Vec<Vec<(String, V)>> {
+        std::iter::repeat_with(Vec::new).take(size).collect()
+    }
+}
+
+impl<V> Default for Dict<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn scan_all(dict: &Dict<usize>, mut cursor: u64, steps: usize) -> (HashSet<String>, u64) {
+        let mut seen = HashSet::new();
+        for _ in 0..steps {
+            cursor = dict.scan(cursor, |key, _| {
+                seen.insert(key.to_string());
+            });
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        (seen, cursor)
+    }
+
+    #[test]
+    fn insert_get_remove() {
+        let mut dict = Dict::new();
+        for i in 0..100 {
+            assert_eq!(dict.insert(i.to_string(), i), None);
+        }
+        assert_eq!(dict.insert("5".to_string(), 50), Some(5));
+        assert_eq!(dict.len(), 100);
+        assert_eq!(dict.get("5"), Some(&50));
+        assert_eq!(dict.buckets.len(), 128);
+
+        for i in 0..100 {
+            assert!(dict.remove(&i.to_string()).is_some());
+        }
+        assert_eq!(dict.len(), 0);
+        assert_eq!(dict.get("5"), None);
+        assert_eq!(dict.buckets.len(), INITIAL_SIZE);
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut dict = Dict::new();
+        *dict.get_or_insert_with("a", || 1) += 1;
+        *dict.get_or_insert_with("a", || 1) += 1;
+
+        assert_eq!(dict.get("a"), Some(&3));
+        assert_eq!(dict.iter().count(), 1);
+    }
+
+    #[test]
+    fn sample() {
+        let mut dict = Dict::new();
+        assert_eq!(dict.sample(0, 5).count(), 0);
+
+        for i in 0..100 {
+            dict.insert(i.to_string(), i);
+        }
+        for start in [0, 7, u64::MAX] {
+            let sampled = dict
+                .sample(start, 5)
+                .map(|(key, _)| key)
+                .collect::<HashSet<_>>();
+            assert_eq!(sampled.len(), 5);
+        }
+        assert_eq!(dict.sample(0, 1000).count(), 100);
+    }
+
+    #[test]
+    fn scan_visits_everything() {
+        let mut dict = Dict::new();
+        for i in 0..1000 {
+            dict.insert(i.to_string(), i);
+        }
+
+        let (seen, cursor) = scan_all(&dict, 0, usize::MAX);
+        assert_eq!(cursor, 0);
+        assert_eq!(seen.len(), 1000);
+    }
+
+    #[test]
+    fn scan_survives_growing() {
+        let mut dict = Dict::new();
+        for i in 0..100 {
+            dict.insert(i.to_string(), i);
+        }
+
+        let (mut seen, cursor) = scan_all(&dict, 0, 50);
+        for i in 100..10_000 {
+            dict.insert(i.to_string(), i);
+        }
+        seen.extend(scan_all(&dict, cursor, usize::MAX).0);
+
+        assert!((0..100).all(|i| seen.contains(&i.to_string())));
+    }
+
+    #[test]
+    fn scan_survives_shrinking() {
+        let mut dict = Dict::new();
+        for i in 0..10_000 {
+            dict.insert(i.to_string(), i);
+        }
+
+        let (mut seen, cursor) = scan_all(&dict, 0, 5000);
+        for i in 100..10_000 {
+            dict.remove(&i.to_string());
+        }
+        seen.extend(scan_all(&dict, cursor, usize::MAX).0);
+
+        assert!((0..100).all(|i| seen.contains(&i.to_string())));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::store::now;
+use std::{
+    cell::Cell,
+    hash::{BuildHasher, RandomState},
+};
+
+// How keys are chosen to be evicted once the memory limit is reached
+// See: https://redis.io/docs/latest/develop/reference/eviction/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl Policy {
+    const ALL: [Self; 8] = [
+        Self::NoEviction,
+        Self::AllKeysLru,
+        Self::AllKeysLfu,
+        Self::AllKeysRandom,
+        Self::VolatileLru,
+        Self::VolatileLfu,
+        Self::VolatileRandom,
+        Self::VolatileTtl,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::NoEviction => "noeviction",
+            Self::AllKeysLru => "allkeys-lru",
+            Self::AllKeysLfu => "allkeys-lfu",
+            Self::AllKeysRandom => "allkeys-random",
+            Self::VolatileLru => "volatile-lru",
+            Self::VolatileLfu => "volatile-lfu",
+            Self::VolatileRandom => "volatile-random",
+            Self::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+
+    // For keeping the policy in an atomic
+    pub const fn to_index(self) -> u8 {
+        self as u8
+    }
+
+    pub const fn from_index(index: u8) -> Self {
+        Self::ALL[index as usize]
+    }
+
+    // Only keys with a deadline are evicted
+    pub const fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            Self::VolatileLru | Self::VolatileLfu | Self::VolatileRandom | Self::VolatileTtl
+        )
+    }
+
+    pub const fn is_lfu(self) -> bool {
+        matches!(self, Self::AllKeysLfu | Self::VolatileLfu)
+    }
+
+    pub const fn is_random(self) -> bool {
+        matches!(self, Self::AllKeysRandom | Self::VolatileRandom)
+    }
+}
+
+// When, and how, keys are evicted, where a limit of 0 means there is none
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxMemory {
+    pub limit: usize,
+    pub policy: Policy,
+    // How many keys are sampled from each shard to find the best ones to evict
+    pub samples: usize,
+}
+
+impl Default for MaxMemory {
+    fn default() -> Self {
+        Self {
+            limit: 0,
+            policy: Policy::NoEviction,
+            samples: 5,
+        }
+    }
+}
+
+// LFU counters start above zero, so new keys are not evicted straight away, grow logarithmically
+// with accesses, and decrement for each minute without any. These are the defaults for
+// `lfu-log-factor` and `lfu-decay-time` in Redis.
+const LFU_INIT_VAL: u64 = 5;
+const LFU_LOG_FACTOR: f64 = 10.0;
+const LFU_DECAY_MINUTES: u64 = 1;
+const LFU_COUNTER_MAX: u64 = 255;
+
+// Like Redis, each key records either when it was last accessed, for LRU and the other policies, or
+// for LFU an 8 bit access counter along with the minute it last decayed in the bits above
+pub fn initial_access(policy: Policy) -> u64 {
+    if policy.is_lfu() {
+        minutes() << 8 | LFU_INIT_VAL
+    } else {
+        now()
+    }
+}
+
+pub fn access(policy: Policy, previous: u64) -> u64 {
+    if !policy.is_lfu() {
+        return now();
+    }
+
+    let counter = lfu_decay(previous);
+    let counter = if counter < LFU_COUNTER_MAX {
+        // The more accesses there have been, the less likely another one is counted
+        #[allow(clippy::cast_precision_loss)]
+        let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+        #[allow(clippy::cast_precision_loss)]
+        let random = random() as f64 / u64::MAX as f64;
+        counter + u64::from(random < 1.0 / base.mul_add(LFU_LOG_FACTOR, 1.0))
+    } else {
+        counter
+    };
+
+    minutes() << 8 | counter
+}
+
+// The access recorded for a key restored with IDLETIME or FREQ, as per `objectSetLRUOrLFU` in
+// Redis, which ignores whichever doesn't match the policy
+pub fn restored_access(
+    policy: Policy,
+    idle_time: Option<u64>,
+    frequency: Option<u8>,
+) -> Option<u64> {
+    if policy.is_lfu() {
+        frequency.map(|frequency| minutes() << 8 | u64::from(frequency))
+    } else {
+        idle_time.map(|seconds| now().saturating_sub(seconds.saturating_mul(1000)))
+    }
+}
+
+// How strongly a key should be evicted, so the higher the better
+pub fn idle(policy: Policy, access: u64, deadline: Option<u64>) -> u64 {
+    match policy {
+        Policy::VolatileTtl => u64::MAX - deadline.unwrap_or(u64::MAX),
+        policy if policy.is_lfu() => LFU_COUNTER_MAX - lfu_decay(access),
+        _ => now().saturating_sub(access),
+    }
+}
+
+// The LFU counter, having decayed for each period since the key was last accessed, as reported by
+// OBJECT FREQ
+pub fn lfu_decay(access: u64) -> u64 {
+    let counter = access & LFU_COUNTER_MAX;
+    let periods = minutes().saturating_sub(access >> 8) / LFU_DECAY_MINUTES;
+    counter.saturating_sub(periods)
+}
+
+fn minutes() -> u64 {
+    now() / 60_000
+}
+
+// Like Redis, enough of the best keys to evict are kept between evictions, so that each eviction
+// has more than just the latest sample to choose from
+const POOL_SIZE: usize = 16;
+
+#[derive(Debug, Default)]
+pub struct EvictionPool {
+    // Ordered by how strongly each key should be evicted, best last, along with its shard and
+    // database
+    entries: Vec<(u64, usize, usize, String)>,
+}
+
+impl EvictionPool {
+    pub fn insert(&mut self, idle: u64, shard: usize, db: usize, key: &str) {
+        if let Some(position) = self
+            .entries
+            .iter()
+            .position(|(_, s, d, k)| *s == shard && *d == db && k == key)
+        {
+            self.entries.remove(position);
+        }
+        if self.entries.len() == POOL_SIZE {
+            if idle <= self.entries[0].0 {
+                return;
+            }
+            self.entries.remove(0);
+        }
+
+        let position = self.entries.partition_point(|(i, _, _, _)| *i <= idle);
+        self.entries
+            .insert(position, (idle, shard, db, key.to_string()));
+    }
+
+    // The best key to evict, along with its shard and database
+    pub fn pop(&mut self) -> Option<(usize, usize, String)> {
+        self.entries
+            .pop()
+            .map(|(_, shard, db, key)| (shard, db, key))
+    }
+}
+
+// A xorshift generator for each thread, which is plenty for sampling keys
+pub fn random() -> u64 {
+    thread_local! {
+        static STATE: Cell<u64> = Cell::new(RandomState::new().hash_one(0) | 1);
+    }
+
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn policy_names() {
+        for policy in Policy::ALL {
+            assert_eq!(Policy::from_name(policy.name()), Some(policy));
+            assert_eq!(Policy::from_index(policy.to_index()), policy);
+        }
+        assert_eq!(Policy::from_name("ALLKEYS-LRU"), Some(Policy::AllKeysLru));
+        assert_eq!(Policy::from_name("lru"), None);
+    }
+
+    #[test]
+    fn lfu_counter_grows_logarithmically() {
+        let mut metadata = initial_access(Policy::AllKeysLfu);
+        assert_eq!(metadata & LFU_COUNTER_MAX, LFU_INIT_VAL);
+
+        for _ in 0..1000 {
+            metadata = access(Policy::AllKeysLfu, metadata);
+        }
+        let counter = metadata & LFU_COUNTER_MAX;
+        assert!(counter > 10 && counter < 40, "{counter}");
+        assert_eq!(idle(Policy::AllKeysLfu, metadata, None), 255 - counter);
+    }
+
+    #[test]
+    fn lfu_counter_decays() {
+        let access = (minutes() - 3) << 8 | 10;
+        assert_eq!(lfu_decay(access), 7);
+
+        let access = (minutes() - 100) << 8 | 10;
+        assert_eq!(lfu_decay(access), 0);
+    }
+
+    #[test]
+    fn idle_scores() {
+        assert!(idle(Policy::AllKeysLru, now() - 1000, None) >= 1000);
+        assert!(idle(Policy::VolatileTtl, 0, Some(10)) > idle(Policy::VolatileTtl, 0, Some(20)));
+    }
+
+    #[test]
+    fn pool_keeps_the_best() {
+        let mut pool = EvictionPool::default();
+        for idle in 0..100 {
+            pool.insert(idle, 0, 0, &idle.to_string());
+        }
+        pool.insert(50, 0, 0, "50");
+        pool.insert(98, 1, 0, "again");
+        pool.insert(99, 0, 0, "99");
+        pool.insert(97, 0, 1, "99");
+
+        assert_eq!(pool.entries.len(), POOL_SIZE);
+        assert_eq!(pool.pop(), Some((0, 0, "99".to_string())));
+        assert_eq!(pool.pop(), Some((1, 0, "again".to_string())));
+        assert_eq!(pool.pop(), Some((0, 0, "98".to_string())));
+        assert_eq!(pool.pop(), Some((0, 1, "99".to_string())));
+        assert!(pool.entries.iter().all(|(idle, _, _, _)| *idle >= 86));
+    }
+}
@@ -0,0 +1,531 @@
+// Geospatial indexing on top of sorted sets, where the score of each member is its location as a
+// 52-bit interleaved geohash, as per Redis
+// See: https://github.com/redis/redis/blob/unstable/src/geohash.c
+// See: https://github.com/redis/redis/blob/unstable/src/geohash_helper.c
+
+use crate::sorted_set::SortedSet;
+
+pub const LONGITUDE_MIN: f64 = -180.0;
+pub const LONGITUDE_MAX: f64 = 180.0;
+pub const LATITUDE_MIN: f64 = -85.051_128_78;
+pub const LATITUDE_MAX: f64 = 85.051_128_78;
+
+const STEP_MAX: u8 = 26;
+const EARTH_RADIUS_IN_METERS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+const ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unit {
+    Meters,
+    Kilometers,
+    Feet,
+    Miles,
+}
+
+impl Unit {
+    pub const fn to_meters(self) -> f64 {
+        match self {
+            Self::Meters => 1.0,
+            Self::Kilometers => 1000.0,
+            Self::Feet => 0.3048,
+            Self::Miles => 1609.34,
+        }
+    }
+}
+
+impl TryFrom<&str> for Unit {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            x if x.eq_ignore_ascii_case("m") => Ok(Self::Meters),
+            x if x.eq_ignore_ascii_case("km") => Ok(Self::Kilometers),
+            x if x.eq_ignore_ascii_case("ft") => Ok(Self::Feet),
+            x if x.eq_ignore_ascii_case("mi") => Ok(Self::Miles),
+            _ => Err("ERR unsupported unit provided. please use M, KM, FT, MI"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Radius(f64),
+    Box(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin<'a> {
+    Member(&'a [u8]),
+    Coordinates(f64, f64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+// The options for GEOSEARCH and GEOSEARCHSTORE, where the shape is in `unit`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Search<'a> {
+    pub origin: Origin<'a>,
+    pub shape: Shape,
+    pub unit: Unit,
+    pub order: Option<Order>,
+    pub count: Option<usize>,
+    pub any: bool,
+    pub with_coordinates: bool,
+    pub with_distance: bool,
+    pub with_hash: bool,
+    pub store_distance: bool,
+}
+
+pub struct Point<'a> {
+    pub member: &'a [u8],
+    pub score: f64,
+    pub longitude: f64,
+    pub latitude: f64,
+    // In meters
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Hash {
+    bits: u64,
+    step: u8,
+}
+
+impl Hash {
+    const ZERO: Self = Self { bits: 0, step: 0 };
+
+    // The range of scores covered by this cell, with the maximum exclusive
+    const fn scores(self) -> (u64, u64) {
+        let shift = 52 - self.step as u32 * 2;
+        (self.bits << shift, (self.bits + 1) << shift)
+    }
+
+    fn moved(mut self, x: i8, y: i8) -> Self {
+        let step = self.step as u32 * 2;
+        if x != 0 {
+            let mut lon = self.bits & 0xaaaa_aaaa_aaaa_aaaa;
+            let lat = self.bits & 0x5555_5555_5555_5555;
+            let zz = 0x5555_5555_5555_5555 >> (64 - step);
+            lon = if x > 0 {
+                lon.wrapping_add(zz + 1)
+            } else {
+                (lon | zz).wrapping_sub(zz + 1)
+            };
+            lon &= 0xaaaa_aaaa_aaaa_aaaa >> (64 - step);
+            self.bits = lon | lat;
+        }
+        if y != 0 {
+            let lon = self.bits & 0xaaaa_aaaa_aaaa_aaaa;
+            let mut lat = self.bits & 0x5555_5555_5555_5555;
+            let zz = 0xaaaa_aaaa_aaaa_aaaa >> (64 - step);
+            lat = if y > 0 {
+                lat.wrapping_add(zz + 1)
+            } else {
+                (lat | zz).wrapping_sub(zz + 1)
+            };
+            lat &= 0x5555_5555_5555_5555 >> (64 - step);
+            self.bits = lon | lat;
+        }
+
+        self
+    }
+}
+
+struct Area {
+    longitude: (f64, f64),
+    latitude: (f64, f64),
+}
+
+pub fn is_valid(longitude: f64, latitude: f64) -> bool {
+    (LONGITUDE_MIN..=LONGITUDE_MAX).contains(&longitude)
+        && (LATITUDE_MIN..=LATITUDE_MAX).contains(&latitude)
+}
+
+// Encodes a location as a 52-bit score, which should have been validated with `is_valid`
+pub fn encode(longitude: f64, latitude: f64) -> u64 {
+    encode_with_ranges(
+        (LONGITUDE_MIN, LONGITUDE_MAX),
+        (LATITUDE_MIN, LATITUDE_MAX),
+        longitude,
+        latitude,
+        STEP_MAX,
+    )
+    .bits
+}
+
+// Decodes a score into the center of its cell, as longitude and latitude
+pub fn decode(score: u64) -> (f64, f64) {
+    let area = decode_with_ranges(
+        (LONGITUDE_MIN, LONGITUDE_MAX),
+        (LATITUDE_MIN, LATITUDE_MAX),
+        Hash {
+            bits: score,
+            step: STEP_MAX,
+        },
+    );
+
+    let longitude = (area.longitude.0 + area.longitude.1) / 2.0;
+    let latitude = (area.latitude.0 + area.latitude.1) / 2.0;
+    (
+        longitude.clamp(LONGITUDE_MIN, LONGITUDE_MAX),
+        latitude.clamp(LATITUDE_MIN, LATITUDE_MAX),
+    )
+}
+
+// The standard 11 character geohash, which unlike scores uses latitudes between -90 and 90
+pub fn geohash(score: u64) -> String {
+    let (longitude, latitude) = decode(score);
+    let hash = encode_with_ranges(
+        (-180.0, 180.0),
+        (-90.0, 90.0),
+        longitude,
+        latitude,
+        STEP_MAX,
+    );
+
+    (0..11)
+        .map(|index| {
+            // There are only 52 bits, so the last character is always zero
+            let character = if index == 10 {
+                0
+            } else {
+                (hash.bits >> (52 - (index + 1) * 5)) & 0x1f
+            };
+            char::from(ALPHABET[character as usize])
+        })
+        .collect()
+}
+
+// Haversine distance in meters
+pub fn distance(longitude1: f64, latitude1: f64, longitude2: f64, latitude2: f64) -> f64 {
+    let (latitude1, latitude2) = (latitude1.to_radians(), latitude2.to_radians());
+    let v = ((longitude2.to_radians() - longitude1.to_radians()) / 2.0).sin();
+    if v == 0.0 {
+        return latitude_distance(latitude1, latitude2);
+    }
+
+    let u = ((latitude2 - latitude1) / 2.0).sin();
+    let a = u * u + latitude1.cos() * latitude2.cos() * v * v;
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+// Formats coordinates the same as Redis, with 17 decimal places and trailing zeros removed
+pub fn format_coordinate(value: f64) -> String {
+    let formatted = format!("{value:.17}");
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+// Finds the members of `set` within `shape` (in meters) around the given center, checking the cell
+// containing the center and its neighbours, which gives the same order as Redis. `limit` stops the
+// search early, as used by `COUNT ... ANY`.
+pub fn search(
+    set: &SortedSet,
+    longitude: f64,
+    latitude: f64,
+    shape: Shape,
+    limit: Option<usize>,
+) -> Vec<Point<'_>> {
+    let mut points = Vec::new();
+    let cells = cells(longitude, latitude, shape);
+
+    // Neighbours can be the same cell for huge areas, so those matching the previously searched
+    // one are skipped, although like Redis this never applies to the center
+    let mut previous = 0;
+    for (index, cell) in cells.iter().enumerate() {
+        if *cell == Hash::ZERO || (previous != 0 && *cell == cells[previous]) {
+            continue;
+        }
+        if limit.is_some_and(|limit| points.len() >= limit) {
+            break;
+        }
+        previous = index;
+
+        let (min, max) = cell.scores();
+        #[allow(clippy::cast_precision_loss)]
+        for (member, score) in set.range(min as f64, max as f64) {
+            #[allow(clippy::cast_precision_loss)]
+            if score >= max as f64 {
+                break;
+            }
+
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let (x, y) = decode(score as u64);
+            if let Some(distance) = within(shape, longitude, latitude, x, y) {
+                points.push(Point {
+                    member,
+                    score,
+                    longitude: x,
+                    latitude: y,
+                    distance,
+                });
+                if limit.is_some_and(|limit| points.len() >= limit) {
+                    break;
+                }
+            }
+        }
+    }
+
+    points
+}
+
+fn within(shape: Shape, longitude: f64, latitude: f64, x: f64, y: f64) -> Option<f64> {
+    match shape {
+        Shape::Radius(radius) => {
+            let distance = distance(longitude, latitude, x, y);
+            (distance <= radius).then_some(distance)
+        }
+        Shape::Box(width, height) => {
+            if latitude_distance(latitude.to_radians(), y.to_radians()) > height / 2.0 {
+                return None;
+            }
+            if distance(x, y, longitude, y) > width / 2.0 {
+                return None;
+            }
+            Some(distance(longitude, latitude, x, y))
+        }
+    }
+}
+
+fn latitude_distance(latitude1: f64, latitude2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (latitude2 - latitude1).abs()
+}
+
+fn encode_with_ranges(
+    longitude_range: (f64, f64),
+    latitude_range: (f64, f64),
+    longitude: f64,
+    latitude: f64,
+    step: u8,
+) -> Hash {
+    let scale = f64::from(1u32 << step);
+    let latitude = (latitude - latitude_range.0) / (latitude_range.1 - latitude_range.0) * scale;
+    let longitude =
+        (longitude - longitude_range.0) / (longitude_range.1 - longitude_range.0) * scale;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    Hash {
+        bits: interleave(latitude as u32, longitude as u32),
+        step,
+    }
+}
+
+fn decode_with_ranges(longitude_range: (f64, f64), latitude_range: (f64, f64), hash: Hash) -> Area {
+    let (latitude, longitude) = deinterleave(hash.bits);
+    let scale = f64::from(1u32 << hash.step);
+    let (latitude, longitude) = (f64::from(latitude), f64::from(longitude));
+    let latitude_scale = latitude_range.1 - latitude_range.0;
+    let longitude_scale = longitude_range.1 - longitude_range.0;
+
+    Area {
+        latitude: (
+            latitude_range.0 + (latitude / scale) * latitude_scale,
+            latitude_range.0 + ((latitude + 1.0) / scale) * latitude_scale,
+        ),
+        longitude: (
+            longitude_range.0 + (longitude / scale) * longitude_scale,
+            longitude_range.0 + ((longitude + 1.0) / scale) * longitude_scale,
+        ),
+    }
+}
+
+// Latitude takes the even bits, and longitude the odd bits
+fn interleave(latitude: u32, longitude: u32) -> u64 {
+    fn spread(value: u32) -> u64 {
+        let mut value = u64::from(value);
+        value = (value | (value << 16)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value << 8)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        (value | (value << 1)) & 0x5555_5555_5555_5555
+    }
+
+    spread(latitude) | (spread(longitude) << 1)
+}
+
+fn deinterleave(value: u64) -> (u32, u32) {
+    fn squash(mut value: u64) -> u32 {
+        value &= 0x5555_5555_5555_5555;
+        value = (value | (value >> 1)) & 0x3333_3333_3333_3333;
+        value = (value | (value >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value >> 4)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value >> 8)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value >> 16)) & 0x0000_0000_ffff_ffff;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let value = value as u32;
+        value
+    }
+
+    (squash(value), squash(value >> 1))
+}
+
+fn estimate_step(mut radius: f64, latitude: f64) -> u8 {
+    if radius == 0.0 {
+        return STEP_MAX;
+    }
+
+    let mut step: i32 = 1;
+    while radius < MERCATOR_MAX {
+        radius *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+
+    // Cells get narrower towards the poles
+    if latitude.abs() > 66.0 {
+        step -= 1;
+        if latitude.abs() > 80.0 {
+            step -= 1;
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let step = step.clamp(1, i32::from(STEP_MAX)) as u8;
+    step
+}
+
+// The cell containing the center followed by its neighbours, in the order north, south, east, west,
+// north east, north west, south east and south west. Neighbours that cannot contain any results
+// are `Hash::ZERO`.
+fn cells(longitude: f64, latitude: f64, shape: Shape) -> [Hash; 9] {
+    let (half_width, half_height, radius) = match shape {
+        Shape::Radius(radius) => (radius, radius, radius),
+        Shape::Box(width, height) => (width / 2.0, height / 2.0, width.hypot(height) / 2.0),
+    };
+
+    // Bounding box of the search
+    let latitude_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+    let longitude_delta = |latitude: f64| {
+        (half_width / EARTH_RADIUS_IN_METERS / latitude.to_radians().cos()).to_degrees()
+    };
+    let longitude_delta = if latitude < 0.0 {
+        longitude_delta(latitude - latitude_delta)
+    } else {
+        longitude_delta(latitude + latitude_delta)
+    };
+    let (min_longitude, max_longitude) = (longitude - longitude_delta, longitude + longitude_delta);
+    let (min_latitude, max_latitude) = (latitude - latitude_delta, latitude + latitude_delta);
+
+    let ranges = ((LONGITUDE_MIN, LONGITUDE_MAX), (LATITUDE_MIN, LATITUDE_MAX));
+    let mut step = estimate_step(radius, latitude);
+    let mut hash = encode_with_ranges(ranges.0, ranges.1, longitude, latitude, step);
+
+    // The estimated step may be too small when the search area is near the edge of the cell
+    let decode = |hash: Hash| decode_with_ranges(ranges.0, ranges.1, hash);
+    let too_small = decode(hash.moved(0, 1)).latitude.1 < max_latitude
+        || decode(hash.moved(0, -1)).latitude.0 > min_latitude
+        || decode(hash.moved(1, 0)).longitude.1 < max_longitude
+        || decode(hash.moved(-1, 0)).longitude.0 > min_longitude;
+    if step > 1 && too_small {
+        step -= 1;
+        hash = encode_with_ranges(ranges.0, ranges.1, longitude, latitude, step);
+    }
+
+    let mut cells = [
+        hash,
+        hash.moved(0, 1),
+        hash.moved(0, -1),
+        hash.moved(1, 0),
+        hash.moved(-1, 0),
+        hash.moved(1, 1),
+        hash.moved(-1, 1),
+        hash.moved(1, -1),
+        hash.moved(-1, -1),
+    ];
+
+    // Exclude neighbours outside of the search area
+    if step >= 2 {
+        let area = decode(hash);
+        let mut exclude = |indexes: [usize; 3]| {
+            for index in indexes {
+                cells[index] = Hash::ZERO;
+            }
+        };
+        if area.latitude.0 < min_latitude {
+            exclude([2, 7, 8]);
+        }
+        if area.latitude.1 > max_latitude {
+            exclude([1, 5, 6]);
+        }
+        if area.longitude.0 < min_longitude {
+            exclude([4, 6, 8]);
+        }
+        if area.longitude.1 > max_longitude {
+            exclude([3, 5, 7]);
+        }
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Palermo and Catania, from the Redis documentation
+    const PALERMO: (f64, f64) = (13.361_389, 38.115_556);
+    const CATANIA: (f64, f64) = (15.087_269, 37.502_669);
+
+    #[test]
+    fn encode_matches_redis() {
+        assert_eq!(encode(PALERMO.0, PALERMO.1), 3_479_099_956_230_698);
+        assert_eq!(encode(CATANIA.0, CATANIA.1), 3_479_447_370_796_909);
+    }
+
+    #[test]
+    fn decode_matches_redis() {
+        let (longitude, latitude) = decode(3_479_099_956_230_698);
+        assert_eq!(format_coordinate(longitude), "13.36138933897018433");
+        assert_eq!(format_coordinate(latitude), "38.11555639549629859");
+    }
+
+    #[test]
+    fn geohash_matches_redis() {
+        assert_eq!(geohash(encode(PALERMO.0, PALERMO.1)), "sqc8b49rny0");
+        assert_eq!(geohash(encode(CATANIA.0, CATANIA.1)), "sqdtr74hyu0");
+    }
+
+    #[test]
+    fn distance_matches_redis() {
+        let palermo = decode(encode(PALERMO.0, PALERMO.1));
+        let catania = decode(encode(CATANIA.0, CATANIA.1));
+        let distance = distance(palermo.0, palermo.1, catania.0, catania.1);
+
+        assert_eq!(format!("{distance:.4}"), "166274.1516");
+    }
+
+    #[test]
+    fn interleave_round_trips() {
+        let value = interleave(0x0123_4567, 0x89ab_cdef);
+        assert_eq!(deinterleave(value), (0x0123_4567, 0x89ab_cdef));
+    }
+
+    #[test]
+    fn search_by_radius_and_box() {
+        let mut set = SortedSet::new();
+        for (member, (longitude, latitude)) in [(b"Palermo", PALERMO), (b"Catania", CATANIA)] {
+            #[allow(clippy::cast_precision_loss)]
+            set.insert(member.to_vec(), encode(longitude, latitude) as f64);
+        }
+
+        let points = search(&set, 15.0, 37.0, Shape::Radius(200_000.0), None);
+        let mut members: Vec<_> = points.iter().map(|point| point.member).collect();
+        members.sort_unstable();
+        assert_eq!(members, vec![&b"Catania"[..], b"Palermo"]);
+
+        let points = search(&set, 15.0, 37.0, Shape::Radius(100_000.0), None);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].member, b"Catania");
+
+        let points = search(&set, 15.0, 37.0, Shape::Box(400_000.0, 400_000.0), Some(1));
+        assert_eq!(points.len(), 1);
+    }
+}
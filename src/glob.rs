@@ -0,0 +1,163 @@
+// Glob-style pattern matching, as used by KEYS and SCAN, ported from `stringmatchlen` in Redis
+// See: https://github.com/redis/redis/blob/unstable/src/util.c
+//
+// Supports `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and escaping any of these with `\`
+
+// Like Redis, patterns nested deeper than this are considered to not match
+const MAX_NESTING: usize = 1000;
+
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let mut skip_longer_matches = false;
+    matches_from(pattern, string, &mut skip_longer_matches, 0)
+}
+
+fn matches_from(
+    pattern: &[u8],
+    string: &[u8],
+    skip_longer_matches: &mut bool,
+    nesting: usize,
+) -> bool {
+    if nesting > MAX_NESTING {
+        return false;
+    }
+
+    let (mut p, mut s) = (0, 0);
+    while p < pattern.len() && s < string.len() {
+        match pattern[p] {
+            b'*' => {
+                while pattern.get(p + 1) == Some(&b'*') {
+                    p += 1;
+                }
+                if p + 1 == pattern.len() {
+                    return true;
+                }
+
+                while s < string.len() {
+                    let rest = &pattern[p + 1..];
+                    if matches_from(rest, &string[s..], skip_longer_matches, nesting + 1) {
+                        return true;
+                    }
+                    if *skip_longer_matches {
+                        return false;
+                    }
+                    s += 1;
+                }
+
+                // The rest of the pattern matches nowhere in the rest of the string, so earlier
+                // `*`s matching more of the string cannot help either
+                *skip_longer_matches = true;
+                return false;
+            }
+            b'?' => s += 1,
+            b'[' => {
+                p += 1;
+                let not = pattern.get(p) == Some(&b'^');
+                if not {
+                    p += 1;
+                }
+
+                let mut matched = false;
+                loop {
+                    let remaining = pattern.len() - p;
+                    if remaining >= 2 && pattern[p] == b'\\' {
+                        p += 1;
+                        matched |= pattern[p] == string[s];
+                    } else if remaining == 0 {
+                        // Unterminated, so treat the end of the pattern as the closing bracket
+                        p -= 1;
+                        break;
+                    } else if pattern[p] == b']' {
+                        break;
+                    } else if remaining >= 3 && pattern[p + 1] == b'-' {
+                        let (start, end) = if pattern[p] <= pattern[p + 2] {
+                            (pattern[p], pattern[p + 2])
+                        } else {
+                            (pattern[p + 2], pattern[p])
+                        };
+                        p += 2;
+                        matched |= (start..=end).contains(&string[s]);
+                    } else {
+                        matched |= pattern[p] == string[s];
+                    }
+                    p += 1;
+                }
+
+                if matched == not {
+                    return false;
+                }
+                s += 1;
+            }
+            character => {
+                let character = if character == b'\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    pattern[p]
+                } else {
+                    character
+                };
+                if character != string[s] {
+                    return false;
+                }
+                s += 1;
+            }
+        }
+        p += 1;
+
+        if s == string.len() {
+            while pattern.get(p) == Some(&b'*') {
+                p += 1;
+            }
+            break;
+        }
+    }
+
+    p == pattern.len() && s == string.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        // Like Redis, the empty string is never matched, which KEYS and SCAN work around for `*`
+        assert!(!matches(b"*", b""));
+        assert!(matches(b"*", b"anything"));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(!matches(b"h?llo", b"hllo"));
+        assert!(matches(b"h*llo", b"hllo"));
+        assert!(matches(b"h*llo", b"heeeello"));
+        assert!(matches(b"user:*:name", b"user:1000:name"));
+        assert!(!matches(b"user:*:name", b"user:1000:email"));
+        assert!(matches(b"a**", b"a"));
+        assert!(!matches(b"", b"a"));
+        assert!(!matches(b"a", b""));
+    }
+
+    #[test]
+    fn character_classes() {
+        assert!(matches(b"h[ae]llo", b"hello"));
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[ae]llo", b"hillo"));
+        assert!(matches(b"h[^e]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"h[b-a]llo", b"hallo"));
+        assert!(!matches(b"h[a-b]llo", b"hcllo"));
+        assert!(matches(b"[\\]]", b"]"));
+        assert!(matches(b"a[b", b"ab"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches(b"h\\*llo", b"h*llo"));
+        assert!(!matches(b"h\\*llo", b"hello"));
+        assert!(matches(b"\\?", b"?"));
+        assert!(matches(b"a\\", b"a\\"));
+    }
+
+    #[test]
+    fn pathological_pattern_is_fast() {
+        let string = [b'a'; 100];
+        assert!(!matches(b"a*a*a*a*a*a*a*a*a*a*a*a*a*b", &string));
+    }
+}
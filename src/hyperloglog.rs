@@ -0,0 +1,627 @@
+// HyperLogLog using the same representation Redis stores within string values, so they can be
+// exchanged with Redis byte for byte
+// See: https://github.com/redis/redis/blob/unstable/src/hyperloglog.c
+//
+// The value starts with a 16 byte header, being the "HYLL" magic, the encoding, 3 unused bytes
+// and the cached cardinality (little endian, with the most significant bit set when invalid).
+//
+// The registers follow the header, either in the dense encoding (16384 6-bit registers packed
+// least significant bit first) or the sparse encoding, which is a sequence of run length encoded
+// opcodes:
+// - ZERO: 00xxxxxx, a run of 1-64 zero registers
+// - XZERO: 01xxxxxx yyyyyyyy, a run of 1-16384 zero registers
+// - VAL: 1vvvvvxx, a run of 1-4 registers holding the value 1-32
+
+const HEADER_SIZE: usize = 16;
+const MAGIC: &[u8] = b"HYLL";
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+const P: u32 = 14;
+const Q: u32 = 64 - P;
+pub const REGISTERS: usize = 1 << P;
+const REGISTER_BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << REGISTER_BITS) - 1;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * REGISTER_BITS).div_ceil(8);
+
+const SPARSE_MAX_BYTES: usize = 3000;
+const ZERO_MAX_LENGTH: usize = 64;
+const VAL_MAX_VALUE: u8 = 32;
+const VAL_MAX_LENGTH: usize = 4;
+
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+// Returned when a value looks like a HyperLogLog, but its registers cannot be decoded
+#[derive(Debug, PartialEq, Eq)]
+pub struct Corrupted;
+
+pub type Registers = [u8; REGISTERS];
+
+// Creates an empty HyperLogLog, which uses the sparse encoding
+pub fn new() -> Vec<u8> {
+    let mut value = Vec::with_capacity(HEADER_SIZE + 2);
+    value.extend_from_slice(MAGIC);
+    value.push(SPARSE);
+    value.extend_from_slice(&[0; 11]);
+    value.extend_from_slice(&xzero(REGISTERS));
+
+    value
+}
+
+pub fn is_valid(value: &[u8]) -> bool {
+    value.len() >= HEADER_SIZE
+        && value.starts_with(MAGIC)
+        && match value[4] {
+            DENSE => value.len() == DENSE_SIZE,
+            SPARSE => true,
+            _ => false,
+        }
+}
+
+// Adds `element`, returning whether any register was updated
+pub fn add(value: &mut Vec<u8>, element: &[u8]) -> Result<bool, Corrupted> {
+    let (index, count) = pattern(element);
+
+    let updated = if value[4] == DENSE {
+        set_dense(&mut value[HEADER_SIZE..], index, count)
+    } else {
+        set_sparse(value, index, count)?
+    };
+    if updated {
+        invalidate_cache(value);
+    }
+
+    Ok(updated)
+}
+
+// The cardinality cached within the header, unless changed since it was
+pub fn cached(value: &[u8]) -> Option<u64> {
+    if value[15] & 0x80 != 0 {
+        return None;
+    }
+    let mut cached = [0; 8];
+    cached.copy_from_slice(&value[8..16]);
+    Some(u64::from_le_bytes(cached))
+}
+
+// Returns the cardinality, caching it within the header for later calls
+pub fn count(value: &mut [u8]) -> Result<u64, Corrupted> {
+    if let Some(cached) = cached(value) {
+        return Ok(cached);
+    }
+
+    let mut histogram = [0; 64];
+    if value[4] == DENSE {
+        for index in 0..REGISTERS {
+            histogram[get_dense(&value[HEADER_SIZE..], index) as usize] += 1;
+        }
+    } else {
+        for (run, register) in runs(value)? {
+            histogram[register as usize] += run;
+        }
+    }
+
+    let cardinality = estimate(&histogram);
+    value[8..16].copy_from_slice(&cardinality.to_le_bytes());
+
+    Ok(cardinality)
+}
+
+// Merges the registers from `value` into `registers`, keeping the maximum of each
+pub fn merge(registers: &mut Registers, value: &[u8]) -> Result<(), Corrupted> {
+    if value[4] == DENSE {
+        for (index, register) in registers.iter_mut().enumerate() {
+            *register = (*register).max(get_dense(&value[HEADER_SIZE..], index));
+        }
+    } else {
+        let mut index = 0;
+        for (run, value) in runs(value)? {
+            for register in &mut registers[index..index + run] {
+                *register = (*register).max(value);
+            }
+            index += run;
+        }
+    }
+
+    Ok(())
+}
+
+// Returns the cardinality of already merged registers
+pub fn count_registers(registers: &Registers) -> u64 {
+    let mut histogram = [0; 64];
+    for register in registers {
+        histogram[*register as usize] += 1;
+    }
+
+    estimate(&histogram)
+}
+
+// Raises the registers of `value` to those in `registers`, converting to the dense encoding first
+// when `dense` is set
+pub fn store(value: &mut Vec<u8>, registers: &Registers, dense: bool) -> Result<(), Corrupted> {
+    if dense {
+        to_dense(value)?;
+    }
+
+    for (index, register) in registers.iter().enumerate() {
+        if *register == 0 {
+            continue;
+        }
+        if value[4] == DENSE {
+            set_dense(&mut value[HEADER_SIZE..], index, *register);
+        } else {
+            set_sparse(value, index, *register)?;
+        }
+    }
+    invalidate_cache(value);
+
+    Ok(())
+}
+
+pub fn is_dense(value: &[u8]) -> bool {
+    value[4] == DENSE
+}
+
+fn invalidate_cache(value: &mut [u8]) {
+    value[15] |= 0x80;
+}
+
+// Returns the register for `element` and the length of the run of zeros in the rest of its hash,
+// plus one
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let mut hash = murmur_hash_64a(element, 0xadc8_3b19);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    hash >>= P;
+    // Ensures the count is at most Q + 1
+    hash |= 1 << Q;
+
+    #[allow(clippy::cast_possible_truncation)]
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+// See: https://github.com/aappleby/smhasher/blob/master/src/MurmurHash2.cpp
+fn murmur_hash_64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut hash = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        hash ^= k;
+        hash = hash.wrapping_mul(M);
+    }
+
+    let remainder = chunks.remainder();
+    if !remainder.is_empty() {
+        for (index, byte) in remainder.iter().enumerate() {
+            hash ^= u64::from(*byte) << (8 * index);
+        }
+        hash = hash.wrapping_mul(M);
+    }
+
+    hash ^= hash >> R;
+    hash = hash.wrapping_mul(M);
+    hash ^= hash >> R;
+
+    hash
+}
+
+fn get_dense(registers: &[u8], index: usize) -> u8 {
+    let byte = index * REGISTER_BITS / 8;
+    let shift = (index * REGISTER_BITS) & 7;
+    let low = u16::from(registers[byte]);
+    let high = u16::from(registers.get(byte + 1).copied().unwrap_or(0));
+
+    #[allow(clippy::cast_possible_truncation)]
+    let register = ((low | (high << 8)) >> shift) as u8 & REGISTER_MAX;
+    register
+}
+
+// Returns whether the register was updated, which only happens when `count` is larger
+fn set_dense(registers: &mut [u8], index: usize, count: u8) -> bool {
+    if count <= get_dense(registers, index) {
+        return false;
+    }
+
+    let byte = index * REGISTER_BITS / 8;
+    let shift = (index * REGISTER_BITS) & 7;
+    let mask = u16::from(REGISTER_MAX) << shift;
+    let value = u16::from(count) << shift;
+
+    #[allow(clippy::cast_possible_truncation)]
+    {
+        registers[byte] = (registers[byte] & !(mask as u8)) | value as u8;
+        if let Some(next) = registers.get_mut(byte + 1) {
+            *next = (*next & !((mask >> 8) as u8)) | (value >> 8) as u8;
+        }
+    }
+
+    true
+}
+
+enum Opcode {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Opcode {
+    fn decode(value: &[u8], position: usize) -> Option<Self> {
+        let byte = *value.get(position)?;
+        Some(match byte & 0xc0 {
+            0x00 => Self::Zero((byte & 0x3f) as usize + 1),
+            0x40 => {
+                let next = *value.get(position + 1)?;
+                Self::XZero((((byte & 0x3f) as usize) << 8 | next as usize) + 1)
+            }
+            _ => Self::Val(((byte >> 2) & 0x1f) + 1, (byte & 0x03) as usize + 1),
+        })
+    }
+
+    const fn size(&self) -> usize {
+        match self {
+            Self::XZero(_) => 2,
+            _ => 1,
+        }
+    }
+
+    const fn length(&self) -> usize {
+        match self {
+            Self::Zero(length) | Self::XZero(length) | Self::Val(_, length) => *length,
+        }
+    }
+}
+
+fn zero(length: usize) -> u8 {
+    #[allow(clippy::cast_possible_truncation)]
+    let byte = (length - 1) as u8;
+    byte
+}
+
+fn xzero(length: usize) -> [u8; 2] {
+    let length = length - 1;
+
+    #[allow(clippy::cast_possible_truncation)]
+    [(length >> 8) as u8 | 0x40, (length & 0xff) as u8]
+}
+
+fn val(value: u8, length: usize) -> u8 {
+    #[allow(clippy::cast_possible_truncation)]
+    let length = (length - 1) as u8;
+    ((value - 1) << 2) | length | 0x80
+}
+
+// Zero runs longer than a ZERO opcode can hold need an XZERO opcode
+fn push_zeros(sequence: &mut Vec<u8>, length: usize) {
+    if length > ZERO_MAX_LENGTH {
+        sequence.extend_from_slice(&xzero(length));
+    } else {
+        sequence.push(zero(length));
+    }
+}
+
+// Decodes the sparse encoding into runs of registers with the same value, checking that they
+// cover every register
+fn runs(value: &[u8]) -> Result<Vec<(usize, u8)>, Corrupted> {
+    let mut runs = Vec::new();
+    let mut position = HEADER_SIZE;
+    let mut registers = 0;
+
+    while position < value.len() {
+        let opcode = Opcode::decode(value, position).ok_or(Corrupted)?;
+        let register = match opcode {
+            Opcode::Val(value, _) => value,
+            _ => 0,
+        };
+        registers += opcode.length();
+        if registers > REGISTERS {
+            return Err(Corrupted);
+        }
+
+        runs.push((opcode.length(), register));
+        position += opcode.size();
+    }
+
+    if registers == REGISTERS {
+        Ok(runs)
+    } else {
+        Err(Corrupted)
+    }
+}
+
+fn to_dense(value: &mut Vec<u8>) -> Result<(), Corrupted> {
+    if value[4] == DENSE {
+        return Ok(());
+    }
+
+    let mut dense = vec![0; DENSE_SIZE];
+    dense[..HEADER_SIZE].copy_from_slice(&value[..HEADER_SIZE]);
+    dense[4] = DENSE;
+
+    let mut index = 0;
+    for (run, register) in runs(value)? {
+        if register > 0 {
+            for index in index..index + run {
+                set_dense(&mut dense[HEADER_SIZE..], index, register);
+            }
+        }
+        index += run;
+    }
+    *value = dense;
+
+    Ok(())
+}
+
+// Sets the register at `index` to `count` if larger, mirroring `hllSparseSet` so that the
+// resulting opcodes are identical to those Redis would produce. This promotes the value to the
+// dense encoding when the count or size no longer fits the sparse encoding.
+fn set_sparse(value: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, Corrupted> {
+    if count > VAL_MAX_VALUE {
+        return promote(value, index, count);
+    }
+
+    // Find the opcode covering `index`
+    let mut position = HEADER_SIZE;
+    let mut previous = None;
+    let mut first = 0;
+    let mut opcode = None;
+    while position < value.len() {
+        let current = Opcode::decode(value, position).ok_or(Corrupted)?;
+        if index < first + current.length() {
+            opcode = Some(current);
+            break;
+        }
+        previous = Some(position);
+        position += current.size();
+        first += current.length();
+    }
+    let opcode = opcode.ok_or(Corrupted)?;
+    let last = first + opcode.length() - 1;
+
+    match opcode {
+        // The register already holds a larger value
+        Opcode::Val(current, _) if current >= count => return Ok(false),
+        // A single register can be updated in place
+        Opcode::Val(_, 1) | Opcode::Zero(1) => value[position] = val(count, 1),
+        // Otherwise, split the run into up to three opcodes
+        _ => {
+            let mut sequence = Vec::with_capacity(5);
+            match opcode {
+                Opcode::Val(current, _) => {
+                    if index != first {
+                        sequence.push(val(current, index - first));
+                    }
+                    sequence.push(val(count, 1));
+                    if index != last {
+                        sequence.push(val(current, last - index));
+                    }
+                }
+                _ => {
+                    if index != first {
+                        push_zeros(&mut sequence, index - first);
+                    }
+                    sequence.push(val(count, 1));
+                    if index != last {
+                        push_zeros(&mut sequence, last - index);
+                    }
+                }
+            }
+
+            let size = opcode.size();
+            if sequence.len() > size && value.len() + sequence.len() - size > SPARSE_MAX_BYTES {
+                return promote(value, index, count);
+            }
+            value.splice(position..position + size, sequence);
+        }
+    }
+
+    // Merge adjacent VAL opcodes holding the same value around the update
+    let mut position = previous.unwrap_or(HEADER_SIZE);
+    let mut scan = 5;
+    while position < value.len() && scan > 0 {
+        scan -= 1;
+        match Opcode::decode(value, position) {
+            Some(Opcode::Val(current, length)) => {
+                if let Some(Opcode::Val(next, next_length)) = Opcode::decode(value, position + 1) {
+                    if current == next && length + next_length <= VAL_MAX_LENGTH {
+                        value[position + 1] = val(current, length + next_length);
+                        value.remove(position);
+                        continue;
+                    }
+                }
+                position += 1;
+            }
+            Some(opcode) => position += opcode.size(),
+            None => break,
+        }
+    }
+
+    Ok(true)
+}
+
+fn promote(value: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, Corrupted> {
+    to_dense(value)?;
+    set_dense(&mut value[HEADER_SIZE..], index, count);
+
+    Ok(true)
+}
+
+// Estimates the cardinality from a histogram of register values
+// See: https://arxiv.org/abs/1702.01284
+fn estimate(histogram: &[usize; 64]) -> u64 {
+    #[allow(clippy::cast_precision_loss)]
+    let m = REGISTERS as f64;
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+    for count in histogram[1..=Q as usize].iter().rev() {
+        #[allow(clippy::cast_precision_loss)]
+        let count = *count as f64;
+        z += count;
+        z *= 0.5;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let zero = histogram[0] as f64;
+    z += m * sigma(zero / m);
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let estimate = (ALPHA_INF * m * m / z).round() as u64;
+    estimate
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn new_matches_redis() {
+        // Equivalent to `PFADD key` followed by `GET key` against Redis
+        assert_eq!(
+            new(),
+            b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        );
+    }
+
+    #[test]
+    fn add_updates_registers_once() {
+        let mut value = new();
+        for element in [b"a", b"b", b"c"] {
+            assert_eq!(add(&mut value, element), Ok(true));
+        }
+        assert_eq!(add(&mut value, b"a"), Ok(false));
+
+        assert!(!is_dense(&value));
+        assert_eq!(count(&mut value), Ok(3));
+    }
+
+    #[test]
+    fn sparse_opcodes_round_trip() {
+        assert!(matches!(
+            Opcode::decode(&xzero(16384), 0),
+            Some(Opcode::XZero(16384))
+        ));
+        assert!(matches!(
+            Opcode::decode(&xzero(65), 0),
+            Some(Opcode::XZero(65))
+        ));
+        assert!(matches!(
+            Opcode::decode(&[zero(64)], 0),
+            Some(Opcode::Zero(64))
+        ));
+        assert!(matches!(
+            Opcode::decode(&[val(32, 4)], 0),
+            Some(Opcode::Val(32, 4))
+        ));
+    }
+
+    #[test]
+    fn count_small_cardinalities() {
+        let mut value = new();
+        assert_eq!(count(&mut value), Ok(0));
+
+        for element in 0..1000 {
+            add(&mut value, format!("element:{element}").as_bytes()).unwrap();
+        }
+        let cardinality = count(&mut value).unwrap();
+        assert!(cardinality.abs_diff(1000) < 20, "{cardinality}");
+
+        // Cached until the next update
+        assert_eq!(value[15] & 0x80, 0);
+        assert_eq!(count(&mut value), Ok(cardinality));
+    }
+
+    #[test]
+    fn promotes_to_dense() {
+        let mut value = new();
+        for element in 0..10_000 {
+            add(&mut value, format!("element:{element}").as_bytes()).unwrap();
+        }
+
+        assert!(is_dense(&value));
+        assert!(is_valid(&value));
+        let cardinality = count(&mut value).unwrap();
+        assert!(cardinality.abs_diff(10_000) < 200, "{cardinality}");
+    }
+
+    #[test]
+    fn merge_sparse_and_dense() {
+        let mut sparse = new();
+        let mut dense = new();
+        for element in 0..100 {
+            add(&mut sparse, format!("sparse:{element}").as_bytes()).unwrap();
+        }
+        to_dense(&mut dense).unwrap();
+        for element in 0..100 {
+            add(&mut dense, format!("dense:{element}").as_bytes()).unwrap();
+        }
+
+        let mut registers = [0; REGISTERS];
+        merge(&mut registers, &sparse).unwrap();
+        merge(&mut registers, &dense).unwrap();
+        let cardinality = count_registers(&registers);
+        assert!(cardinality.abs_diff(200) < 10, "{cardinality}");
+
+        let mut merged = new();
+        store(&mut merged, &registers, false).unwrap();
+        assert!(!is_dense(&merged));
+        assert_eq!(count(&mut merged), Ok(cardinality));
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(!is_valid(b"HYLL"));
+        assert!(!is_valid(
+            b"NOPE\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        ));
+        assert!(!is_valid(
+            b"HYLL\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"
+        ));
+
+        let mut truncated = new();
+        truncated.pop();
+        assert_eq!(count(&mut truncated), Ok(0));
+        invalidate_cache(&mut truncated);
+        assert_eq!(count(&mut truncated), Err(Corrupted));
+    }
+}
@@ -1,8 +1,25 @@
+mod aof;
+mod bitmap;
 mod client;
+mod cluster;
 mod command;
+mod config;
+mod connection;
+mod dict;
+mod evict;
+mod geo;
+mod glob;
+mod hyperloglog;
+mod pubsub;
+mod rdb;
+mod replication;
 mod resp;
+mod sentinel;
 mod server;
+mod sorted_set;
 mod store;
 mod threadpool;
 
+pub use config::Config;
+pub use sentinel::Sentinel;
 pub use server::Server;
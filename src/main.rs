@@ -1,9 +1,17 @@
 use anyhow::Result;
-use redis_starter_rust::Server;
+use redis_starter_rust::{Config, Sentinel, Server};
+use std::env;
 
 fn main() -> Result<()> {
-    let server = Server::bind("127.0.0.1:6379")?;
-    server.start()?;
+    let config = Config::from_args(env::args().skip(1))?;
+    let addr = format!("127.0.0.1:{}", config.port);
+    if config.sentinel.is_some() {
+        let sentinel = Sentinel::bind(&addr, config)?;
+        sentinel.start()?;
+    } else {
+        let server = Server::bind(&addr, config)?;
+        server.start()?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,531 @@
+use crate::{
+    cluster, glob,
+    resp::{Array, BulkString, Integer, NullBulkString, Push},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, MutexGuard, OnceLock, PoisonError, RwLock,
+    },
+    thread,
+};
+
+// Clients subscribe to channels, or to patterns matching channels, and are sent every message
+// published to them. Messages are queued for each subscriber and written by a thread of its own, so
+// that publishers never wait on subscribers which are slow to read them. Shard channels are kept
+// apart, as in cluster mode they belong to the slot they hash to like keys, and their messages are
+// only sent to the subscribers of the node serving it.
+// See: https://redis.io/docs/latest/develop/interact/pubsub/
+// See: https://redis.io/docs/latest/develop/interact/pubsub/#sharded-pubsub
+
+// Like the default hard limit of `client-output-buffer-limit pubsub 32mb 8mb 60`, subscribers which
+// fall this far behind are disconnected, rather than queuing messages for them without bound
+const OUTPUT_LIMIT: usize = 32 * 1024 * 1024;
+
+type Registry = RwLock<HashMap<String, HashMap<u64, Arc<Subscriber>>>>;
+
+// What a client subscribes to, with the names of the commands and messages for each
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Channel,
+    Pattern,
+    Shard,
+}
+
+impl Kind {
+    const fn subscribe(self) -> &'static [u8] {
+        match self {
+            Self::Channel => b"subscribe",
+            Self::Pattern => b"psubscribe",
+            Self::Shard => b"ssubscribe",
+        }
+    }
+
+    const fn unsubscribe(self) -> &'static [u8] {
+        match self {
+            Self::Channel => b"unsubscribe",
+            Self::Pattern => b"punsubscribe",
+            Self::Shard => b"sunsubscribe",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PubSub {
+    channels: Registry,
+    patterns: Registry,
+    shard_channels: Registry,
+    next_id: AtomicU64,
+}
+
+impl PubSub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    const fn registry(&self, kind: Kind) -> &Registry {
+        match kind {
+            Kind::Channel => &self.channels,
+            Kind::Pattern => &self.patterns,
+            Kind::Shard => &self.shard_channels,
+        }
+    }
+
+    // Sends the message to every subscriber of the channel, and of each pattern matching it,
+    // returning how many were sent it
+    pub fn publish(&self, channel: &str, message: &[u8]) -> usize {
+        let mut receivers = 0;
+        let channels = self.channels.read().unwrap_or_else(PoisonError::into_inner);
+        for subscriber in channels.get(channel).into_iter().flat_map(HashMap::values) {
+            let push = [&b"message"[..], channel.as_bytes(), message];
+            receivers += usize::from(subscriber.push(&push));
+        }
+        drop(channels);
+
+        let patterns = self.patterns.read().unwrap_or_else(PoisonError::into_inner);
+        for (pattern, subscribers) in patterns.iter() {
+            if !glob::matches(pattern.as_bytes(), channel.as_bytes()) {
+                continue;
+            }
+            for subscriber in subscribers.values() {
+                let push = [
+                    &b"pmessage"[..],
+                    pattern.as_bytes(),
+                    channel.as_bytes(),
+                    message,
+                ];
+                receivers += usize::from(subscriber.push(&push));
+            }
+        }
+
+        receivers
+    }
+
+    // Sends the message to every subscriber of the shard channel, returning how many were sent it
+    pub fn publish_shard(&self, channel: &str, message: &[u8]) -> usize {
+        let channels = self
+            .shard_channels
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let push = [&b"smessage"[..], channel.as_bytes(), message];
+        channels
+            .get(channel)
+            .into_iter()
+            .flat_map(HashMap::values)
+            .filter(|subscriber| subscriber.push(&push))
+            .count()
+    }
+
+    // Unsubscribes every client from the shard channels of a slot this node no longer serves, as
+    // per `removeChannelsInSlot`, telling each of them so
+    pub fn unsubscribe_slot(&self, slot: u16) {
+        let mut channels = self
+            .shard_channels
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        let names = channels
+            .keys()
+            .filter(|channel| cluster::key_slot(channel) == slot)
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            let subscribers = channels.remove(&name).unwrap_or_default();
+            for subscriber in subscribers.values() {
+                let mut subscribed = subscriber.subscribed();
+                if let Some(channels) = subscribed.get_mut(&Kind::Shard) {
+                    channels.remove(&name);
+                }
+                drop(subscribed);
+                subscriber.confirm(Kind::Shard.unsubscribe(), Kind::Shard, Some(&name));
+            }
+        }
+    }
+
+    // The channels, or shard channels, with any subscribers, which match the pattern if given, as
+    // per PUBSUB CHANNELS and SHARDCHANNELS
+    pub fn channels(&self, kind: Kind, pattern: Option<&str>) -> Vec<String> {
+        let channels = self
+            .registry(kind)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        channels
+            .keys()
+            .filter(|channel| {
+                pattern.is_none_or(|pattern| glob::matches(pattern.as_bytes(), channel.as_bytes()))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn subscribers(&self, kind: Kind, channel: &str) -> usize {
+        let channels = self
+            .registry(kind)
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        channels.get(channel).map_or(0, HashMap::len)
+    }
+
+    // The number of patterns with any subscribers, as per PUBSUB NUMPAT
+    pub fn patterns(&self) -> usize {
+        self.patterns
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+}
+
+// A subscribed client, as messages are sent to it
+struct Subscriber {
+    id: u64,
+    sender: Sender<Vec<u8>>,
+    // The bytes queued but not yet written
+    queued: AtomicUsize,
+    resp3: AtomicBool,
+    // What the client is subscribed to, which is shared for shard channels to be taken away from
+    // it when the slot they belong to is migrated
+    subscribed: Mutex<HashMap<Kind, HashSet<String>>>,
+    // Set once the writer starts, for disconnecting the client should it fall too far behind
+    stream: OnceLock<TcpStream>,
+    disconnected: AtomicBool,
+}
+
+impl Subscriber {
+    fn subscribed(&self) -> MutexGuard<'_, HashMap<Kind, HashSet<String>>> {
+        self.subscribed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Like Redis, shard channels are counted apart from channels and patterns
+    fn count(&self, kind: Kind) -> usize {
+        let subscribed = self.subscribed();
+        let count = |kind| subscribed.get(&kind).map_or(0, HashSet::len);
+        match kind {
+            Kind::Channel | Kind::Pattern => count(Kind::Channel) + count(Kind::Pattern),
+            Kind::Shard => count(Kind::Shard),
+        }
+    }
+
+    // Queues the reply for each channel or pattern, with the number subscribed to once done
+    fn confirm(&self, action: &[u8], kind: Kind, name: Option<&str>) {
+        let count = i64::try_from(self.count(kind)).unwrap_or(i64::MAX);
+        let name = name.map_or_else(NullBulkString::encode, |name| {
+            BulkString::from(name.as_bytes()).encode()
+        });
+        let elements = vec![
+            BulkString::from(action).encode(),
+            name,
+            Integer::from(count).encode(),
+        ];
+        let resp3 = self.resp3.load(Ordering::Relaxed);
+        self.send(encode(elements, resp3));
+    }
+
+    // Queues a message, as a push in RESP3 or an array in RESP2, returning whether it was queued
+    fn push(&self, elements: &[&[u8]]) -> bool {
+        let elements = elements
+            .iter()
+            .map(|element| BulkString::from(*element).encode())
+            .collect();
+        self.send(encode(elements, self.resp3.load(Ordering::Relaxed)))
+    }
+
+    fn send(&self, data: Vec<u8>) -> bool {
+        if self.disconnected.load(Ordering::Relaxed) {
+            return false;
+        }
+        let length = data.len();
+        if self.queued.fetch_add(length, Ordering::Relaxed) + length > OUTPUT_LIMIT {
+            eprintln!("disconnecting subscriber {} for falling behind", self.id);
+            self.disconnected.store(true, Ordering::Relaxed);
+            if let Some(stream) = self.stream.get() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+            return false;
+        }
+
+        self.sender.send(data).is_ok()
+    }
+}
+
+fn encode(elements: Vec<Vec<u8>>, resp3: bool) -> Vec<u8> {
+    if resp3 {
+        Push::from(elements).encode()
+    } else {
+        Array::from(elements).encode()
+    }
+}
+
+// The subscriptions of one client, which, once it has subscribed to anything, is sent every reply
+// through the same queue as its messages, so that they are written in order. The client is
+// unsubscribed from everything once dropped.
+pub struct Subscription {
+    pubsub: Arc<PubSub>,
+    subscriber: Arc<Subscriber>,
+    // Taken by the writer once started
+    receiver: Option<Receiver<Vec<u8>>>,
+}
+
+impl Subscription {
+    pub fn new(pubsub: &Arc<PubSub>, resp3: bool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let subscriber = Subscriber {
+            id: pubsub.next_id.fetch_add(1, Ordering::Relaxed),
+            sender,
+            queued: AtomicUsize::new(0),
+            resp3: AtomicBool::new(resp3),
+            subscribed: Mutex::new(HashMap::new()),
+            stream: OnceLock::new(),
+            disconnected: AtomicBool::new(false),
+        };
+        Self {
+            pubsub: Arc::clone(pubsub),
+            subscriber: Arc::new(subscriber),
+            receiver: Some(receiver),
+        }
+    }
+
+    // The number of channels, patterns and shard channels subscribed to, while any of which the
+    // client is in subscribed mode
+    pub fn count(&self) -> usize {
+        self.subscriber
+            .subscribed()
+            .values()
+            .map(HashSet::len)
+            .sum()
+    }
+
+    pub fn set_resp3(&self, resp3: bool) {
+        self.subscriber.resp3.store(resp3, Ordering::Relaxed);
+    }
+
+    // Queues the reply to a command
+    pub fn reply(&self, reply: Vec<u8>) {
+        if !reply.is_empty() {
+            self.subscriber.send(reply);
+        }
+    }
+
+    // Subscribes to each channel or pattern, queuing a confirmation for each while the registry is
+    // locked, so that it comes before any message published to it
+    pub fn subscribe(&self, kind: Kind, names: &[&str]) {
+        let mut registry = self
+            .pubsub
+            .registry(kind)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for name in names {
+            let mut subscribed = self.subscriber.subscribed();
+            if subscribed.entry(kind).or_default().insert(name.to_string()) {
+                let subscriber = Arc::clone(&self.subscriber);
+                registry
+                    .entry(name.to_string())
+                    .or_default()
+                    .insert(subscriber.id, subscriber);
+            }
+            drop(subscribed);
+            self.subscriber.confirm(kind.subscribe(), kind, Some(name));
+        }
+    }
+
+    // Unsubscribes from each channel or pattern, or from all of them when none are given
+    pub fn unsubscribe(&self, kind: Kind, names: &[&str]) {
+        let names = if names.is_empty() {
+            let subscribed = self.subscriber.subscribed();
+            let names = subscribed.get(&kind).into_iter().flatten();
+            names.cloned().collect()
+        } else {
+            names.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        if names.is_empty() {
+            self.subscriber.confirm(kind.unsubscribe(), kind, None);
+            return;
+        }
+
+        let mut registry = self
+            .pubsub
+            .registry(kind)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for name in &names {
+            let removed = self
+                .subscriber
+                .subscribed()
+                .get_mut(&kind)
+                .is_some_and(|subscribed| subscribed.remove(name));
+            if removed {
+                remove(&mut registry, name, self.subscriber.id);
+            }
+            self.subscriber
+                .confirm(kind.unsubscribe(), kind, Some(name));
+        }
+    }
+
+    // Starts writing what is queued to the client, unless already started
+    pub fn start(&mut self, stream: &TcpStream) -> io::Result<()> {
+        let Some(receiver) = self.receiver.take() else {
+            return Ok(());
+        };
+        let _ = self.subscriber.stream.set(stream.try_clone()?);
+
+        let mut stream = stream.try_clone()?;
+        let subscriber = Arc::downgrade(&self.subscriber);
+        thread::spawn(move || {
+            for data in receiver {
+                if let Err(error) = stream.write_all(&data) {
+                    eprintln!("failed to write to subscriber: {error}");
+                    let _ = stream.shutdown(Shutdown::Both);
+                    break;
+                }
+                if let Some(subscriber) = subscriber.upgrade() {
+                    subscriber.queued.fetch_sub(data.len(), Ordering::Relaxed);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Taken before locking the registries, which are otherwise always locked first
+        let subscribed = std::mem::take(&mut *self.subscriber.subscribed());
+        for (kind, names) in &subscribed {
+            let mut registry = self
+                .pubsub
+                .registry(*kind)
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            for name in names {
+                remove(&mut registry, name, self.subscriber.id);
+            }
+        }
+    }
+}
+
+// Channels and patterns are only kept while they have subscribers
+fn remove(registry: &mut HashMap<String, HashMap<u64, Arc<Subscriber>>>, name: &str, id: u64) {
+    if let Some(subscribers) = registry.get_mut(name) {
+        subscribers.remove(&id);
+        if subscribers.is_empty() {
+            registry.remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn messages(subscription: &Subscription) -> Vec<Vec<u8>> {
+        subscription.receiver.as_ref().unwrap().try_iter().collect()
+    }
+
+    #[test]
+    fn publish() {
+        let pubsub = Arc::new(PubSub::new());
+        let a = Subscription::new(&pubsub, false);
+        let b = Subscription::new(&pubsub, true);
+        a.subscribe(Kind::Channel, &["news", "news"]);
+        b.subscribe(Kind::Pattern, &["n*"]);
+        assert_eq!(
+            messages(&a),
+            [
+                b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n",
+                b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n",
+            ]
+        );
+        assert_eq!(
+            messages(&b),
+            [b">3\r\n$10\r\npsubscribe\r\n$2\r\nn*\r\n:1\r\n"]
+        );
+
+        assert_eq!(pubsub.publish("news", b"hi"), 2);
+        assert_eq!(pubsub.publish("other", b"hi"), 0);
+        assert_eq!(
+            messages(&a),
+            [b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n"]
+        );
+        assert_eq!(
+            messages(&b),
+            [&b">4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$2\r\nhi\r\n"[..]]
+        );
+
+        assert_eq!(pubsub.channels(Kind::Channel, None), ["news"]);
+        assert!(pubsub.channels(Kind::Channel, Some("x*")).is_empty());
+        assert_eq!(pubsub.subscribers(Kind::Channel, "news"), 1);
+        assert_eq!(pubsub.patterns(), 1);
+
+        a.unsubscribe(Kind::Channel, &[]);
+        a.unsubscribe(Kind::Channel, &[]);
+        assert_eq!(
+            messages(&a),
+            [
+                &b"*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:0\r\n"[..],
+                b"*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n",
+            ]
+        );
+        drop(b);
+        assert_eq!(pubsub.publish("news", b"hi"), 0);
+        assert!(pubsub.channels(Kind::Channel, None).is_empty());
+        assert_eq!(pubsub.patterns(), 0);
+    }
+
+    #[test]
+    fn slow_subscriber() {
+        let pubsub = Arc::new(PubSub::new());
+        let subscription = Subscription::new(&pubsub, false);
+        subscription.subscribe(Kind::Channel, &["news"]);
+
+        let message = vec![0; OUTPUT_LIMIT / 4];
+        let sent = (0..8)
+            .map(|_| pubsub.publish("news", &message))
+            .sum::<usize>();
+        assert_eq!(sent, 3);
+        assert!(subscription.subscriber.disconnected.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn shard_channels() {
+        let pubsub = Arc::new(PubSub::new());
+        let subscription = Subscription::new(&pubsub, false);
+        subscription.subscribe(Kind::Channel, &["news"]);
+        subscription.subscribe(Kind::Shard, &["{user}.a", "{user}.b", "other"]);
+        assert_eq!(
+            messages(&subscription)[1..],
+            [
+                &b"*3\r\n$10\r\nssubscribe\r\n$8\r\n{user}.a\r\n:1\r\n"[..],
+                b"*3\r\n$10\r\nssubscribe\r\n$8\r\n{user}.b\r\n:2\r\n",
+                b"*3\r\n$10\r\nssubscribe\r\n$5\r\nother\r\n:3\r\n",
+            ]
+        );
+        assert_eq!(subscription.count(), 4);
+
+        // Shard channels and channels are apart
+        assert_eq!(pubsub.publish("{user}.a", b"hi"), 0);
+        assert_eq!(pubsub.publish_shard("news", b"hi"), 0);
+        assert_eq!(pubsub.publish_shard("{user}.a", b"hi"), 1);
+        assert_eq!(
+            messages(&subscription),
+            [b"*3\r\n$8\r\nsmessage\r\n$8\r\n{user}.a\r\n$2\r\nhi\r\n"]
+        );
+        assert_eq!(pubsub.channels(Kind::Channel, None), ["news"]);
+        assert_eq!(pubsub.channels(Kind::Shard, Some("o*")), ["other"]);
+        assert_eq!(pubsub.subscribers(Kind::Shard, "other"), 1);
+
+        pubsub.unsubscribe_slot(cluster::key_slot("user"));
+        // In any order, and so with any count
+        let unsubscribed = messages(&subscription);
+        assert_eq!(unsubscribed.len(), 2);
+        assert!(unsubscribed
+            .iter()
+            .all(|message| message.starts_with(b"*3\r\n$12\r\nsunsubscribe\r\n$8\r\n{user}.")));
+        assert_eq!(pubsub.publish_shard("{user}.a", b"hi"), 0);
+        assert_eq!(pubsub.channels(Kind::Shard, None), ["other"]);
+        assert_eq!(subscription.count(), 2);
+    }
+}
@@ -0,0 +1,751 @@
+mod compact;
+mod crc64;
+mod lzf;
+mod reader;
+mod writer;
+
+use crate::{
+    config::Config,
+    store::{now, Snapshot, Store, Value},
+};
+use anyhow::{bail, ensure, Context, Result};
+use reader::Reader;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process, str,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+};
+use writer::Writer;
+
+// Snapshots of the keyspace, in the format written by Redis
+// See: https://rdb.fnordig.de/file_format.html
+// See: https://github.com/redis/redis/blob/unstable/src/rdb.h
+
+// The newest format version which can be read, and the version written, as per Redis 7.2
+const MAX_VERSION: u32 = 12;
+const VERSION: u32 = 11;
+
+// Opcodes, which appear in place of the type of a value
+const OPCODE_SLOT_INFO: u8 = 0xf4;
+const OPCODE_FUNCTION2: u8 = 0xf5;
+const OPCODE_IDLE: u8 = 0xf8;
+const OPCODE_FREQ: u8 = 0xf9;
+const OPCODE_AUX: u8 = 0xfa;
+const OPCODE_RESIZEDB: u8 = 0xfb;
+const OPCODE_EXPIRETIME_MS: u8 = 0xfc;
+const OPCODE_EXPIRETIME: u8 = 0xfd;
+const OPCODE_SELECTDB: u8 = 0xfe;
+const OPCODE_EOF: u8 = 0xff;
+
+// The types of value, and how they are encoded
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_HASH_ZIPMAP: u8 = 9;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+// How a string is encoded, when not prefixed by its length
+const ENCODING_INT8: u8 = 0;
+const ENCODING_INT16: u8 = 1;
+const ENCODING_INT32: u8 = 2;
+const ENCODING_LZF: u8 = 3;
+
+// The nodes of a quicklist hold either a single element, or a listpack of them
+const QUICKLIST_NODE_PLAIN: usize = 1;
+const QUICKLIST_NODE_PACKED: usize = 2;
+
+// A key read from an RDB file, along with the database it belongs in and any deadline
+#[derive(Debug, PartialEq)]
+struct Entry {
+    db: usize,
+    key: Vec<u8>,
+    value: Value,
+    expires: Option<u64>,
+}
+
+// Loads the RDB file at `path` into the store, if there is one, returning how many keys were
+// loaded. Keys which have already expired are skipped, as they are by Redis.
+pub fn load(store: &Store, path: &Path) -> Result<usize> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(error) => {
+            return Err(error).with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+
+    let loaded =
+        load_data(store, &data).with_context(|| format!("failed to load {}", path.display()))?;
+    println!("loaded {loaded} keys from {}", path.display());
+    Ok(loaded)
+}
+
+// Loads an RDB file which has already been read, such as one sent by a master
+pub fn load_data(store: &Store, data: &[u8]) -> Result<usize> {
+    let now = now();
+    let dirty = store.dirty();
+    let mut loaded = 0;
+    parse(data, |entry| {
+        ensure!(
+            entry.db < store.databases(),
+            "RDB file was created with a server configured to have more than {} databases",
+            store.databases()
+        );
+        if entry.expires.is_some_and(|deadline| deadline <= now) {
+            return Ok(());
+        }
+        let Ok(key) = String::from_utf8(entry.key) else {
+            eprintln!("skipping RDB key which is not UTF-8");
+            return Ok(());
+        };
+
+        let mut keyspace = store.write(entry.db, [key.as_str()]);
+        keyspace.insert(&key, entry.value);
+        if entry.expires.is_some() {
+            keyspace.set_expires(&key, entry.expires);
+        }
+        loaded += 1;
+
+        Ok(())
+    })?;
+    // The keys loaded are already saved
+    store.saved(store.dirty() - dirty);
+
+    Ok(loaded)
+}
+
+// Visits each key in an RDB file, having checked the header, and the checksum at the end
+fn parse(data: &[u8], mut visit: impl FnMut(Entry) -> Result<()>) -> Result<()> {
+    let mut reader = Reader::new(data);
+    let header = reader.take(9).context("RDB file is too short")?;
+    ensure!(header.starts_with(b"REDIS"), "not an RDB file");
+    let version = str::from_utf8(&header[5..])
+        .ok()
+        .and_then(|version| version.parse::<u32>().ok())
+        .context("invalid RDB version")?;
+    ensure!(
+        (1..=MAX_VERSION).contains(&version),
+        "unsupported RDB version {version}"
+    );
+
+    let (mut db, mut expires) = (0, None);
+    loop {
+        match reader.u8()? {
+            OPCODE_AUX => {
+                let name = reader.string()?;
+                let value = reader.string()?;
+                println!(
+                    "RDB {}: {}",
+                    String::from_utf8_lossy(&name),
+                    String::from_utf8_lossy(&value)
+                );
+            }
+            // Hints for how large the hash tables for the database should be
+            OPCODE_RESIZEDB => {
+                reader.length()?;
+                reader.length()?;
+            }
+            OPCODE_SLOT_INFO => {
+                for _ in 0..3 {
+                    reader.length()?;
+                }
+            }
+            OPCODE_EXPIRETIME_MS => expires = Some(reader.u64_le()?),
+            OPCODE_EXPIRETIME => expires = Some(u64::from(reader.u32_le()?) * 1000),
+            OPCODE_SELECTDB => db = reader.length()?,
+            // Eviction metadata for the next key, which starts afresh instead
+            OPCODE_IDLE => {
+                reader.length()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            // Functions are not supported, but needn't stop the keys from loading
+            OPCODE_FUNCTION2 => {
+                reader.string()?;
+                eprintln!("skipping RDB function library");
+            }
+            OPCODE_EOF => break,
+            value_type => {
+                let key = reader.string()?;
+                let value = reader.value(value_type).with_context(|| {
+                    format!("invalid value for {:?}", String::from_utf8_lossy(&key))
+                })?;
+                visit(Entry {
+                    db,
+                    key,
+                    value,
+                    expires: expires.take(),
+                })?;
+            }
+        }
+    }
+
+    // From version 5 the file ends with a checksum of everything before it, unless checksums were
+    // turned off when it was written, in which case it is 0
+    if version >= 5 {
+        let length = data.len() - reader.remaining();
+        let expected = reader
+            .u64_le()
+            .context("RDB file is missing its checksum")?;
+        let actual = crc64::update(0, &data[..length]);
+        if expected != 0 && expected != actual {
+            bail!("RDB checksum mismatch, expected {expected:#x} but got {actual:#x}");
+        }
+    }
+
+    Ok(())
+}
+
+// Writes every key in the snapshot, with some of the same auxiliary fields as Redis. `aof_base`
+// marks the file as the base of an append-only file.
+pub fn write<W: Write>(snapshot: &Snapshot, out: W, aof_base: bool) -> Result<W> {
+    let mut writer = Writer::new(out);
+    writer.bytes(format!("REDIS{VERSION:04}").as_bytes())?;
+    let ctime = (now() / 1000).to_string();
+    for (name, value) in [
+        ("redis-ver", "7.2.0"),
+        ("redis-bits", "64"),
+        ("ctime", &ctime),
+        ("aof-base", if aof_base { "1" } else { "0" }),
+    ] {
+        writer.u8(OPCODE_AUX)?;
+        writer.string(name.as_bytes())?;
+        writer.string(value.as_bytes())?;
+    }
+
+    for (db, keys) in snapshot.iter().enumerate() {
+        if keys.is_empty() {
+            continue;
+        }
+
+        writer.u8(OPCODE_SELECTDB)?;
+        writer.length(db)?;
+        let expires = keys.iter().filter(|(_, _, expires)| expires.is_some());
+        writer.u8(OPCODE_RESIZEDB)?;
+        writer.length(keys.len())?;
+        writer.length(expires.count())?;
+
+        for (key, value, expires) in keys {
+            if let Some(expires) = expires {
+                writer.u8(OPCODE_EXPIRETIME_MS)?;
+                writer.bytes(&expires.to_le_bytes())?;
+            }
+            // The key comes between the type of the value and the value itself
+            writer.u8(writer::value_type(value))?;
+            writer.string(key.as_bytes())?;
+            writer.value(value)?;
+        }
+    }
+
+    writer.u8(OPCODE_EOF)?;
+    Ok(writer.finish()?)
+}
+
+// Writes the snapshot to a temporary file alongside `path`, which then replaces it, so that a
+// failed save never leaves a partial file behind
+pub fn save(snapshot: &Snapshot, path: &Path, aof_base: bool) -> Result<()> {
+    let temp = path.with_file_name(format!("temp-{}.rdb", process::id()));
+    let result = File::create(&temp)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| write(snapshot, BufWriter::new(file), aof_base))
+        .and_then(|file| {
+            let file = file.into_inner().map_err(|error| error.into_error())?;
+            file.sync_all()?;
+            fs::rename(&temp, path)?;
+            Ok(())
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+
+    result.with_context(|| format!("failed to save {}", path.display()))
+}
+
+// The serialized value used by DUMP and RESTORE, which is its type and value as written to an RDB
+// file, followed by the format version and a checksum of everything before it
+// See: https://github.com/redis/redis/blob/unstable/src/cluster.c (`createDumpPayload`)
+pub fn dump(value: &Value) -> Vec<u8> {
+    let mut writer = Writer::new(Vec::new());
+    writer
+        .u8(writer::value_type(value))
+        .and_then(|()| writer.value(value))
+        .and_then(|()| writer.bytes(&VERSION.to_le_bytes()[..2]))
+        .and_then(|()| writer.finish())
+        .expect("writing to a Vec should not fail")
+}
+
+// Why a DUMP payload could not be restored
+#[derive(Debug, PartialEq, Eq)]
+pub enum PayloadError {
+    // From a newer version of Redis, or corrupted
+    Checksum,
+    // The checksum matches, but the value can't be read
+    Format,
+}
+
+// The value in a DUMP payload, as per `verifyDumpPayload` in Redis
+pub fn restore(payload: &[u8]) -> Result<Value, PayloadError> {
+    let Some(length) = payload.len().checked_sub(10) else {
+        return Err(PayloadError::Checksum);
+    };
+    let (body, footer) = payload.split_at(length);
+    let version = u16::from_le_bytes([footer[0], footer[1]]);
+    let mut crc = [0; 8];
+    crc.copy_from_slice(&footer[2..]);
+    if u32::from(version) > MAX_VERSION
+        || u64::from_le_bytes(crc) != crc64::update(0, &payload[..length + 2])
+    {
+        return Err(PayloadError::Checksum);
+    }
+
+    let mut reader = Reader::new(body);
+    let value = reader
+        .u8()
+        .and_then(|value_type| reader.value(value_type))
+        .map_err(|_| PayloadError::Format)?;
+    if reader.remaining() > 0 {
+        return Err(PayloadError::Format);
+    }
+
+    Ok(value)
+}
+
+// The arguments of RESTORE, other than the key. `ttl` is in milliseconds, and is a deadline with
+// `absolute_ttl`, or no deadline when 0.
+pub struct Restore<'a> {
+    pub ttl: u64,
+    pub payload: &'a [u8],
+    pub replace: bool,
+    pub absolute_ttl: bool,
+    // In seconds, which is only used with LRU eviction, and the LFU counter, only used with LFU
+    pub idle_time: Option<u64>,
+    pub frequency: Option<u8>,
+}
+
+// How long to wait before trying a save point again after a background save fails, as per
+// `CONFIG_BGSAVE_RETRY_DELAY` in Redis
+const RETRY_DELAY: u64 = 5;
+
+// The state of saving snapshots, with SAVE and BGSAVE, or when a save point is reached
+pub struct Saves {
+    // Only one save happens at a time, as they write to the same temporary file
+    in_progress: AtomicBool,
+    // Whether BGSAVE SCHEDULE asked for another save while one was in progress
+    scheduled: AtomicBool,
+    // In seconds since the Unix epoch, when the last save succeeded, and was last attempted
+    last_save: AtomicU64,
+    last_attempt: AtomicU64,
+    last_failed: AtomicBool,
+}
+
+impl Saves {
+    // As there is nothing to save when starting, it counts as the last save, like in Redis
+    pub fn new() -> Self {
+        let now = now() / 1000;
+        Self {
+            in_progress: AtomicBool::new(false),
+            scheduled: AtomicBool::new(false),
+            last_save: AtomicU64::new(now),
+            last_attempt: AtomicU64::new(now),
+            last_failed: AtomicBool::new(false),
+        }
+    }
+
+    // As per LASTSAVE
+    pub fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::Relaxed)
+    }
+
+    pub fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::Relaxed)
+    }
+
+    pub fn last_failed(&self) -> bool {
+        self.last_failed.load(Ordering::Relaxed)
+    }
+
+    // Saves in the foreground, returning `Ok(false)` when a save is already in progress
+    pub fn save(&self, store: &Store, path: &Path) -> Result<bool> {
+        if self.in_progress.swap(true, Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let dirty = store.dirty();
+        let result = save(&store.snapshot(), path, false);
+        self.finish(store, dirty, result.is_ok());
+        result.map(|()| true)
+    }
+
+    // Takes a snapshot, and saves it in the background, returning false when a save is already in
+    // progress
+    pub fn background_save(self: &Arc<Self>, store: &Arc<Store>, path: PathBuf) -> bool {
+        if self.in_progress.swap(true, Ordering::Relaxed) {
+            return false;
+        }
+
+        let dirty = store.dirty();
+        let snapshot = store.snapshot();
+        let (saves, store) = (Arc::clone(self), Arc::clone(store));
+        thread::spawn(move || {
+            let result = save(&snapshot, &path, false);
+            match &result {
+                Ok(()) => println!("background save to {} succeeded", path.display()),
+                Err(error) => eprintln!("background save failed: {error:#}"),
+            }
+            // The snapshot is dropped before another save can start
+            drop(snapshot);
+            saves.finish(&store, dirty, result.is_ok());
+        });
+
+        true
+    }
+
+    // Asks for a background save once the one in progress finishes, as per BGSAVE SCHEDULE
+    pub fn schedule(&self) {
+        self.scheduled.store(true, Ordering::Relaxed);
+    }
+
+    fn finish(&self, store: &Store, dirty: u64, saved: bool) {
+        let now = now() / 1000;
+        if saved {
+            store.saved(dirty);
+            self.last_save.store(now, Ordering::Relaxed);
+        }
+        self.last_attempt.store(now, Ordering::Relaxed);
+        self.last_failed.store(!saved, Ordering::Relaxed);
+        self.in_progress.store(false, Ordering::Relaxed);
+    }
+
+    // Called periodically to start any scheduled save, or one for a save point which has been
+    // reached, being at least as many changes as given within as many seconds of the last save
+    pub fn cron(self: &Arc<Self>, store: &Arc<Store>, config: &Config) {
+        if self.in_progress() {
+            return;
+        }
+
+        let now = now() / 1000;
+        let since_save = now.saturating_sub(self.last_save());
+        let can_retry = !self.last_failed()
+            || now.saturating_sub(self.last_attempt.load(Ordering::Relaxed)) > RETRY_DELAY;
+        let reached = config
+            .save_points
+            .iter()
+            .find(|(seconds, changes)| store.dirty() >= *changes && since_save > *seconds);
+
+        if let Some((seconds, changes)) = reached.filter(|_| can_retry) {
+            println!("{changes} changes in {seconds} seconds, saving...");
+        } else if !self.scheduled.load(Ordering::Relaxed) {
+            return;
+        }
+        self.scheduled.store(false, Ordering::Relaxed);
+        self.background_save(store, config.rdb_path());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{sorted_set::SortedSet, store::Hash};
+    use bytes::Bytes;
+
+    // Adds the end of file opcode and checksum
+    fn finish(mut data: Vec<u8>) -> Vec<u8> {
+        data.push(OPCODE_EOF);
+        let crc = crc64::update(0, &data);
+        data.extend(crc.to_le_bytes());
+        data
+    }
+
+    fn parse_all(data: &[u8]) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        parse(data, |entry| {
+            entries.push(entry);
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    fn string(db: usize, key: &str, value: &str, expires: Option<u64>) -> Entry {
+        Entry {
+            db,
+            key: key.as_bytes().to_vec(),
+            value: Value::String(Bytes::copy_from_slice(value.as_bytes())),
+            expires,
+        }
+    }
+
+    #[test]
+    fn parse_file() {
+        let mut data = b"REDIS0011".to_vec();
+        data.extend([OPCODE_AUX, 9]);
+        data.extend(b"redis-ver");
+        data.extend([5]);
+        data.extend(b"7.2.0");
+        data.extend([OPCODE_SELECTDB, 0, OPCODE_RESIZEDB, 2, 1]);
+        data.extend([TYPE_STRING, 3]);
+        data.extend(b"foo");
+        data.extend([3]);
+        data.extend(b"bar");
+        data.push(OPCODE_EXPIRETIME_MS);
+        data.extend(1_700_000_000_000_u64.to_le_bytes());
+        data.extend([TYPE_STRING, 1, b'n', 0xc0, 0xfe]);
+        data.extend([OPCODE_SELECTDB, 3]);
+        // "aaaaaaaaaa", compressed
+        data.extend([
+            TYPE_STRING,
+            1,
+            b'z',
+            0xc3,
+            5,
+            10,
+            0x00,
+            b'a',
+            0xe0,
+            0x00,
+            0x00,
+        ]);
+        data.extend([OPCODE_EXPIRETIME]);
+        data.extend(1_700_000_000_u32.to_le_bytes());
+        data.extend([TYPE_ZSET_2, 1, b's', 1, 1, b'm']);
+        data.extend(1.5_f64.to_le_bytes());
+        data.extend([TYPE_HASH, 1, b'h', 1, 1, b'f', 0xc1, 0x2c, 0x01]);
+        let data = finish(data);
+
+        let mut set = SortedSet::new();
+        set.insert(b"m".to_vec(), 1.5);
+        let hash = Hash::from([(b"f".to_vec(), b"300".to_vec())]);
+        assert_eq!(
+            parse_all(&data).unwrap(),
+            vec![
+                string(0, "foo", "bar", None),
+                string(0, "n", "-2", Some(1_700_000_000_000)),
+                string(3, "z", "aaaaaaaaaa", None),
+                Entry {
+                    db: 3,
+                    key: b"s".to_vec(),
+                    value: Value::SortedSet(set),
+                    expires: Some(1_700_000_000_000),
+                },
+                Entry {
+                    db: 3,
+                    key: b"h".to_vec(),
+                    value: Value::Hash(hash),
+                    expires: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn checksum() {
+        let mut data = finish(b"REDIS0011".to_vec());
+        assert!(parse_all(&data).is_ok());
+
+        let length = data.len();
+        data[length - 1] ^= 1;
+        assert!(parse_all(&data).is_err());
+
+        // Checksums can be turned off
+        data[length - 8..].fill(0);
+        assert!(parse_all(&data).is_ok());
+
+        // And aren't written before version 5
+        assert!(parse_all(b"REDIS0004\xff").is_ok());
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(parse_all(b"").is_err());
+        assert!(parse_all(b"RESIS0011").is_err());
+        assert!(parse_all(&finish(b"REDIS0099".to_vec())).is_err());
+        assert!(parse_all(b"REDIS0011\x00\x01a").is_err());
+        assert!(parse_all(&finish(b"REDIS0011\x07\x01a\x00".to_vec())).is_err());
+    }
+
+    #[test]
+    fn write_and_parse() {
+        let mut set = SortedSet::new();
+        set.insert(b"m".to_vec(), -2.5);
+        set.insert(b"n".to_vec(), f64::INFINITY);
+        let long = "x".repeat(20_000);
+        let snapshot = vec![
+            vec![
+                (
+                    "small".to_string(),
+                    Value::String(Bytes::from_static(b"-12")),
+                    None,
+                ),
+                (
+                    "medium".to_string(),
+                    Value::String(Bytes::from_static(b"300")),
+                    None,
+                ),
+                (
+                    "large".to_string(),
+                    Value::String(Bytes::from_static(b"100000")),
+                    Some(5),
+                ),
+                (
+                    "padded".to_string(),
+                    Value::String(Bytes::from_static(b"007")),
+                    None,
+                ),
+                (
+                    "long".to_string(),
+                    Value::String(Bytes::from(long.clone())),
+                    None,
+                ),
+            ],
+            vec![],
+            vec![
+                (
+                    "list".to_string(),
+                    Value::List([b"a".to_vec(), b"1".to_vec()].into()),
+                    None,
+                ),
+                ("set".to_string(), Value::Set([b"a".to_vec()].into()), None),
+                ("zset".to_string(), Value::SortedSet(set), None),
+                (
+                    "hash".to_string(),
+                    Value::Hash(Hash::from([(b"f".to_vec(), b"v".to_vec())])),
+                    None,
+                ),
+            ],
+        ];
+
+        let data = write(&snapshot, Vec::new(), false).unwrap();
+        let entries = parse_all(&data).unwrap();
+        let expected = snapshot
+            .into_iter()
+            .enumerate()
+            .flat_map(|(db, keys)| {
+                keys.into_iter().map(move |(key, value, expires)| Entry {
+                    db,
+                    key: key.into_bytes(),
+                    value,
+                    expires,
+                })
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(entries, expected);
+
+        // Integers are written as such
+        assert!(data.windows(7).any(|window| window == b"small\xc0\xf4"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("save-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dump.rdb");
+
+        let store = Store::new();
+        store.set(1, "a".to_string(), Bytes::from_static(b"1"), None);
+        let saves = Saves::new();
+        assert!(saves.save(&store, &path).unwrap());
+        assert_eq!(store.dirty(), 0);
+        assert!(!saves.last_failed());
+
+        let loaded = Store::new();
+        assert_eq!(load(&loaded, &path).unwrap(), 1);
+        assert_eq!(loaded.get(1, "a"), Ok(Some(Bytes::from_static(b"1"))));
+        assert_eq!(loaded.dirty(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // The directory no longer exists
+        assert!(saves.save(&store, &path).is_err());
+        assert!(saves.last_failed());
+    }
+
+    #[test]
+    fn load_into_store() {
+        let mut data = b"REDIS0011".to_vec();
+        data.extend([TYPE_STRING, 1, b'a', 1, b'1']);
+        data.push(OPCODE_EXPIRETIME_MS);
+        data.extend(1_u64.to_le_bytes());
+        data.extend([TYPE_STRING, 1, b'b', 1, b'2']);
+        data.extend([
+            TYPE_SET_INTSET,
+            1,
+            b'c',
+            12,
+            2,
+            0,
+            0,
+            0,
+            2,
+            0,
+            0,
+            0,
+            1,
+            0,
+            2,
+            0,
+        ]);
+        let data = finish(data);
+
+        let path = std::env::temp_dir().join(format!("load-{}.rdb", std::process::id()));
+        fs::write(&path, data).unwrap();
+        let store = Store::new();
+        let loaded = load(&store, &path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), 2);
+        let keyspace = store.read(0, ["a", "b", "c"]);
+        assert_eq!(
+            keyspace.get::<Bytes>("a"),
+            Ok(Some(&Bytes::from_static(b"1")))
+        );
+        assert!(!keyspace.contains("b"));
+        assert_eq!(keyspace.peek("c").unwrap().encoding(), "intset");
+
+        assert_eq!(load(&store, Path::new("/nonexistent/dump.rdb")).unwrap(), 0);
+    }
+
+    #[test]
+    fn dump_and_restore() {
+        // As dumped by Redis 6 for the string 10
+        let payload = b"\x00\xc0\n\n\x00n\x9fWE\x0e\xaec\xbb";
+        let value = Value::String(Bytes::from_static(b"10"));
+        assert_eq!(restore(payload), Ok(value.clone()));
+        assert_eq!(&dump(&value)[..3], &payload[..3]);
+        assert_eq!(&dump(&value)[3..5], &[11, 0]);
+
+        let mut set = SortedSet::new();
+        set.insert(b"a".to_vec(), 1.5);
+        let value = Value::SortedSet(set);
+        assert_eq!(restore(&dump(&value)), Ok(value));
+
+        let mut corrupted = payload.to_vec();
+        corrupted[2] = b'9';
+        assert_eq!(restore(&corrupted), Err(PayloadError::Checksum));
+        let mut newer = payload.to_vec();
+        newer[3] = 13;
+        assert_eq!(restore(&newer), Err(PayloadError::Checksum));
+        assert_eq!(restore(b"short"), Err(PayloadError::Checksum));
+
+        // An unknown type, with a valid checksum
+        let mut unknown = vec![0x7f, 0x0b, 0x00];
+        let crc = crc64::update(0, &unknown);
+        unknown.extend(crc.to_le_bytes());
+        assert_eq!(restore(&unknown), Err(PayloadError::Format));
+    }
+}
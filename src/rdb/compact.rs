@@ -0,0 +1,231 @@
+// The compact encodings Redis uses for small collections, which RDB files hold as strings. Integers
+// are turned back into strings, as Redis does when reading them.
+use super::reader::Reader;
+use anyhow::{bail, ensure, Result};
+
+const END: u8 = 0xff;
+
+// Entries, each prefixed with the length of the previous one so the list can be walked backwards
+// See: https://github.com/redis/redis/blob/unstable/src/ziplist.c
+pub fn ziplist(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader::new(data);
+    // The total bytes, the offset of the last entry, and the number of entries
+    reader.take(10)?;
+
+    let mut entries = Vec::new();
+    while reader.peek() != Some(END) {
+        if reader.u8()? == 254 {
+            reader.take(4)?;
+        }
+
+        let encoding = reader.u8()?;
+        let entry = match encoding >> 6 {
+            0 => reader.take(usize::from(encoding & 0x3f))?.to_vec(),
+            1 => {
+                let length = usize::from(encoding & 0x3f) << 8 | usize::from(reader.u8()?);
+                reader.take(length)?.to_vec()
+            }
+            2 => {
+                let length = usize::try_from(reader.u32_be()?)?;
+                reader.take(length)?.to_vec()
+            }
+            _ => {
+                let integer = match encoding {
+                    0xc0 => i64::from(reader.i16_le()?),
+                    0xd0 => i64::from(reader.i32_le()?),
+                    0xe0 => reader.i64_le()?,
+                    0xf0 => i64::from(reader.i24_le()?),
+                    0xfe => i64::from(reader.i8()?),
+                    // Small integers are held in the encoding itself, offset by one
+                    0xf1..=0xfd => i64::from(encoding & 0x0f) - 1,
+                    _ => bail!("unknown ziplist entry encoding {encoding:#x}"),
+                };
+                integer.to_string().into_bytes()
+            }
+        };
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+// Entries, each followed by its own length so the list can be walked backwards
+// See: https://github.com/redis/redis/blob/unstable/src/listpack.c
+pub fn listpack(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader::new(data);
+    // The total bytes, and the number of entries
+    reader.take(6)?;
+
+    let mut entries = Vec::new();
+    while reader.peek() != Some(END) {
+        let start = reader.remaining();
+        let encoding = reader.u8()?;
+        let entry = if encoding & 0x80 == 0 {
+            i64::from(encoding).to_string().into_bytes()
+        } else if encoding & 0xc0 == 0x80 {
+            reader.take(usize::from(encoding & 0x3f))?.to_vec()
+        } else if encoding & 0xe0 == 0xc0 {
+            // A 13 bit signed integer
+            let integer = i64::from(encoding & 0x1f) << 8 | i64::from(reader.u8()?);
+            let integer = if integer >= 1 << 12 {
+                integer - (1 << 13)
+            } else {
+                integer
+            };
+            integer.to_string().into_bytes()
+        } else if encoding & 0xf0 == 0xe0 {
+            let length = usize::from(encoding & 0x0f) << 8 | usize::from(reader.u8()?);
+            reader.take(length)?.to_vec()
+        } else {
+            let integer = match encoding {
+                0xf0 => {
+                    let length = usize::try_from(reader.u32_le()?)?;
+                    entries.push(reader.take(length)?.to_vec());
+                    skip_backlen(&mut reader, start)?;
+                    continue;
+                }
+                0xf1 => i64::from(reader.i16_le()?),
+                0xf2 => i64::from(reader.i24_le()?),
+                0xf3 => i64::from(reader.i32_le()?),
+                0xf4 => reader.i64_le()?,
+                _ => bail!("unknown listpack entry encoding {encoding:#x}"),
+            };
+            integer.to_string().into_bytes()
+        };
+        entries.push(entry);
+        skip_backlen(&mut reader, start)?;
+    }
+
+    Ok(entries)
+}
+
+// Skips the length of the entry which started with `start` bytes remaining, which takes up 7 bits
+// of each of its bytes
+fn skip_backlen(reader: &mut Reader, start: usize) -> Result<()> {
+    let length = start - reader.remaining();
+    let bytes = match length {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2_097_150 => 3,
+        2_097_151..=268_435_454 => 4,
+        _ => 5,
+    };
+    reader.take(bytes)?;
+
+    Ok(())
+}
+
+// Sorted integers, all of the same width
+// See: https://github.com/redis/redis/blob/unstable/src/intset.c
+pub fn intset(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader::new(data);
+    let width = reader.u32_le()?;
+    let length = reader.u32_le()?;
+
+    let mut members = Vec::new();
+    for _ in 0..length {
+        let member = match width {
+            2 => i64::from(reader.i16_le()?),
+            4 => i64::from(reader.i32_le()?),
+            8 => reader.i64_le()?,
+            _ => bail!("unknown intset encoding {width}"),
+        };
+        members.push(member.to_string().into_bytes());
+    }
+    ensure!(reader.remaining() == 0, "intset has trailing data");
+
+    Ok(members)
+}
+
+// Fields and values, which Redis no longer uses but can still load
+// See: https://github.com/redis/redis/blob/unstable/src/zipmap.c
+pub fn zipmap(data: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    fn length(reader: &mut Reader) -> Result<Option<usize>> {
+        match reader.u8()? {
+            END => Ok(None),
+            254 => Ok(Some(usize::try_from(reader.u32_le()?)?)),
+            length => Ok(Some(usize::from(length))),
+        }
+    }
+
+    let mut reader = Reader::new(data);
+    // The number of entries, when there are less than 254
+    reader.u8()?;
+
+    let mut entries = Vec::new();
+    while let Some(field_length) = length(&mut reader)? {
+        let field = reader.take(field_length)?.to_vec();
+        let Some(value_length) = length(&mut reader)? else {
+            bail!("zipmap field is missing a value");
+        };
+        // Unused bytes after the value, left by updates in place
+        let free = reader.u8()?;
+        let value = reader.take(value_length)?.to_vec();
+        reader.take(usize::from(free))?;
+        entries.push((field, value));
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strings(entries: &[&str]) -> Vec<Vec<u8>> {
+        entries
+            .iter()
+            .map(|entry| entry.as_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn ziplist_entries() {
+        let data = [
+            20, 0, 0, 0, 15, 0, 0, 0, 3, 0, // Header
+            0, 0x01, b'a', // "a"
+            3, 0xf6, // 5, held in the encoding
+            2, 0xc0, 0x2c, 0x01, // 300 as an int16
+            0xff,
+        ];
+        assert_eq!(ziplist(&data).unwrap(), strings(&["a", "5", "300"]));
+        assert!(ziplist(&data[..data.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn listpack_entries() {
+        let data = [
+            22, 0, 0, 0, 4, 0, // Header
+            0x81, b'a', 2, // "a"
+            5, 1, // 5 as a 7 bit integer
+            0x85, b'h', b'e', b'l', b'l', b'o', 6, // "hello"
+            0xdc, 0x18, 2, // -1000 as a 13 bit integer
+            0xff,
+        ];
+        assert_eq!(
+            listpack(&data).unwrap(),
+            strings(&["a", "5", "hello", "-1000"])
+        );
+        assert!(listpack(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn intset_members() {
+        let data = [2, 0, 0, 0, 3, 0, 0, 0, 0xfd, 0xff, 1, 0, 2, 0];
+        assert_eq!(intset(&data).unwrap(), strings(&["-3", "1", "2"]));
+        assert!(intset(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn zipmap_entries() {
+        let data = [
+            1, // Entries
+            1, b'a', 2, 1, b'b', b'c', 0, // "a" => "bc", with a free byte
+            0xff,
+        ];
+        assert_eq!(
+            zipmap(&data).unwrap(),
+            vec![(b"a".to_vec(), b"bc".to_vec())]
+        );
+    }
+}
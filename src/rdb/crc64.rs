@@ -0,0 +1,46 @@
+// The CRC-64 variant used by Redis for RDB files and DUMP payloads, known as CRC-64/Jones, with
+// the reflected form of the polynomial 0xad93d23594c935a9
+// See: https://github.com/redis/redis/blob/unstable/src/crc64.c
+const POLYNOMIAL: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+// Continues the checksum `crc` over more data, starting from 0
+pub fn update(crc: u64, data: &[u8]) -> u64 {
+    data.iter().fold(crc, |crc, byte| {
+        #[allow(clippy::cast_possible_truncation)]
+        let index = (crc ^ u64::from(*byte)) as u8;
+        TABLE[usize::from(index)] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(update(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+
+        let crc = update(0, b"1234");
+        assert_eq!(update(crc, b"56789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+}
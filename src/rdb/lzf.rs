@@ -0,0 +1,70 @@
+use anyhow::{ensure, Context, Result};
+
+// Decompresses LZF data, as used for long strings in RDB files, which is a series of literal runs
+// and back references into the output so far
+// See: https://github.com/redis/redis/blob/unstable/src/lzf_d.c
+pub fn decompress(input: &[u8], length: usize) -> Result<Vec<u8>> {
+    // The length comes from the file, so isn't trusted to allocate up front
+    let mut output = Vec::new();
+    let mut input = input.iter().copied();
+
+    while let Some(control) = input.next() {
+        let control = usize::from(control);
+        if control < 1 << 5 {
+            // A run of up to 32 literal bytes
+            let run = control + 1;
+            let start = output.len();
+            output.extend(input.by_ref().take(run));
+            ensure!(output.len() - start == run, "LZF literal run is truncated");
+            continue;
+        }
+
+        // A back reference, with the length in the top 3 bits, extended by the next byte when they
+        // are all set
+        let mut run = control >> 5;
+        if run == 7 {
+            run += usize::from(input.next().context("LZF back reference is truncated")?);
+        }
+        run += 2;
+        let low = input.next().context("LZF back reference is truncated")?;
+        let offset = ((control & 0x1f) << 8) + usize::from(low) + 1;
+        ensure!(
+            offset <= output.len(),
+            "LZF back reference is out of bounds"
+        );
+
+        // The reference may overlap the bytes being copied, so they are copied one at a time
+        let start = output.len() - offset;
+        for i in 0..run {
+            output.push(output[start + i]);
+        }
+    }
+
+    ensure!(
+        output.len() == length,
+        "LZF data decompressed to {} bytes rather than {length}",
+        output.len()
+    );
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decompress_runs_and_references() {
+        assert_eq!(
+            decompress(&[0x02, b'a', b'b', b'c', 0x20, 0x02], 6).unwrap(),
+            b"abcabc"
+        );
+        assert_eq!(
+            decompress(&[0x00, b'a', 0xe0, 0x00, 0x00], 10).unwrap(),
+            b"aaaaaaaaaa"
+        );
+
+        assert!(decompress(&[0x02, b'a'], 3).is_err());
+        assert!(decompress(&[0x20, 0x05], 3).is_err());
+        assert!(decompress(&[0x00, b'a'], 2).is_err());
+    }
+}
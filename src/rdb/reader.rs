@@ -0,0 +1,241 @@
+use super::{compact, lzf, *};
+use crate::{
+    sorted_set::SortedSet,
+    store::{Hash, List, Value},
+};
+use anyhow::{bail, ensure, Context, Result};
+use bytes::Bytes;
+use std::str;
+
+// Reads the parts of an RDB file, or a DUMP payload, from the front of the data
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+// Either a length, or how a string is encoded in place of one
+enum Length {
+    Length(usize),
+    Encoded(u8),
+}
+
+impl<'a> Reader<'a> {
+    pub const fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    // How many bytes are left
+    pub const fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        ensure!(count <= self.data.len(), "unexpected end of RDB data");
+        let (taken, rest) = self.data.split_at(count);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("N bytes were taken"))
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    pub fn i8(&mut self) -> Result<i8> {
+        Ok(i8::from_le_bytes(self.array()?))
+    }
+
+    pub fn i16_le(&mut self) -> Result<i16> {
+        Ok(i16::from_le_bytes(self.array()?))
+    }
+
+    // Sign extended from 24 bits
+    pub fn i24_le(&mut self) -> Result<i32> {
+        let [a, b, c] = self.array()?;
+        Ok(i32::from_le_bytes([0, a, b, c]) >> 8)
+    }
+
+    pub fn i32_le(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32_le(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32_be(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn i64_le(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64_le(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    // The top two bits of the first byte say how long the length is, or that a string is encoded
+    // differently, with the rest of the bits saying how
+    fn length_or_encoding(&mut self) -> Result<Length> {
+        let first = self.u8()?;
+        let length = match first >> 6 {
+            0 => u64::from(first & 0x3f),
+            1 => u64::from(first & 0x3f) << 8 | u64::from(self.u8()?),
+            2 if first == 0x80 => u64::from(self.u32_be()?),
+            2 if first == 0x81 => u64::from_be_bytes(self.array()?),
+            2 => bail!("invalid RDB length encoding {first:#x}"),
+            _ => return Ok(Length::Encoded(first & 0x3f)),
+        };
+
+        Ok(Length::Length(
+            usize::try_from(length).context("RDB length is too large")?,
+        ))
+    }
+
+    pub fn length(&mut self) -> Result<usize> {
+        match self.length_or_encoding()? {
+            Length::Length(length) => Ok(length),
+            Length::Encoded(_) => bail!("expected an RDB length, got an encoded string"),
+        }
+    }
+
+    // Strings may be stored as integers, or compressed
+    pub fn string(&mut self) -> Result<Vec<u8>> {
+        match self.length_or_encoding()? {
+            Length::Length(length) => Ok(self.take(length)?.to_vec()),
+            Length::Encoded(ENCODING_INT8) => Ok(self.i8()?.to_string().into_bytes()),
+            Length::Encoded(ENCODING_INT16) => Ok(self.i16_le()?.to_string().into_bytes()),
+            Length::Encoded(ENCODING_INT32) => Ok(self.i32_le()?.to_string().into_bytes()),
+            Length::Encoded(ENCODING_LZF) => {
+                let compressed = self.length()?;
+                let length = self.length()?;
+                lzf::decompress(self.take(compressed)?, length)
+            }
+            Length::Encoded(encoding) => bail!("unknown RDB string encoding {encoding}"),
+        }
+    }
+
+    // As a string of up to 252 characters, with the lengths above that marking infinities and NaN
+    fn double(&mut self) -> Result<f64> {
+        match self.u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            length => parse_double(self.take(usize::from(length))?),
+        }
+    }
+
+    fn binary_double(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    fn strings(&mut self) -> Result<Vec<Vec<u8>>> {
+        (0..self.length()?).map(|_| self.string()).collect()
+    }
+
+    pub fn value(&mut self, value_type: u8) -> Result<Value> {
+        let value = match value_type {
+            TYPE_STRING => Value::String(Bytes::from(self.string()?)),
+            TYPE_LIST => Value::List(self.strings()?.into()),
+            TYPE_SET => Value::Set(self.strings()?.into_iter().collect()),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut set = SortedSet::new();
+                for _ in 0..self.length()? {
+                    let member = self.string()?;
+                    let score = if value_type == TYPE_ZSET_2 {
+                        self.binary_double()?
+                    } else {
+                        self.double()?
+                    };
+                    ensure!(!score.is_nan(), "sorted set score is NaN");
+                    set.insert(member, score);
+                }
+                Value::SortedSet(set)
+            }
+            TYPE_HASH => {
+                let mut hash = Hash::new();
+                for _ in 0..self.length()? {
+                    let field = self.string()?;
+                    hash.insert(field, self.string()?);
+                }
+                Value::Hash(hash)
+            }
+            TYPE_HASH_ZIPMAP => {
+                Value::Hash(compact::zipmap(&self.string()?)?.into_iter().collect())
+            }
+            TYPE_LIST_ZIPLIST => Value::List(compact::ziplist(&self.string()?)?.into()),
+            TYPE_SET_INTSET => Value::Set(compact::intset(&self.string()?)?.into_iter().collect()),
+            TYPE_SET_LISTPACK => {
+                Value::Set(compact::listpack(&self.string()?)?.into_iter().collect())
+            }
+            TYPE_ZSET_ZIPLIST => sorted_set(compact::ziplist(&self.string()?)?)?,
+            TYPE_ZSET_LISTPACK => sorted_set(compact::listpack(&self.string()?)?)?,
+            TYPE_HASH_ZIPLIST => hash(compact::ziplist(&self.string()?)?)?,
+            TYPE_HASH_LISTPACK => hash(compact::listpack(&self.string()?)?)?,
+            TYPE_LIST_QUICKLIST => {
+                let mut list = List::new();
+                for _ in 0..self.length()? {
+                    list.extend(compact::ziplist(&self.string()?)?);
+                }
+                Value::List(list)
+            }
+            TYPE_LIST_QUICKLIST_2 => {
+                let mut list = List::new();
+                for _ in 0..self.length()? {
+                    match self.length()? {
+                        QUICKLIST_NODE_PLAIN => list.push_back(self.string()?),
+                        QUICKLIST_NODE_PACKED => list.extend(compact::listpack(&self.string()?)?),
+                        container => bail!("unknown quicklist node container {container}"),
+                    }
+                }
+                Value::List(list)
+            }
+            _ => bail!("unsupported RDB value type {value_type}"),
+        };
+
+        Ok(value)
+    }
+}
+
+fn parse_double(value: &[u8]) -> Result<f64> {
+    str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .with_context(|| format!("invalid double {:?}", String::from_utf8_lossy(value)))
+}
+
+// Members and scores, one after the other
+fn sorted_set(entries: Vec<Vec<u8>>) -> Result<Value> {
+    ensure!(entries.len() % 2 == 0, "sorted set is missing a score");
+
+    let mut set = SortedSet::new();
+    let mut entries = entries.into_iter();
+    while let (Some(member), Some(score)) = (entries.next(), entries.next()) {
+        let score = parse_double(&score)?;
+        ensure!(!score.is_nan(), "sorted set score is NaN");
+        set.insert(member, score);
+    }
+
+    Ok(Value::SortedSet(set))
+}
+
+// Fields and values, one after the other
+fn hash(entries: Vec<Vec<u8>>) -> Result<Value> {
+    ensure!(entries.len() % 2 == 0, "hash field is missing a value");
+
+    let mut hash = Hash::new();
+    let mut entries = entries.into_iter();
+    while let (Some(field), Some(value)) = (entries.next(), entries.next()) {
+        hash.insert(field, value);
+    }
+
+    Ok(Value::Hash(hash))
+}
@@ -0,0 +1,116 @@
+use super::{crc64, *};
+use crate::store::Value;
+use std::io::{self, Write};
+
+// Writes the parts of an RDB file, keeping a checksum of everything written so far
+pub struct Writer<W> {
+    inner: W,
+    crc: u64,
+}
+
+impl<W: Write> Writer<W> {
+    pub const fn new(inner: W) -> Self {
+        Self { inner, crc: 0 }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc = crc64::update(self.crc, bytes);
+        self.inner.write_all(bytes)
+    }
+
+    pub fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.bytes(&[value])
+    }
+
+    // The shortest of the length encodings which fits
+    pub fn length(&mut self, length: usize) -> io::Result<()> {
+        if let Ok(length @ 0..0x40) = u8::try_from(length) {
+            return self.u8(length);
+        }
+        if let Ok(length @ 0..0x4000) = u16::try_from(length) {
+            return self.bytes(&(0x4000 | length).to_be_bytes());
+        }
+        if let Ok(length) = u32::try_from(length) {
+            self.u8(0x80)?;
+            return self.bytes(&length.to_be_bytes());
+        }
+
+        self.u8(0x81)?;
+        self.bytes(&(length as u64).to_be_bytes())
+    }
+
+    // Like Redis, strings which are integers are written as such when they fit in 32 bits, and
+    // would be read back exactly as they were
+    pub fn string(&mut self, string: &[u8]) -> io::Result<()> {
+        let integer = std::str::from_utf8(string)
+            .ok()
+            .and_then(|string| string.parse::<i32>().ok())
+            .filter(|integer| integer.to_string().as_bytes() == string);
+        let Some(integer) = integer else {
+            self.length(string.len())?;
+            return self.bytes(string);
+        };
+
+        if let Ok(integer) = i8::try_from(integer) {
+            self.u8(0xc0 | ENCODING_INT8)?;
+            self.bytes(&integer.to_le_bytes())
+        } else if let Ok(integer) = i16::try_from(integer) {
+            self.u8(0xc0 | ENCODING_INT16)?;
+            self.bytes(&integer.to_le_bytes())
+        } else {
+            self.u8(0xc0 | ENCODING_INT32)?;
+            self.bytes(&integer.to_le_bytes())
+        }
+    }
+
+    // Collections are written in the plain encodings, which every version of Redis can read, and
+    // converts as needed
+    pub fn value(&mut self, value: &Value) -> io::Result<()> {
+        match value {
+            Value::String(string) => self.string(string),
+            Value::List(list) => {
+                self.length(list.len())?;
+                list.iter().try_for_each(|element| self.string(element))
+            }
+            Value::Set(set) => {
+                self.length(set.len())?;
+                set.iter().try_for_each(|member| self.string(member))
+            }
+            Value::SortedSet(set) => {
+                self.length(set.len())?;
+                set.range(f64::NEG_INFINITY, f64::INFINITY)
+                    .try_for_each(|(member, score)| {
+                        self.string(member)?;
+                        self.bytes(&score.to_le_bytes())
+                    })
+            }
+            Value::Hash(hash) => {
+                self.length(hash.len())?;
+                hash.iter().try_for_each(|(field, value)| {
+                    self.string(field)?;
+                    self.string(value)
+                })
+            }
+        }
+    }
+
+    // The checksum of everything written so far, which is written last
+    pub fn finish(mut self) -> io::Result<W> {
+        let crc = self.crc;
+        self.bytes(&crc.to_le_bytes())?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+// The type written before the value, as per `Writer::value`
+pub const fn value_type(value: &Value) -> u8 {
+    match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Set(_) => TYPE_SET,
+        Value::SortedSet(_) => TYPE_ZSET_2,
+        Value::Hash(_) => TYPE_HASH,
+    }
+}
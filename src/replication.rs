@@ -0,0 +1,1044 @@
+use crate::{
+    aof::Aof,
+    client::Client,
+    command::Command,
+    connection::{is_timeout, Connection},
+    evict, rdb,
+    resp::{command, Array, BulkString, Integer, RespType},
+    store::{Snapshot, Store},
+};
+use anyhow::{anyhow, bail, Context, Result};
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::Write,
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+// Replicas connect to a master, which sends them a snapshot of the keyspace followed by every write
+// since, so that they stay a copy of it
+// See: https://redis.io/docs/latest/operate/oss_and_stack/management/replication/
+
+// How long a replica waits before connecting to its master again, as per `replicationCron`
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// How often a replica acknowledges the writes it has applied, as per `replicationCron`
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+// The longest WAIT goes between checking for acknowledgements, in case it missed being woken up
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Replication {
+    ids: Mutex<Ids>,
+    // How many bytes of writes have been sent to replicas, or for a replica, received from its
+    // master
+    offset: AtomicU64,
+    // `None` until the first replica connects
+    feed: Mutex<Option<Feed>>,
+    // Writes made while there is nothing to feed hold this for reading, so that a replica
+    // connecting waits for them to finish, and none can be missed from both its snapshot and feed
+    unfed: RwLock<()>,
+    // The master being replicated, if this is a replica
+    master: Mutex<Option<Master>>,
+    // How many links to masters have been started
+    links: AtomicU64,
+    // As per `repl-backlog-size`
+    backlog_size: AtomicUsize,
+    // Notified whenever a replica acknowledges writes, for WAIT
+    acks: Mutex<()>,
+    acked: Condvar,
+}
+
+// The histories of writes which replicas can continue
+struct Ids {
+    // Identifies the history of writes, which a replica takes from its master
+    id: String,
+    // The history before the last change of master, such as after a failover, and the offset up
+    // to which it is shared with the current one
+    previous: Option<(String, u64)>,
+}
+
+// What is sent to replicas, which is the same for all of them
+pub struct Feed {
+    // The database last selected in the feed, so that SELECT is only sent when it changes
+    db: Option<usize>,
+    replicas: Vec<Replica>,
+    next_id: u64,
+    backlog: Backlog,
+    // Whether this is a replica, which passes on what its master sends rather than its own writes
+    proxy: bool,
+}
+
+impl Feed {
+    fn new(backlog_size: usize) -> Self {
+        Self {
+            db: None,
+            replicas: Vec::new(),
+            next_id: 0,
+            backlog: Backlog::new(backlog_size),
+            proxy: false,
+        }
+    }
+
+    fn send(&mut self, offset: &AtomicU64, data: Vec<u8>) -> u64 {
+        let length = data.len() as u64;
+        let offset = offset.fetch_add(length, Ordering::Relaxed) + length;
+        self.backlog.push(&data);
+
+        // Replicas which have disconnected are dropped, which ends their feed
+        let data = Arc::<[u8]>::from(data);
+        self.replicas
+            .retain(|replica| replica.sender.send(Arc::clone(&data)).is_ok());
+        offset
+    }
+}
+
+// The most recent writes sent to replicas, so that one which reconnects can be sent those it
+// missed, rather than a full snapshot
+// See: https://redis.io/docs/latest/operate/oss_and_stack/management/replication/#partial-resynchronizations-after-restarts-and-failovers
+struct Backlog {
+    data: VecDeque<u8>,
+    size: usize,
+}
+
+impl Backlog {
+    const fn new(size: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            size,
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        self.data.extend(data);
+        self.trim();
+    }
+
+    fn resize(&mut self, size: usize) {
+        self.size = size;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        let excess = self.data.len().saturating_sub(self.size);
+        self.data.drain(..excess);
+    }
+
+    // The offset of the oldest write kept, given that of the end of the backlog
+    fn start(&self, offset: u64) -> u64 {
+        offset.saturating_sub(self.data.len() as u64)
+    }
+
+    // The writes after `from`, unless some of them are no longer kept
+    fn since(&self, from: u64, offset: u64) -> Option<Vec<u8>> {
+        if from < self.start(offset) || from > offset {
+            return None;
+        }
+        let skipped = usize::try_from(from - self.start(offset)).ok()?;
+        Some(self.data.range(skipped..).copied().collect())
+    }
+}
+
+struct Replica {
+    id: u64,
+    ip: String,
+    // The port the replica listens on, as given with REPLCONF
+    port: Option<u16>,
+    // Writes waiting to be sent, once the snapshot has been
+    sender: Sender<Arc<[u8]>>,
+    state: Arc<ReplicaState>,
+}
+
+#[derive(Default)]
+struct ReplicaState {
+    // Whether the snapshot has been sent
+    online: AtomicBool,
+    // The offset the replica last acknowledged with REPLCONF ACK
+    ack: AtomicU64,
+    // The offset up to which the replica has written its append-only file to disk, as given with
+    // FACK, for WAITAOF
+    fack: AtomicU64,
+}
+
+struct Master {
+    // Identifies the thread linking to the master, which stops once it is replaced
+    link_id: u64,
+    host: String,
+    port: u16,
+    link: Link,
+    // The connection to the master, once made, so that it can be closed when it's replaced
+    stream: Option<TcpStream>,
+}
+
+impl Master {
+    fn close(self) {
+        if let Some(stream) = self.stream {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// What the thread linking to a master needs to apply what it sends
+struct Upstream {
+    id: u64,
+    host: String,
+    port: u16,
+    // The port this instance listens on, which the master lists its replicas with
+    listening_port: u16,
+    client: Client,
+    store: Arc<Store>,
+    aof: Arc<Aof>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Link {
+    Connecting,
+    Syncing,
+    Up,
+}
+
+// Held while a write runs, so that writes are sent to replicas in the order they happen
+pub enum FeedGuard<'a> {
+    // Only held to stop a replica connecting while the write runs, with the offset still counted
+    // for WAITAOF
+    Unfed(&'a AtomicU64, #[allow(dead_code)] RwLockReadGuard<'a, ()>),
+    Fed(&'a AtomicU64, MutexGuard<'a, Option<Feed>>),
+}
+
+impl FeedGuard<'_> {
+    pub const fn is_fed(&self) -> bool {
+        matches!(self, Self::Fed(..))
+    }
+
+    // Sends a write of this instance, unless it is a replica, returning the offset after it
+    pub fn propagate(&mut self, db: usize, entry: &[u8]) -> u64 {
+        let (offset, feed) = match self {
+            Self::Fed(offset, feed) => (offset, feed),
+            Self::Unfed(offset, _) => {
+                let length = entry.len() as u64;
+                return offset.fetch_add(length, Ordering::Relaxed) + length;
+            }
+        };
+        let feed = feed.as_mut().expect("feed should be started");
+        if feed.proxy {
+            return offset.load(Ordering::Relaxed);
+        }
+
+        let mut data = Vec::with_capacity(entry.len());
+        if feed.db != Some(db) {
+            data.extend(command(&[b"SELECT", db.to_string().as_bytes()]));
+            feed.db = Some(db);
+        }
+        data.extend_from_slice(entry);
+        feed.send(offset, data)
+    }
+
+    // Sends a request from the master of this replica as it is, so that its replicas share the
+    // offsets of its master, returning the offset after it
+    pub fn proxy(&mut self, request: &[u8]) -> u64 {
+        let Self::Fed(offset, feed) = self else {
+            return 0;
+        };
+        let feed = feed.as_mut().expect("feed should be started");
+        feed.db = None;
+        feed.send(offset, request.to_vec())
+    }
+}
+
+// How the feed of a replica starts
+enum Start {
+    // The writes it missed since it was last connected, from the backlog
+    Continue(Vec<u8>),
+    // A snapshot of the keyspace, and the offset it was taken at
+    FullResync(u64, Snapshot),
+}
+
+impl Replication {
+    pub fn new(backlog_size: usize) -> Self {
+        Self {
+            ids: Mutex::new(Ids {
+                id: new_id(),
+                previous: None,
+            }),
+            offset: AtomicU64::new(0),
+            feed: Mutex::new(None),
+            unfed: RwLock::new(()),
+            master: Mutex::new(None),
+            links: AtomicU64::new(0),
+            backlog_size: AtomicUsize::new(backlog_size),
+            acks: Mutex::new(()),
+            acked: Condvar::new(),
+        }
+    }
+
+    pub fn set_backlog_size(&self, size: usize) {
+        self.backlog_size.store(size, Ordering::Relaxed);
+        if let Some(feed) = self.feed().as_mut() {
+            feed.backlog.resize(size);
+        }
+    }
+
+    fn feed(&self) -> MutexGuard<'_, Option<Feed>> {
+        self.feed.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn master(&self) -> MutexGuard<'_, Option<Master>> {
+        self.master.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn ids(&self) -> MutexGuard<'_, Ids> {
+        self.ids.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn id(&self) -> String {
+        self.ids().id.clone()
+    }
+
+    // Starts a new history, which replicas of the previous one can still continue up to here
+    fn shift_id(&self, id: String) {
+        let mut ids = self.ids();
+        let previous = std::mem::replace(&mut ids.id, id);
+        ids.previous = Some((previous, self.offset() + 1));
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    // Whether this is a master, or a replica whose link to its master is up
+    pub fn is_synced(&self) -> bool {
+        self.master()
+            .as_ref()
+            .is_none_or(|master| master.link == Link::Up)
+    }
+
+    // Taken before running a write, which once a replica has connected means one at a time
+    pub fn lock(&self) -> FeedGuard<'_> {
+        let unfed = self.unfed.read().unwrap_or_else(PoisonError::into_inner);
+        let feed = self.feed();
+        if feed.is_some() {
+            FeedGuard::Fed(&self.offset, feed)
+        } else {
+            FeedGuard::Unfed(&self.offset, unfed)
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.master().is_some()
+    }
+
+    // Asks replicas to acknowledge the writes they have applied, as for WAIT
+    pub fn request_acks(&self) {
+        if let Some(feed) = self.feed().as_mut() {
+            if !feed.proxy {
+                feed.send(&self.offset, command(&[b"REPLCONF", b"GETACK", b"*"]));
+            }
+        }
+    }
+
+    // The number of replicas which have acknowledged applying the writes up to `offset`, or with
+    // `fsynced`, writing them to their append-only file on disk
+    pub fn acknowledged(&self, offset: u64, fsynced: bool) -> usize {
+        let feed = self.feed();
+        let replicas = feed.as_ref().map_or(&[][..], |feed| &feed.replicas);
+        replicas
+            .iter()
+            .filter(|replica| {
+                let ack = if fsynced {
+                    &replica.state.fack
+                } else {
+                    &replica.state.ack
+                };
+                ack.load(Ordering::Relaxed) >= offset
+            })
+            .count()
+    }
+
+    // Waits until `done`, which is checked whenever replicas acknowledge writes, or until the
+    // deadline, if any
+    pub fn wait(&self, deadline: Option<Instant>, mut done: impl FnMut() -> bool) {
+        let mut acks = self.acks.lock().unwrap_or_else(PoisonError::into_inner);
+        while !done() {
+            let timeout = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining.min(WAIT_INTERVAL),
+                    _ => return,
+                },
+                None => WAIT_INTERVAL,
+            };
+            acks = self
+                .acked
+                .wait_timeout(acks, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    // Takes over the connection of a replica which sent PSYNC, sending it the writes it missed if
+    // it can continue from `offset` of history `id`, or else a snapshot of the keyspace, then the
+    // writes since, from threads of its own
+    pub fn add_replica(
+        self: &Arc<Self>,
+        store: &Store,
+        stream: TcpStream,
+        port: Option<u16>,
+        (id, offset): (&str, i64),
+    ) -> Result<()> {
+        let ip = stream.peer_addr()?.ip().to_string();
+        let writer = stream.try_clone()?;
+        let state = Arc::new(ReplicaState::default());
+        let (sender, receiver) = mpsc::channel();
+
+        // The snapshot is taken while writes are held up, so that it matches the start of the feed
+        let (id, start, replica) = {
+            let _unfed = self.unfed.write().unwrap_or_else(PoisonError::into_inner);
+            let mut feed = self.feed();
+            let feed = feed.get_or_insert_with(|| Feed::new(self.backlog_size()));
+            let start = match self.missed(feed, id, offset) {
+                Some(missed) => Start::Continue(missed),
+                None => Start::FullResync(self.offset(), store.snapshot()),
+            };
+            feed.next_id += 1;
+            feed.replicas.push(Replica {
+                id: feed.next_id,
+                ip,
+                port,
+                sender,
+                state: Arc::clone(&state),
+            });
+            (self.id(), start, feed.next_id)
+        };
+        match start {
+            Start::Continue(_) => println!("replica {replica} connected, continuing its history"),
+            Start::FullResync(..) => {
+                println!("replica {replica} connected, starting full resynchronization");
+            }
+        }
+
+        let sent = Arc::clone(&state);
+        thread::spawn(move || {
+            if let Err(error) = send_feed(&writer, &id, start, receiver, &sent) {
+                eprintln!("replica {replica} disconnected: {error:#}");
+            }
+            let _ = writer.shutdown(Shutdown::Both);
+        });
+
+        let replication = Arc::clone(self);
+        thread::spawn(move || {
+            read_acks(stream, &state, &replication);
+            if let Some(feed) = replication.feed().as_mut() {
+                feed.replicas.retain(|connected| connected.id != replica);
+            }
+        });
+
+        Ok(())
+    }
+
+    fn backlog_size(&self) -> usize {
+        self.backlog_size.load(Ordering::Relaxed)
+    }
+
+    // The writes a replica missed, if it has this history, or the one before, up to `offset`, as
+    // given with PSYNC, which counts from 1, and they are all still in the backlog
+    fn missed(&self, feed: &Feed, id: &str, offset: i64) -> Option<Vec<u8>> {
+        let offset = u64::try_from(offset).ok()?;
+        let ids = self.ids();
+        let continues = ids.id == id
+            || ids
+                .previous
+                .as_ref()
+                .is_some_and(|(previous, end)| previous == id && offset <= *end);
+        if !continues {
+            return None;
+        }
+        feed.backlog.since(offset.checked_sub(1)?, self.offset())
+    }
+
+    // Replicates the master from a thread of its own, connecting again whenever the link fails,
+    // with `client` applying the writes it sends. Replicas of this instance are disconnected, so
+    // that they follow the change of history.
+    pub fn replicate(
+        self: &Arc<Self>,
+        host: String,
+        port: u16,
+        client: Client,
+        store: Arc<Store>,
+        aof: Arc<Aof>,
+        listening_port: u16,
+    ) {
+        let id = self.links.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut master = self.master();
+        if let Some(previous) = master.take() {
+            previous.close();
+        }
+        *master = Some(Master {
+            link_id: id,
+            host: host.clone(),
+            port,
+            link: Link::Connecting,
+            stream: None,
+        });
+        if let Some(feed) = self.feed().as_mut() {
+            feed.replicas.clear();
+        }
+        drop(master);
+
+        let upstream = Upstream {
+            id,
+            host,
+            port,
+            listening_port,
+            client,
+            store,
+            aof,
+        };
+        let replication = Arc::clone(self);
+        thread::spawn(move || {
+            while replication.linked(id).is_some() {
+                if let Err(error) = replication.sync(&upstream) {
+                    let (host, port) = (&upstream.host, upstream.port);
+                    eprintln!("replication from {host}:{port} failed: {error:#}");
+                }
+                replication.set_link(id, Link::Connecting);
+                thread::sleep(RECONNECT_DELAY);
+            }
+        });
+    }
+
+    // As per REPLICAOF NO ONE, stops replicating the master, continuing its history as a new one,
+    // returning false if this wasn't a replica
+    pub fn promote(&self) -> bool {
+        let mut master = self.master();
+        let Some(previous) = master.take() else {
+            return false;
+        };
+        previous.close();
+
+        // Writes are held up until the feed carries those of this instance
+        let mut feed = self.feed();
+        self.shift_id(new_id());
+        if let Some(feed) = feed.as_mut() {
+            feed.proxy = false;
+            feed.db = None;
+            // Replicas continue the new history once they connect again
+            feed.replicas.clear();
+        }
+        true
+    }
+
+    pub fn is_replica_of(&self, host: &str, port: u16) -> bool {
+        self.master()
+            .as_ref()
+            .is_some_and(|master| master.host == host && master.port == port)
+    }
+
+    // The master, if the link with `id` is still the one to it, which is held while writes from
+    // it are applied, so that they stop as soon as the master changes
+    fn linked(&self, id: u64) -> Option<MutexGuard<'_, Option<Master>>> {
+        let master = self.master();
+        master
+            .as_ref()
+            .is_some_and(|master| master.link_id == id)
+            .then_some(master)
+    }
+
+    fn set_link(&self, id: u64, link: Link) {
+        if let Some(mut master) = self.linked(id) {
+            master.as_mut().expect("master should be linked").link = link;
+        }
+    }
+
+    // Performs the handshake with the master, loads the snapshot it sends, then applies writes
+    // until the connection fails
+    fn sync(&self, upstream: &Upstream) -> Result<()> {
+        let stream = TcpStream::connect((upstream.host.as_str(), upstream.port))
+            .context("failed to connect")?;
+        match self.linked(upstream.id) {
+            Some(mut master) => {
+                master.as_mut().expect("master should be linked").stream =
+                    Some(stream.try_clone()?);
+            }
+            None => bail!("the master has changed"),
+        }
+        let mut master = Connection::new(stream);
+        master.expect(&[b"PING"], "+PONG")?;
+        let listening_port = upstream.listening_port.to_string();
+        master.expect(
+            &[b"REPLCONF", b"listening-port", listening_port.as_bytes()],
+            "+OK",
+        )?;
+        master.expect(&[b"REPLCONF", b"capa", b"eof", b"capa", b"psync2"], "+OK")?;
+
+        // Like Redis, the history this instance has is offered for the master to continue, which
+        // it won't unless this was its replica, or a replica of the same master, before
+        let (id, offset) = (self.id(), (self.offset() + 1).to_string());
+        master.send(&[b"PSYNC", id.as_bytes(), offset.as_bytes()])?;
+        let reply = master.read_line()?;
+        if let Some(new_id) = reply.strip_prefix("+CONTINUE") {
+            let _linked = self.linked(upstream.id).context("the master has changed")?;
+            // The master has a history of its own if it was a replica before a failover
+            let new_id = new_id.trim();
+            if !new_id.is_empty() && new_id != id {
+                self.shift_id(new_id.to_string());
+            }
+            println!("continuing the history of the master from offset {offset}");
+            self.start_proxy(false);
+        } else {
+            let (id, offset) = reply
+                .strip_prefix("+FULLRESYNC ")
+                .and_then(|reply| reply.split_once(' '))
+                .and_then(|(id, offset)| Some((id.to_string(), offset.parse::<u64>().ok()?)))
+                .ok_or_else(|| anyhow!("unexpected reply to PSYNC: {reply:?}"))?;
+            self.full_resync(&mut master, upstream, id, offset)?;
+        }
+        self.set_link(upstream.id, Link::Up);
+
+        // The master is acknowledged to every so often, even while it sends nothing
+        let aof = &upstream.aof;
+        master.stream.set_read_timeout(Some(ACK_INTERVAL))?;
+        self.send_ack(&mut master, aof)?;
+        let mut last_ack = Instant::now();
+        loop {
+            if last_ack.elapsed() >= ACK_INTERVAL {
+                self.send_ack(&mut master, aof)?;
+                last_ack = Instant::now();
+            }
+            let request = match master.read_request() {
+                Ok(Some(request)) => request,
+                Ok(None) => bail!("the master closed the connection"),
+                Err(error) if is_timeout(&error) => continue,
+                Err(error) => return Err(error),
+            };
+
+            let _linked = self.linked(upstream.id).context("the master has changed")?;
+            let command = RespType::try_from(request.as_slice())
+                .map_err(anyhow::Error::from)
+                .and_then(|request| Command::try_from(request).map_err(|error| anyhow!("{error}")));
+            match command {
+                // The acknowledgement doesn't count the request for it
+                Ok(Command::ReplConfGetAck) => {
+                    self.send_ack(&mut master, aof)?;
+                    last_ack = Instant::now();
+                    self.lock().proxy(&request);
+                }
+                // Which passes on the request as well
+                Ok(command) => upstream.client.apply(command, &request),
+                Err(error) => {
+                    eprintln!("invalid command from the master: {error:#}");
+                    self.lock().proxy(&request);
+                }
+            }
+        }
+    }
+
+    // Tells the master the offset of the writes applied, and of those written to disk when the
+    // append-only file is on
+    fn send_ack(&self, master: &mut Connection, aof: &Aof) -> Result<()> {
+        let offset = self.offset().to_string();
+        match aof.fsynced() {
+            Some(fsynced) => {
+                let fsynced = fsynced.to_string();
+                let ack: [&[u8]; 5] = [
+                    b"REPLCONF",
+                    b"ACK",
+                    offset.as_bytes(),
+                    b"FACK",
+                    fsynced.as_bytes(),
+                ];
+                master.send(&ack)
+            }
+            None => master.send(&[b"REPLCONF", b"ACK", offset.as_bytes()]),
+        }
+    }
+
+    // Loads the snapshot the master sends, replacing the keyspace
+    fn full_resync(
+        &self,
+        master: &mut Connection,
+        upstream: &Upstream,
+        id: String,
+        offset: u64,
+    ) -> Result<()> {
+        self.set_link(upstream.id, Link::Syncing);
+        let length = master
+            .read_line()?
+            .strip_prefix('$')
+            .and_then(|length| length.parse().ok())
+            .context("expected the length of the snapshot")?;
+        let snapshot = master.read_exact(length)?;
+
+        // The keyspace is left as it was if the master changed while the snapshot was sent
+        let _linked = self.linked(upstream.id).context("the master has changed")?;
+        let (store, aof) = (&upstream.store, &upstream.aof);
+        store.flush(None, false);
+        let loaded = rdb::load_data(store, &snapshot).context("failed to load the snapshot")?;
+        println!("loaded {loaded} keys from the master");
+        drop(snapshot);
+
+        *self.ids() = Ids { id, previous: None };
+        self.offset.store(offset, Ordering::Relaxed);
+        self.start_proxy(true);
+        // Like Redis, the append-only file starts over from the keyspace the master sent
+        if aof.is_on() {
+            if let Err(error) = aof.rewrite(store) {
+                eprintln!("failed to rewrite the append-only file: {error:#}");
+            }
+        }
+        Ok(())
+    }
+
+    // Passes on what the master sends to replicas of this replica from now on, starting over
+    // when the history has changed, which makes them sync again
+    fn start_proxy(&self, reset: bool) {
+        let mut feed = self.feed();
+        let feed = feed.get_or_insert_with(|| Feed::new(self.backlog_size()));
+        if reset {
+            feed.replicas.clear();
+            feed.backlog.data.clear();
+        }
+        feed.proxy = true;
+    }
+
+    // As per ROLE, whether this is a master or a replica, along with its offset and either its
+    // replicas or master
+    // See: https://redis.io/docs/latest/commands/role/
+    pub fn role(&self) -> Vec<u8> {
+        let offset = i64::try_from(self.offset()).unwrap_or(i64::MAX);
+        let bulk = |value: &str| BulkString::from(value.as_bytes()).encode();
+        if let Some(master) = self.master().as_ref() {
+            let (state, offset) = match master.link {
+                Link::Connecting => ("connecting", -1),
+                Link::Syncing => ("sync", -1),
+                Link::Up => ("connected", offset),
+            };
+            return Array::from(vec![
+                bulk("slave"),
+                bulk(&master.host),
+                Integer::from(i64::from(master.port)).encode(),
+                bulk(state),
+                Integer::from(offset).encode(),
+            ])
+            .encode();
+        }
+
+        let feed = self.feed();
+        let replicas = feed
+            .as_ref()
+            .map_or(&[][..], |feed| &feed.replicas)
+            .iter()
+            .map(|replica| {
+                let ack = replica.state.ack.load(Ordering::Relaxed);
+                Array::from(vec![
+                    bulk(&replica.ip),
+                    bulk(&replica.port.unwrap_or(0).to_string()),
+                    bulk(&ack.to_string()),
+                ])
+                .encode()
+            })
+            .collect::<Vec<_>>();
+        Array::from(vec![
+            bulk("master"),
+            Integer::from(offset).encode(),
+            Array::from(replicas).encode(),
+        ])
+        .encode()
+    }
+
+    // The replication section of INFO
+    pub fn info(&self) -> String {
+        let mut info = String::from("# Replication\r\n");
+        let master = self.master();
+        match master.as_ref() {
+            Some(master) => {
+                let _ = write!(
+                    info,
+                    "role:slave\r\n\
+                     master_host:{}\r\n\
+                     master_port:{}\r\n\
+                     master_link_status:{}\r\n\
+                     master_sync_in_progress:{}\r\n\
+                     slave_repl_offset:{}\r\n",
+                    master.host,
+                    master.port,
+                    if master.link == Link::Up {
+                        "up"
+                    } else {
+                        "down"
+                    },
+                    u8::from(master.link == Link::Syncing),
+                    self.offset(),
+                );
+            }
+            None => info.push_str("role:master\r\n"),
+        }
+        drop(master);
+
+        let feed = self.feed();
+        let replicas = feed.as_ref().map_or(&[][..], |feed| &feed.replicas);
+        let _ = write!(info, "connected_slaves:{}\r\n", replicas.len());
+        for (index, replica) in replicas.iter().enumerate() {
+            let online = replica.state.online.load(Ordering::Relaxed);
+            let _ = write!(
+                info,
+                "slave{index}:ip={},port={},state={},offset={},lag=0\r\n",
+                replica.ip,
+                replica.port.unwrap_or(0),
+                if online { "online" } else { "wait_bgsave" },
+                replica.state.ack.load(Ordering::Relaxed),
+            );
+        }
+        let offset = self.offset();
+        let (histlen, first_byte_offset) = feed.as_ref().map_or((0, 0), |feed| {
+            let histlen = feed.backlog.data.len();
+            (histlen, feed.backlog.start(offset) + 1)
+        });
+        let active = feed.is_some();
+        drop(feed);
+
+        let ids = self.ids();
+        let (id2, second_offset) = ids.previous.as_ref().map_or_else(
+            || ("0".repeat(40), -1),
+            |(id, offset)| (id.clone(), i64::try_from(*offset).unwrap_or(i64::MAX)),
+        );
+        let _ = write!(
+            info,
+            "master_replid:{}\r\n\
+             master_replid2:{id2}\r\n\
+             master_repl_offset:{offset}\r\n\
+             second_repl_offset:{second_offset}\r\n\
+             repl_backlog_active:{}\r\n\
+             repl_backlog_size:{}\r\n\
+             repl_backlog_first_byte_offset:{first_byte_offset}\r\n\
+             repl_backlog_histlen:{histlen}\r\n",
+            ids.id,
+            u8::from(active),
+            self.backlog_size(),
+        );
+        info
+    }
+}
+
+// 40 random hexadecimal characters, like the replication IDs of Redis, and the IDs of sentinels
+pub fn new_id() -> String {
+    let id = format!(
+        "{:016x}{:016x}{:016x}",
+        evict::random(),
+        evict::random(),
+        evict::random()
+    );
+    id[..40].to_string()
+}
+
+// Sends the writes the replica missed, or else the snapshot as an RDB file, then the writes since as
+// they happen
+fn send_feed(
+    mut stream: &TcpStream,
+    id: &str,
+    start: Start,
+    receiver: Receiver<Arc<[u8]>>,
+    state: &ReplicaState,
+) -> Result<()> {
+    match start {
+        Start::Continue(missed) => {
+            stream.write_all(format!("+CONTINUE {id}\r\n").as_bytes())?;
+            stream.write_all(&missed)?;
+        }
+        Start::FullResync(offset, snapshot) => {
+            stream.write_all(format!("+FULLRESYNC {id} {offset}\r\n").as_bytes())?;
+            let data = rdb::write(&snapshot, Vec::new(), false)?;
+            // Unlike a bulk string, the file isn't followed by \r\n
+            stream.write_all(format!("${}\r\n", data.len()).as_bytes())?;
+            stream.write_all(&data)?;
+        }
+    }
+    state.online.store(true, Ordering::Relaxed);
+
+    for data in receiver {
+        stream.write_all(&data)?;
+    }
+    Ok(())
+}
+
+// Reads what the replica sends, which is only REPLCONF ACK, until it disconnects
+fn read_acks(stream: TcpStream, state: &ReplicaState, replication: &Replication) {
+    let mut replica = Connection::new(stream);
+    while let Ok(Some(request)) = replica.read_request() {
+        let command = RespType::try_from(request.as_slice())
+            .ok()
+            .and_then(|request| Command::try_from(request).ok());
+        if let Some(Command::ReplConfAck(offset, fsynced)) = command {
+            let _acks = replication
+                .acks
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            state.ack.store(offset, Ordering::Relaxed);
+            if let Some(fsynced) = fsynced {
+                state.fack.store(fsynced, Ordering::Relaxed);
+            }
+            replication.acked.notify_all();
+        }
+    }
+    let _ = replica.stream.shutdown(Shutdown::Both);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn full_resync_then_feed() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), "1".into(), None);
+        let replication = Arc::new(Replication::new(1024));
+        assert!(!replication.lock().is_fed());
+
+        let mut replica = connect(&replication, &store, ("?", -1));
+
+        let reply = replica.read_line().unwrap();
+        assert_eq!(reply, format!("+FULLRESYNC {} 0", replication.id()));
+        let length = replica.read_line().unwrap()[1..].parse().unwrap();
+        let snapshot = replica.read_exact(length).unwrap();
+        let loaded = Store::new();
+        assert_eq!(rdb::load_data(&loaded, &snapshot).unwrap(), 1);
+
+        let entry = command(&[b"SET", b"b", b"2"]);
+        let mut feed = replication.lock();
+        assert!(feed.is_fed());
+        feed.propagate(1, &entry);
+        feed.propagate(1, &entry);
+        drop(feed);
+
+        let select = command(&[b"SELECT", b"1"]);
+        assert_eq!(replica.read_request().unwrap(), Some(select.clone()));
+        assert_eq!(replica.read_request().unwrap(), Some(entry.clone()));
+        assert_eq!(replica.read_request().unwrap(), Some(entry.clone()));
+        assert_eq!(
+            replication.offset(),
+            (select.len() + entry.len() * 2) as u64
+        );
+        let info = replication.info();
+        assert!(info.contains("slave0:ip=127.0.0.1,port=6380,"));
+        assert!(info.contains("repl_backlog_active:1\r\n"));
+        assert!(info.contains("repl_backlog_first_byte_offset:1\r\n"));
+    }
+
+    #[test]
+    fn partial_resync() {
+        let store = Store::new();
+        let replication = Arc::new(Replication::new(100));
+        let id = replication.id();
+        drop(connect(&replication, &store, ("?", -1)));
+
+        let entry = command(&[b"SET", b"key", b"value"]);
+        let mut feed = replication.lock();
+        feed.propagate(0, &entry);
+        let offset = replication.offset();
+        feed.propagate(0, &entry);
+        drop(feed);
+
+        let mut replica = connect(&replication, &store, (&id, offset as i64 + 1));
+        assert_eq!(replica.read_line().unwrap(), format!("+CONTINUE {id}"));
+        assert_eq!(replica.read_request().unwrap(), Some(entry.clone()));
+
+        // Another history can't be continued
+        let mut replica = connect(&replication, &store, (&new_id(), offset as i64 + 1));
+        assert!(replica.read_line().unwrap().starts_with("+FULLRESYNC "));
+
+        // Replicas of the previous history can continue it up to where the new one started
+        replication.shift_id(new_id());
+        replication.lock().propagate(0, &entry);
+        let mut replica = connect(&replication, &store, (&id, offset as i64 + 1));
+        let reply = format!("+CONTINUE {}", replication.id());
+        assert_eq!(replica.read_line().unwrap(), reply);
+        assert_eq!(replica.read_request().unwrap(), Some(entry.clone()));
+        assert_eq!(replica.read_request().unwrap(), Some(entry.clone()));
+        let offset = replication.offset() as i64 + 1;
+        let mut replica = connect(&replication, &store, (&id, offset));
+        assert!(replica.read_line().unwrap().starts_with("+FULLRESYNC "));
+
+        // Nor can writes which have been dropped from the backlog
+        let mut replica = connect(&replication, &store, (&replication.id(), 1));
+        assert!(replica.read_line().unwrap().starts_with("+FULLRESYNC "));
+
+        assert!(replication
+            .info()
+            .contains(&format!("master_replid2:{id}\r\n")));
+    }
+
+    #[test]
+    fn wait_for_acks() {
+        let store = Store::new();
+        let replication = Arc::new(Replication::new(1024));
+        let mut replica = connect(&replication, &store, ("?", -1));
+        replica.read_line().unwrap();
+        let length = replica.read_line().unwrap()[1..].parse().unwrap();
+        replica.read_exact(length).unwrap();
+
+        let offset = replication
+            .lock()
+            .propagate(0, &command(&[b"SET", b"a", b"1"]));
+        assert_eq!(offset, replication.offset());
+        assert_eq!(replication.acknowledged(offset, false), 0);
+
+        replication.request_acks();
+        replica.read_request().unwrap();
+        replica.read_request().unwrap();
+        let getack = command(&[b"REPLCONF", b"GETACK", b"*"]);
+        assert_eq!(replica.read_request().unwrap(), Some(getack));
+
+        let ack = offset.to_string();
+        replica
+            .send(&[b"REPLCONF", b"ACK", ack.as_bytes(), b"FACK", b"0"])
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        replication.wait(Some(deadline), || {
+            replication.acknowledged(offset, false) == 1
+        });
+        assert!(Instant::now() < deadline);
+        assert_eq!(replication.acknowledged(offset, true), 0);
+        assert_eq!(replication.acknowledged(0, true), 1);
+    }
+
+    #[test]
+    fn promote() {
+        let replication = Replication::new(1024);
+        assert!(!replication.promote());
+        assert_eq!(replication.role(), b"*3\r\n$6\r\nmaster\r\n:0\r\n*0\r\n");
+
+        *replication.master() = Some(Master {
+            link_id: 1,
+            host: "localhost".to_string(),
+            port: 6379,
+            link: Link::Connecting,
+            stream: None,
+        });
+        assert!(replication.is_replica_of("localhost", 6379));
+        assert!(!replication.is_replica_of("localhost", 6380));
+        let role = b"*5\r\n$5\r\nslave\r\n$9\r\nlocalhost\r\n:6379\r\n$10\r\nconnecting\r\n:-1\r\n";
+        assert_eq!(replication.role(), role);
+
+        let id = replication.id();
+        assert!(replication.promote());
+        assert!(!replication.is_replica());
+        assert_ne!(replication.id(), id);
+        let info = replication.info();
+        assert!(info.contains(&format!("master_replid2:{id}\r\n")));
+        assert!(info.contains("second_repl_offset:1\r\n"));
+    }
+
+    fn connect(replication: &Arc<Replication>, store: &Store, psync: (&str, i64)) -> Connection {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let replica = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        replication
+            .add_replica(store, stream, Some(6380), psync)
+            .unwrap();
+        Connection::new(replica)
+    }
+}
@@ -1,4 +1,9 @@
-use std::{collections::VecDeque, str};
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    io::{self, IoSlice, Write},
+    str,
+};
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct SimpleError<'a> {
@@ -6,6 +11,10 @@ pub struct SimpleError<'a> {
 }
 
 impl SimpleError<'_> {
+    pub const fn new(inner: &str) -> SimpleError<'_> {
+        SimpleError { inner }
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         // 1 for type
         // 2 for terminator
@@ -16,6 +25,10 @@ impl SimpleError<'_> {
 
         value
     }
+
+    pub const fn as_str(&self) -> &str {
+        self.inner
+    }
 }
 
 impl<'a> From<&'a str> for SimpleError<'a> {
@@ -44,6 +57,10 @@ impl SimpleString<'_> {
 
         value
     }
+
+    pub const fn as_str(&self) -> &str {
+        self.inner
+    }
 }
 
 impl<'a> From<&'a str> for SimpleString<'a> {
@@ -57,7 +74,7 @@ pub struct BulkString<'a> {
     inner: &'a [u8],
 }
 
-impl BulkString<'_> {
+impl<'a> BulkString<'a> {
     pub fn encode(&self) -> Vec<u8> {
         // TODO: Idiomatic way to convert usize to &[u8]?
         let mut value = Vec::new();
@@ -69,10 +86,14 @@ impl BulkString<'_> {
         value
     }
 
-    pub fn as_string(&self) -> Option<&str> {
+    pub fn as_string(&self) -> Option<&'a str> {
         str::from_utf8(self.inner).ok()
     }
 
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.inner
+    }
+
     pub fn to_vec(&self) -> Vec<u8> {
         self.inner.to_vec()
     }
@@ -81,6 +102,17 @@ impl BulkString<'_> {
         self.as_string()
             .and_then(|value| str::parse::<u64>(value).ok())
     }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_string()
+            .and_then(|value| str::parse::<i64>(value).ok())
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_string()
+            .and_then(|value| str::parse::<f64>(value).ok())
+            .filter(|value| !value.is_nan())
+    }
 }
 
 impl<'a> From<&'a [u8]> for BulkString<'a> {
@@ -98,6 +130,169 @@ impl NullBulkString {
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub struct Integer {
+    inner: i64,
+}
+
+impl Integer {
+    pub fn encode(&self) -> Vec<u8> {
+        format!(":{}\r\n", self.inner).into_bytes()
+    }
+
+    pub const fn value(&self) -> i64 {
+        self.inner
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(inner: i64) -> Self {
+        Self { inner }
+    }
+}
+
+// Elements are stored already encoded, so that an `Array` can hold any mix of types
+#[derive(Debug, PartialEq, Eq)]
+pub struct Array {
+    inner: Vec<Vec<u8>>,
+}
+
+impl Array {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = format!("*{}\r\n", self.inner.len()).into_bytes();
+        for element in &self.inner {
+            value.extend(element);
+        }
+        value
+    }
+}
+
+// A command as a RESP array of bulk strings, as it would be sent by a client
+pub fn command(arguments: &[&[u8]]) -> Vec<u8> {
+    let arguments = arguments
+        .iter()
+        .map(|argument| BulkString::from(*argument).encode())
+        .collect::<Vec<_>>();
+    Array::from(arguments).encode()
+}
+
+impl From<Vec<Vec<u8>>> for Array {
+    fn from(inner: Vec<Vec<u8>>) -> Self {
+        Self { inner }
+    }
+}
+
+// An out-of-band message of RESP3, such as for pub/sub, which is otherwise the same as an array
+#[derive(Debug, PartialEq, Eq)]
+pub struct Push {
+    inner: Vec<Vec<u8>>,
+}
+
+impl Push {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = format!(">{}\r\n", self.inner.len()).into_bytes();
+        for element in &self.inner {
+            value.extend(element);
+        }
+        value
+    }
+}
+
+impl From<Vec<Vec<u8>>> for Push {
+    fn from(inner: Vec<Vec<u8>>) -> Self {
+        Self { inner }
+    }
+}
+
+// A RESP3 map, from already encoded keys to already encoded values
+#[derive(Debug, PartialEq, Eq)]
+pub struct Map {
+    inner: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Map {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut value = format!("%{}\r\n", self.inner.len()).into_bytes();
+        for (key, element) in &self.inner {
+            value.extend(key);
+            value.extend(element);
+        }
+        value
+    }
+}
+
+impl From<Vec<(Vec<u8>, Vec<u8>)>> for Map {
+    fn from(inner: Vec<(Vec<u8>, Vec<u8>)>) -> Self {
+        Self { inner }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct NullArray;
+
+impl NullArray {
+    pub fn encode() -> Vec<u8> {
+        b"*-1\r\n".to_vec()
+    }
+}
+
+// An encoded response made up of buffers which are written one after the other with vectored I/O,
+// so that values shared with the store are written to the socket without being copied
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Response {
+    segments: Vec<Bytes>,
+}
+
+impl Response {
+    // Encodes a bulk string around the value, rather than copying the value into the response
+    pub fn bulk_string(value: Bytes) -> Self {
+        let header = format!("${}\r\n", value.len()).into_bytes();
+        Self {
+            segments: vec![header.into(), value, Bytes::from_static(b"\r\n")],
+        }
+    }
+
+    #[cfg(test)]
+    pub fn segments(&self) -> &[Bytes] {
+        &self.segments
+    }
+
+    // The whole response in one buffer, copying any values shared with the store
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.segments.concat()
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut slices = self
+            .segments
+            .iter()
+            .map(|segment| IoSlice::new(segment))
+            .collect::<Vec<_>>();
+        let mut slices = slices.as_mut_slice();
+
+        // Skips any empty segments at the start
+        IoSlice::advance_slices(&mut slices, 0);
+        while !slices.is_empty() {
+            match writer.write_vectored(slices) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => IoSlice::advance_slices(&mut slices, written),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Vec<u8>> for Response {
+    fn from(value: Vec<u8>) -> Self {
+        Self {
+            segments: vec![value.into()],
+        }
+    }
+}
+
 // This is needed to support the heterogeneous arrays used in RESP
 // See https://redis.io/docs/latest/develop/reference/protocol-spec/#arrays
 #[derive(Debug, PartialEq, Eq)]
@@ -106,6 +301,9 @@ pub enum RespType<'a> {
     SimpleError(SimpleError<'a>),
     BulkString(BulkString<'a>),
     Array(VecDeque<RespType<'a>>),
+    // Only parsed in replies from other instances, as are nulls, for both bulk strings and arrays
+    Integer(Integer),
+    Null,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -115,6 +313,7 @@ pub enum Error {
     UnterminatedSequence,
     InvalidUTF8,
     InvalidUnsigned32BitNumber,
+    InvalidInteger,
     InvalidLength,
     ExtraBytes,
 }
@@ -128,6 +327,7 @@ impl std::fmt::Display for Error {
             Self::UnterminatedSequence => write!(f, "Missing \\r\\n termination"),
             Self::InvalidUTF8 => write!(f, "Unable to convert [u8] to valid UTF8"),
             Self::InvalidUnsigned32BitNumber => write!(f, "Invalid u32"),
+            Self::InvalidInteger => write!(f, "Invalid integer"),
             Self::InvalidLength => write!(f, "Invalid length supplied"),
             Self::ExtraBytes => write!(f, "Extra bytes exist at the end of the parsed type"),
         }
@@ -158,54 +358,71 @@ macro_rules! find_length {
     }};
 }
 
-impl<'a> TryFrom<&'a [u8]> for RespType<'a> {
-    type Error = Error;
+impl<'a> RespType<'a> {
+    // Parses the value at the start of `value`, returning it along with the bytes after it
+    pub fn parse_prefix(value: &'a [u8]) -> Result<(Self, &'a [u8]), Error> {
+        if value.is_empty() {
+            return Err(Error::EmptyValue);
+        }
 
-    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
-        fn try_with_remaining(value: &[u8]) -> Result<(RespType, &[u8]), Error> {
-            match value[0] {
-                b'+' => find_crlf!(value, |cr| {
-                    let result = str::from_utf8(&value[1..cr]).map_err(|_| Error::InvalidUTF8)?;
-                    Ok((RespType::SimpleString(result.into()), &value[cr + 2..]))
-                }),
-                b'-' => find_crlf!(value, |cr| {
-                    let result = str::from_utf8(&value[1..cr]).map_err(|_| Error::InvalidUTF8)?;
-                    Ok((RespType::SimpleError(result.into()), &value[cr + 2..]))
-                }),
-                b'$' => {
-                    let (length, remaining) = find_crlf!(value, |cr| find_length!(value, cr))?;
-
-                    find_crlf!(remaining, |cr| {
-                        let value = &remaining[..cr];
-                        if value.len() == length as usize {
-                            Ok((RespType::BulkString(value.into()), &remaining[cr + 2..]))
-                        } else {
-                            Err(Error::InvalidLength)
-                        }
-                    })
+        match value[0] {
+            b'+' => find_crlf!(value, |cr| {
+                let result = str::from_utf8(&value[1..cr]).map_err(|_| Error::InvalidUTF8)?;
+                Ok((RespType::SimpleString(result.into()), &value[cr + 2..]))
+            }),
+            b'-' => find_crlf!(value, |cr| {
+                let result = str::from_utf8(&value[1..cr]).map_err(|_| Error::InvalidUTF8)?;
+                Ok((RespType::SimpleError(result.into()), &value[cr + 2..]))
+            }),
+            b':' => find_crlf!(value, |cr| {
+                let result = str::from_utf8(&value[1..cr])
+                    .map_err(|_| Error::InvalidUTF8)?
+                    .parse::<i64>()
+                    .map_err(|_| Error::InvalidInteger)?;
+                Ok((RespType::Integer(result.into()), &value[cr + 2..]))
+            }),
+            b'$' | b'*' if value.starts_with(b"$-1\r\n") || value.starts_with(b"*-1\r\n") => {
+                Ok((RespType::Null, &value[5..]))
+            }
+            b'$' => {
+                let (length, remaining) = find_crlf!(value, |cr| find_length!(value, cr))?;
+
+                // Bulk strings are binary safe, so may contain \r\n themselves
+                let length = length as usize;
+                if remaining.len() < length + 2 {
+                    return Err(Error::UnterminatedSequence);
                 }
-                b'*' => {
-                    let (length, remaining) = find_crlf!(value, |cr| find_length!(value, cr))?;
-                    let mut elements = VecDeque::new();
-                    let mut remaining = remaining;
-
-                    for _ in 1..=length {
-                        let (element, remainder) = try_with_remaining(remaining)?;
-                        elements.push_back(element);
-                        remaining = remainder;
-                    }
-
-                    Ok((RespType::Array(elements), remaining))
+                if &remaining[length..length + 2] != b"\r\n" {
+                    return Err(Error::InvalidLength);
                 }
-                _ => Err(Error::UnknownType(char::from(value[0]))),
+                Ok((
+                    RespType::BulkString(remaining[..length].into()),
+                    &remaining[length + 2..],
+                ))
             }
-        }
+            b'*' => {
+                let (length, remaining) = find_crlf!(value, |cr| find_length!(value, cr))?;
+                let mut elements = VecDeque::new();
+                let mut remaining = remaining;
+
+                for _ in 1..=length {
+                    let (element, remainder) = Self::parse_prefix(remaining)?;
+                    elements.push_back(element);
+                    remaining = remainder;
+                }
 
-        if value.is_empty() {
-            return Err(Error::EmptyValue);
+                Ok((RespType::Array(elements), remaining))
+            }
+            _ => Err(Error::UnknownType(char::from(value[0]))),
         }
+    }
+}
 
-        let (result, remaining) = try_with_remaining(value)?;
+impl<'a> TryFrom<&'a [u8]> for RespType<'a> {
+    type Error = Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let (result, remaining) = Self::parse_prefix(value)?;
         if remaining.is_empty() {
             Ok(result)
         } else {
@@ -250,6 +467,17 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn parse_binary_bulk_string() -> Result<(), Error> {
+        let input: &[u8] = b"$4\r\n\r\n\r\n\r\n";
+        let result = RespType::try_from(input)?;
+        assert_eq!(result, RespType::BulkString(b"\r\n\r\n"[..].into()));
+
+        let input: &[u8] = b"$4\r\nRu";
+        assert_eq!(RespType::try_from(input), Err(Error::UnterminatedSequence));
+        Ok(())
+    }
+
     #[test]
     fn invalid_u32() {
         let input: &[u8] = b"$h\r\nhello\r\n";
@@ -265,6 +493,22 @@ mod test {
         assert_eq!(RespType::try_from(input), Err(Error::InvalidLength));
     }
 
+    #[test]
+    fn parse_integer_and_nulls() -> Result<(), Error> {
+        let input: &[u8] = b"*3\r\n:-12\r\n$-1\r\n*-1\r\n";
+        let result = RespType::try_from(input)?;
+        let expected = [
+            RespType::Integer((-12).into()),
+            RespType::Null,
+            RespType::Null,
+        ];
+        assert_eq!(result, RespType::Array(expected.into()));
+
+        let input: &[u8] = b":1.5\r\n";
+        assert_eq!(RespType::try_from(input), Err(Error::InvalidInteger));
+        Ok(())
+    }
+
     #[test]
     fn parse_array_same_type() -> Result<(), Error> {
         let input: &[u8] = b"*1\r\n$4\r\nPING\r\n";
@@ -300,6 +544,20 @@ mod test {
         assert_eq!(RespType::try_from(input), Err(Error::ExtraBytes));
     }
 
+    #[test]
+    fn parse_prefix() -> Result<(), Error> {
+        let input: &[u8] = b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n";
+        let (first, remaining) = RespType::parse_prefix(input)?;
+        assert_eq!(
+            first,
+            RespType::Array(VecDeque::from([RespType::BulkString(b"PING"[..].into())]))
+        );
+
+        // Arrays missing elements are incomplete, rather than causing a panic
+        assert_eq!(RespType::parse_prefix(remaining), Err(Error::EmptyValue));
+        Ok(())
+    }
+
     #[test]
     fn invalid_utf8_in_simple_string() {
         // Invalid UTF-8 starting with + and finishing with \r\n
@@ -314,6 +572,39 @@ mod test {
         assert_eq!(RespType::try_from(input), Err(Error::InvalidUTF8));
     }
 
+    #[test]
+    fn encode_integer() {
+        assert_eq!(Integer::from(-42).encode(), b":-42\r\n");
+    }
+
+    #[test]
+    fn encode_array() {
+        let array = Array::from(vec![
+            Integer::from(1).encode(),
+            NullBulkString::encode(),
+            BulkString::from(&b"Rust"[..]).encode(),
+        ]);
+
+        assert_eq!(array.encode(), b"*3\r\n:1\r\n$-1\r\n$4\r\nRust\r\n");
+    }
+
+    #[test]
+    fn write_response() -> io::Result<()> {
+        let value = Bytes::from(vec![b'x'; 100_000]);
+        let response = Response::bulk_string(value.clone());
+        assert_eq!(response.segments()[1].as_ptr(), value.as_ptr());
+
+        let mut written = Vec::new();
+        response.write_to(&mut written)?;
+        assert_eq!(written, BulkString::from(&value[..]).encode());
+
+        let mut written = Vec::new();
+        Response::from(Vec::new()).write_to(&mut written)?;
+        assert!(written.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn parse_simple_error() -> Result<(), Error> {
         let input: &[u8] = b"-ERR unknown command 'asdf'\r\n";
@@ -0,0 +1,1531 @@
+use crate::{
+    command::Command,
+    config::Config,
+    connection::{is_timeout, Connection},
+    evict,
+    replication::new_id,
+    resp::{Array, BulkString, Integer, NullArray, RespType, SimpleError, SimpleString},
+};
+use anyhow::{bail, Result};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::Write,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+// Sentinels monitor masters and their replicas, agree with each other when a master is down, and
+// then elect one of themselves to promote one of its replicas in its place
+// See: https://redis.io/docs/latest/operate/oss_and_stack/management/sentinel/
+
+pub const DEFAULT_PORT: u16 = 26379;
+
+const DEFAULT_DOWN_AFTER: Duration = Duration::from_secs(30);
+const DEFAULT_FAILOVER_TIMEOUT: Duration = Duration::from_secs(180);
+
+// How often instances are checked, and how often each kind of request is sent, as per `sentinel.c`
+const TICK: Duration = Duration::from_millis(100);
+const PING_PERIOD: Duration = Duration::from_secs(1);
+const INFO_PERIOD: Duration = Duration::from_secs(10);
+// While the master is down or being failed over, INFO is sent this often instead
+const FAST_INFO_PERIOD: Duration = Duration::from_secs(1);
+const HELLO_PERIOD: Duration = Duration::from_secs(2);
+const ASK_PERIOD: Duration = Duration::from_secs(1);
+// How long another sentinel saying that the master is down counts towards it being objectively down
+const DOWN_REPLY_VALIDITY: Duration = Duration::from_secs(5);
+// The longest an election for the leader of a failover goes on
+const ELECTION_TIMEOUT: Duration = Duration::from_secs(10);
+// Up to this many milliseconds are added to when the next failover may start, so that sentinels
+// don't all ask for votes at once
+const MAX_DESYNC: u64 = 1000;
+// How long an instance reports the wrong role or master before it is reconfigured, so that a new
+// configuration has time to spread between sentinels first
+const RECONFIGURE_DELAY: Duration = Duration::from_secs(8);
+// For connecting to, reading from and writing to other instances
+const IO_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Sentinels announce themselves, and their configuration of each master, to each other on this
+// channel
+const HELLO_CHANNEL: &str = "__sentinel__:hello";
+
+type Address = (String, u16);
+
+// A master to monitor, as given with `--sentinel monitor <name> <ip> <port> <quorum>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    // How many sentinels must agree that the master is down before it is failed over
+    pub quorum: usize,
+    pub down_after: Duration,
+    pub failover_timeout: Duration,
+    // Other sentinels monitoring the master, which are otherwise only found from their hellos
+    pub known_sentinels: Vec<Address>,
+}
+
+// A directive, such as `down-after-milliseconds mymaster 5000`, as in the configuration file of a
+// sentinel
+pub fn configure(monitors: &mut Vec<Monitor>, directive: &str) -> Result<(), &'static str> {
+    let arguments = directive.split_whitespace().collect::<Vec<_>>();
+    let Some((&directive, arguments)) = arguments.split_first() else {
+        return Err("Unrecognized sentinel configuration statement");
+    };
+
+    if directive.eq_ignore_ascii_case("monitor") {
+        let &[name, host, port, quorum] = arguments else {
+            return Err("Wrong number of arguments");
+        };
+        if monitors.iter().any(|monitor| monitor.name == name) {
+            return Err("Duplicated master name.");
+        }
+        monitors.push(Monitor {
+            name: name.to_string(),
+            host: host.to_string(),
+            port: parse_port(port)?,
+            quorum: quorum
+                .parse()
+                .ok()
+                .filter(|quorum| *quorum > 0)
+                .ok_or("Quorum must be 1 or greater.")?,
+            down_after: DEFAULT_DOWN_AFTER,
+            failover_timeout: DEFAULT_FAILOVER_TIMEOUT,
+            known_sentinels: Vec::new(),
+        });
+        return Ok(());
+    }
+
+    let Some((&name, arguments)) = arguments.split_first() else {
+        return Err("Wrong number of arguments");
+    };
+    let monitor = monitors
+        .iter_mut()
+        .find(|monitor| monitor.name == name)
+        .ok_or("No such master with specified name.")?;
+    match (directive.to_ascii_lowercase().as_str(), arguments) {
+        ("down-after-milliseconds", &[milliseconds]) => {
+            monitor.down_after = parse_milliseconds(milliseconds)?;
+        }
+        ("failover-timeout", &[milliseconds]) => {
+            monitor.failover_timeout = parse_milliseconds(milliseconds)?;
+        }
+        ("known-sentinel", &[host, port]) => {
+            let port = parse_port(port)?;
+            monitor.known_sentinels.push((host.to_string(), port));
+        }
+        _ => return Err("Unrecognized sentinel configuration statement"),
+    }
+
+    Ok(())
+}
+
+fn parse_port(port: &str) -> Result<u16, &'static str> {
+    port.parse()
+        .ok()
+        .filter(|port| *port > 0)
+        .ok_or("Invalid port number")
+}
+
+fn parse_milliseconds(milliseconds: &str) -> Result<Duration, &'static str> {
+    milliseconds
+        .parse()
+        .ok()
+        .filter(|milliseconds| *milliseconds > 0)
+        .map(Duration::from_millis)
+        .ok_or("negative or zero time parameter.")
+}
+
+// A master or replica, as last heard from
+struct Instance {
+    address: Address,
+    // When it last replied to PING as expected, or else when monitoring it started
+    last_pong: Instant,
+    last_ping: Option<Instant>,
+    // When INFO was last sent, and last replied to
+    info_sent: Option<Instant>,
+    last_info: Option<Instant>,
+    // When this sentinel last published its hello on it, for other sentinels subscribed to it
+    hello_sent: Option<Instant>,
+    // Subjectively down, as in this sentinel hasn't had a reply for too long
+    sdown: bool,
+    // As last reported with INFO, and since when
+    role: Option<Role>,
+    role_since: Instant,
+    link_up: bool,
+    offset: u64,
+    // When it was last told to replicate the master, having reported the wrong role or master
+    reconfigured: Option<Instant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Role {
+    Master,
+    // Along with the master it replicates
+    Replica(Address),
+}
+
+impl Instance {
+    const fn new(address: Address, now: Instant) -> Self {
+        Self {
+            address,
+            last_pong: now,
+            last_ping: None,
+            info_sent: None,
+            last_info: None,
+            hello_sent: None,
+            sdown: false,
+            role: None,
+            role_since: now,
+            link_up: false,
+            offset: 0,
+            reconfigured: None,
+        }
+    }
+
+    // The fields of the instance, as listed by SENTINEL MASTER and SENTINEL REPLICAS
+    fn fields(&self, name: String, flags: &str, now: Instant) -> Vec<(&'static str, String)> {
+        let role = match self.role {
+            Some(Role::Master) => "master",
+            Some(Role::Replica(_)) => "slave",
+            None => "",
+        };
+        vec![
+            ("name", name),
+            ("ip", self.address.0.clone()),
+            ("port", self.address.1.to_string()),
+            ("flags", flags.to_string()),
+            (
+                "last-ok-ping-reply",
+                milliseconds_since(self.last_pong, now),
+            ),
+            (
+                "info-refresh",
+                self.last_info
+                    .map_or_else(|| "0".to_string(), |last| milliseconds_since(last, now)),
+            ),
+            ("role-reported", role.to_string()),
+            (
+                "role-reported-time",
+                milliseconds_since(self.role_since, now),
+            ),
+        ]
+    }
+}
+
+fn milliseconds_since(instant: Instant, now: Instant) -> String {
+    now.saturating_duration_since(instant)
+        .as_millis()
+        .to_string()
+}
+
+// Another sentinel monitoring the same master
+struct Peer {
+    address: Address,
+    // Only known once it sends a hello
+    id: Option<String>,
+    last_hello: Option<Instant>,
+    last_sent_hello: Option<Instant>,
+    last_ask: Option<Instant>,
+    // When it last replied that the master is down
+    master_down: Option<Instant>,
+    // The sentinel it voted for to lead a failover, and in which epoch
+    leader: Option<String>,
+    leader_epoch: u64,
+}
+
+impl Peer {
+    const fn new(address: Address) -> Self {
+        Self {
+            address,
+            id: None,
+            last_hello: None,
+            last_sent_hello: None,
+            last_ask: None,
+            master_down: None,
+            leader: None,
+            leader_epoch: 0,
+        }
+    }
+}
+
+struct Failover {
+    epoch: u64,
+    started: Instant,
+    // Started with SENTINEL FAILOVER, so without agreement or an election
+    forced: bool,
+    // The replica being promoted, once this sentinel has been elected to lead the failover
+    promoting: Option<Address>,
+}
+
+// What is known about a master, its replicas, and the other sentinels monitoring it
+struct Master {
+    name: String,
+    quorum: usize,
+    down_after: Duration,
+    failover_timeout: Duration,
+    instance: Instance,
+    replicas: Vec<Instance>,
+    sentinels: Vec<Peer>,
+    // Objectively down, as in enough sentinels agree that it is down
+    odown: bool,
+    // The epoch of the failover which last changed the address of the master
+    config_epoch: u64,
+    // The sentinel this one voted for to lead a failover, and in which epoch
+    leader: Option<String>,
+    leader_epoch: u64,
+    failover: Option<Failover>,
+    // No failover starts until twice the failover timeout after this
+    failover_start: Option<Instant>,
+}
+
+impl Master {
+    fn new(monitor: Monitor, now: Instant) -> Self {
+        Self {
+            name: monitor.name,
+            quorum: monitor.quorum,
+            down_after: monitor.down_after,
+            failover_timeout: monitor.failover_timeout,
+            instance: Instance::new((monitor.host, monitor.port), now),
+            replicas: Vec::new(),
+            sentinels: monitor.known_sentinels.into_iter().map(Peer::new).collect(),
+            odown: false,
+            config_epoch: 0,
+            leader: None,
+            leader_epoch: 0,
+            failover: None,
+            failover_start: None,
+        }
+    }
+
+    fn instance_mut(&mut self, address: &Address) -> Option<&mut Instance> {
+        if self.instance.address == *address {
+            return Some(&mut self.instance);
+        }
+        self.replicas
+            .iter_mut()
+            .find(|replica| replica.address == *address)
+    }
+
+    fn flags(&self) -> String {
+        let mut flags = String::from("master");
+        if self.instance.sdown {
+            flags.push_str(",s_down");
+        }
+        if self.odown {
+            flags.push_str(",o_down");
+        }
+        if self.failover.is_some() {
+            flags.push_str(",failover_in_progress");
+        }
+        flags
+    }
+
+    fn fields(&self, now: Instant) -> Vec<(&'static str, String)> {
+        let mut fields = self.instance.fields(self.name.clone(), &self.flags(), now);
+        fields.extend([
+            ("config-epoch", self.config_epoch.to_string()),
+            ("num-slaves", self.replicas.len().to_string()),
+            ("num-other-sentinels", self.sentinels.len().to_string()),
+            ("quorum", self.quorum.to_string()),
+            (
+                "down-after-milliseconds",
+                self.down_after.as_millis().to_string(),
+            ),
+            (
+                "failover-timeout",
+                self.failover_timeout.as_millis().to_string(),
+            ),
+        ]);
+        fields
+    }
+
+    // Updates what is known about the master or one of its replicas from its reply to INFO
+    fn refresh(&mut self, address: &Address, info: &str, now: Instant) {
+        let mut role = None;
+        let mut master = (String::new(), 0);
+        let mut link_up = false;
+        let mut offset = 0;
+        let mut replicas = Vec::new();
+        for (name, value) in info.lines().filter_map(|line| line.split_once(':')) {
+            match name {
+                "role" => role = Some(value == "master"),
+                "master_host" => value.clone_into(&mut master.0),
+                "master_port" => master.1 = value.parse().unwrap_or(0),
+                "master_link_status" => link_up = value == "up",
+                "slave_repl_offset" | "master_repl_offset" => offset = value.parse().unwrap_or(0),
+                // Such as `slave0:ip=127.0.0.1,port=6380,state=online,offset=42,lag=0`
+                name if name.starts_with("slave") && value.starts_with("ip=") => {
+                    let mut replica = (String::new(), 0);
+                    for (field, value) in value.split(',').filter_map(|field| field.split_once('='))
+                    {
+                        match field {
+                            "ip" => value.clone_into(&mut replica.0),
+                            "port" => replica.1 = value.parse().unwrap_or(0),
+                            _ => {}
+                        }
+                    }
+                    replicas.push(replica);
+                }
+                _ => {}
+            }
+        }
+        let Some(is_master) = role else {
+            return;
+        };
+        let role = if is_master {
+            Role::Master
+        } else {
+            Role::Replica(master)
+        };
+
+        let name = self.name.clone();
+        let is_monitored_master = self.instance.address == *address;
+        let Some(instance) = self.instance_mut(address) else {
+            return;
+        };
+        instance.last_info = Some(now);
+        instance.link_up = link_up;
+        instance.offset = offset;
+        if instance.role.as_ref() != Some(&role) {
+            instance.role = Some(role);
+            instance.role_since = now;
+        }
+
+        // Replicas are found from the INFO of their master
+        if is_monitored_master {
+            for replica in replicas {
+                if replica.1 == 0 || self.instance_mut(&replica).is_some() {
+                    continue;
+                }
+                println!("+slave slave {} {} @ {name}", replica.0, replica.1);
+                self.replicas.push(Instance::new(replica, now));
+            }
+        }
+    }
+
+    // The replica with the most writes, out of those still up and recently heard from
+    fn select_replica(&self, now: Instant) -> Option<&Instance> {
+        let info_validity = if self.instance.sdown {
+            5 * PING_PERIOD
+        } else {
+            3 * INFO_PERIOD
+        };
+        self.replicas
+            .iter()
+            .filter(|replica| {
+                !replica.sdown
+                    && matches!(replica.role, Some(Role::Replica(_)))
+                    && replica
+                        .last_info
+                        .is_some_and(|last| now.saturating_duration_since(last) < info_validity)
+            })
+            .max_by_key(|replica| replica.offset)
+    }
+
+    // Starts monitoring the master at a new address, with the old master as one of its replicas
+    fn switch(&mut self, address: Address, now: Instant) {
+        let old = self.instance.address.clone();
+        println!(
+            "+switch-master {} {} {} {} {}",
+            self.name, old.0, old.1, address.0, address.1
+        );
+        let mut replicas = self
+            .replicas
+            .drain(..)
+            .map(|replica| replica.address)
+            .filter(|replica| *replica != address)
+            .collect::<Vec<_>>();
+        replicas.push(old);
+        self.replicas = replicas
+            .into_iter()
+            .map(|replica| Instance::new(replica, now))
+            .collect();
+        self.instance = Instance::new(address, now);
+        self.odown = false;
+        self.failover = None;
+        for peer in &mut self.sentinels {
+            peer.master_down = None;
+        }
+    }
+}
+
+// What a request to another instance is for, so that its reply can be applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Ping,
+    Info,
+    Hello,
+    Ask,
+    ReplicaOf,
+}
+
+struct Request {
+    master: String,
+    to: Address,
+    kind: Kind,
+    arguments: Vec<String>,
+}
+
+impl Request {
+    fn new(master: &str, to: &Address, kind: Kind, arguments: &[&str]) -> Self {
+        Self {
+            master: master.to_string(),
+            to: to.clone(),
+            kind,
+            arguments: arguments.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+struct State {
+    id: String,
+    // The address other sentinels reach this one on
+    address: Address,
+    // Increases with each failover attempted by any sentinel, so that each has its own election
+    current_epoch: u64,
+    masters: Vec<Master>,
+}
+
+impl State {
+    // Checks on every master, returning the requests to send to other instances as a result
+    fn plan(&mut self, now: Instant) -> Vec<Request> {
+        let mut requests = Vec::new();
+        for index in 0..self.masters.len() {
+            self.check_down(index, now);
+            self.step_failover(index, now, &mut requests);
+            self.monitor(index, now, &mut requests);
+            self.reconfigure(index, now, &mut requests);
+        }
+        requests
+    }
+
+    fn check_down(&mut self, index: usize, now: Instant) {
+        let master = &mut self.masters[index];
+        let down_after = master.down_after;
+        let name = master.name.clone();
+        let kinds = std::iter::once(("master", &mut master.instance))
+            .chain(master.replicas.iter_mut().map(|replica| ("slave", replica)));
+        for (kind, instance) in kinds {
+            let sdown = now.saturating_duration_since(instance.last_pong) > down_after;
+            if sdown != instance.sdown {
+                instance.sdown = sdown;
+                // What it reports is asked for again as soon as it is back, as it may have changed
+                if !sdown {
+                    instance.info_sent = None;
+                }
+                let (host, port) = &instance.address;
+                let sign = if sdown { '+' } else { '-' };
+                println!("{sign}sdown {kind} {name} {host} {port}");
+            }
+        }
+
+        let agreeing = master
+            .sentinels
+            .iter()
+            .filter(|peer| {
+                peer.master_down
+                    .is_some_and(|at| now.saturating_duration_since(at) < DOWN_REPLY_VALIDITY)
+            })
+            .count();
+        let odown = master.instance.sdown && 1 + agreeing >= master.quorum;
+        if odown != master.odown {
+            master.odown = odown;
+            let (host, port) = &master.instance.address;
+            let sign = if odown { '+' } else { '-' };
+            let quorum = master.quorum;
+            println!(
+                "{sign}odown master {name} {host} {port} #quorum {}/{quorum}",
+                1 + agreeing
+            );
+        }
+    }
+
+    // Moves a failover on from one stage to the next, or starts one if the master is down
+    fn step_failover(&mut self, index: usize, now: Instant, requests: &mut Vec<Request>) {
+        let id = self.id.clone();
+        let master = &mut self.masters[index];
+        let Some(failover) = &master.failover else {
+            let waiting = master
+                .failover_start
+                .is_some_and(|start| now < start + 2 * master.failover_timeout);
+            if master.odown && !waiting {
+                self.current_epoch += 1;
+                let epoch = self.current_epoch;
+                println!("+new-epoch {epoch}");
+                println!("+try-failover master {}", master.name);
+                master.failover = Some(Failover {
+                    epoch,
+                    started: now,
+                    forced: false,
+                    promoting: None,
+                });
+                master.failover_start = Some(now + desync());
+                // Votes are asked for straight away
+                for peer in &mut master.sentinels {
+                    peer.last_ask = None;
+                }
+            }
+            return;
+        };
+
+        let (epoch, started, forced) = (failover.epoch, failover.started, failover.forced);
+        let elapsed = now.saturating_duration_since(started);
+        if let Some(promoting) = failover.promoting.clone() {
+            let promoted = master
+                .replicas
+                .iter()
+                .any(|replica| replica.address == promoting && replica.role == Some(Role::Master));
+            if promoted {
+                println!(
+                    "+promoted-slave slave {} {} @ {}",
+                    promoting.0, promoting.1, master.name
+                );
+                master.config_epoch = epoch;
+                master.switch(promoting, now);
+                let port = master.instance.address.1.to_string();
+                for replica in &master.replicas {
+                    let arguments = ["REPLICAOF", &master.instance.address.0, &port];
+                    requests.push(Request::new(
+                        &master.name,
+                        &replica.address,
+                        Kind::ReplicaOf,
+                        &arguments,
+                    ));
+                }
+            } else if elapsed > master.failover_timeout {
+                println!("-failover-abort-slave-timeout master {}", master.name);
+                master.failover = None;
+            }
+            return;
+        }
+
+        if !forced && !master.odown {
+            println!("-failover-abort-not-odown master {}", master.name);
+            master.failover = None;
+            return;
+        }
+        let leader = if forced {
+            Some(id.clone())
+        } else {
+            self.leader(index, epoch, now)
+        };
+        let master = &mut self.masters[index];
+        if leader.as_ref() != Some(&id) {
+            if elapsed > ELECTION_TIMEOUT.min(master.failover_timeout) {
+                println!("-failover-abort-not-elected master {}", master.name);
+                master.failover = None;
+            }
+            return;
+        }
+
+        println!("+elected-leader master {} epoch {epoch}", master.name);
+        let Some(replica) = master.select_replica(now) else {
+            println!("-failover-abort-no-good-slave master {}", master.name);
+            master.failover = None;
+            return;
+        };
+        let address = replica.address.clone();
+        println!(
+            "+selected-slave slave {} {} @ {}",
+            address.0, address.1, master.name
+        );
+        requests.push(Request::new(
+            &master.name,
+            &address,
+            Kind::ReplicaOf,
+            &["REPLICAOF", "NO", "ONE"],
+        ));
+        if let Some(failover) = &mut master.failover {
+            failover.promoting = Some(address);
+        }
+    }
+
+    // The winner of the election for the leader of a failover in `epoch`, as per
+    // `sentinelGetLeader`, which needs a majority of the sentinels and at least a quorum of votes
+    fn leader(&mut self, index: usize, epoch: u64, now: Instant) -> Option<String> {
+        let master = &self.masters[index];
+        let voters = master.sentinels.len() + 1;
+        let quorum = master.quorum;
+        let mut votes = HashMap::<String, usize>::new();
+        for peer in &master.sentinels {
+            if let Some(leader) = peer.leader.as_ref().filter(|_| peer.leader_epoch == epoch) {
+                *votes.entry(leader.clone()).or_default() += 1;
+            }
+        }
+        let winner = |votes: &HashMap<String, usize>| {
+            votes
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then_with(|| a.0.cmp(b.0)))
+                .map(|(leader, count)| (leader.clone(), *count))
+        };
+
+        // This sentinel votes for whoever has the most votes so far, or else for itself
+        let candidate = winner(&votes).map_or_else(|| self.id.clone(), |(leader, _)| leader);
+        let (vote, vote_epoch) = self.vote(index, epoch, &candidate, now);
+        if let Some(vote) = vote.filter(|_| vote_epoch == epoch) {
+            *votes.entry(vote).or_default() += 1;
+        }
+
+        winner(&votes)
+            .filter(|(_, count)| *count > voters / 2 && *count >= quorum)
+            .map(|(leader, _)| leader)
+    }
+
+    // Votes for `candidate` to lead a failover of the master in `epoch`, unless this sentinel
+    // already voted in that epoch, returning the vote it has, as per `sentinelVoteLeader`
+    fn vote(
+        &mut self,
+        index: usize,
+        epoch: u64,
+        candidate: &str,
+        now: Instant,
+    ) -> (Option<String>, u64) {
+        if epoch > self.current_epoch {
+            self.current_epoch = epoch;
+            println!("+new-epoch {epoch}");
+        }
+
+        let master = &mut self.masters[index];
+        if master.leader_epoch < epoch && self.current_epoch <= epoch {
+            master.leader = Some(candidate.to_string());
+            master.leader_epoch = self.current_epoch;
+            println!("+vote-for-leader {candidate} {}", self.current_epoch);
+            // Sentinels which voted for another don't start a failover of their own for a while
+            if candidate != self.id {
+                master.failover_start = Some(now + desync());
+            }
+        }
+
+        (master.leader.clone(), master.leader_epoch)
+    }
+
+    // The periodic requests to the master, its replicas, and other sentinels
+    fn monitor(&mut self, index: usize, now: Instant, requests: &mut Vec<Request>) {
+        let hello = self.hello(index);
+        let id = self.id.clone();
+        let epoch = self.current_epoch.to_string();
+        let master = &mut self.masters[index];
+        let due = |last: Option<Instant>, period| {
+            last.is_none_or(|last| now.saturating_duration_since(last) >= period)
+        };
+
+        let urgent = master.instance.sdown || master.failover.is_some();
+        let info_period = if urgent {
+            FAST_INFO_PERIOD
+        } else {
+            INFO_PERIOD
+        };
+        let name = master.name.clone();
+        for instance in std::iter::once(&mut master.instance).chain(&mut master.replicas) {
+            if due(instance.last_ping, PING_PERIOD) {
+                instance.last_ping = Some(now);
+                requests.push(Request::new(
+                    &name,
+                    &instance.address,
+                    Kind::Ping,
+                    &["PING"],
+                ));
+            }
+            if due(instance.info_sent, info_period) {
+                instance.info_sent = Some(now);
+                let arguments = ["INFO", "replication"];
+                requests.push(Request::new(
+                    &name,
+                    &instance.address,
+                    Kind::Info,
+                    &arguments,
+                ));
+            }
+            if due(instance.hello_sent, HELLO_PERIOD) {
+                instance.hello_sent = Some(now);
+                let arguments = ["PUBLISH", HELLO_CHANNEL, &hello];
+                requests.push(Request::new(
+                    &name,
+                    &instance.address,
+                    Kind::Hello,
+                    &arguments,
+                ));
+            }
+        }
+
+        // Votes are only asked for while this sentinel is waiting to be elected
+        let candidate = match &master.failover {
+            Some(failover) if !failover.forced && failover.promoting.is_none() => id.as_str(),
+            _ => "*",
+        };
+        let (host, port) = &master.instance.address;
+        let port = port.to_string();
+        for peer in &mut master.sentinels {
+            if peer.address == self.address {
+                continue;
+            }
+            if due(peer.last_sent_hello, HELLO_PERIOD) {
+                peer.last_sent_hello = Some(now);
+                let arguments = ["PUBLISH", HELLO_CHANNEL, &hello];
+                requests.push(Request::new(&name, &peer.address, Kind::Hello, &arguments));
+            }
+            if master.instance.sdown && due(peer.last_ask, ASK_PERIOD) {
+                peer.last_ask = Some(now);
+                let arguments = [
+                    "SENTINEL",
+                    "is-master-down-by-addr",
+                    host,
+                    &port,
+                    &epoch,
+                    candidate,
+                ];
+                requests.push(Request::new(&name, &peer.address, Kind::Ask, &arguments));
+            }
+        }
+    }
+
+    // The masters and replicas being monitored, whose hello channel this sentinel subscribes to, to
+    // find the other sentinels monitoring them
+    fn instances(&self) -> Vec<Address> {
+        self.masters
+            .iter()
+            .flat_map(|master| std::iter::once(&master.instance).chain(&master.replicas))
+            .map(|instance| instance.address.clone())
+            .collect()
+    }
+
+    // Tells replicas which report the wrong role or master to replicate the master, such as the old
+    // master once it is back after a failover
+    fn reconfigure(&mut self, index: usize, now: Instant, requests: &mut Vec<Request>) {
+        let master = &mut self.masters[index];
+        if master.failover.is_some() {
+            return;
+        }
+
+        let expected = Role::Replica(master.instance.address.clone());
+        let (host, port) = &master.instance.address;
+        let port = port.to_string();
+        for replica in &mut master.replicas {
+            let Some(role) = replica.role.as_ref().filter(|role| **role != expected) else {
+                continue;
+            };
+            let settled = now.saturating_duration_since(replica.role_since) >= RECONFIGURE_DELAY;
+            let recent = replica
+                .reconfigured
+                .is_some_and(|last| now.saturating_duration_since(last) < RECONFIGURE_DELAY);
+            if replica.sdown || !settled || recent {
+                continue;
+            }
+
+            let event = if *role == Role::Master {
+                "+convert-to-slave"
+            } else {
+                "+fix-slave-config"
+            };
+            let (replica_host, replica_port) = &replica.address;
+            println!(
+                "{event} slave {replica_host} {replica_port} @ {} {host} {}",
+                master.name, port
+            );
+            replica.reconfigured = Some(now);
+            let arguments = ["REPLICAOF", host, &port];
+            requests.push(Request::new(
+                &master.name,
+                &replica.address,
+                Kind::ReplicaOf,
+                &arguments,
+            ));
+        }
+    }
+
+    // As per `sentinelSendHello`, with this sentinel, its current epoch, and its configuration of
+    // the master
+    fn hello(&self, index: usize) -> String {
+        let master = &self.masters[index];
+        let (host, port) = &self.address;
+        let (master_host, master_port) = &master.instance.address;
+        format!(
+            "{host},{port},{},{},{},{master_host},{master_port},{}",
+            self.id, self.current_epoch, master.name, master.config_epoch
+        )
+    }
+
+    // Applies the reply to a request sent while monitoring a master
+    fn apply(&mut self, request: &Request, reply: &[u8], now: Instant) {
+        let Ok(reply) = RespType::try_from(reply) else {
+            return;
+        };
+        let Some(master) = self
+            .masters
+            .iter_mut()
+            .find(|master| master.name == request.master)
+        else {
+            return;
+        };
+
+        match (request.kind, reply) {
+            (Kind::Ping, reply) => {
+                // Instances loading their data or without a link to their master are still up
+                let up = match reply {
+                    RespType::SimpleString(reply) => reply.as_str() == "PONG",
+                    RespType::SimpleError(error) => {
+                        error.as_str().starts_with("LOADING")
+                            || error.as_str().starts_with("MASTERDOWN")
+                    }
+                    _ => false,
+                };
+                if let Some(instance) = master.instance_mut(&request.to).filter(|_| up) {
+                    instance.last_pong = now;
+                }
+            }
+            (Kind::Info, RespType::BulkString(info)) => {
+                if let Some(info) = info.as_string() {
+                    master.refresh(&request.to, info, now);
+                }
+            }
+            // Whether the other sentinel thinks the master is down, and who it voted for, if asked
+            (Kind::Ask, RespType::Array(reply)) => {
+                let reply = Vec::from(reply);
+                let [RespType::Integer(down), RespType::BulkString(leader), RespType::Integer(epoch)] =
+                    &reply[..]
+                else {
+                    return;
+                };
+                let Some(peer) = master
+                    .sentinels
+                    .iter_mut()
+                    .find(|peer| peer.address == request.to)
+                else {
+                    return;
+                };
+                peer.master_down = (down.value() == 1).then_some(now);
+                if let Some(leader) = leader.as_string().filter(|leader| *leader != "*") {
+                    peer.leader = Some(leader.to_string());
+                    peer.leader_epoch = u64::try_from(epoch.value()).unwrap_or(0);
+                }
+            }
+            (Kind::ReplicaOf, RespType::SimpleError(error)) => {
+                let (host, port) = &request.to;
+                eprintln!("failed to reconfigure {host}:{port}: {}", error.as_str());
+            }
+            _ => {}
+        }
+    }
+
+    // A hello from another sentinel, which is added to those monitoring the master if it is new,
+    // and whose configuration of the master is taken if newer
+    fn receive_hello(&mut self, hello: &str, now: Instant) -> Result<()> {
+        let fields = hello.split(',').collect::<Vec<_>>();
+        let &[host, port, id, epoch, name, master_host, master_port, config_epoch] = &fields[..]
+        else {
+            bail!("invalid hello {hello:?}");
+        };
+        let address = (host.to_string(), port.parse()?);
+        let (epoch, config_epoch) = (epoch.parse::<u64>()?, config_epoch.parse::<u64>()?);
+        let master_address = (master_host.to_string(), master_port.parse()?);
+        if id == self.id || address == self.address {
+            return Ok(());
+        }
+        let Some(master) = self.masters.iter_mut().find(|master| master.name == name) else {
+            return Ok(());
+        };
+
+        if epoch > self.current_epoch {
+            self.current_epoch = epoch;
+            println!("+new-epoch {epoch}");
+        }
+        let peer = master
+            .sentinels
+            .iter()
+            .position(|peer| peer.id.as_deref() == Some(id))
+            .or_else(|| {
+                master
+                    .sentinels
+                    .iter()
+                    .position(|peer| peer.address == address)
+            });
+        let peer = if let Some(peer) = peer {
+            &mut master.sentinels[peer]
+        } else {
+            println!("+sentinel sentinel {id} {host} {port} @ {name}");
+            master.sentinels.push(Peer::new(address.clone()));
+            master.sentinels.last_mut().expect("just pushed")
+        };
+        peer.address = address;
+        peer.id = Some(id.to_string());
+        peer.last_hello = Some(now);
+
+        if config_epoch > master.config_epoch {
+            master.config_epoch = config_epoch;
+            if master_address != master.instance.address {
+                println!("+config-update-from sentinel {id} {host} {port} @ {name}");
+                master.switch(master_address, now);
+            }
+        }
+
+        Ok(())
+    }
+
+    // As per SENTINEL IS-MASTER-DOWN-BY-ADDR, with a vote if asked for one
+    fn is_master_down(&mut self, address: &Address, epoch: u64, candidate: &str) -> Vec<u8> {
+        let Some(index) = self
+            .masters
+            .iter()
+            .position(|master| master.instance.address == *address)
+        else {
+            return is_master_down_reply(false, None, 0);
+        };
+
+        let down = self.masters[index].instance.sdown;
+        if candidate == "*" {
+            return is_master_down_reply(down, None, 0);
+        }
+        let (leader, leader_epoch) = self.vote(index, epoch, candidate, Instant::now());
+        is_master_down_reply(down, leader.as_deref(), leader_epoch)
+    }
+
+    // As per SENTINEL FAILOVER, which fails over the master without asking other sentinels
+    fn force_failover(&mut self, name: &str, now: Instant) -> Vec<u8> {
+        let Some(master) = self.masters.iter_mut().find(|master| master.name == name) else {
+            return SimpleError::from("ERR No such master with that name").encode();
+        };
+        if master.failover.is_some() {
+            return SimpleError::from("INPROG Failover already in progress").encode();
+        }
+        if master.select_replica(now).is_none() {
+            return SimpleError::from("NOGOODSLAVE No suitable replica to promote").encode();
+        }
+
+        self.current_epoch += 1;
+        println!("+new-epoch {}", self.current_epoch);
+        master.failover = Some(Failover {
+            epoch: self.current_epoch,
+            started: now,
+            forced: true,
+            promoting: None,
+        });
+        master.failover_start = Some(now);
+        SimpleString::new("OK").encode()
+    }
+
+    fn master(&self, name: &str) -> Result<&Master, Vec<u8>> {
+        self.masters
+            .iter()
+            .find(|master| master.name == name)
+            .ok_or_else(|| SimpleError::from("ERR No such master with that name").encode())
+    }
+
+    fn info(&self) -> String {
+        let mut info = String::new();
+        let _ = write!(
+            info,
+            "# Server\r\n\
+             redis_mode:sentinel\r\n\
+             tcp_port:{}\r\n\
+             run_id:{}\r\n\
+             \r\n\
+             # Sentinel\r\n\
+             sentinel_masters:{}\r\n\
+             sentinel_tilt:0\r\n\
+             sentinel_running_scripts:0\r\n\
+             sentinel_scripts_queue_length:0\r\n",
+            self.address.1,
+            self.id,
+            self.masters.len(),
+        );
+        for (index, master) in self.masters.iter().enumerate() {
+            let status = if master.odown {
+                "odown"
+            } else if master.instance.sdown {
+                "sdown"
+            } else {
+                "ok"
+            };
+            let (host, port) = &master.instance.address;
+            let _ = write!(
+                info,
+                "master{index}:name={},status={status},address={host}:{port},slaves={},\
+                 sentinels={}\r\n",
+                master.name,
+                master.replicas.len(),
+                master.sentinels.len() + 1,
+            );
+        }
+        info
+    }
+
+    fn run(&mut self, command: Command) -> Vec<u8> {
+        let now = Instant::now();
+        match command {
+            Command::Ping => SimpleString::new("PONG").encode(),
+            Command::Info(_) => BulkString::from(self.info().as_bytes()).encode(),
+            Command::Role => {
+                let names = self
+                    .masters
+                    .iter()
+                    .map(|master| BulkString::from(master.name.as_bytes()).encode())
+                    .collect::<Vec<_>>();
+                Array::from(vec![
+                    BulkString::from(&b"sentinel"[..]).encode(),
+                    Array::from(names).encode(),
+                ])
+                .encode()
+            }
+            Command::Publish(channel, message) if channel == HELLO_CHANNEL => {
+                let hello = message.as_string().unwrap_or_default();
+                if let Err(error) = self.receive_hello(hello, now) {
+                    eprintln!("{error:#}");
+                }
+                Integer::from(1).encode()
+            }
+            Command::Publish(..) => {
+                SimpleError::from("ERR Only HELLO messages are accepted by Sentinel instances.")
+                    .encode()
+            }
+            Command::SentinelMyId => BulkString::from(self.id.as_bytes()).encode(),
+            Command::SentinelMasters => {
+                let masters = self
+                    .masters
+                    .iter()
+                    .map(|master| encode_fields(master.fields(now)))
+                    .collect::<Vec<_>>();
+                Array::from(masters).encode()
+            }
+            Command::SentinelMaster(name) => self
+                .master(name)
+                .map_or_else(|error| error, |master| encode_fields(master.fields(now))),
+            Command::SentinelReplicas(name) => self.master(name).map_or_else(
+                |error| error,
+                |master| {
+                    let replicas = master.replicas.iter().map(|replica| {
+                        let (host, port) = &replica.address;
+                        let flags = if replica.sdown {
+                            "slave,s_down"
+                        } else {
+                            "slave"
+                        };
+                        let mut fields = replica.fields(format!("{host}:{port}"), flags, now);
+                        let (master_host, master_port) = match &replica.role {
+                            Some(Role::Replica((host, port))) => (host.clone(), port.to_string()),
+                            _ => ("?".to_string(), "0".to_string()),
+                        };
+                        let link = if replica.link_up { "ok" } else { "err" };
+                        fields.extend([
+                            ("master-link-status", link.to_string()),
+                            ("master-host", master_host),
+                            ("master-port", master_port),
+                            ("slave-repl-offset", replica.offset.to_string()),
+                        ]);
+                        encode_fields(fields)
+                    });
+                    Array::from(replicas.collect::<Vec<_>>()).encode()
+                },
+            ),
+            Command::SentinelSentinels(name) => self.master(name).map_or_else(
+                |error| error,
+                |master| {
+                    let sentinels = master.sentinels.iter().map(|peer| {
+                        let id = peer.id.clone().unwrap_or_default();
+                        let (host, port) = &peer.address;
+                        encode_fields(vec![
+                            ("name", id.clone()),
+                            ("ip", host.clone()),
+                            ("port", port.to_string()),
+                            ("runid", id),
+                            ("flags", "sentinel".to_string()),
+                            (
+                                "last-hello-message",
+                                peer.last_hello.map_or_else(
+                                    || "0".to_string(),
+                                    |last| milliseconds_since(last, now),
+                                ),
+                            ),
+                            (
+                                "voted-leader",
+                                peer.leader.clone().unwrap_or_else(|| "?".to_string()),
+                            ),
+                            ("voted-leader-epoch", peer.leader_epoch.to_string()),
+                        ])
+                    });
+                    Array::from(sentinels.collect::<Vec<_>>()).encode()
+                },
+            ),
+            Command::SentinelGetMasterAddrByName(name) => {
+                let Ok(master) = self.master(name) else {
+                    return NullArray::encode();
+                };
+                let (host, port) = &master.instance.address;
+                Array::from(vec![
+                    BulkString::from(host.as_bytes()).encode(),
+                    BulkString::from(port.to_string().as_bytes()).encode(),
+                ])
+                .encode()
+            }
+            Command::SentinelIsMasterDownByAddr(host, port, epoch, candidate) => {
+                self.is_master_down(&(host.to_string(), port), epoch, candidate)
+            }
+            Command::SentinelFailover(name) => self.force_failover(name, now),
+            _ => SimpleError::from("ERR unknown command").encode(),
+        }
+    }
+}
+
+fn desync() -> Duration {
+    Duration::from_millis(evict::random() % MAX_DESYNC)
+}
+
+// Fields and their values, as a flat array
+fn encode_fields(fields: Vec<(&'static str, String)>) -> Vec<u8> {
+    let fields = fields
+        .into_iter()
+        .flat_map(|(name, value)| {
+            [
+                BulkString::from(name.as_bytes()).encode(),
+                BulkString::from(value.as_bytes()).encode(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    Array::from(fields).encode()
+}
+
+fn is_master_down_reply(down: bool, leader: Option<&str>, epoch: u64) -> Vec<u8> {
+    Array::from(vec![
+        Integer::from(i64::from(down)).encode(),
+        BulkString::from(leader.unwrap_or("*").as_bytes()).encode(),
+        Integer::from(i64::try_from(epoch).unwrap_or(i64::MAX)).encode(),
+    ])
+    .encode()
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Connections to the instances being monitored and other sentinels, which are only used by the
+// thread monitoring them, and are dropped on any error to be connected again next time
+#[derive(Default)]
+struct Links {
+    connections: HashMap<Address, Connection>,
+    // Subscribed to the hello channel of each instance, and only read from
+    subscriptions: HashMap<Address, Connection>,
+}
+
+impl Links {
+    fn send(&mut self, request: &Request) -> Result<Vec<u8>> {
+        let mut connection = match self.connections.remove(&request.to) {
+            Some(connection) => connection,
+            None => connect(&request.to)?,
+        };
+
+        let arguments = request
+            .arguments
+            .iter()
+            .map(String::as_bytes)
+            .collect::<Vec<_>>();
+        connection.send(&arguments)?;
+        let Some(reply) = connection.read_request()? else {
+            bail!("connection closed");
+        };
+        self.connections.insert(request.to.clone(), connection);
+        Ok(reply)
+    }
+
+    // The hellos published on the instances since last time, subscribing to any new instance,
+    // without waiting for any more
+    fn hellos(&mut self, instances: &[Address]) -> Vec<String> {
+        self.subscriptions
+            .retain(|address, _| instances.contains(address));
+        let mut hellos = Vec::new();
+        for address in instances {
+            let subscription = match self.subscriptions.remove(address) {
+                Some(subscription) => Ok(subscription),
+                None => subscribe(address),
+            };
+            let Ok(mut subscription) = subscription else {
+                continue;
+            };
+            loop {
+                match subscription.read_request() {
+                    Ok(Some(message)) => hellos.extend(hello(&message)),
+                    Err(error) if is_timeout(&error) => {
+                        self.subscriptions.insert(address.clone(), subscription);
+                        break;
+                    }
+                    Ok(None) | Err(_) => break,
+                }
+            }
+        }
+        hellos
+    }
+}
+
+fn connect(address: &Address) -> Result<Connection> {
+    let (host, port) = address;
+    let Some(address) = (host.as_str(), *port).to_socket_addrs()?.next() else {
+        bail!("no address for {host}:{port}");
+    };
+    let stream = TcpStream::connect_timeout(&address, IO_TIMEOUT)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    Ok(Connection::new(stream))
+}
+
+// Subscribes to the hello channel of an instance, which is then read from without blocking
+fn subscribe(address: &Address) -> Result<Connection> {
+    let mut connection = connect(address)?;
+    connection.send(&[b"SUBSCRIBE", HELLO_CHANNEL.as_bytes()])?;
+    connection.stream.set_nonblocking(true)?;
+    Ok(connection)
+}
+
+// The hello in a message pushed to a subscriber, rather than its confirmation of the subscription
+fn hello(message: &[u8]) -> Option<String> {
+    let Ok(RespType::Array(message)) = RespType::try_from(message) else {
+        return None;
+    };
+    let message = Vec::from(message);
+    let [RespType::BulkString(kind), RespType::BulkString(channel), RespType::BulkString(hello)] =
+        &message[..]
+    else {
+        return None;
+    };
+    if kind.as_string() != Some("message") || channel.as_string() != Some(HELLO_CHANNEL) {
+        return None;
+    }
+    hello.as_string().map(ToString::to_string)
+}
+
+// Runs on its own thread, never holding the state while waiting on other instances
+fn monitor(state: &Mutex<State>) {
+    let mut links = Links::default();
+    loop {
+        thread::sleep(TICK);
+        let (requests, instances) = {
+            let mut state = lock(state);
+            (state.plan(Instant::now()), state.instances())
+        };
+        let replies = requests
+            .into_iter()
+            .filter_map(|request| {
+                let reply = links.send(&request).ok()?;
+                Some((request, reply))
+            })
+            .collect::<Vec<_>>();
+        // Other sentinels are found from the hellos they publish on the instances
+        let hellos = links.hellos(&instances);
+
+        let mut state = lock(state);
+        let now = Instant::now();
+        for (request, reply) in replies {
+            state.apply(&request, &reply, now);
+        }
+        for hello in hellos {
+            if let Err(error) = state.receive_hello(&hello, now) {
+                eprintln!("{error:#}");
+            }
+        }
+    }
+}
+
+fn handle(state: &Mutex<State>, stream: TcpStream) -> Result<()> {
+    let mut connection = Connection::new(stream);
+    while let Some(request) = connection.read_request()? {
+        let request = RespType::try_from(request.as_slice())?;
+        let reply = match Command::try_from(request) {
+            Ok(command) => lock(state).run(command),
+            Err(error) => SimpleError::from(error).encode(),
+        };
+        connection.stream.write_all(&reply)?;
+    }
+
+    Ok(())
+}
+
+pub struct Sentinel {
+    listener: TcpListener,
+    state: Arc<Mutex<State>>,
+}
+
+impl Sentinel {
+    #[allow(clippy::missing_errors_doc)]
+    pub fn bind(addr: &str, config: Config) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let now = Instant::now();
+        let masters = config
+            .sentinel
+            .unwrap_or_default()
+            .into_iter()
+            .map(|monitor| Master::new(monitor, now))
+            .collect();
+        let state = State {
+            id: new_id(),
+            address: (local.ip().to_string(), local.port()),
+            current_epoch: 0,
+            masters,
+        };
+
+        Ok(Self {
+            listener,
+            state: Arc::new(Mutex::new(state)),
+        })
+    }
+
+    #[allow(clippy::missing_errors_doc)]
+    pub fn start(&self) -> Result<()> {
+        println!("Sentinel ID is {}", lock(&self.state).id);
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || monitor(&state));
+
+        // Connections from other sentinels stay open, so each has its own thread rather than one
+        // from a pool
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            let state = Arc::clone(&self.state);
+            thread::spawn(move || {
+                if let Err(error) = handle(&state, stream) {
+                    eprintln!("Client error: {error}");
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::server::Server;
+
+    fn with_peers(peers: usize) -> State {
+        let mut monitors = Vec::new();
+        configure(&mut monitors, "monitor mymaster 127.0.0.1 6379 2").unwrap();
+        let mut master = Master::new(monitors.remove(0), Instant::now());
+        for port in 0..peers {
+            let port = 26380 + u16::try_from(port).unwrap();
+            master.sentinels.push(Peer::new(("127.0.0.1".into(), port)));
+        }
+        State {
+            id: "me".to_string(),
+            address: ("127.0.0.1".to_string(), 26379),
+            current_epoch: 0,
+            masters: vec![master],
+        }
+    }
+
+    #[test]
+    fn directives() {
+        let mut monitors = Vec::new();
+        configure(&mut monitors, "monitor mymaster 127.0.0.1 6379 2").unwrap();
+        configure(&mut monitors, "failover-timeout mymaster 5000").unwrap();
+        configure(&mut monitors, "known-sentinel mymaster 127.0.0.1 26380").unwrap();
+        assert_eq!(monitors[0].failover_timeout, Duration::from_secs(5));
+        assert_eq!(monitors[0].known_sentinels, [("127.0.0.1".into(), 26380)]);
+
+        assert!(configure(&mut monitors, "monitor mymaster 127.0.0.1 6380 2").is_err());
+        assert!(configure(&mut monitors, "monitor other 127.0.0.1 6380 0").is_err());
+        assert!(configure(&mut monitors, "failover-timeout other 5000").is_err());
+        assert!(configure(&mut monitors, "down-after-milliseconds mymaster -1").is_err());
+        assert!(configure(&mut monitors, "parallel-syncs mymaster 1").is_err());
+    }
+
+    #[test]
+    fn election() {
+        let now = Instant::now();
+
+        // Without votes from others, a sentinel votes for itself, which is not a majority
+        let mut state = with_peers(2);
+        state.current_epoch = 1;
+        assert_eq!(state.leader(0, 1, now), None);
+        assert_eq!(state.masters[0].leader.as_deref(), Some("me"));
+
+        // Once voted, later candidates in the same epoch are refused
+        assert_eq!(state.vote(0, 1, "other", now), (Some("me".to_string()), 1));
+        state.masters[0].sentinels[0].leader = Some("me".to_string());
+        state.masters[0].sentinels[0].leader_epoch = 1;
+        assert_eq!(state.leader(0, 1, now).as_deref(), Some("me"));
+
+        // Sentinels vote for whoever others voted for, and newer epochs are taken
+        let mut state = with_peers(2);
+        state.masters[0].sentinels[0].leader = Some("other".to_string());
+        state.masters[0].sentinels[0].leader_epoch = 3;
+        assert_eq!(state.leader(0, 3, now).as_deref(), Some("other"));
+        assert_eq!(state.current_epoch, 3);
+        assert!(state.masters[0].failover_start.is_some());
+    }
+
+    #[test]
+    fn hello_and_info() {
+        let now = Instant::now();
+        let mut state = with_peers(0);
+        state
+            .receive_hello("127.0.0.1,26380,other,2,mymaster,127.0.0.1,6379,0", now)
+            .unwrap();
+        assert_eq!(state.current_epoch, 2);
+        assert_eq!(state.masters[0].sentinels.len(), 1);
+        // Hellos from itself are ignored
+        state
+            .receive_hello("127.0.0.1,26379,me,2,mymaster,127.0.0.1,6379,0", now)
+            .unwrap();
+        assert_eq!(state.masters[0].sentinels.len(), 1);
+
+        let master = &mut state.masters[0];
+        let address = master.instance.address.clone();
+        let info = "# Replication\r\nrole:master\r\nconnected_slaves:1\r\n\
+                    slave0:ip=127.0.0.1,port=6380,state=online,offset=42,lag=0\r\n";
+        master.refresh(&address, info, now);
+        assert_eq!(master.replicas.len(), 1);
+        assert_eq!(master.instance.role, Some(Role::Master));
+
+        // A newer configuration from another sentinel switches to the promoted replica
+        state
+            .receive_hello("127.0.0.1,26380,other,3,mymaster,127.0.0.1,6380,3", now)
+            .unwrap();
+        let master = &state.masters[0];
+        assert_eq!(master.instance.address, ("127.0.0.1".to_string(), 6380));
+        assert_eq!(master.replicas[0].address, ("127.0.0.1".to_string(), 6379));
+        assert_eq!(master.config_epoch, 3);
+    }
+
+    #[test]
+    fn discovery() {
+        let config = Config {
+            dir: std::env::temp_dir().join("redis-starter-rust-sentinel-discovery"),
+            ..Config::default()
+        };
+        let server = Server::bind("127.0.0.1:0", config).unwrap();
+        let port = server.local_addr().unwrap().port();
+        thread::spawn(move || server.start());
+
+        // Sentinels which don't know each other find each other from their hellos on the master
+        let sentinels = [(); 2].map(|()| {
+            let mut monitors = Vec::new();
+            let directive = format!("monitor mymaster 127.0.0.1 {port} 2");
+            configure(&mut monitors, &directive).unwrap();
+            let config = Config {
+                sentinel: Some(monitors),
+                ..Config::default()
+            };
+            let sentinel = Arc::new(Sentinel::bind("127.0.0.1:0", config).unwrap());
+            thread::spawn({
+                let sentinel = Arc::clone(&sentinel);
+                move || sentinel.start()
+            });
+            sentinel
+        });
+        let ids = sentinels
+            .each_ref()
+            .map(|sentinel| lock(&sentinel.state).id.clone());
+        let knows = |sentinel: &Sentinel, id: &str| {
+            lock(&sentinel.state).masters[0]
+                .sentinels
+                .iter()
+                .any(|peer| peer.id.as_deref() == Some(id))
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !(knows(&sentinels[0], &ids[1]) && knows(&sentinels[1], &ids[0])) {
+            assert!(
+                Instant::now() < deadline,
+                "sentinels never found each other"
+            );
+            thread::sleep(TICK);
+        }
+    }
+}
@@ -1,37 +1,431 @@
-use crate::{client::Client, store::Store, threadpool::ThreadPool};
+use crate::{
+    aof::{self, Aof},
+    client::Client,
+    cluster::Cluster,
+    config::Config,
+    pubsub::PubSub,
+    rdb::{self, Saves},
+    replication::Replication,
+    store::Store,
+};
 use anyhow::Result;
-use std::{net::TcpListener, sync::Arc};
+use std::{
+    net::{Ipv4Addr, TcpListener},
+    sync::{Arc, PoisonError, RwLock},
+    thread,
+    time::Duration,
+};
+
+// How often background tasks run, as per the default `hz` of 10 in Redis, with expiring keys
+// allowed up to a quarter of that time
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+const ACTIVE_EXPIRE_CYCLE_TIME: Duration = Duration::from_millis(25);
 
 pub struct Server {
     listener: TcpListener,
     store: Arc<Store>,
+    config: Arc<RwLock<Config>>,
+    saves: Arc<Saves>,
+    aof: Arc<Aof>,
+    replication: Arc<Replication>,
+    pubsub: Arc<PubSub>,
+    cluster: Option<Arc<Cluster>>,
 }
 
 impl Server {
     #[allow(clippy::missing_errors_doc)]
-    pub fn bind(addr: &str) -> Result<Self> {
+    pub fn bind(addr: &str, config: Config) -> Result<Self> {
+        let mut store = Store::with_databases(config.databases);
+        if config.cluster_enabled {
+            store.track_slots();
+        }
+        let store = Arc::new(store);
+        store.set_max_memory(config.max_memory);
+        let saves = Arc::new(Saves::new());
+        let aof = Arc::new(Aof::new());
+        let replication = Arc::new(Replication::new(config.repl_backlog_size));
+        let pubsub = Arc::new(PubSub::new());
+
+        // Like Redis, the append-only file is loaded instead of the RDB file when it is on
+        let append_only = config.append_only;
+        let config = Arc::new(RwLock::new(config));
+        if append_only {
+            let client = Client::new(
+                Arc::clone(&store),
+                Arc::clone(&config),
+                Arc::clone(&saves),
+                Arc::clone(&aof),
+                Arc::clone(&replication),
+                Arc::clone(&pubsub),
+            );
+            let config = config.read().unwrap_or_else(PoisonError::into_inner);
+            let manifest = aof::load(&client, &store, &config)?;
+            aof.start(&store, &config, manifest)?;
+        } else {
+            let config = config.read().unwrap_or_else(PoisonError::into_inner);
+            rdb::load(&store, &config.rdb_path())?;
+        }
+
+        let listener = TcpListener::bind(addr)?;
+        let cluster = Self::cluster(&listener, &config, &pubsub)?;
+
         Ok(Self {
-            listener: TcpListener::bind(addr)?,
-            store: Arc::new(Store::new()),
+            listener,
+            store,
+            config,
+            saves,
+            aof,
+            replication,
+            pubsub,
+            cluster,
         })
     }
 
+    // The node of the cluster this server is, when cluster mode is on, announcing the address
+    // clients connect to, or localhost when listening on every address
+    fn cluster(
+        listener: &TcpListener,
+        config: &RwLock<Config>,
+        pubsub: &Arc<PubSub>,
+    ) -> Result<Option<Arc<Cluster>>> {
+        let config = config.read().unwrap_or_else(PoisonError::into_inner);
+        if !config.cluster_enabled {
+            return Ok(None);
+        }
+
+        let address = listener.local_addr()?;
+        let host = if address.ip().is_unspecified() {
+            Ipv4Addr::LOCALHOST.to_string()
+        } else {
+            address.ip().to_string()
+        };
+        let timeout = Duration::from_millis(config.cluster_node_timeout);
+        let cluster = Cluster::bind(
+            &host,
+            address.port(),
+            config.cluster_port,
+            timeout,
+            Arc::clone(pubsub),
+        )?;
+        Ok(Some(Arc::new(cluster)))
+    }
+
+    fn client(&self) -> Client {
+        Client::new(
+            Arc::clone(&self.store),
+            Arc::clone(&self.config),
+            Arc::clone(&self.saves),
+            Arc::clone(&self.aof),
+            Arc::clone(&self.replication),
+            Arc::clone(&self.pubsub),
+        )
+        .with_cluster(self.cluster.clone())
+    }
+
+    #[cfg(test)]
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
     #[allow(clippy::missing_errors_doc)]
     pub fn start(&self) -> Result<()> {
-        let pool = ThreadPool::new(4);
+        let config = self.config.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some((host, port)) = config.replica_of.clone() {
+            let client = self.client().for_master();
+            let (store, aof) = (Arc::clone(&self.store), Arc::clone(&self.aof));
+            self.replication
+                .replicate(host, port, client, store, aof, config.port);
+        }
+        drop(config);
+
+        if let Some(cluster) = &self.cluster {
+            cluster.start();
+        }
+
+        let store = Arc::clone(&self.store);
+        let config = Arc::clone(&self.config);
+        let saves = Arc::clone(&self.saves);
+        let aof = Arc::clone(&self.aof);
+        thread::spawn(move || loop {
+            thread::sleep(CRON_INTERVAL);
+            store.active_expire_cycle(ACTIVE_EXPIRE_CYCLE_TIME);
+            store.peak_memory();
+            let config = config.read().unwrap_or_else(PoisonError::into_inner);
+            saves.cron(&store, &config);
+            aof.cron();
+        });
 
         loop {
             let (stream, client_addr) = self.listener.accept()?;
             dbg!(client_addr);
 
-            let store = Arc::clone(&self.store);
-            pool.execute(move || {
-                let mut client = Client::new(stream, store);
-                // TODO: No support for `Result` in current `ThreadPool` implementation
-                if let Err(error) = client.handle() {
+            // Connections from replicas, sentinels and subscribers stay open, so each has its own
+            // thread rather than one from a pool, which they would otherwise take up for good
+            let mut client = self.client();
+            thread::spawn(move || {
+                if let Err(error) = client.handle(stream) {
                     eprintln!("Client error: {error}");
                 }
             });
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        connection::Connection,
+        resp::{command, BulkString},
+        store::Value,
+    };
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        sync::mpsc,
+    };
+
+    fn ping(stream: &mut TcpStream) {
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+        read(stream, b"+PONG\r\n");
+    }
+
+    fn start(name: &str) -> std::net::SocketAddr {
+        let config = Config {
+            dir: std::env::temp_dir().join(format!("redis-starter-rust-{name}")),
+            ..Config::default()
+        };
+        let server = Server::bind("127.0.0.1:0", config).unwrap();
+        let address = server.listener.local_addr().unwrap();
+        thread::spawn(move || server.start());
+        address
+    }
+
+    fn read(stream: &mut TcpStream, expected: &[u8]) {
+        let mut reply = vec![0; expected.len()];
+        stream.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[test]
+    fn open_connections() {
+        let address = start("open-connections");
+
+        // Connections which stay open, such as those of sentinels, don't hold up any others
+        let mut connections = (0..8)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect::<Vec<_>>();
+        for connection in &mut connections {
+            connection
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            ping(connection);
+        }
+        for connection in connections.iter_mut().rev() {
+            ping(connection);
+        }
+    }
+
+    #[test]
+    fn idle_subscribers() {
+        let address = start("idle-subscribers");
+        let mut subscribers = (0..8)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect::<Vec<_>>();
+        for subscriber in &mut subscribers {
+            subscriber
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            subscriber
+                .write_all(b"*2\r\n$9\r\nSUBSCRIBE\r\n$4\r\nnews\r\n")
+                .unwrap();
+            read(subscriber, b"*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        }
+
+        let mut publisher = TcpStream::connect(address).unwrap();
+        publisher
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        publisher
+            .write_all(b"*3\r\n$7\r\nPUBLISH\r\n$4\r\nnews\r\n$2\r\nhi\r\n")
+            .unwrap();
+        read(&mut publisher, b":8\r\n");
+        for subscriber in &mut subscribers {
+            read(
+                subscriber,
+                b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n",
+            );
+        }
+    }
+
+    fn connect(address: std::net::SocketAddr) -> TcpStream {
+        let stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    #[test]
+    fn large_values() {
+        let address = start("large-values");
+        let mut stream = connect(address);
+        let value = vec![b'x'; 3 * 1024 * 1024];
+        stream
+            .write_all(&command(&[b"SET", b"big", &value]))
+            .unwrap();
+        read(&mut stream, b"+OK\r\n");
+        stream.write_all(&command(&[b"GET", b"big"])).unwrap();
+        read(&mut stream, &BulkString::from(&value[..]).encode());
+    }
+
+    #[test]
+    fn pipelining() {
+        let address = start("pipelining");
+        let mut stream = connect(address);
+        let requests = [
+            command(&[b"SET", b"a", b"1"]),
+            command(&[b"SET", b"b", b"2"]),
+            command(&[b"GET", b"b"]),
+        ]
+        .concat();
+
+        // Requests sent together are each replied to, as are those split across writes
+        stream.write_all(&requests).unwrap();
+        read(&mut stream, b"+OK\r\n+OK\r\n$1\r\n2\r\n");
+        let (first, second) = requests.split_at(requests.len() / 2);
+        stream.write_all(first).unwrap();
+        thread::sleep(Duration::from_millis(50));
+        stream.write_all(second).unwrap();
+        read(&mut stream, b"+OK\r\n+OK\r\n$1\r\n2\r\n");
+    }
+
+    // Bytes which don't compress, for payloads as large as the value
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    }
+
+    fn read_bulk(stream: &mut TcpStream) -> Vec<u8> {
+        let mut header = Vec::new();
+        while !header.ends_with(b"\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            header.push(byte[0]);
+        }
+        let length = std::str::from_utf8(&header[1..header.len() - 2])
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        let mut value = vec![0; length + 2];
+        stream.read_exact(&mut value).unwrap();
+        value.truncate(length);
+        value
+    }
+
+    #[test]
+    fn large_dump_and_restore() {
+        let address = start("large-dump-and-restore");
+        let mut stream = connect(address);
+        let value = noise(1024 * 1024);
+        stream
+            .write_all(&command(&[b"SET", b"big", &value]))
+            .unwrap();
+        read(&mut stream, b"+OK\r\n");
+        stream.write_all(&command(&[b"DUMP", b"big"])).unwrap();
+        let payload = read_bulk(&mut stream);
+        assert!(payload.len() > value.len());
+
+        stream
+            .write_all(&command(&[b"RESTORE", b"copy", b"0", &payload]))
+            .unwrap();
+        read(&mut stream, b"+OK\r\n");
+        stream.write_all(&command(&[b"GET", b"copy"])).unwrap();
+        assert_eq!(read_bulk(&mut stream), value);
+    }
+
+    #[test]
+    fn pipelined_restore_asking() {
+        let address = start("pipelined-restore-asking");
+        let mut stream = connect(address);
+        let value = noise(256 * 1024);
+        stream
+            .write_all(&command(&[b"SET", b"big", &value]))
+            .unwrap();
+        read(&mut stream, b"+OK\r\n");
+        stream.write_all(&command(&[b"DUMP", b"big"])).unwrap();
+        let payload = read_bulk(&mut stream);
+
+        // Like the MIGRATE of Redis, which sends SELECT along with the first key
+        let requests = [
+            command(&[b"SELECT", b"1"]),
+            command(&[b"RESTORE-ASKING", b"big", b"0", &payload]),
+        ]
+        .concat();
+        stream.write_all(&requests).unwrap();
+        read(&mut stream, b"+OK\r\n+OK\r\n");
+        stream.write_all(&command(&[b"GET", b"big"])).unwrap();
+        assert_eq!(read_bulk(&mut stream), value);
+    }
+
+    #[test]
+    fn writes_during_migrate() {
+        // A target which holds its reply to RESTORE until told to
+        let target = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = target.local_addr().unwrap().port().to_string();
+        let (restored, restore) = mpsc::channel();
+        let (reply, replying) = mpsc::channel();
+        thread::spawn(move || {
+            let mut connection = Connection::new(target.accept().unwrap().0);
+            connection.read_request().unwrap();
+            connection.stream.write_all(b"+OK\r\n").unwrap();
+            restored.send(connection.read_request().unwrap()).unwrap();
+            replying.recv().unwrap();
+            connection.stream.write_all(b"+OK\r\n").unwrap();
+        });
+
+        let address = start("writes-during-migrate");
+        let mut stream = connect(address);
+        let mut migrating = connect(address);
+        stream
+            .write_all(&command(&[b"SET", b"key", b"before"]))
+            .unwrap();
+        read(&mut stream, b"+OK\r\n");
+        migrating
+            .write_all(&command(&[
+                b"MIGRATE",
+                b"127.0.0.1",
+                port.as_bytes(),
+                b"key",
+                b"0",
+                b"5000",
+            ]))
+            .unwrap();
+        let request = restore.recv().unwrap().unwrap();
+        stream
+            .write_all(&command(&[b"SET", b"key", b"during"]))
+            .unwrap();
+        read(&mut stream, b"-TRYAGAIN Keys are being migrated\r\n");
+        reply.send(()).unwrap();
+        read(&mut migrating, b"+OK\r\n");
+
+        // The key moved as it was, and only to the target
+        let payload = rdb::dump(&Value::String("before".into()));
+        assert!(request
+            .windows(payload.len())
+            .any(|window| window == payload));
+        stream.write_all(&command(&[b"GET", b"key"])).unwrap();
+        read(&mut stream, b"$-1\r\n");
+        stream
+            .write_all(&command(&[b"SET", b"key", b"after"]))
+            .unwrap();
+        read(&mut stream, b"+OK\r\n");
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    ops::Bound,
+};
+
+// Scores are ordered using `f64::total_cmp`, which is a total order as long as -0.0 is normalised
+// and NaN never makes it in
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// Roughly the memory used for each member besides the member itself, which is held twice
+const MEMBER_OVERHEAD: usize = 80;
+
+// Restricts updates to members which either already exist, or do not
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    IfExists,
+    IfMissing,
+}
+
+// Members ordered by score, then lexicographically, with lookup of the score for a member
+// See: https://redis.io/docs/latest/develop/data-types/sorted-sets/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, Score>,
+    ordered: BTreeSet<(Score, Vec<u8>)>,
+    // The total length of the members, for estimating the memory used
+    member_bytes: usize,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn memory(&self) -> usize {
+        self.member_bytes * 2 + self.len() * MEMBER_OVERHEAD
+    }
+
+    // Estimates the memory used from the first `samples` members, or all of them for 0, as per
+    // MEMORY USAGE in Redis
+    pub fn sampled_memory(&self, samples: usize) -> usize {
+        if samples == 0 || samples >= self.len() {
+            return self.memory();
+        }
+
+        let sampled = self
+            .ordered
+            .iter()
+            .take(samples)
+            .map(|(_, member)| member.len() * 2 + MEMBER_OVERHEAD)
+            .sum::<usize>();
+        sampled * self.len() / samples
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).map(|score| score.0)
+    }
+
+    // Adds or updates the score for `member`, returning whether it was newly added
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        debug_assert!(!score.is_nan());
+        let score = Score(if score == 0.0 { 0.0 } else { score });
+
+        match self.scores.insert(member.clone(), score) {
+            Some(previous) => {
+                if previous != score {
+                    self.ordered.remove(&(previous, member.clone()));
+                    self.ordered.insert((score, member));
+                }
+                false
+            }
+            None => {
+                self.member_bytes += member.len();
+                self.ordered.insert((score, member));
+                true
+            }
+        }
+    }
+
+    // Members with a score between `min` and `max` inclusive, in ascending order of score
+    pub fn range(&self, min: f64, max: f64) -> impl Iterator<Item = (&[u8], f64)> {
+        self.ordered
+            .range((Bound::Included((Score(min), Vec::new())), Bound::Unbounded))
+            .take_while(move |(score, _)| score.0 <= max)
+            .map(|(score, member)| (member.as_slice(), score.0))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ordered_by_score_then_member() {
+        let mut set = SortedSet::new();
+        assert!(set.insert(b"c".to_vec(), 1.0));
+        assert!(set.insert(b"b".to_vec(), 1.0));
+        assert!(set.insert(b"a".to_vec(), 2.0));
+        assert!(!set.insert(b"a".to_vec(), -1.0));
+
+        let members: Vec<_> = set
+            .range(f64::NEG_INFINITY, f64::INFINITY)
+            .map(|(member, _)| member)
+            .collect();
+        assert_eq!(members, vec![&b"a"[..], b"b", b"c"]);
+        assert_eq!(set.score(b"a"), Some(-1.0));
+    }
+
+    #[test]
+    fn range_is_inclusive() {
+        let mut set = SortedSet::new();
+        for (member, score) in [(b"a", 1.0), (b"b", 2.0), (b"c", 3.0), (b"d", 4.0)] {
+            set.insert(member.to_vec(), score);
+        }
+
+        let members: Vec<_> = set.range(2.0, 3.0).map(|(member, _)| member).collect();
+        assert_eq!(members, vec![&b"b"[..], b"c"]);
+        assert_eq!(set.range(3.0, 2.0).count(), 0);
+    }
+
+    #[test]
+    fn sampled_memory() {
+        let mut set = SortedSet::new();
+        for i in 0..100 {
+            set.insert(format!("{i:03}").into_bytes(), f64::from(i));
+        }
+
+        assert_eq!(set.sampled_memory(5), set.memory());
+        assert_eq!(set.sampled_memory(1000), set.memory());
+        set.insert(vec![b'a'; 1000], 100.0);
+        assert!(set.sampled_memory(5) < set.sampled_memory(0));
+    }
+}
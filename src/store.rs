@@ -1,68 +1,1985 @@
+use crate::{
+    cluster,
+    dict::Dict,
+    evict::{self, EvictionPool, MaxMemory, Policy},
+    sorted_set::SortedSet,
+    threadpool::ThreadPool,
+};
+use bytes::Bytes;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    marker::PhantomData,
+    mem,
+    ops::{Deref, DerefMut},
     str,
-    sync::Mutex,
-    time::{Duration, Instant},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+// The wall clock time in milliseconds since the Unix epoch, which expiry deadlines are stored as,
+// so that they remain meaningful after a restart or on another server
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+        })
+}
+
+// The keyspace is split into this many shards by default, each with its own lock, so that clients
+// accessing different keys rarely contend
+const DEFAULT_SHARDS: usize = 16;
+
+// The number of numbered databases, as per the default for `databases` in Redis
+pub const DEFAULT_DATABASES: usize = 16;
+
+// Values needing more allocations than this to be freed are dropped in the background, as per
+// `LAZYFREE_THRESHOLD` in Redis
+const LAZYFREE_THRESHOLD: usize = 64;
+
+// Bounds for each active expiry cycle, as per Redis with the default `active-expire-effort`. Each
+// loop samples some keys with a deadline, and the cycle continues while more than the acceptable
+// percentage of those were stale, until it runs out of time.
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+const ACTIVE_EXPIRE_ACCEPTABLE_STALE: usize = 10;
+
+// Roughly the memory used by each key besides the key and value themselves, such as for the hash
+// table entry, and the access time for eviction, along with that for keys with a deadline
+const ENTRY_OVERHEAD: usize = 64;
+const EXPIRES_OVERHEAD: usize = 32;
+
 pub struct Store {
-    // TODO: Use `RwLock` instead?
-    inner: Mutex<HashMap<String, Entry>>,
+    // Each shard holds its part of every database, indexed by the database number, so that moving
+    // keys between databases, or swapping them, needs no more locks than within one
+    shards: Vec<RwLock<Vec<Db>>>,
+    databases: usize,
+    lazyfree: ThreadPool,
+    // The shard, and database within it, the next active expiry cycle starts from, when the last
+    // one ran out of time, as per `slot`
+    expire_slot: AtomicUsize,
+    expire_stats: Mutex<ExpireStats>,
+    // An estimate of the memory used by the keys and values in every shard, which is what the
+    // memory limit applies to
+    used_memory: Arc<AtomicUsize>,
+    peak_memory: AtomicUsize,
+    // The settings for `MaxMemory`, as atomics since the policy is checked on every access
+    maxmemory: AtomicUsize,
+    maxmemory_policy: AtomicU8,
+    maxmemory_samples: AtomicUsize,
+    eviction_pool: Mutex<EvictionPool>,
+    // The shard, and database within it, the next key is evicted from for the random policies, as
+    // per `slot`
+    evict_slot: AtomicUsize,
+    evicted_keys: AtomicU64,
+    // The number of changes to keys since the last snapshot was saved, for save points
+    dirty: AtomicU64,
+    // Keys being sent to another instance by MIGRATE, by database, which can't be written to until
+    // it has them
+    migrating: Mutex<HashSet<(usize, String)>>,
 }
 
+// A copy of every database taken at one point in time, as keys, values and deadlines, to be saved
+// while clients carry on changing the store
+pub type Snapshot = Vec<Vec<(String, Value, Option<u64>)>>;
+
 impl Store {
+    #[cfg(test)]
     pub fn new() -> Self {
+        Self::with_layout(DEFAULT_DATABASES, DEFAULT_SHARDS)
+    }
+
+    pub fn with_databases(databases: usize) -> Self {
+        Self::with_layout(databases, DEFAULT_SHARDS)
+    }
+
+    #[cfg(test)]
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_layout(DEFAULT_DATABASES, shards)
+    }
+
+    fn with_layout(databases: usize, shards: usize) -> Self {
+        assert!(databases > 0 && shards > 0);
+
+        let used_memory = Arc::new(AtomicUsize::new(0));
+        let max_memory = MaxMemory::default();
         Self {
-            inner: Mutex::new(HashMap::new()),
+            shards: std::iter::repeat_with(|| {
+                let dbs = std::iter::repeat_with(|| Db::new(Arc::clone(&used_memory)))
+                    .take(databases)
+                    .collect();
+                RwLock::new(dbs)
+            })
+            .take(shards)
+            .collect(),
+            databases,
+            lazyfree: ThreadPool::new(1),
+            expire_slot: AtomicUsize::new(0),
+            expire_stats: Mutex::default(),
+            used_memory,
+            peak_memory: AtomicUsize::new(0),
+            maxmemory: AtomicUsize::new(max_memory.limit),
+            maxmemory_policy: AtomicU8::new(max_memory.policy.to_index()),
+            maxmemory_samples: AtomicUsize::new(max_memory.samples),
+            eviction_pool: Mutex::default(),
+            evict_slot: AtomicUsize::new(0),
+            evicted_keys: AtomicU64::new(0),
+            dirty: AtomicU64::new(0),
+            migrating: Mutex::default(),
+        }
+    }
+
+    // Marks the keys as being migrated until the returned guard is dropped, unless any of them
+    // already are
+    pub fn hold_for_migration(&self, db: usize, keys: &[&str]) -> Option<Migrating<'_>> {
+        let mut migrating = self
+            .migrating
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let keys = keys.iter().map(|key| (db, (*key).to_string()));
+        let keys = keys.collect::<HashSet<_>>();
+        if !migrating.is_disjoint(&keys) {
+            return None;
+        }
+        migrating.extend(keys.iter().cloned());
+        Some(Migrating { store: self, keys })
+    }
+
+    pub fn is_migrating(&self, db: usize, keys: &[&str]) -> bool {
+        let migrating = self
+            .migrating
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        !migrating.is_empty()
+            && keys
+                .iter()
+                .any(|key| migrating.contains(&(db, (*key).to_string())))
+    }
+
+    // Indexes keys by their hash slot, for cluster mode, where keys are counted, listed and
+    // migrated by slot
+    pub fn track_slots(&mut self) {
+        for shard in &mut self.shards {
+            let dbs = shard.get_mut().unwrap_or_else(PoisonError::into_inner);
+            for db in dbs {
+                db.slots = Some(HashMap::new());
+            }
+        }
+    }
+
+    pub const fn databases(&self) -> usize {
+        self.databases
+    }
+
+    pub fn max_memory(&self) -> MaxMemory {
+        MaxMemory {
+            limit: self.maxmemory.load(Ordering::Relaxed),
+            policy: self.policy(),
+            samples: self.maxmemory_samples.load(Ordering::Relaxed),
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+    pub fn set_max_memory(&self, max_memory: MaxMemory) {
+        self.maxmemory.store(max_memory.limit, Ordering::Relaxed);
+        self.maxmemory_policy
+            .store(max_memory.policy.to_index(), Ordering::Relaxed);
+        self.maxmemory_samples
+            .store(max_memory.samples, Ordering::Relaxed);
+    }
+
+    fn policy(&self) -> Policy {
+        Policy::from_index(self.maxmemory_policy.load(Ordering::Relaxed))
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+
+    // The most memory used since starting, which is updated periodically and when reported, like
+    // in Redis
+    pub fn peak_memory(&self) -> usize {
+        let used = self.used_memory();
+        self.peak_memory
+            .fetch_max(used, Ordering::Relaxed)
+            .max(used)
+    }
+
+    // Values are reference counted, so this is cheap however large the value is
+    pub fn get(&self, db: usize, key: &str) -> Result<Option<Bytes>, WrongType> {
         println!("getting value for {key:?}");
-        let mut lock = self.inner.lock().ok()?;
 
-        match lock.get(key) {
-            Some(entry)
-                if entry.expires.is_some() && entry.expires.map(|ttl| ttl <= Instant::now())? =>
-            {
-                println!("removing value as expired...");
-                lock.remove(key);
+        self.read(db, [key])
+            .get::<Bytes>(key)
+            .map(|value| value.cloned())
+    }
 
-                None
+    pub fn set(&self, db: usize, key: String, value: Bytes, ttl: Option<Duration>) {
+        println!("setting value for {key:?}");
+
+        let mut keyspace = self.write(db, [key.as_str()]);
+        keyspace.insert(&key, value);
+        if let Some(ttl) = ttl {
+            let ttl = u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX);
+            keyspace.set_expires(&key, Some(now().saturating_add(ttl)));
+        }
+    }
+
+    // Locks the shards holding `keys` in database `db` for reading, so commands touching several
+    // keys see them at the same point in time
+    pub fn read<'k>(&self, db: usize, keys: impl IntoIterator<Item = &'k str>) -> ReadKeyspace<'_> {
+        let shards = keys.into_iter().map(|key| self.shard(key)).collect();
+        self.lock(db, shards, |shard| {
+            shard.read().unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+
+    // Locks the shards holding `keys` in database `db` for writing, so commands touching several
+    // keys, or reading and then updating a value, happen atomically
+    pub fn write<'k>(
+        &self,
+        db: usize,
+        keys: impl IntoIterator<Item = &'k str>,
+    ) -> WriteKeyspace<'_> {
+        let shards = keys.into_iter().map(|key| self.shard(key)).collect();
+        self.lock(db, shards, |shard| {
+            shard.write().unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+
+    // Locks every shard for reading, for commands looking at the whole of database `db`
+    pub fn read_all(&self, db: usize) -> ReadKeyspace<'_> {
+        self.lock(db, (0..self.shards.len()).collect(), |shard| {
+            shard.read().unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+
+    // Every key in every database, copied while holding a read lock on each shard at the same time,
+    // so that writes are only blocked while copying rather than while the copy is saved. Strings
+    // are reference counted, so only collections are copied in full.
+    pub fn snapshot(&self) -> Snapshot {
+        let shards = self
+            .shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner))
+            .collect::<Vec<_>>();
+
+        let now = now();
+        (0..self.databases)
+            .map(|db| {
+                shards
+                    .iter()
+                    .flat_map(|dbs| {
+                        let db = &dbs[db];
+                        db.values.iter().filter_map(move |(key, entry)| {
+                            let expires = db.expires.get(key).copied();
+                            if expires.is_some_and(|deadline| deadline <= now) {
+                                return None;
+                            }
+                            Some((key.to_string(), entry.value.clone(), expires))
+                        })
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The number of changes to keys since the last save
+    pub fn dirty(&self) -> u64 {
+        self.dirty.load(Ordering::Relaxed)
+    }
+
+    fn changed(&self, changes: u64) {
+        self.dirty.fetch_add(changes, Ordering::Relaxed);
+    }
+
+    // Called once a snapshot taken when there were `dirty` changes is saved, leaving any changes
+    // made since then
+    pub fn saved(&self, dirty: u64) {
+        self.dirty.fetch_sub(dirty, Ordering::Relaxed);
+    }
+
+    // Shards are always locked in ascending order, so that commands locking several of them at
+    // the same time cannot deadlock
+    fn lock<'a, G>(
+        &'a self,
+        db: usize,
+        mut shards: Vec<usize>,
+        lock: impl Fn(&'a RwLock<Vec<Db>>) -> G,
+    ) -> Keyspace<'a, G> {
+        assert!(db < self.databases, "database {db} should exist");
+        shards.sort_unstable();
+        shards.dedup();
+
+        let shards = shards
+            .into_iter()
+            .map(|shard| (shard, lock(&self.shards[shard])))
+            .collect();
+
+        Keyspace {
+            store: self,
+            db,
+            shards,
+        }
+    }
+
+    // Every shard locked for writing, in ascending order, for changes to whole databases
+    fn write_every_shard(&self) -> Vec<RwLockWriteGuard<'_, Vec<Db>>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.write().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+
+    // A shard and a database within it, as a single index so they can be taken turns at
+    fn slot(&self, slot: usize) -> (usize, usize) {
+        (slot % self.shards.len(), slot / self.shards.len())
+    }
+
+    fn slots(&self) -> usize {
+        self.shards.len() * self.databases
+    }
+
+    // FNV-1a, which is much cheaper than the SipHash used within each shard, and only needs to
+    // spread keys across a few shards
+    fn shard(&self, key: &str) -> usize {
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+
+        #[allow(clippy::cast_possible_truncation)]
+        let shard = (hash % self.shards.len() as u64) as usize;
+        shard
+    }
+
+    // Drops a value removed from the keyspace, off the request path when it is large enough
+    pub fn free(&self, value: Value) {
+        if value.allocations() > LAZYFREE_THRESHOLD {
+            self.lazyfree.execute(move || drop(value));
+        }
+    }
+
+    // Visits part of database `db`, returning the cursor to continue from. The cursor is made up of
+    // a shard, in the lowest part, and the cursor within that shard, as per `Dict::scan`. Only one
+    // shard is locked at a time.
+    pub fn scan(&self, db: usize, cursor: u64, visit: impl FnMut(&str, &Value)) -> u64 {
+        let shards = self.shards.len() as u64;
+        let (shard, cursor) = (cursor % shards, cursor / shards);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let dbs = self.shards[shard as usize]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        match dbs[db].scan(cursor, visit) {
+            0 if shard + 1 == shards => 0,
+            0 => shard + 1,
+            cursor => cursor * shards + shard,
+        }
+    }
+
+    // Removes expired keys which are never accessed again, sampling keys with a deadline in each
+    // shard while many of them turn out to be stale. Only one shard is locked at a time, and only
+    // for each loop, so that clients are not blocked for the whole cycle.
+    // See: https://github.com/redis/redis/blob/unstable/src/expire.c (`activeExpireCycle`)
+    pub fn active_expire_cycle(&self, time_limit: Duration) {
+        let start = Instant::now();
+        let (mut total_sampled, mut total_expired) = (0, 0);
+        let mut timed_out = false;
+
+        let first = self.expire_slot.load(Ordering::Relaxed);
+        for slot in (first..self.slots()).chain(0..first) {
+            self.expire_slot
+                .store((slot + 1) % self.slots(), Ordering::Relaxed);
+            let (shard, db) = self.slot(slot);
+
+            loop {
+                let mut dbs = self.shards[shard]
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let Some((sampled, expired)) = dbs[db].expire_sample(ACTIVE_EXPIRE_KEYS_PER_LOOP)
+                else {
+                    break;
+                };
+                drop(dbs);
+                total_sampled += sampled;
+                total_expired += expired;
+
+                if start.elapsed() > time_limit {
+                    timed_out = true;
+                    break;
+                }
+                if sampled > 0 && expired * 100 / sampled <= ACTIVE_EXPIRE_ACCEPTABLE_STALE {
+                    break;
+                }
             }
-            Some(entry) => Some(entry.inner.clone()),
-            None => None,
+
+            if timed_out {
+                break;
+            }
+        }
+
+        // A running average, so the stat reflects recent cycles rather than just the last one
+        #[allow(clippy::cast_precision_loss)]
+        let stale = if total_sampled > 0 {
+            total_expired as f64 / total_sampled as f64
+        } else {
+            0.0
+        };
+        let mut stats = self
+            .expire_stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        stats.stale_perc = stale.mul_add(0.05, stats.stale_perc * 0.95);
+        stats.time_cap_reached_count += u64::from(timed_out);
+    }
+
+    // Whether more memory is used than the limit, if any, and so keys are to be evicted
+    pub fn is_over_max_memory(&self) -> bool {
+        let limit = self.maxmemory.load(Ordering::Relaxed);
+        limit != 0 && self.used_memory() > limit
+    }
+
+    // Evicts keys, as per the policy, until the memory used is back under the limit, passing each
+    // one evicted, along with its database, to `evicted`. Fails when the memory used is over the
+    // limit and no key can be evicted, in which case commands which may use more memory are
+    // refused.
+    // See: https://github.com/redis/redis/blob/unstable/src/evict.c (`performEvictions`)
+    pub fn evict(&self, mut evicted: impl FnMut(usize, &str)) -> Result<(), OutOfMemory> {
+        let max_memory = self.max_memory();
+        if max_memory.limit == 0 {
+            return Ok(());
+        }
+
+        while self.used_memory() > max_memory.limit {
+            let key = match max_memory.policy {
+                Policy::NoEviction => None,
+                policy if policy.is_random() => self.random_evictable_key(policy),
+                _ => self.pooled_key(max_memory),
+            };
+            let Some((shard, db, key)) = key else {
+                return Err(OutOfMemory);
+            };
+
+            let mut dbs = self.shards[shard]
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            if let Some((entry, _)) = dbs[db].remove(&key) {
+                drop(dbs);
+                println!("evicted {key:?}");
+                self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+                self.free(entry.value);
+                evicted(db, &key);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Like Redis, shards and databases take turns to have a key evicted
+    fn random_evictable_key(&self, policy: Policy) -> Option<(usize, usize, String)> {
+        for _ in 0..self.slots() {
+            let slot = self.evict_slot.fetch_add(1, Ordering::Relaxed) % self.slots();
+            let (shard, db_index) = self.slot(slot);
+            let dbs = self.shards[shard]
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            let db = &dbs[db_index];
+
+            let key = if policy.is_volatile() {
+                db.expires
+                    .sample(evict::random(), 1)
+                    .next()
+                    .map(|(key, _)| key)
+            } else {
+                db.values
+                    .sample(evict::random(), 1)
+                    .next()
+                    .map(|(key, _)| key)
+            };
+            if let Some(key) = key {
+                return Some((shard, db_index, key.to_string()));
+            }
+        }
+
+        None
+    }
+
+    // Samples keys from every shard into the pool, and takes the best one to evict which still
+    // exists, sampling again if none do
+    fn pooled_key(&self, max_memory: MaxMemory) -> Option<(usize, usize, String)> {
+        let mut pool = self
+            .eviction_pool
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        loop {
+            let mut evictable = 0;
+            for (shard, dbs) in self.shards.iter().enumerate() {
+                let dbs = dbs.read().unwrap_or_else(PoisonError::into_inner);
+                for (db_index, db) in dbs.iter().enumerate() {
+                    evictable += db.sample_evictable(max_memory, |idle, key| {
+                        pool.insert(idle, shard, db_index, key);
+                    });
+                }
+            }
+            if evictable == 0 {
+                return None;
+            }
+
+            while let Some((shard, db_index, key)) = pool.pop() {
+                let dbs = self.shards[shard]
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner);
+                let db = &dbs[db_index];
+                let exists = if max_memory.policy.is_volatile() {
+                    db.expires.get(&key).is_some()
+                } else {
+                    db.values.get(&key).is_some()
+                };
+                if exists {
+                    return Some((shard, db_index, key));
+                }
+            }
+        }
+    }
+
+    // A random key from database `db`, starting from a random shard, or `None` when it is empty
+    pub fn random_key(&self, db: usize) -> Option<String> {
+        #[allow(clippy::cast_possible_truncation)]
+        let first = evict::random() as usize % self.shards.len();
+        for shard in (first..self.shards.len()).chain(0..first) {
+            let dbs = self.shards[shard]
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            let db = &dbs[db];
+            let key = db
+                .values
+                .sample(evict::random(), db.values.len())
+                .find(|(key, _)| !db.is_expired(key));
+            if let Some((key, _)) = key {
+                return Some(key.to_string());
+            }
+        }
+
+        None
+    }
+
+    // Exchanges the contents of two databases, which every client sees happen at once
+    pub fn swap_dbs(&self, a: usize, b: usize) {
+        for mut dbs in self.write_every_shard() {
+            dbs.swap(a, b);
         }
     }
 
-    pub fn set(&self, key: String, value: Vec<u8>, ttl: Option<Duration>) {
-        if let Ok(value) = str::from_utf8(value.as_slice()) {
-            println!("setting '{value}' for '{key}'");
+    // Removes every key from database `db`, or from all of them for `None`. When `lazy`, the keys
+    // are dropped in the background, with the memory they used accounted for once they are.
+    pub fn flush(&self, db: Option<usize>, lazy: bool) {
+        let mut flushed = Vec::new();
+        for mut dbs in self.write_every_shard() {
+            for (index, db_in_shard) in dbs.iter_mut().enumerate() {
+                if db.is_none_or(|db| db == index) {
+                    flushed.push(db_in_shard.clear());
+                }
+            }
+        }
+        self.changed(flushed.iter().map(|db| db.values.len() as u64).sum());
+
+        let used_memory = Arc::clone(&self.used_memory);
+        let free = move || {
+            let memory = flushed.iter().map(Flushed::memory).sum::<usize>();
+            drop(flushed);
+            used_memory.fetch_sub(memory, Ordering::Relaxed);
+        };
+        if lazy {
+            self.lazyfree.execute(free);
         } else {
-            println!("setting binary value for '{key}'");
+            free();
+        }
+    }
+
+    pub fn info(&self) -> Info {
+        let mut keyspace = vec![KeyspaceInfo::default(); self.databases];
+        let mut ttl_sums = vec![0; self.databases];
+        let mut expired_keys = 0;
+        for dbs in &self.shards {
+            let dbs = dbs.read().unwrap_or_else(PoisonError::into_inner);
+            for (index, db) in dbs.iter().enumerate() {
+                keyspace[index].keys += db.values.len();
+                keyspace[index].expires += db.expires.len();
+                ttl_sums[index] += db.average_ttl * db.expires.len() as u64;
+                expired_keys += db.expired_keys;
+            }
+        }
+        for (info, ttl_sum) in keyspace.iter_mut().zip(ttl_sums) {
+            info.average_ttl = ttl_sum.checked_div(info.expires as u64).unwrap_or(0);
+        }
+
+        let expire_stats = *self
+            .expire_stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        Info {
+            keyspace,
+            used_memory: self.used_memory(),
+            peak_memory: self.peak_memory(),
+            max_memory: self.max_memory(),
+            stats: Stats {
+                expired_keys,
+                expired_stale_perc: expire_stats.stale_perc,
+                expired_time_cap_reached_count: expire_stats.time_cap_reached_count,
+                evicted_keys: self.evicted_keys.load(Ordering::Relaxed),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ExpireStats {
+    stale_perc: f64,
+    time_cap_reached_count: u64,
+}
+
+// Counters reported by INFO
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub expired_keys: u64,
+    pub expired_stale_perc: f64,
+    pub expired_time_cap_reached_count: u64,
+    pub evicted_keys: u64,
+}
+
+pub struct Info {
+    // For each database
+    pub keyspace: Vec<KeyspaceInfo>,
+    pub used_memory: usize,
+    pub peak_memory: usize,
+    pub max_memory: MaxMemory,
+    pub stats: Stats,
+}
+
+impl Info {
+    pub fn keys(&self) -> usize {
+        self.keyspace.iter().map(|db| db.keys).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyspaceInfo {
+    pub keys: usize,
+    pub expires: usize,
+    pub average_ttl: u64,
+}
+
+impl KeyspaceInfo {
+    // The memory used by the keyspace itself, and by the index of deadlines, rather than the keys
+    // and values
+    pub const fn overhead(&self) -> (usize, usize) {
+        (self.keys * ENTRY_OVERHEAD, self.expires * EXPIRES_OVERHEAD)
+    }
+}
+
+// A shard of one database, with the deadlines of the keys which expire held in a separate index,
+// so that the active expiry cycle only needs to look at keys which can expire
+pub struct Db {
+    values: Dict<Entry>,
+    expires: Dict<u64>,
+    expires_cursor: u64,
+    // An estimate, in milliseconds, from the keys sampled by the active expiry cycle
+    average_ttl: u64,
+    expired_keys: u64,
+    // Shared by every shard of the store
+    used_memory: Arc<AtomicUsize>,
+    // The keys in each hash slot, only tracked in cluster mode
+    slots: Option<HashMap<u16, HashSet<String>>>,
+}
+
+impl Db {
+    fn new(used_memory: Arc<AtomicUsize>) -> Self {
+        Self {
+            values: Dict::new(),
+            expires: Dict::new(),
+            expires_cursor: 0,
+            average_ttl: 0,
+            expired_keys: 0,
+            used_memory,
+            slots: None,
+        }
+    }
+
+    // Takes every key, along with their deadlines
+    fn clear(&mut self) -> Flushed {
+        self.expires_cursor = 0;
+        self.average_ttl = 0;
+        if let Some(slots) = &mut self.slots {
+            slots.clear();
+        }
+        Flushed {
+            values: mem::take(&mut self.values),
+            expires: mem::take(&mut self.expires),
+        }
+    }
+
+    // Expired values are treated as missing, but only removed when next written
+    fn entry(&self, key: &str) -> Option<&Entry> {
+        if self.is_expired(key) {
+            return None;
+        }
+
+        self.values.get(key)
+    }
+
+    fn entry_mut(&mut self, key: &str) -> Option<&mut Entry> {
+        self.remove_if_expired(key);
+
+        self.values.get_mut(key)
+    }
+
+    // The deadline for the key, if it exists
+    fn expires(&self, key: &str) -> Option<Option<u64>> {
+        self.entry(key)?;
+
+        Some(self.expires.get(key).copied())
+    }
+
+    // Replaces any existing value, along with its deadline
+    fn insert(&mut self, key: &str, entry: Entry, expires: Option<u64>) {
+        self.used_memory
+            .fetch_add(entry_memory(key, &entry.value), Ordering::Relaxed);
+        if let Some(previous) = self.values.insert(key.to_string(), entry) {
+            self.used_memory
+                .fetch_sub(entry_memory(key, &previous.value), Ordering::Relaxed);
+        } else {
+            self.track(key);
+        }
+        self.set_expires(key, expires);
+    }
+
+    fn track(&mut self, key: &str) {
+        if let Some(slots) = &mut self.slots {
+            let slot = cluster::key_slot(key);
+            slots.entry(slot).or_default().insert(key.to_string());
+        }
+    }
+
+    fn untrack(&mut self, key: &str) {
+        let Some(slots) = &mut self.slots else {
+            return;
+        };
+        let slot = cluster::key_slot(key);
+        if let Some(keys) = slots.get_mut(&slot) {
+            keys.remove(key);
+            if keys.is_empty() {
+                slots.remove(&slot);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<(Entry, Option<u64>)> {
+        self.remove_if_expired(key);
+
+        let entry = self.values.remove(key)?;
+        self.used_memory
+            .fetch_sub(entry_memory(key, &entry.value), Ordering::Relaxed);
+        self.untrack(key);
+        Some((entry, self.set_expires(key, None)))
+    }
+
+    // Sets or clears the deadline for a key, returning the previous one
+    fn set_expires(&mut self, key: &str, expires: Option<u64>) -> Option<u64> {
+        let previous = match expires {
+            Some(expires) => self.expires.insert(key.to_string(), expires),
+            None => self.expires.remove(key),
+        };
+
+        let memory = EXPIRES_OVERHEAD + key.len();
+        match (previous, expires) {
+            (None, Some(_)) => self.used_memory.fetch_add(memory, Ordering::Relaxed),
+            (Some(_), None) => self.used_memory.fetch_sub(memory, Ordering::Relaxed),
+            _ => 0,
+        };
+        previous
+    }
+
+    fn scan(&self, cursor: u64, mut visit: impl FnMut(&str, &Value)) -> u64 {
+        self.values.scan(cursor, |key, entry| {
+            if !self.is_expired(key) {
+                visit(key, &entry.value);
+            }
+        })
+    }
+
+    fn is_expired(&self, key: &str) -> bool {
+        self.expires
+            .get(key)
+            .is_some_and(|deadline| *deadline <= now())
+    }
+
+    fn remove_if_expired(&mut self, key: &str) {
+        if self.is_expired(key) {
+            println!("removing value as expired...");
+            self.set_expires(key, None);
+            if let Some(entry) = self.values.remove(key) {
+                self.used_memory
+                    .fetch_sub(entry_memory(key, &entry.value), Ordering::Relaxed);
+                self.untrack(key);
+            }
+            self.expired_keys += 1;
+        }
+    }
+    // Continues scanning the keys with a deadline for up to `count` of them, removing those which
+    // have expired, and returning how many were sampled and expired. Returns `None` when no keys
+    // have a deadline.
+    fn expire_sample(&mut self, count: usize) -> Option<(usize, usize)> {
+        let count = count.min(self.expires.len());
+        if count == 0 {
+            return None;
+        }
+
+        // Like Redis, give up after visiting this many empty buckets
+        let max_buckets = count * 20;
+        let now = now();
+        let (mut sampled, mut expired) = (0, Vec::new());
+        let (mut ttl_sum, mut ttl_samples) = (0, 0);
+        let mut cursor = self.expires_cursor;
+        for _ in 0..max_buckets {
+            cursor = self.expires.scan(cursor, |key, deadline| {
+                sampled += 1;
+                if *deadline <= now {
+                    expired.push(key.to_string());
+                } else {
+                    ttl_sum += deadline - now;
+                    ttl_samples += 1;
+                }
+            });
+            if cursor == 0 || sampled >= count {
+                break;
+            }
+        }
+        self.expires_cursor = cursor;
+
+        // Like Redis, a moving average giving each sample about 2% of the weight
+        if let Some(average) = ttl_sum.checked_div(ttl_samples) {
+            let previous = self.average_ttl;
+            self.average_ttl = if previous == 0 {
+                average
+            } else {
+                (previous / 50) * 49 + average / 50
+            };
         }
 
-        if let Ok(mut lock) = self.inner.lock() {
-            let entry = Entry::new(value, ttl);
-            lock.insert(key, entry);
+        for key in &expired {
+            self.remove_if_expired(key);
+        }
+
+        Some((sampled, expired.len()))
+    }
+
+    // Samples keys which the policy allows to be evicted, visiting how strongly each should be,
+    // and returning how many such keys there are
+    fn sample_evictable(&self, max_memory: MaxMemory, mut visit: impl FnMut(u64, &str)) -> usize {
+        let policy = max_memory.policy;
+        if policy.is_volatile() {
+            for (key, deadline) in self.expires.sample(evict::random(), max_memory.samples) {
+                if let Some(entry) = self.values.get(key) {
+                    visit(evict::idle(policy, entry.access(), Some(*deadline)), key);
+                }
+            }
+            self.expires.len()
         } else {
-            eprintln!("Unable to acquire lock on Store");
+            for (key, entry) in self.values.sample(evict::random(), max_memory.samples) {
+                visit(evict::idle(policy, entry.access(), None), key);
+            }
+            self.values.len()
         }
     }
 }
 
+// The memory used by a key and its value, including the overheads of storing them
+fn entry_memory(key: &str, value: &Value) -> usize {
+    ENTRY_OVERHEAD + key.len() + value.memory()
+}
+
+// The keys taken from a shard of a database by FLUSHDB or FLUSHALL, to be dropped
+struct Flushed {
+    values: Dict<Entry>,
+    expires: Dict<u64>,
+}
+
+impl Flushed {
+    fn memory(&self) -> usize {
+        let values = self
+            .values
+            .iter()
+            .map(|(key, entry)| entry_memory(key, &entry.value));
+        let expires = self
+            .expires
+            .iter()
+            .map(|(key, _)| EXPIRES_OVERHEAD + key.len());
+        values.chain(expires).sum()
+    }
+}
+
+// A value, along with when it was last accessed for eviction, which is updated by reads too, so
+// while only holding a read lock
 struct Entry {
-    inner: Vec<u8>,
-    expires: Option<Instant>,
+    value: Value,
+    access: AtomicU64,
 }
 
 impl Entry {
-    fn new(value: Vec<u8>, ttl: Option<Duration>) -> Self {
-        let expires = ttl.map(|ttl| Instant::now() + ttl);
-
+    fn new(value: Value, policy: Policy) -> Self {
         Self {
-            inner: value,
-            expires,
+            value,
+            access: AtomicU64::new(evict::initial_access(policy)),
+        }
+    }
+
+    fn access(&self) -> u64 {
+        self.access.load(Ordering::Relaxed)
+    }
+
+    fn touch(&self, policy: Policy) {
+        self.access
+            .store(evict::access(policy, self.access()), Ordering::Relaxed);
+    }
+}
+
+// Restricts when EXPIRE and friends update the deadline of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpireCondition {
+    NoDeadline,
+    HasDeadline,
+    Later,
+    Earlier,
+}
+
+impl ExpireCondition {
+    // Keys without a deadline are treated as never expiring when comparing deadlines
+    pub fn allows(self, current: Option<u64>, deadline: i64) -> bool {
+        let current = current.map(|current| i64::try_from(current).unwrap_or(i64::MAX));
+        match self {
+            Self::NoDeadline => current.is_none(),
+            Self::HasDeadline => current.is_some(),
+            Self::Later => current.is_some_and(|current| deadline > current),
+            Self::Earlier => current.is_none_or(|current| deadline < current),
+        }
+    }
+}
+
+// Returned when accessing a key holding a different type of value than expected
+#[derive(Debug, PartialEq, Eq)]
+pub struct WrongType;
+
+// Returned when over the memory limit, and no keys could be evicted
+#[derive(Debug, PartialEq, Eq)]
+pub struct OutOfMemory;
+
+// The types of value which only come from RDB files, as there are no commands for them yet
+pub type List = VecDeque<Vec<u8>>;
+pub type Set = HashSet<Vec<u8>>;
+pub type Hash = HashMap<Vec<u8>, Vec<u8>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    List(List),
+    Set(Set),
+    SortedSet(SortedSet),
+    Hash(Hash),
+}
+
+// Like Redis, small collections are encoded compactly, with these being the defaults for settings
+// such as `hash-max-listpack-entries` and `hash-max-listpack-value`
+const LISTPACK_MAX_ENTRIES: usize = 128;
+const LISTPACK_MAX_VALUE: usize = 64;
+const LIST_LISTPACK_MAX_BYTES: usize = 8 * 1024;
+const INTSET_MAX_ENTRIES: usize = 512;
+
+// Roughly the memory used for each element of a list, set or hash besides the element itself
+const ELEMENT_OVERHEAD: usize = 32;
+
+// Whether the string is an integer as formatted by Redis, so could be encoded as one
+fn is_integer(value: &[u8]) -> bool {
+    value.len() <= 20
+        && str::from_utf8(value)
+            .is_ok_and(|value| value.parse::<i64>().is_ok_and(|n| n.to_string() == value))
+}
+
+// Whether a collection of `len` elements is small enough to be encoded as a listpack
+fn is_small<'a>(len: usize, mut values: impl Iterator<Item = &'a [u8]>) -> bool {
+    len <= LISTPACK_MAX_ENTRIES && values.all(|value| value.len() <= LISTPACK_MAX_VALUE)
+}
+
+// Estimates the memory used by a collection from the first `samples` elements, or all of them for
+// 0, given the size of each element
+fn sampled_memory(len: usize, sizes: impl Iterator<Item = usize>, samples: usize) -> usize {
+    if samples == 0 || samples >= len {
+        return sizes.map(|size| size + ELEMENT_OVERHEAD).sum();
+    }
+
+    let sampled = sizes
+        .take(samples)
+        .map(|size| size + ELEMENT_OVERHEAD)
+        .sum::<usize>();
+    sampled * len / samples
+}
+
+impl Value {
+    // As reported by TYPE
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "string",
+            Self::List(_) => "list",
+            Self::Set(_) => "set",
+            Self::SortedSet(_) => "zset",
+            Self::Hash(_) => "hash",
+        }
+    }
+
+    // As reported by OBJECT ENCODING, which for Redis depends on the size of the value
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Self::String(value) => {
+                if is_integer(value) {
+                    "int"
+                } else if value.len() <= 44 {
+                    "embstr"
+                } else {
+                    "raw"
+                }
+            }
+            Self::List(list) => {
+                if list.iter().map(Vec::len).sum::<usize>() <= LIST_LISTPACK_MAX_BYTES {
+                    "listpack"
+                } else {
+                    "quicklist"
+                }
+            }
+            Self::Set(set) => {
+                if set.len() <= INTSET_MAX_ENTRIES && set.iter().all(|member| is_integer(member)) {
+                    "intset"
+                } else if is_small(set.len(), set.iter().map(Vec::as_slice)) {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+            Self::SortedSet(set) => {
+                let members = set
+                    .range(f64::NEG_INFINITY, f64::INFINITY)
+                    .map(|(member, _)| member);
+                if is_small(set.len(), members) {
+                    "listpack"
+                } else {
+                    "skiplist"
+                }
+            }
+            Self::Hash(hash) => {
+                let values = hash
+                    .iter()
+                    .flat_map(|(field, value)| [field.as_slice(), value.as_slice()]);
+                if is_small(hash.len(), values) {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+        }
+    }
+
+    // An estimate, in bytes
+    fn memory(&self) -> usize {
+        self.sampled_memory(0)
+    }
+
+    fn sampled_memory(&self, samples: usize) -> usize {
+        match self {
+            Self::String(value) => value.len(),
+            Self::List(list) => sampled_memory(list.len(), list.iter().map(Vec::len), samples),
+            Self::Set(set) => sampled_memory(set.len(), set.iter().map(Vec::len), samples),
+            Self::SortedSet(set) => set.sampled_memory(samples),
+            Self::Hash(hash) => sampled_memory(
+                hash.len(),
+                hash.iter().map(|(field, value)| field.len() + value.len()),
+                samples,
+            ),
+        }
+    }
+
+    // Roughly how much work dropping the value is
+    fn allocations(&self) -> usize {
+        match self {
+            Self::String(_) => 1,
+            Self::List(list) => list.len(),
+            Self::Set(set) => set.len(),
+            Self::SortedSet(set) => set.len(),
+            Self::Hash(hash) => hash.len(),
+        }
+    }
+}
+
+// Implemented by the types held by each `Value` variant, to allow type checked access
+pub trait ValueType: Sized {
+    fn from_value(value: &Value) -> Option<&Self>;
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self>;
+    fn into_value(self) -> Value;
+}
+
+macro_rules! value_type {
+    ($variant:ident, $type:ty) => {
+        impl ValueType for $type {
+            fn from_value(value: &Value) -> Option<&Self> {
+                match value {
+                    Value::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+
+            fn from_value_mut(value: &mut Value) -> Option<&mut Self> {
+                match value {
+                    Value::$variant(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+
+            fn into_value(self) -> Value {
+                Value::$variant(self)
+            }
+        }
+    };
+}
+
+// Allows moving values between keys without caring about their type
+impl ValueType for Value {
+    fn from_value(value: &Value) -> Option<&Self> {
+        Some(value)
+    }
+
+    fn from_value_mut(value: &mut Value) -> Option<&mut Self> {
+        Some(value)
+    }
+
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+value_type!(String, Bytes);
+value_type!(List, List);
+value_type!(Set, Set);
+value_type!(SortedSet, SortedSet);
+value_type!(Hash, Hash);
+
+pub type ReadKeyspace<'a> = Keyspace<'a, RwLockReadGuard<'a, Vec<Db>>>;
+pub type WriteKeyspace<'a> = Keyspace<'a, RwLockWriteGuard<'a, Vec<Db>>>;
+
+// The locked shards of one database, for some keys or all of them. Accessing a key whose shard was
+// not locked is a bug, and panics.
+pub struct Keyspace<'a, G> {
+    store: &'a Store,
+    db: usize,
+    // Sorted by shard, and usually only one or two of them
+    shards: Vec<(usize, G)>,
+}
+
+impl<G: Deref<Target = Vec<Db>>> Keyspace<'_, G> {
+    fn db(&self, key: &str) -> &Db {
+        let shard = self.store.shard(key);
+        self.shards
+            .iter()
+            .find_map(|(index, dbs)| (*index == shard).then_some(&dbs[self.db]))
+            .expect("shard for key should be locked")
+    }
+
+    // The number of keys in the locked shards, including any which have expired but not yet been
+    // removed, as per DBSIZE
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|(_, dbs)| dbs[self.db].values.len())
+            .sum()
+    }
+
+    // Unlike the other accessors, checking a key exists does not count as accessing it
+    pub fn contains(&self, key: &str) -> bool {
+        self.peek(key).is_some()
+    }
+
+    pub fn peek(&self, key: &str) -> Option<&Value> {
+        self.db(key).entry(key).map(|entry| &entry.value)
+    }
+
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        let entry = self.db(key).entry(key)?;
+        entry.touch(self.store.policy());
+
+        Some(&entry.value)
+    }
+
+    pub fn get<T: ValueType>(&self, key: &str) -> Result<Option<&T>, WrongType> {
+        self.value(key).map_or(Ok(None), |value| {
+            T::from_value(value).map(Some).ok_or(WrongType)
+        })
+    }
+
+    // The keys in the locked shards
+    pub fn keys(&self) -> impl Iterator<Item = (&str, &Value)> {
+        let now = now();
+        self.shards.iter().flat_map(move |(_, dbs)| {
+            let db = &dbs[self.db];
+            db.values
+                .iter()
+                .filter(move |(key, _)| db.expires.get(key).is_none_or(|deadline| *deadline > now))
+                .map(|(key, entry)| (key, &entry.value))
+        })
+    }
+
+    // The keys in a hash slot, when tracked for cluster mode
+    pub fn keys_in_slot(&self, slot: u16) -> impl Iterator<Item = &str> {
+        self.shards.iter().flat_map(move |(_, dbs)| {
+            let db = &dbs[self.db];
+            db.slots
+                .iter()
+                .filter_map(move |slots| slots.get(&slot))
+                .flatten()
+                .filter(|key| !db.is_expired(key))
+                .map(String::as_str)
+        })
+    }
+
+    // The deadline for the key, if it exists, in milliseconds since the Unix epoch
+    pub fn expires(&self, key: &str) -> Option<Option<u64>> {
+        self.db(key).expires(key)
+    }
+
+    // When the key was last accessed, or its LFU counter, as per `evict::access`
+    pub fn access(&self, key: &str) -> Option<u64> {
+        self.db(key).entry(key).map(Entry::access)
+    }
+
+    // The memory used by the key and its value, estimated from `samples` elements of the value, or
+    // all of them for 0
+    pub fn memory_usage(&self, key: &str, samples: usize) -> Option<usize> {
+        let entry = self.db(key).entry(key)?;
+        let expires = self
+            .db(key)
+            .expires
+            .get(key)
+            .map_or(0, |_| EXPIRES_OVERHEAD + key.len());
+
+        Some(ENTRY_OVERHEAD + key.len() + entry.value.sampled_memory(samples) + expires)
+    }
+}
+
+impl<G: DerefMut<Target = Vec<Db>>> Keyspace<'_, G> {
+    fn db_mut(&mut self, key: &str) -> &mut Db {
+        let db = self.db;
+        &mut self.dbs_mut(key)[db]
+    }
+
+    // The shard holding the key, in every database
+    fn dbs_mut(&mut self, key: &str) -> &mut Vec<Db> {
+        let shard = self.store.shard(key);
+        self.shards
+            .iter_mut()
+            .find_map(|(index, dbs)| (*index == shard).then_some(&mut **dbs))
+            .expect("shard for key should be locked")
+    }
+
+    #[cfg(test)]
+    pub fn get_mut<T: ValueType>(
+        &mut self,
+        key: &str,
+    ) -> Result<Option<ValueMut<'_, T>>, WrongType> {
+        let store = self.store;
+        let db = self.db_mut(key);
+        db.remove_if_expired(key);
+
+        let Some(entry) = db.values.get_mut(key) else {
+            return Ok(None);
+        };
+        entry.touch(store.policy());
+        let value = ValueMut::new(&mut entry.value, &db.used_memory)?;
+        store.changed(1);
+        Ok(Some(value))
+    }
+
+    pub fn get_or_insert_with<T: ValueType>(
+        &mut self,
+        key: &str,
+        default: impl FnOnce() -> T,
+    ) -> Result<ValueMut<'_, T>, WrongType> {
+        let store = self.store;
+        let policy = store.policy();
+        let db = self.db_mut(key);
+        db.remove_if_expired(key);
+        if db.values.get(key).is_none() {
+            db.track(key);
+        }
+
+        let mut inserted = false;
+        let entry = db.values.get_or_insert_with(key, || {
+            inserted = true;
+            Entry::new(default().into_value(), policy)
+        });
+        if inserted {
+            db.used_memory
+                .fetch_add(entry_memory(key, &entry.value), Ordering::Relaxed);
+        } else {
+            entry.touch(policy);
+        }
+
+        let value = ValueMut::new(&mut entry.value, &db.used_memory)?;
+        store.changed(1);
+        Ok(value)
+    }
+
+    // Strings are shared with any replies still being written, so are taken out of the keyspace to
+    // be updated, which only copies them if they are still shared, and put back once done with
+    #[cfg(test)]
+    pub fn get_string_mut(&mut self, key: &str) -> Result<Option<StringMut<'_>>, WrongType> {
+        Ok(self.get_mut::<Bytes>(key)?.map(StringMut::new))
+    }
+
+    // Replaces a string of the same length without counting it as a change, for caches kept
+    // within values, such as the cardinality of a HyperLogLog
+    pub fn cache_string(&mut self, key: &str, value: Bytes) {
+        let entry = self.db_mut(key).values.get_mut(key);
+        if let Some(Value::String(string)) = entry.map(|entry| &mut entry.value) {
+            debug_assert_eq!(string.len(), value.len());
+            *string = value;
+        }
+    }
+
+    pub fn get_or_insert_string_with(
+        &mut self,
+        key: &str,
+        default: impl FnOnce() -> Vec<u8>,
+    ) -> Result<StringMut<'_>, WrongType> {
+        let value = self.get_or_insert_with(key, || Bytes::from(default()))?;
+        Ok(StringMut::new(value))
+    }
+
+    // Replaces any existing value, along with its deadline
+    pub fn insert(&mut self, key: &str, value: impl ValueType) {
+        let entry = Entry::new(value.into_value(), self.store.policy());
+        self.db_mut(key).insert(key, entry, None);
+        self.store.changed(1);
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let (entry, _) = self.db_mut(key).remove(key)?;
+        self.store.changed(1);
+        Some(entry.value)
+    }
+
+    // Returns whether the key exists
+    pub fn set_expires(&mut self, key: &str, expires: Option<u64>) -> bool {
+        let db = self.db_mut(key);
+        if db.entry_mut(key).is_none() {
+            return false;
+        }
+
+        db.set_expires(key, expires);
+        self.store.changed(1);
+        true
+    }
+
+    // Overrides when the key was last accessed, or its LFU counter, as per `evict::access`
+    pub fn set_access(&mut self, key: &str, access: u64) {
+        if let Some(entry) = self.db_mut(key).entry_mut(key) {
+            entry.access.store(access, Ordering::Relaxed);
+        }
+    }
+
+    // Moves the value and any expiry from one key to another, replacing the latter
+    pub fn rename(&mut self, from: &str, to: &str) -> bool {
+        let Some((entry, expires)) = self.db_mut(from).remove(from) else {
+            return false;
+        };
+        self.db_mut(to).insert(to, entry, expires);
+        self.store.changed(2);
+
+        true
+    }
+
+    // Moves the key, along with its expiry, to database `to`, unless it already exists there.
+    // Returns whether the key was moved.
+    pub fn move_to(&mut self, key: &str, to: usize) -> bool {
+        let from = self.db;
+        let dbs = self.dbs_mut(key);
+        if dbs[to].entry_mut(key).is_some() {
+            return false;
+        }
+        let Some((entry, expires)) = dbs[from].remove(key) else {
+            return false;
+        };
+        dbs[to].insert(key, entry, expires);
+        self.store.changed(1);
+
+        true
+    }
+
+    // Copies the value and any expiry from one key to another, replacing the latter
+    pub fn copy(&mut self, from: &str, to: &str) -> bool {
+        let policy = self.store.policy();
+        let db = self.db_mut(from);
+        let Some(value) = db.entry_mut(from).map(|entry| entry.value.clone()) else {
+            return false;
+        };
+        let expires = db.expires.get(from).copied();
+        self.db_mut(to)
+            .insert(to, Entry::new(value, policy), expires);
+        self.store.changed(1);
+
+        true
+    }
+}
+
+// Keys held against writes while MIGRATE sends them, which are released once dropped
+pub struct Migrating<'a> {
+    store: &'a Store,
+    keys: HashSet<(usize, String)>,
+}
+
+impl Drop for Migrating<'_> {
+    fn drop(&mut self) {
+        let mut migrating = self
+            .store
+            .migrating
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        migrating.retain(|key| !self.keys.contains(key));
+    }
+}
+
+// A value borrowed from the keyspace to be updated, which accounts for any change in the memory it
+// uses once dropped
+pub struct ValueMut<'a, T> {
+    value: &'a mut Value,
+    used_memory: &'a AtomicUsize,
+    memory: usize,
+    _type: PhantomData<T>,
+}
+
+impl<'a, T: ValueType> ValueMut<'a, T> {
+    fn new(value: &'a mut Value, used_memory: &'a AtomicUsize) -> Result<Self, WrongType> {
+        T::from_value(value).ok_or(WrongType)?;
+
+        let memory = value.memory();
+        Ok(Self {
+            value,
+            used_memory,
+            memory,
+            _type: PhantomData,
+        })
+    }
+}
+
+impl<T: ValueType> Deref for ValueMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        T::from_value(self.value).expect("type was checked when borrowed")
+    }
+}
+
+impl<T: ValueType> DerefMut for ValueMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        T::from_value_mut(self.value).expect("type was checked when borrowed")
+    }
+}
+
+impl<T> Drop for ValueMut<'_, T> {
+    fn drop(&mut self) {
+        let memory = self.value.memory();
+        if memory > self.memory {
+            self.used_memory
+                .fetch_add(memory - self.memory, Ordering::Relaxed);
+        } else {
+            self.used_memory
+                .fetch_sub(self.memory - memory, Ordering::Relaxed);
+        }
+    }
+}
+
+// A string taken out of the keyspace to be updated, which is put back when dropped
+pub struct StringMut<'a> {
+    slot: ValueMut<'a, Bytes>,
+    value: Vec<u8>,
+}
+
+impl<'a> StringMut<'a> {
+    fn new(mut slot: ValueMut<'a, Bytes>) -> Self {
+        let value = Vec::from(mem::take(&mut *slot));
+        Self { slot, value }
+    }
+}
+
+impl Deref for StringMut<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.value
+    }
+}
+
+impl DerefMut for StringMut<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.value
+    }
+}
+
+// The slot is dropped afterwards, accounting for the memory used by the updated string
+impl Drop for StringMut<'_> {
+    fn drop(&mut self) {
+        *self.slot = Bytes::from(mem::take(&mut self.value));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{collections::HashSet, thread};
+
+    #[test]
+    fn rename_keeps_expiry() {
+        let store = Store::new();
+        store.set(
+            0,
+            "a".to_string(),
+            Bytes::from_static(b"1"),
+            Some(Duration::from_millis(10)),
+        );
+
+        let mut keyspace = store.write(0, ["a", "b"]);
+        assert!(keyspace.rename("a", "b"));
+        assert!(!keyspace.rename("a", "b"));
+        assert!(!keyspace.contains("a"));
+        assert_eq!(
+            keyspace.get::<Bytes>("b"),
+            Ok(Some(&Bytes::from_static(b"1")))
+        );
+        drop(keyspace);
+
+        thread::sleep(Duration::from_millis(20));
+        assert!(!store.read(0, ["b"]).contains("b"));
+    }
+
+    #[test]
+    fn set_expires() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), Bytes::from_static(b"1"), None);
+
+        let mut keyspace = store.write(0, ["a"]);
+        assert_eq!(keyspace.expires("a"), Some(None));
+        assert!(keyspace.set_expires("a", Some(now() + 10_000)));
+        assert!(keyspace.expires("a").flatten().is_some());
+        assert!(keyspace.set_expires("a", Some(now() - 1)));
+        assert_eq!(keyspace.expires("a"), None);
+        assert!(!keyspace.set_expires("a", None));
+    }
+
+    #[test]
+    fn active_expire_cycle() {
+        let store = Store::new();
+        for i in 0..1000 {
+            let ttl = (i % 2 == 0).then_some(Duration::from_millis(1));
+            store.set(0, i.to_string(), Bytes::from_static(b"1"), ttl);
+        }
+        thread::sleep(Duration::from_millis(5));
+
+        store.active_expire_cycle(Duration::from_secs(1));
+        let info = store.info();
+        assert_eq!(info.keys(), 500);
+        assert_eq!(info.keyspace[0].expires, 0);
+        assert_eq!(info.stats.expired_keys, 500);
+        assert!(info.stats.expired_stale_perc > 0.0);
+    }
+
+    #[test]
+    fn copy_is_independent() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), Bytes::from_static(b"1"), None);
+
+        let mut keyspace = store.write(0, ["a", "b", "c", "d"]);
+        assert!(keyspace.copy("a", "b"));
+        keyspace.get_string_mut("b").unwrap().unwrap().push(b'2');
+
+        assert_eq!(
+            keyspace.get::<Bytes>("a"),
+            Ok(Some(&Bytes::from_static(b"1")))
+        );
+        assert_eq!(
+            keyspace.get::<Bytes>("b"),
+            Ok(Some(&Bytes::from_static(b"12")))
+        );
+        assert!(!keyspace.copy("c", "d"));
+    }
+
+    #[test]
+    fn string_mut_leaves_shared_values() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), Bytes::from_static(b"1"), None);
+        let shared = store.get(0, "a").unwrap().unwrap();
+
+        store
+            .write(0, ["a"])
+            .get_or_insert_string_with("a", Vec::new)
+            .unwrap()
+            .push(b'2');
+
+        assert_eq!(shared, Bytes::from_static(b"1"));
+        assert_eq!(store.get(0, "a"), Ok(Some(Bytes::from_static(b"12"))));
+    }
+
+    #[test]
+    fn used_memory() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), Bytes::from_static(b"1"), None);
+        assert_eq!(store.used_memory(), ENTRY_OVERHEAD + 2);
+
+        let mut keyspace = store.write(0, ["a", "b", "c"]);
+        keyspace
+            .get_string_mut("a")
+            .unwrap()
+            .unwrap()
+            .extend([0; 100]);
+        keyspace
+            .get_or_insert_with("b", SortedSet::new)
+            .unwrap()
+            .insert(b"m".to_vec(), 1.0);
+        keyspace.copy("a", "c");
+        assert!(store.used_memory() > 2 * (ENTRY_OVERHEAD + 102));
+
+        for key in ["a", "b", "c"] {
+            keyspace.remove(key);
+        }
+        assert_eq!(store.used_memory(), 0);
+    }
+
+    #[test]
+    fn encoding_and_memory_usage() {
+        for (value, encoding) in [
+            (&b"12"[..], "int"),
+            (b"-12", "int"),
+            (b"012", "embstr"),
+            (&[b'a'; 44], "embstr"),
+            (&[b'a'; 45], "raw"),
+        ] {
+            let value = Value::String(Bytes::copy_from_slice(value));
+            assert_eq!(value.encoding(), encoding);
+        }
+
+        let store = Store::new();
+        let mut keyspace = store.write(0, ["a", "b"]);
+        let mut set = keyspace.get_or_insert_with("a", SortedSet::new).unwrap();
+        for i in 0..128 {
+            set.insert(i.to_string().into_bytes(), 1.0);
+        }
+        drop(set);
+        assert_eq!(keyspace.peek("a").unwrap().encoding(), "listpack");
+        assert_eq!(keyspace.memory_usage("a", 0), Some(store.used_memory()));
+
+        keyspace.set_expires("a", Some(u64::MAX));
+        assert_eq!(keyspace.memory_usage("a", 0), Some(store.used_memory()));
+        keyspace
+            .get_mut::<SortedSet>("a")
+            .unwrap()
+            .unwrap()
+            .insert(vec![b'a'; 65], 1.0);
+        assert_eq!(keyspace.peek("a").unwrap().encoding(), "skiplist");
+        assert_eq!(keyspace.memory_usage("b", 0), None);
+    }
+
+    fn fill(store: &Store, keys: usize, ttl: impl Fn(usize) -> Option<Duration>) {
+        for i in 0..keys {
+            store.set(0, i.to_string(), Bytes::from(vec![0; 100]), ttl(i));
+        }
+    }
+
+    fn set_limit(store: &Store, policy: Policy, keys: usize) {
+        store.set_max_memory(MaxMemory {
+            limit: keys * (ENTRY_OVERHEAD + 102),
+            policy,
+            ..MaxMemory::default()
+        });
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let store = Store::new();
+        fill(&store, 100, |_| None);
+        thread::sleep(Duration::from_millis(10));
+        for i in 0..10 {
+            store.get(0, &i.to_string()).unwrap();
+        }
+
+        set_limit(&store, Policy::AllKeysLru, 50);
+        assert_eq!(store.evict(|_, _| {}), Ok(()));
+        assert!(store.used_memory() <= 50 * (ENTRY_OVERHEAD + 102));
+        assert_eq!(store.info().stats.evicted_keys, 50);
+        assert!((0..10).all(|i| store.read_all(0).contains(&i.to_string())));
+    }
+
+    #[test]
+    fn evict_least_frequently_used() {
+        let store = Store::new();
+        store.set_max_memory(MaxMemory {
+            policy: Policy::AllKeysLfu,
+            ..MaxMemory::default()
+        });
+        fill(&store, 100, |_| None);
+        for _ in 0..100 {
+            for i in 0..10 {
+                store.get(0, &i.to_string()).unwrap();
+            }
+        }
+
+        set_limit(&store, Policy::AllKeysLfu, 50);
+        assert_eq!(store.evict(|_, _| {}), Ok(()));
+        assert_eq!(store.info().keys(), 50);
+        assert!((0..10).all(|i| store.read_all(0).contains(&i.to_string())));
+    }
+
+    #[test]
+    fn evict_volatile() {
+        let store = Store::new();
+        fill(&store, 100, |i| {
+            (i % 2 == 0).then(|| Duration::from_secs(1000 + i as u64))
+        });
+
+        set_limit(&store, Policy::VolatileTtl, 75);
+        assert_eq!(store.evict(|_, _| {}), Ok(()));
+        let keyspace = store.read_all(0);
+        assert!((0..50).all(|i| keyspace.contains(&(i * 2 + 1).to_string())));
+        // The keys closest to expiring go first
+        assert!(!keyspace.contains("0") && keyspace.contains("98"));
+        drop(keyspace);
+
+        // Only keys with a deadline can be evicted
+        for policy in [Policy::VolatileRandom, Policy::VolatileLru] {
+            set_limit(&store, policy, 25);
+            assert_eq!(store.evict(|_, _| {}), Err(OutOfMemory));
+            assert_eq!(store.info().keyspace[0].expires, 0);
+            assert_eq!(store.info().keys(), 50);
+        }
+    }
+
+    #[test]
+    fn evict_random_and_noeviction() {
+        let store = Store::new();
+        fill(&store, 100, |_| None);
+
+        set_limit(&store, Policy::NoEviction, 50);
+        assert_eq!(store.evict(|_, _| {}), Err(OutOfMemory));
+        assert_eq!(store.info().keys(), 100);
+
+        set_limit(&store, Policy::AllKeysRandom, 50);
+        let mut evicted = Vec::new();
+        assert_eq!(
+            store.evict(|db, key| evicted.push((db, key.to_string()))),
+            Ok(())
+        );
+        assert_eq!(store.info().keys(), 50);
+        // Each key evicted is passed on, to be deleted on replicas too
+        assert_eq!(evicted.len(), 50);
+        let keyspace = store.read_all(0);
+        assert!(evicted
+            .iter()
+            .all(|(db, key)| *db == 0 && !keyspace.contains(key)));
+    }
+
+    #[test]
+    fn databases() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), Bytes::from_static(b"0"), None);
+        store.set(1, "a".to_string(), Bytes::from_static(b"1"), None);
+        store.set(1, "b".to_string(), Bytes::from_static(b"1"), None);
+        assert_eq!(store.get(2, "a"), Ok(None));
+        assert_eq!(store.read_all(1).len(), 2);
+
+        assert!(!store.write(0, ["a"]).move_to("a", 1));
+        assert!(store.write(1, ["b"]).move_to("b", 2));
+        assert_eq!(store.get(2, "b"), Ok(Some(Bytes::from_static(b"1"))));
+        assert_eq!(store.random_key(2), Some("b".to_string()));
+        assert_eq!(store.random_key(3), None);
+
+        store.swap_dbs(0, 1);
+        assert_eq!(store.get(0, "a"), Ok(Some(Bytes::from_static(b"1"))));
+        assert_eq!(store.get(1, "a"), Ok(Some(Bytes::from_static(b"0"))));
+
+        store.flush(Some(0), false);
+        assert_eq!(store.info().keys(), 2);
+        store.flush(None, false);
+        assert_eq!(store.info().keys(), 0);
+        assert_eq!(store.used_memory(), 0);
+    }
+
+    #[test]
+    fn dirty_and_snapshot() {
+        let store = Store::new();
+        store.set(0, "a".to_string(), Bytes::from_static(b"1"), None);
+        store.set(2, "b".to_string(), Bytes::from_static(b"2"), None);
+        let mut keyspace = store.write(2, ["b", "c"]);
+        keyspace.set_expires("b", Some(now() + 60_000));
+        keyspace.insert("c", Bytes::from_static(b"3"));
+        keyspace.set_expires("c", Some(1));
+        drop(keyspace);
+        assert_eq!(store.dirty(), 5);
+
+        let snapshot = store.snapshot();
+        assert_eq!(snapshot.len(), DEFAULT_DATABASES);
+        assert_eq!(
+            snapshot[0],
+            vec![(
+                "a".to_string(),
+                Value::String(Bytes::from_static(b"1")),
+                None
+            )]
+        );
+        assert_eq!(snapshot[2].len(), 1);
+        assert_eq!(snapshot[2][0].0, "b");
+
+        store.saved(4);
+        assert!(store.get(0, "missing").unwrap().is_none());
+        assert_eq!(store.dirty(), 1);
+        store.flush(None, false);
+        assert_eq!(store.dirty(), 4);
+    }
+
+    #[test]
+    fn wrong_type() {
+        let store = Store::new();
+        let mut keyspace = store.write(0, ["set"]);
+        keyspace.insert("set", SortedSet::new());
+
+        assert_eq!(keyspace.get::<Bytes>("set"), Err(WrongType));
+        assert_eq!(keyspace.value("set").map(Value::type_name), Some("zset"));
+    }
+
+    #[test]
+    #[should_panic = "shard for key should be locked"]
+    fn unlocked_shard() {
+        let store = Store::with_shards(2);
+        let other = (0..)
+            .map(|i| i.to_string())
+            .find(|key| store.shard(key) != store.shard("a"))
+            .unwrap();
+
+        let _ = store.read(0, ["a"]).contains(&other);
+    }
+
+    #[test]
+    fn scan_every_shard() {
+        let store = Store::new();
+        for i in 0..1000 {
+            store
+                .write(0, [i.to_string().as_str()])
+                .insert(&i.to_string(), Bytes::from_static(b"1"));
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            cursor = store.scan(0, cursor, |key, _| {
+                seen.insert(key.to_string());
+            });
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 1000);
+        assert_eq!(store.read_all(0).keys().count(), 1000);
+    }
+
+    #[test]
+    fn keys_in_slot() {
+        let mut store = Store::new();
+        store.track_slots();
+        let slot = cluster::key_slot("{a}");
+        for key in ["{a}1", "{a}2", "{a}3", "b"] {
+            store.set(0, key.to_string(), Bytes::from_static(b"1"), None);
+        }
+        store
+            .write(0, ["{a}4"])
+            .get_or_insert_with("{a}4", SortedSet::new)
+            .unwrap();
+        store.write(0, ["{a}1", "{a}5"]).rename("{a}1", "{a}5");
+        store.write(0, ["{a}2"]).remove("{a}2");
+        store.set(
+            0,
+            "{a}3".to_string(),
+            Bytes::from_static(b"1"),
+            Some(Duration::ZERO),
+        );
+
+        let keyspace = store.read_all(0);
+        let mut keys = keyspace.keys_in_slot(slot).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, ["{a}4", "{a}5"]);
+        drop(keyspace);
+
+        store.flush(None, false);
+        assert_eq!(store.read_all(0).keys_in_slot(slot).count(), 0);
+    }
+
+    // Compares the previous single `Mutex` around the keyspace with a single shard, and the default
+    // number of shards, under a read-heavy load from several threads. Run with:
+    //   cargo test --release -- --ignored --nocapture sharding_benchmark
+    #[test]
+    #[ignore = "benchmark"]
+    fn sharding_benchmark() {
+        const KEYS: usize = 10_000;
+        const THREADS: usize = 8;
+        const OPERATIONS: usize = 500_000;
+
+        // One in ten operations is a write
+        fn run(name: &str, operation: impl Fn(&str, bool) + Sync) {
+            let keys = (0..KEYS).map(|i| format!("key:{i}")).collect::<Vec<_>>();
+            let start = Instant::now();
+            thread::scope(|scope| {
+                for thread in 0..THREADS {
+                    let (keys, operation) = (&keys, &operation);
+                    scope.spawn(move || {
+                        for i in 0..OPERATIONS {
+                            operation(&keys[(i * 7 + thread * 13) % KEYS], i % 10 == 0);
+                        }
+                    });
+                }
+            });
+
+            let elapsed = start.elapsed();
+            #[allow(clippy::cast_precision_loss)]
+            let rate = (THREADS * OPERATIONS) as f64 / elapsed.as_secs_f64();
+            println!("{name}: {elapsed:?} ({rate:.0} operations/s)");
+        }
+
+        let single = Mutex::new(Dict::new());
+        run("single mutex", |key, write| {
+            let mut dict = single.lock().unwrap();
+            if write {
+                dict.insert(key.to_string(), Bytes::from_static(b"value"));
+            } else {
+                let _ = dict.get(key).cloned();
+            }
+        });
+
+        for shards in [1, DEFAULT_SHARDS] {
+            let store = Store::with_shards(shards);
+            run(&format!("{shards} shard(s)"), |key, write| {
+                if write {
+                    store
+                        .write(0, [key])
+                        .insert(key, Bytes::from_static(b"value"));
+                } else {
+                    let _ = store
+                        .read(0, [key])
+                        .get::<Bytes>(key)
+                        .map(Option::<&_>::cloned);
+                }
+            });
         }
     }
 }